@@ -0,0 +1,321 @@
+//! an append-only ledger of the sessions quarantine started. every record carries the hash of the previous one,
+//! and the hash of the last record is kept next to the ledger, so edits and truncation are noticed by `verify`.
+
+use anyhow::anyhow;
+use bollard::container::Config;
+use bollard::secret::MountTypeEnum;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use crate::config;
+
+/// the `prev` of the first record.
+const GENESIS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Record {
+    /// seconds since the unix epoch
+    pub time: u64,
+    pub user: String,
+    pub project: String,
+    pub image: String,
+    pub image_id: String,
+    pub network: String,
+    /// what about the session weakens its isolation
+    pub weakened: Vec<String>,
+    pub prev: String,
+    #[serde(default)]
+    pub hash: String,
+}
+
+impl Record {
+    fn compute_hash(&self) -> anyhow::Result<String> {
+        let mut unhashed = self.clone();
+        unhashed.hash = String::new();
+        let mut hasher = Sha256::new();
+        hasher.update(serde_json::to_string(&unhashed)?);
+        Ok(format!("{:x}", hasher.finalize()))
+    }
+}
+
+/// where the ledger is, when auditing is enabled in the global config.
+pub fn ledger(settings: Option<&config::Audit>) -> Option<PathBuf> {
+    settings
+        .filter(|settings| settings.enabled)
+        .map(|settings| {
+            settings
+                .path
+                .clone()
+                .unwrap_or_else(|| crate::logging::log_dir().join("audit.jsonl"))
+        })
+}
+
+fn head_file(ledger: &Path) -> PathBuf {
+    let mut head = ledger.as_os_str().to_owned();
+    head.push(".head");
+    PathBuf::from(head)
+}
+
+/// appends a record for the session that is about to start.
+pub fn append(
+    ledger: &Path,
+    project: &Path,
+    config: &Config<String>,
+    image_id: &str,
+) -> anyhow::Result<()> {
+    let head = head_file(ledger);
+    let prev = match std::fs::read_to_string(&head) {
+        Ok(prev) => prev.trim().to_string(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => GENESIS.to_string(),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut record = Record {
+        time: now(),
+        user: std::env::var("USER")
+            .or_else(|_| std::env::var("USERNAME"))
+            .unwrap_or_default(),
+        project: project.display().to_string(),
+        image: config.image.clone().unwrap_or_default(),
+        image_id: image_id.to_string(),
+        network: config
+            .host_config
+            .as_ref()
+            .and_then(|host_config| host_config.network_mode.clone())
+            .unwrap_or_else(|| "default".to_string()),
+        weakened: weakened(config),
+        prev,
+        hash: String::new(),
+    };
+    record.hash = record.compute_hash()?;
+
+    if let Some(dir) = ledger.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(ledger)?;
+    writeln!(file, "{}", serde_json::to_string(&record)?)?;
+    std::fs::write(&head, &record.hash)?;
+    tracing::debug!("recorded the session in `{}`", ledger.display());
+    Ok(())
+}
+
+/// the parts of the container config that weaken the sandbox.
+fn weakened(config: &Config<String>) -> Vec<String> {
+    let mut weakened = vec![];
+    let Some(host_config) = &config.host_config else {
+        return weakened;
+    };
+    if host_config.privileged == Some(true) {
+        weakened.push("privileged".to_string());
+    }
+    let labels = config.labels.clone().unwrap_or_default();
+    if labels.contains_key(crate::ENGINE_SOCKET_LABEL) {
+        weakened.push("engine-socket".to_string());
+    }
+    if let Some(namespaces) = labels.get(crate::HOST_NAMESPACES_LABEL) {
+        for namespace in namespaces.split(',') {
+            weakened.push(format!("host-{}", namespace));
+        }
+    }
+
+    let mounts = host_config
+        .mounts
+        .iter()
+        .flatten()
+        .filter(|mount| mount.typ == Some(MountTypeEnum::BIND))
+        .filter_map(|mount| mount.source.clone());
+    let binds = host_config
+        .binds
+        .iter()
+        .flatten()
+        .filter_map(|bind| bind.split_once(':').map(|(source, _)| source.to_string()));
+    for source in mounts.chain(binds) {
+        if is_sensitive(Path::new(&source)) {
+            weakened.push(format!("sensitive-mount:{}", source));
+        }
+    }
+    weakened
+}
+
+/// whether mounting `source` exposes the root filesystem, system config, or credentials in the home directory.
+fn is_sensitive(source: &Path) -> bool {
+    let mut sensitive = vec![PathBuf::from("/etc"), PathBuf::from("/root")];
+    if let Some(home) = std::env::var_os("HOME").map(PathBuf::from) {
+        for dir in [
+            ".ssh",
+            ".aws",
+            ".gnupg",
+            ".kube",
+            ".docker",
+            ".config/gcloud",
+        ] {
+            sensitive.push(home.join(dir));
+        }
+    }
+    sensitive.iter().any(|path| path.starts_with(source))
+}
+
+/// the records that started within `since..until` in (or below) `dir`.
+pub fn query(
+    ledger: &Path,
+    since: Option<u64>,
+    until: Option<u64>,
+    dir: Option<&Path>,
+) -> anyhow::Result<Vec<Record>> {
+    let mut records = vec![];
+    for record in read(ledger)? {
+        let (_, record) = record?;
+        let in_range = since.is_none_or(|since| record.time >= since)
+            && until.is_none_or(|until| record.time <= until);
+        let in_dir = dir.is_none_or(|dir| Path::new(&record.project).starts_with(dir));
+        if in_range && in_dir {
+            records.push(record);
+        }
+    }
+    Ok(records)
+}
+
+/// checks every hash and link of the chain, and that the last record is the one the head points at.
+/// returns how many records were verified.
+pub fn verify(ledger: &Path) -> anyhow::Result<usize> {
+    let mut prev = GENESIS.to_string();
+    let mut count = 0;
+    for record in read(ledger)? {
+        let (line, record) = record?;
+        if record.prev != prev {
+            return Err(anyhow!(
+                "line {}: the chain is broken, a record before it was removed or changed",
+                line
+            ));
+        }
+        if record.compute_hash()? != record.hash {
+            return Err(anyhow!("line {}: the record was edited", line));
+        }
+        prev = record.hash;
+        count += 1;
+    }
+
+    let head = std::fs::read_to_string(head_file(ledger)).unwrap_or_default();
+    match head.trim() == prev || (count == 0 && head.is_empty()) {
+        true => Ok(count),
+        false => Err(anyhow!(
+            "the last record is not the one `{}` points at, records were removed from the end",
+            head_file(ledger).display()
+        )),
+    }
+}
+
+/// the records of the ledger with their line numbers. a missing ledger has no records.
+fn read(ledger: &Path) -> anyhow::Result<Vec<anyhow::Result<(usize, Record)>>> {
+    let file = match std::fs::File::open(ledger) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e.into()),
+    };
+    Ok(std::io::BufReader::new(file)
+        .lines()
+        .enumerate()
+        .map(|(i, line)| {
+            let line = line?;
+            let record = serde_json::from_str::<Record>(&line)
+                .map_err(|e| anyhow!("line {}: invalid record: {}", i + 1, e))?;
+            Ok((i + 1, record))
+        })
+        .collect())
+}
+
+fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+/// parses `YYYY-MM-DD`, `YYYY-MM-DDTHH:MM:SS` (utc) or a duration into the past like `24h` or `7d`.
+pub fn parse_time(s: &str) -> anyhow::Result<u64> {
+    if let Some(days) = s
+        .strip_suffix('d')
+        .and_then(|days| days.parse::<u64>().ok())
+    {
+        return Ok(now().saturating_sub(days * 24 * 60 * 60));
+    }
+    if let Some(hours) = s
+        .strip_suffix('h')
+        .and_then(|hours| hours.parse::<u64>().ok())
+    {
+        return Ok(now().saturating_sub(hours * 60 * 60));
+    }
+
+    let invalid = || {
+        anyhow!(
+            "invalid time `{}`. expected YYYY-MM-DD, YYYY-MM-DDTHH:MM:SS, 24h or 7d",
+            s
+        )
+    };
+    let (date, time) = s.split_once('T').unwrap_or((s, "00:00:00"));
+    let date = date
+        .split('-')
+        .map(|part| part.parse::<i64>().map_err(|_| invalid()))
+        .collect::<anyhow::Result<Vec<i64>>>()?;
+    let time = time
+        .split(':')
+        .map(|part| part.parse::<i64>().map_err(|_| invalid()))
+        .collect::<anyhow::Result<Vec<i64>>>()?;
+    let (&[year, month, day], &[hour, minute, second]) = (&date[..], &time[..]) else {
+        return Err(invalid());
+    };
+    if !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 59
+    {
+        return Err(invalid());
+    }
+    let secs = days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60 + second;
+    u64::try_from(secs).map_err(|_| invalid())
+}
+
+/// `YYYY-MM-DDTHH:MM:SSZ`
+pub fn format_time(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs / 3600 % 24,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+/// days since 1970-01-01 of a date in the proleptic gregorian calendar (howard hinnant's algorithm).
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
@@ -0,0 +1,110 @@
+//! registry credentials from the docker cli's `config.json`, so pulls of private images work like `docker pull`.
+
+use base64::Engine;
+use bollard::auth::DockerCredentials;
+use serde_json::Value;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+/// the key docker hub's credentials are stored under.
+const DOCKER_HUB: &str = "https://index.docker.io/v1/";
+
+/// the credentials for `registry` (eg: `ghcr.io` or `docker.io`), from a credential helper or `auths`.
+/// no credentials is not an error, the pull is anonymous then.
+pub fn credentials(registry: &str) -> Option<DockerCredentials> {
+    let server = match registry {
+        "docker.io" | "index.docker.io" | "registry-1.docker.io" => DOCKER_HUB,
+        registry => registry,
+    };
+    let config = std::fs::read_to_string(config_path()?).ok()?;
+    let config = serde_json::from_str::<Value>(&config).ok()?;
+
+    let helper = config
+        .get("credHelpers")
+        .and_then(|helpers| helpers.get(server))
+        .or_else(|| config.get("credsStore"))
+        .and_then(Value::as_str);
+    if let Some(helper) = helper {
+        match from_helper(helper, server) {
+            Some(credentials) => return Some(credentials),
+            None => tracing::debug!(
+                "the credential helper `{}` has nothing for `{}`",
+                helper,
+                server
+            ),
+        }
+    }
+
+    let auths = config.get("auths")?.as_object()?;
+    let entry = [
+        server.to_string(),
+        format!("https://{}", server),
+        format!("http://{}", server),
+    ]
+    .iter()
+    .find_map(|key| auths.get(key))?;
+
+    if let Some(token) = entry.get("identitytoken").and_then(Value::as_str) {
+        return Some(DockerCredentials {
+            identitytoken: Some(token.to_string()),
+            serveraddress: Some(server.to_string()),
+            ..Default::default()
+        });
+    }
+    let decoded = base64::engine::general_purpose::STANDARD
+        .decode(entry.get("auth")?.as_str()?)
+        .ok()?;
+    let (username, password) = String::from_utf8(decoded)
+        .ok()?
+        .split_once(':')
+        .map(|(username, password)| (username.to_string(), password.to_string()))?;
+    Some(DockerCredentials {
+        username: Some(username),
+        password: Some(password),
+        serveraddress: Some(server.to_string()),
+        ..Default::default()
+    })
+}
+
+/// `$DOCKER_CONFIG/config.json`, falling back to `~/.docker/config.json`.
+fn config_path() -> Option<PathBuf> {
+    let dir = std::env::var_os("DOCKER_CONFIG")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".docker")))?;
+    Some(dir.join("config.json"))
+}
+
+/// asks `docker-credential-<helper>`, which reads the server from stdin and prints `{"Username", "Secret"}`.
+fn from_helper(helper: &str, server: &str) -> Option<DockerCredentials> {
+    let mut child = Command::new(format!("docker-credential-{}", helper))
+        .arg("get")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+    child.stdin.take()?.write_all(server.as_bytes()).ok()?;
+    let output = child.wait_with_output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    let response = serde_json::from_slice::<Value>(&output.stdout).ok()?;
+    let username = response.get("Username")?.as_str()?;
+    let secret = response.get("Secret")?.as_str()?.to_string();
+    Some(match username {
+        // helpers report identity tokens this way
+        "<token>" => DockerCredentials {
+            identitytoken: Some(secret),
+            serveraddress: Some(server.to_string()),
+            ..Default::default()
+        },
+        username => DockerCredentials {
+            username: Some(username.to_string()),
+            password: Some(secret),
+            serveraddress: Some(server.to_string()),
+            ..Default::default()
+        },
+    })
+}
@@ -0,0 +1,161 @@
+//! `--dockerfile` and `--build`: builds the sandbox image from a local Dockerfile instead of pulling it.
+
+use anyhow::anyhow;
+use bollard::image::BuildImageOptions;
+use bollard::Docker;
+use futures::StreamExt;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::copy;
+
+/// a Dockerfile and the directory it is built in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Context {
+    pub dir: PathBuf,
+    pub dockerfile: PathBuf,
+}
+
+impl Context {
+    /// `--dockerfile` alone is built in its own directory, `--build` alone builds the `Dockerfile` of the directory.
+    pub fn new(dockerfile: Option<&Path>, dir: Option<&Path>) -> anyhow::Result<Self> {
+        let dir = match (dir, dockerfile) {
+            (Some(dir), _) => dir.to_path_buf(),
+            (None, Some(dockerfile)) => dockerfile
+                .parent()
+                .filter(|parent| !parent.as_os_str().is_empty())
+                .unwrap_or(Path::new("."))
+                .to_path_buf(),
+            (None, None) => unreachable!("--build or --dockerfile is given"),
+        };
+        let dockerfile = dockerfile.map_or_else(|| dir.join("Dockerfile"), Path::to_path_buf);
+        let canonical = |path: &Path| {
+            path.canonicalize()
+                .map_err(|e| anyhow!("unable to find `{}`: {}", path.display(), e))
+        };
+        let (dir, dockerfile) = (canonical(&dir)?, canonical(&dockerfile)?);
+        if !dir.is_dir() {
+            return Err(anyhow!(
+                "the build context `{}` is not a directory",
+                dir.display()
+            ));
+        }
+        if !dockerfile.starts_with(&dir) || !dockerfile.is_file() {
+            return Err(anyhow!(
+                "`{}` is not a file in the build context `{}`",
+                dockerfile.display(),
+                dir.display()
+            ));
+        }
+        Ok(Self { dir, dockerfile })
+    }
+
+    /// `quarantine-build-HASH:latest`, the same for every build of this Dockerfile in this directory,
+    /// so a rebuild replaces the previous image.
+    pub fn tag(&self) -> String {
+        let mut hash = Sha256::new();
+        hash.update(self.dir.to_string_lossy().as_bytes());
+        hash.update([0]);
+        hash.update(self.dockerfile.to_string_lossy().as_bytes());
+        let id = hash.finalize()[..4]
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>();
+        format!("quarantine-build-{}:latest", id)
+    }
+
+    /// the path of the Dockerfile in the context, as the daemon expects it.
+    fn dockerfile_name(&self) -> String {
+        self.dockerfile
+            .strip_prefix(&self.dir)
+            .unwrap_or(&self.dockerfile)
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    /// the `.dockerignore` next to the Dockerfile, or else the one of the context. the Dockerfile itself is
+    /// always sent, the daemon needs it.
+    fn ignored(&self) -> ignore::gitignore::Gitignore {
+        let next_to = self.dockerfile.with_file_name(".dockerignore");
+        let file = match next_to.is_file() {
+            true => next_to,
+            false => self.dir.join(".dockerignore"),
+        };
+        copy::rules(
+            &self.dir,
+            &[file],
+            &[format!("!/{}", self.dockerfile_name())],
+        )
+    }
+}
+
+/// builds the image, logging the output as it arrives, and returns its tag. a failed step is reported with
+/// what it printed.
+pub async fn build(
+    docker: &Docker,
+    context: &Context,
+    build_args: &[(String, String)],
+) -> anyhow::Result<String> {
+    let tag = context.tag();
+    let (archive, files) = copy::archive(&context.dir, &context.ignored())?;
+    tracing::info!(
+        "building `{}` in `{}` ({} files) as {}",
+        context.dockerfile.display(),
+        context.dir.display(),
+        files,
+        tag
+    );
+
+    let options = BuildImageOptions {
+        dockerfile: context.dockerfile_name(),
+        t: tag.clone(),
+        rm: true,
+        forcerm: true,
+        buildargs: build_args.iter().cloned().collect::<HashMap<_, _>>(),
+        ..Default::default()
+    };
+    let mut stream = docker.build_image(options, None, Some(archive.into()));
+    // the output of the step that is running, for the error when it fails
+    let mut step = Vec::<String>::new();
+    let failed = |error: &str, step: &[String]| {
+        let mut message = format!(
+            "unable to build `{}`: {}",
+            context.dockerfile.display(),
+            error
+        );
+        if !step.is_empty() {
+            message.push_str("\n\n");
+            message.push_str(&step.join("\n"));
+        }
+        anyhow!(message)
+    };
+    while let Some(info) = stream.next().await {
+        let info = info.map_err(|e| failed(&e.to_string(), &step))?;
+        if let Some(error) = info
+            .error_detail
+            .and_then(|detail| detail.message)
+            .or(info.error)
+        {
+            return Err(failed(error.trim(), &step));
+        }
+        for line in info.stream.iter().flat_map(|output| output.lines()) {
+            let line = line.trim_end();
+            if line.is_empty() {
+                continue;
+            }
+            if line.starts_with("Step ") {
+                step.clear();
+            }
+            step.push(line.to_string());
+            tracing::info!("{}", line);
+        }
+        if let Some(status) = info.status {
+            tracing::debug!("{} {}", status, info.progress.unwrap_or_default());
+        }
+    }
+    tracing::info!("built {}", tag);
+    Ok(tag)
+}
@@ -0,0 +1,145 @@
+//! `--cache` and `--cache-dir`: named volumes for package manager caches, kept across sessions.
+
+use anyhow::anyhow;
+use bollard::secret::{Mount, MountTypeEnum};
+use bollard::volume::{CreateVolumeOptions, ListVolumesOptions, RemoveVolumeOptions};
+use bollard::Docker;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// set on the cache volumes, the name of the cache.
+pub const CACHE_LABEL: &str = "quarantine.cache";
+
+/// where the presets that can choose their cache directory keep it.
+const CACHE_ROOT: &str = "/var/cache/quarantine";
+
+/// the caches `--cache` knows.
+pub const PRESETS: [&str; 4] = ["cargo", "pip", "npm", "go"];
+
+/// a named volume and where it is mounted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cache {
+    pub volume: String,
+    pub target: String,
+}
+
+impl Cache {
+    /// the volume `quarantine-cache-NAME`, eg: of `--cache-dir NAME:/container/path`.
+    pub fn new(name: &str, target: &str) -> Self {
+        Self {
+            volume: format!("quarantine-cache-{}", name),
+            target: target.to_string(),
+        }
+    }
+
+    pub fn mount(&self) -> Mount {
+        Mount {
+            source: Some(self.volume.clone()),
+            target: Some(self.target.clone()),
+            typ: Some(MountTypeEnum::VOLUME),
+            ..Default::default()
+        }
+    }
+}
+
+/// the volumes of a `--cache` preset and the variables that point the tool at them. cargo's `target` is
+/// kept per project (`project`, the mounted working directory), the downloads are shared.
+pub fn preset(name: &str, project: Option<(&Path, &str)>) -> (Vec<Cache>, Vec<String>) {
+    match name {
+        "cargo" => {
+            let mut caches = vec![
+                Cache::new("cargo-registry", "/usr/local/cargo/registry"),
+                Cache::new("cargo-git", "/usr/local/cargo/git"),
+            ];
+            if let Some((dir, mount)) = project {
+                let hash = Sha256::digest(dir.to_string_lossy().as_bytes());
+                let id = hash[..4]
+                    .iter()
+                    .map(|b| format!("{:02x}", b))
+                    .collect::<String>();
+                caches.push(Cache::new(
+                    &format!("cargo-target-{}", id),
+                    &format!("{}/target", mount),
+                ));
+            }
+            (caches, vec![])
+        }
+        "pip" => tool("pip", "PIP_CACHE_DIR"),
+        "npm" => tool("npm", "npm_config_cache"),
+        "go" => {
+            let (mut caches, mut env) = tool("go-mod", "GOMODCACHE");
+            let (build, build_env) = tool("go-build", "GOCACHE");
+            caches.extend(build);
+            env.extend(build_env);
+            (caches, env)
+        }
+        _ => unreachable!("`{}` is not one of the presets", name),
+    }
+}
+
+/// a cache under `CACHE_ROOT`, found through `variable` whatever the user's home is.
+fn tool(name: &str, variable: &str) -> (Vec<Cache>, Vec<String>) {
+    let target = format!("{}/{}", CACHE_ROOT, name);
+    let env = format!("{}={}", variable, target);
+    (vec![Cache::new(name, &target)], vec![env])
+}
+
+/// creates the volumes that don't exist yet. creating an existing volume leaves it as it is.
+pub async fn ensure(docker: &Docker, caches: &[Cache]) -> anyhow::Result<()> {
+    for cache in caches {
+        let name = cache
+            .volume
+            .strip_prefix("quarantine-cache-")
+            .unwrap_or(&cache.volume);
+        docker
+            .create_volume(CreateVolumeOptions {
+                name: cache.volume.as_str(),
+                labels: HashMap::from([(CACHE_LABEL, name)]),
+                ..Default::default()
+            })
+            .await
+            .map_err(|e| {
+                anyhow!(
+                    "unable to create the cache volume `{}`: {}",
+                    cache.volume,
+                    e
+                )
+            })?;
+        tracing::info!("cache `{}` is mounted at `{}`", cache.volume, cache.target);
+    }
+    Ok(())
+}
+
+/// the cache volumes, with where they are stored.
+pub async fn list(docker: &Docker) -> anyhow::Result<Vec<(String, String)>> {
+    let volumes = docker
+        .list_volumes(Some(ListVolumesOptions {
+            filters: HashMap::from([("label", vec![CACHE_LABEL])]),
+        }))
+        .await?
+        .volumes
+        .unwrap_or_default();
+    Ok(volumes
+        .into_iter()
+        .map(|volume| (volume.name, volume.mountpoint))
+        .collect())
+}
+
+/// removes every cache volume that no container uses.
+pub async fn clean(docker: &Docker) -> anyhow::Result<usize> {
+    let mut removed = 0;
+    for (name, _) in list(docker).await? {
+        match docker
+            .remove_volume(&name, None::<RemoveVolumeOptions>)
+            .await
+        {
+            Ok(()) => {
+                tracing::info!("removed cache volume: {}", name);
+                removed += 1;
+            }
+            Err(e) => tracing::warn!("unable to remove the cache volume `{}`: {}", name, e),
+        }
+    }
+    Ok(removed)
+}
@@ -0,0 +1,81 @@
+use bollard::image::ListImagesOptions;
+use bollard::Docker;
+use clap_complete::CompletionCandidate;
+use std::ffi::OsStr;
+use std::future::Future;
+use std::time::Duration;
+
+/// completion runs every time the user presses TAB, so it must never keep the shell waiting on the daemon.
+const COMPLETION_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// suggests the runtimes registered with the local daemon (eg: `runc`, `runsc`, `kata-runtime`).
+pub fn runtimes(current: &OsStr) -> Vec<CompletionCandidate> {
+    let current = current.to_string_lossy();
+
+    let runtimes: Vec<String> = query_daemon(|docker| async move {
+        let info = docker.info().await.ok()?;
+        Some(info.runtimes.unwrap_or_default().into_keys().collect())
+    })
+    .unwrap_or_default();
+
+    let mut runtimes: Vec<String> = runtimes
+        .into_iter()
+        .filter(|runtime| runtime.starts_with(&*current))
+        .collect();
+    runtimes.sort();
+
+    runtimes.into_iter().map(CompletionCandidate::new).collect()
+}
+
+/// suggests `repo:tag` references of the images available locally, most recently created first.
+pub fn images(current: &OsStr) -> Vec<CompletionCandidate> {
+    let current = current.to_string_lossy();
+
+    let mut images = query_daemon(|docker| async move {
+        docker
+            .list_images(None::<ListImagesOptions<String>>)
+            .await
+            .ok()
+    })
+    .unwrap_or_default();
+    images.sort_by_key(|image| std::cmp::Reverse(image.created));
+
+    let mut seen = std::collections::HashSet::new();
+    images
+        .into_iter()
+        .flat_map(|image| image.repo_tags)
+        .filter(|reference| reference != "<none>:<none>")
+        .filter(|reference| reference.starts_with(&*current))
+        .filter(|reference| seen.insert(reference.clone()))
+        .map(CompletionCandidate::new)
+        .collect()
+}
+
+/// runs `f` against the local daemon on its own runtime and gives up after `COMPLETION_TIMEOUT`.
+fn query_daemon<F, Fut, T>(f: F) -> Option<T>
+where
+    F: FnOnce(Docker) -> Fut + Send,
+    Fut: Future<Output = Option<T>>,
+    T: Send,
+{
+    // completers are called from inside the main tokio runtime, which can't be blocked on directly
+    std::thread::scope(|scope| {
+        scope
+            .spawn(|| {
+                let runtime = tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()
+                    .ok()?;
+                runtime.block_on(async {
+                    let docker = Docker::connect_with_local_defaults().ok()?;
+                    tokio::time::timeout(COMPLETION_TIMEOUT, f(docker))
+                        .await
+                        .ok()
+                        .flatten()
+                })
+            })
+            .join()
+            .ok()
+            .flatten()
+    })
+}
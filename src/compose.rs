@@ -0,0 +1,541 @@
+//! `--compose-service`: runs the sandbox from a service of a compose file, and `--with-deps` starts the
+//! services it depends on next to it.
+
+use anyhow::anyhow;
+use bollard::container::{
+    Config, CreateContainerOptions, ListContainersOptions, NetworkingConfig,
+    RemoveContainerOptions, StartContainerOptions,
+};
+use bollard::network::{CreateNetworkOptions, ListNetworksOptions};
+use bollard::secret::{EndpointSettings, HostConfig, PortBinding, PortMap};
+use bollard::Docker;
+use serde_yaml::{Mapping, Value};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::config::FileConfig;
+use crate::pull;
+
+/// the compose files that are looked for, in the order compose itself prefers them.
+const FILES: [&str; 4] = [
+    "compose.yaml",
+    "compose.yml",
+    "docker-compose.yaml",
+    "docker-compose.yml",
+];
+
+/// set on the sidecars and their network, the value is the name of the session container.
+pub const SIDECAR_LABEL: &str = "quarantine.sidecar-of";
+
+/// service keys that are understood, so they aren't reported as unsupported.
+const MAPPED: [&str; 5] = ["image", "environment", "volumes", "ports", "depends_on"];
+
+/// the settings of a compose service that quarantine can map.
+#[derive(Debug, Default)]
+pub struct Service {
+    pub image: Option<String>,
+    /// `environment` and `volumes`
+    pub config: FileConfig,
+    pub ports: Vec<Port>,
+}
+
+/// a published port, `[HOST_IP:][HOST_PORT:]CONTAINER_PORT[/PROTOCOL]`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Port {
+    pub host_ip: Option<String>,
+    pub host_port: Option<String>,
+    /// with the protocol, eg: `80/tcp`
+    pub container_port: String,
+}
+
+/// a service started next to the session container.
+#[derive(Debug)]
+pub struct Sidecar {
+    pub service: String,
+    pub config: Config<String>,
+}
+
+/// the session service and, with `--with-deps`, the services it depends on (dependencies first).
+#[derive(Debug)]
+pub struct Compose {
+    pub service: Service,
+    pub dependencies: Vec<(String, Service)>,
+}
+
+/// `file`, or the compose file of the directory.
+pub fn find(dir: &Path, file: Option<&Path>) -> anyhow::Result<PathBuf> {
+    match file {
+        Some(file) => Ok(dir.join(file)),
+        None => FILES
+            .iter()
+            .map(|name| dir.join(name))
+            .find(|path| path.is_file())
+            .ok_or_else(|| {
+                anyhow!(
+                    "no compose file found in `{}`. pass one with --compose-file",
+                    dir.display()
+                )
+            }),
+    }
+}
+
+pub fn load(path: &Path, name: &str, with_deps: bool) -> anyhow::Result<Compose> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| anyhow!("unable to read `{}`: {}", path.display(), e))?;
+    let document = serde_yaml::from_str::<Value>(&contents)
+        .map_err(|e| anyhow!("invalid `{}`: {}", path.display(), e))?;
+    let services = document
+        .get("services")
+        .and_then(Value::as_mapping)
+        .ok_or_else(|| anyhow!("`{}` has no services", path.display()))?;
+    let base_dir = path.parent().unwrap_or(Path::new("."));
+
+    let service = |name: &str| -> anyhow::Result<Service> {
+        let Some(service) = services.get(name) else {
+            return Err(anyhow!(
+                "`{}` has no service `{}`. available services are {}",
+                path.display(),
+                name,
+                services
+                    .keys()
+                    .filter_map(Value::as_str)
+                    .map(|name| format!("`{}`", name))
+                    .collect::<Vec<String>>()
+                    .join(" ")
+            ));
+        };
+        parse_service(name, service, base_dir)
+    };
+
+    let session = service(name)?;
+    let mut dependencies = vec![];
+    if with_deps {
+        let mut order = vec![];
+        visit(name, services, &mut vec![], &mut order)?;
+        for dependency in order.into_iter().filter(|dependency| dependency != name) {
+            let service = service(&dependency)?;
+            dependencies.push((dependency, service));
+        }
+    }
+    Ok(Compose {
+        service: session,
+        dependencies,
+    })
+}
+
+/// orders the dependencies of `name` so that every service comes after the ones it depends on.
+fn visit(
+    name: &str,
+    services: &Mapping,
+    path: &mut Vec<String>,
+    order: &mut Vec<String>,
+) -> anyhow::Result<()> {
+    if order.iter().any(|done| done == name) {
+        return Ok(());
+    }
+    if path.iter().any(|visiting| visiting == name) {
+        return Err(anyhow!(
+            "the services depend on each other in a cycle: {} -> {}",
+            path.join(" -> "),
+            name
+        ));
+    }
+    path.push(name.to_string());
+    for dependency in services.get(name).map(depends_on).unwrap_or_default() {
+        visit(&dependency, services, path, order)?;
+    }
+    path.pop();
+    order.push(name.to_string());
+    Ok(())
+}
+
+fn parse_service(name: &str, service: &Value, base_dir: &Path) -> anyhow::Result<Service> {
+    if service.get("build").is_some() && service.get("image").is_none() {
+        return Err(anyhow!(
+            "the service `{}` builds its image, which quarantine can't do. build it and pass it with `-i`",
+            name
+        ));
+    }
+    let unsupported = service
+        .as_mapping()
+        .into_iter()
+        .flat_map(|service| service.keys())
+        .filter_map(Value::as_str)
+        .filter(|key| !MAPPED.contains(key))
+        .map(|key| format!("`{}`", key))
+        .collect::<Vec<String>>();
+    if !unsupported.is_empty() {
+        tracing::warn!(
+            "ignoring these keys of the service `{}`: {}",
+            name,
+            unsupported.join(", ")
+        );
+    }
+
+    let mut parsed = Service {
+        image: service
+            .get("image")
+            .and_then(Value::as_str)
+            .map(String::from),
+        ..Default::default()
+    };
+
+    match service.get("environment") {
+        Some(Value::Mapping(env)) => {
+            for (key, value) in env {
+                let Some(key) = key.as_str() else { continue };
+                let value = match value {
+                    Value::Null => std::env::var(key).ok(),
+                    value => scalar(value),
+                };
+                if let Some(value) = value {
+                    parsed.config.env.insert(key.to_string(), value);
+                }
+            }
+        }
+        Some(Value::Sequence(env)) => {
+            for entry in env.iter().filter_map(Value::as_str) {
+                let value = match entry.split_once('=') {
+                    Some((key, value)) => Some((key.to_string(), value.to_string())),
+                    // taken from the host, like compose does
+                    None => std::env::var(entry)
+                        .ok()
+                        .map(|value| (entry.to_string(), value)),
+                };
+                parsed.config.env.extend(value);
+            }
+        }
+        _ => {}
+    }
+
+    for volume in service
+        .get("volumes")
+        .and_then(Value::as_sequence)
+        .into_iter()
+        .flatten()
+    {
+        match volume_to_bind(volume, base_dir) {
+            Some(bind) => parsed.config.mounts.push(bind),
+            None => tracing::warn!(
+                "ignoring the volume `{}` of the service `{}`",
+                serde_yaml::to_string(volume).unwrap_or_default().trim(),
+                name
+            ),
+        }
+    }
+
+    for port in service
+        .get("ports")
+        .and_then(Value::as_sequence)
+        .into_iter()
+        .flatten()
+    {
+        match parse_port(port) {
+            Some(port) => parsed.ports.push(port),
+            None => tracing::warn!(
+                "ignoring the port `{}` of the service `{}`",
+                serde_yaml::to_string(port).unwrap_or_default().trim(),
+                name
+            ),
+        }
+    }
+
+    Ok(parsed)
+}
+
+fn depends_on(service: &Value) -> Vec<String> {
+    match service.get("depends_on") {
+        Some(Value::Sequence(services)) => services
+            .iter()
+            .filter_map(Value::as_str)
+            .map(String::from)
+            .collect(),
+        // the long syntax, `depends_on: { db: { condition: ... } }`
+        Some(Value::Mapping(services)) => services
+            .keys()
+            .filter_map(Value::as_str)
+            .map(String::from)
+            .collect(),
+        _ => vec![],
+    }
+}
+
+fn scalar(value: &Value) -> Option<String> {
+    match value {
+        Value::String(value) => Some(value.clone()),
+        Value::Number(value) => Some(value.to_string()),
+        Value::Bool(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+/// the short `SRC:DEST[:MODE]` or the long syntax of a volume as a `SRC:DEST[:ro]` bind.
+/// relative sources are resolved against the directory of the compose file, named volumes stay as they are.
+fn volume_to_bind(volume: &Value, base_dir: &Path) -> Option<String> {
+    let (source, target, read_only) = match volume {
+        Value::String(spec) => {
+            let mut parts = spec.splitn(3, ':');
+            let source = parts.next()?;
+            let target = parts.next()?;
+            let read_only = parts
+                .next()
+                .is_some_and(|mode| mode.split(',').any(|mode| mode == "ro"));
+            (source.to_string(), target.to_string(), read_only)
+        }
+        Value::Mapping(_) => {
+            let typ = volume
+                .get("type")
+                .and_then(Value::as_str)
+                .unwrap_or("volume");
+            if typ != "bind" && typ != "volume" {
+                return None;
+            }
+            (
+                volume.get("source")?.as_str()?.to_string(),
+                volume.get("target")?.as_str()?.to_string(),
+                volume
+                    .get("read_only")
+                    .and_then(Value::as_bool)
+                    .unwrap_or_default(),
+            )
+        }
+        _ => return None,
+    };
+
+    let source = match source.strip_prefix("~/") {
+        Some(rest) => PathBuf::from(std::env::var_os("HOME")?)
+            .join(rest)
+            .display()
+            .to_string(),
+        None if source.starts_with('.') => base_dir.join(&source).display().to_string(),
+        None => source,
+    };
+    let bind = format!("{}:{}", source, target);
+    Some(match read_only {
+        true => format!("{}:ro", bind),
+        false => bind,
+    })
+}
+
+/// the short `[HOST_IP:][HOST_PORT:]CONTAINER_PORT[/PROTOCOL]` or the long syntax of a port.
+/// port ranges aren't supported.
+pub fn parse_port(port: &Value) -> Option<Port> {
+    let port = match port {
+        Value::Number(port) => Port {
+            host_ip: None,
+            host_port: None,
+            container_port: format!("{}/tcp", port),
+        },
+        Value::String(spec) => {
+            let (spec, protocol) = spec.rsplit_once('/').unwrap_or((spec, "tcp"));
+            let mut parts = spec.rsplitn(3, ':');
+            let container_port = parts.next()?;
+            Port {
+                host_port: parts.next().map(String::from),
+                host_ip: parts
+                    .next()
+                    .map(|ip| ip.trim_start_matches('[').trim_end_matches(']').to_string()),
+                container_port: format!("{}/{}", container_port, protocol),
+            }
+        }
+        Value::Mapping(_) => Port {
+            host_ip: port
+                .get("host_ip")
+                .and_then(Value::as_str)
+                .map(String::from),
+            host_port: port.get("published").and_then(scalar),
+            container_port: format!(
+                "{}/{}",
+                port.get("target").and_then(scalar)?,
+                port.get("protocol")
+                    .and_then(Value::as_str)
+                    .unwrap_or("tcp")
+            ),
+        },
+        _ => return None,
+    };
+
+    let is_port = |port: &str| port.parse::<u16>().is_ok();
+    let valid = port
+        .container_port
+        .split_once('/')
+        .is_some_and(|(port, _)| is_port(port))
+        && port.host_port.as_deref().is_none_or(is_port);
+    valid.then_some(port)
+}
+
+/// `exposed_ports` and `port_bindings` for publishing `ports`.
+pub fn publish(ports: &[Port]) -> (HashMap<String, HashMap<(), ()>>, PortMap) {
+    let mut exposed = HashMap::new();
+    let mut bindings = PortMap::new();
+    for port in ports {
+        exposed.insert(port.container_port.clone(), HashMap::new());
+        bindings
+            .entry(port.container_port.clone())
+            .or_default()
+            .get_or_insert_with(Vec::new)
+            .push(PortBinding {
+                host_ip: port.host_ip.clone(),
+                host_port: port.host_port.clone(),
+            });
+    }
+    (exposed, bindings)
+}
+
+/// joins `network` under the alias `service`.
+pub fn networking_config(network: &str, service: &str) -> NetworkingConfig<String> {
+    NetworkingConfig {
+        endpoints_config: HashMap::from([(
+            network.to_string(),
+            EndpointSettings {
+                aliases: Some(vec![service.to_string()]),
+                ..Default::default()
+            },
+        )]),
+    }
+}
+
+/// the container config of a dependency of the session named `session`.
+pub fn sidecar(session: &str, name: &str, service: Service) -> anyhow::Result<Sidecar> {
+    let image = service.image.ok_or_else(|| {
+        anyhow!(
+            "the service `{}` has no image, which --with-deps needs",
+            name
+        )
+    })?;
+    let (exposed_ports, port_bindings) = publish(&service.ports);
+    Ok(Sidecar {
+        service: name.to_string(),
+        config: Config {
+            image: Some(image),
+            env: Some(
+                service
+                    .config
+                    .env
+                    .into_iter()
+                    .map(|(key, value)| format!("{}={}", key, value))
+                    .collect(),
+            ),
+            exposed_ports: Some(exposed_ports),
+            labels: Some(HashMap::from([(
+                SIDECAR_LABEL.to_string(),
+                session.to_string(),
+            )])),
+            host_config: Some(HostConfig {
+                binds: Some(service.config.mounts),
+                port_bindings: Some(port_bindings),
+                network_mode: Some(session.to_string()),
+                ..Default::default()
+            }),
+            networking_config: Some(networking_config(session, name)),
+            ..Default::default()
+        },
+    })
+}
+
+/// creates the network of the session `session` and starts the sidecars on it, in order.
+/// whatever was started is removed again when one of them fails.
+/// `pull` is `None` with `--offline`, the images must be present then. an `internal` network has no way out,
+/// the sidecars and the sandbox only reach each other.
+pub async fn up(
+    docker: &Docker,
+    session: &str,
+    sidecars: &[Sidecar],
+    pull: Option<&pull::Options>,
+    internal: bool,
+) -> anyhow::Result<()> {
+    // leftovers of an earlier session that was not cleaned up
+    down(docker, session).await?;
+
+    let started = async {
+        tracing::info!("creating network: {}", session);
+        docker
+            .create_network(CreateNetworkOptions {
+                name: session,
+                internal,
+                labels: HashMap::from([(SIDECAR_LABEL, session)]),
+                ..Default::default()
+            })
+            .await?;
+
+        for sidecar in sidecars {
+            if let Some(options) = pull {
+                let image = sidecar.config.image.as_deref().unwrap_or_default();
+                pull::pull_image(docker, image, options).await?;
+            }
+
+            let name = format!("{}-{}", session, sidecar.service);
+            tracing::info!(
+                "starting sidecar `{}` for the service `{}`",
+                name,
+                sidecar.service
+            );
+            docker
+                .create_container(
+                    Some(CreateContainerOptions {
+                        name: name.as_str(),
+                        platform: None,
+                    }),
+                    sidecar.config.clone(),
+                )
+                .await?;
+            docker
+                .start_container(&name, None::<StartContainerOptions<String>>)
+                .await?;
+        }
+        anyhow::Ok(())
+    };
+
+    if let Err(e) = started.await {
+        if let Err(e) = down(docker, session).await {
+            tracing::warn!("unable to remove the sidecars of `{}`: {}", session, e);
+        }
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// removes the sidecars and the network of the session `session`, if it has any.
+pub async fn down(docker: &Docker, session: &str) -> anyhow::Result<()> {
+    let label = format!("{}={}", SIDECAR_LABEL, session);
+    let sidecars = docker
+        .list_containers(Some(ListContainersOptions {
+            all: true,
+            filters: HashMap::from([("label", vec![label.as_str()])]),
+            ..Default::default()
+        }))
+        .await?;
+    for sidecar in sidecars {
+        let Some(id) = sidecar.id else { continue };
+        docker
+            .remove_container(
+                &id,
+                Some(RemoveContainerOptions {
+                    force: true,
+                    v: true,
+                    link: false,
+                }),
+            )
+            .await?;
+        tracing::info!(
+            "removed sidecar: {}",
+            sidecar
+                .names
+                .unwrap_or_default()
+                .join(",")
+                .trim_start_matches('/')
+        );
+    }
+
+    let networks = docker
+        .list_networks(Some(ListNetworksOptions {
+            filters: HashMap::from([("label", vec![label.as_str()])]),
+        }))
+        .await?;
+    for network in networks {
+        let Some(id) = network.id else { continue };
+        docker.remove_network(&id).await?;
+        tracing::info!("removed network: {}", network.name.unwrap_or(id));
+    }
+    Ok(())
+}
@@ -0,0 +1,738 @@
+use anyhow::anyhow;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+/// the name of the project config, looked up in the working directory and then in its parents.
+pub const PROJECT_CONFIG: &str = "quarantine.toml";
+
+/// the keys a config file can have. others are reported and ignored.
+const KEYS: &[&str] = &[
+    "image",
+    "runtime",
+    "persist",
+    "env",
+    "mounts",
+    "shm_size",
+    "on_start",
+    "on_stop",
+    "audit",
+    "auto",
+    "registry_mirrors",
+    "registry_mirror_map",
+    "verify",
+    "network",
+    "user_map",
+];
+
+/// settings read from the config files. later settings take precedence:
+/// the global config, then the `[image."pattern"]` rules of the global config that match, then the project config.
+#[derive(Debug, Default, Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct FileConfig {
+    /// the image when none is given on the command line, from `image = "node:20"`
+    #[serde(
+        skip_deserializing,
+        rename = "image",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub image_name: Option<String>,
+
+    /// the `--runtime` when none is given. a project can't replace the runtime of the global config
+    pub runtime: Option<String>,
+
+    /// leave the container running at the end of the session, like `--persist`
+    pub persist: Option<bool>,
+
+    /// extra environment variables for the sandbox
+    pub env: BTreeMap<String, String>,
+
+    /// extra bind mounts, as `SRC:DEST[:ro]`
+    pub mounts: Vec<String>,
+
+    /// size of `/dev/shm` (eg: `1g`)
+    pub shm_size: Option<String>,
+
+    /// commands run inside the container after it starts, before the shell attaches
+    pub on_start: Vec<String>,
+
+    /// commands run inside the container right before it is stopped
+    pub on_stop: Vec<String>,
+
+    /// settings for images whose reference matches the glob pattern, in declaration order.
+    /// taken out of `image` when it is a table
+    #[serde(skip_serializing)]
+    pub image: toml::Table,
+
+    /// the session ledger. only read from the global config
+    pub audit: Option<Audit>,
+
+    /// extra `--auto` rules. a rule for a marker quarantine knows replaces the built-in one
+    pub auto: Vec<AutoRule>,
+
+    /// pull-through caches for docker hub, tried in order before docker hub itself. only read from the global config
+    pub registry_mirrors: Vec<String>,
+
+    /// pull-through caches for other registries (eg: `"ghcr.io" = ["ghcr-cache.internal"]`)
+    pub registry_mirror_map: BTreeMap<String, Vec<String>>,
+
+    /// require a valid cosign signature, usually in an `[image."pattern"]` rule. only read from the global config
+    pub verify: Option<Verify>,
+
+    /// the `--network` when none is given, eg: `none`. only read from the global config
+    pub network: Option<String>,
+
+    /// `false` runs as the image's user like `--as-root`. the host user is the default
+    pub user_map: Option<bool>,
+}
+
+#[derive(Debug, Clone, Deserialize, serde::Serialize)]
+pub struct AutoRule {
+    /// the file that identifies the kind of project (eg: `deno.json`)
+    pub marker: String,
+    pub image: String,
+}
+
+#[derive(Debug, Default, Clone, Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct Audit {
+    pub enabled: bool,
+
+    /// where the ledger is kept. defaults to `audit.jsonl` next to the logs
+    pub path: Option<PathBuf>,
+}
+
+/// who has to have signed the image, either a cosign `key` or a keyless certificate identity.
+#[derive(Debug, Default, Clone, Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct Verify {
+    pub key: Option<PathBuf>,
+    pub certificate_identity: Option<String>,
+    pub certificate_oidc_issuer: Option<String>,
+}
+
+impl FileConfig {
+    pub fn merge(&mut self, other: FileConfig) {
+        self.image_name = other.image_name.or(self.image_name.take());
+        self.runtime = other.runtime.or(self.runtime.take());
+        self.persist = other.persist.or(self.persist.take());
+        self.env.extend(other.env);
+        self.mounts.extend(other.mounts);
+        self.shm_size = other.shm_size.or(self.shm_size.take());
+        self.on_start.extend(other.on_start);
+        self.on_stop.extend(other.on_stop);
+        // the more specific rules go first
+        self.auto.splice(0..0, other.auto);
+        self.verify = other.verify.or(self.verify.take());
+        self.network = other.network.or(self.network.take());
+        self.user_map = other.user_map.or(self.user_map.take());
+        self.registry_mirrors.extend(other.registry_mirrors);
+        for (registry, mirrors) in other.registry_mirror_map {
+            self.registry_mirror_map
+                .entry(registry)
+                .or_default()
+                .extend(mirrors);
+        }
+    }
+}
+
+/// the merged config, and where it came from.
+#[derive(Debug, Default)]
+pub struct Loaded {
+    pub config: FileConfig,
+    pub files: Vec<PathBuf>,
+    /// the `[image."pattern"]` rules that matched the image
+    pub image_rules: Vec<String>,
+}
+
+/// `$XDG_CONFIG_HOME/quarantine`, falling back to `~/.config/quarantine`.
+pub fn config_dir() -> PathBuf {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .unwrap_or_else(std::env::temp_dir)
+        .join("quarantine")
+}
+
+/// `config.toml` in the config dir.
+pub fn global_config() -> PathBuf {
+    config_dir().join("config.toml")
+}
+
+/// the closest `quarantine.toml`, in the working directory or one of its parents.
+pub fn find_project(working_dir: &Path) -> Option<PathBuf> {
+    working_dir
+        .ancestors()
+        .map(|dir| dir.join(PROJECT_CONFIG))
+        .find(|path| path.is_file())
+}
+
+/// the `image` of the project config, or else of the global config. used when no image is given,
+/// before the `[image."pattern"]` rules can be matched.
+pub fn image(working_dir: &Path, project_dir: &Path) -> anyhow::Result<Option<String>> {
+    let vars = Vars::new(project_dir);
+    let global = Some(global_config()).filter(|path| path.is_file());
+    for path in find_project(working_dir).into_iter().chain(global) {
+        if let Some(image) = load_file(&path, &vars)?.image_name {
+            tracing::info!("using the image `{}` of `{}`", image, path.display());
+            return Ok(Some(image));
+        }
+    }
+    Ok(None)
+}
+
+/// loads `config.toml` from the config dir and the closest `quarantine.toml`, when they exist.
+/// `project_dir` is what `${PWD}` expands to.
+pub fn load(working_dir: &Path, project_dir: &Path, image_name: &str) -> anyhow::Result<Loaded> {
+    load_from(&global_config(), working_dir, project_dir, image_name)
+}
+
+/// `load` with `global` in place of `config.toml`.
+fn load_from(
+    global: &Path,
+    working_dir: &Path,
+    project_dir: &Path,
+    image_name: &str,
+) -> anyhow::Result<Loaded> {
+    let vars = Vars::new(project_dir);
+    let mut loaded = Loaded::default();
+
+    if global.is_file() {
+        tracing::info!("using config `{}`", global.display());
+        let mut config = load_file(global, &vars)?;
+        let rules = std::mem::take(&mut config.image);
+        loaded.config.audit = config.audit.take();
+        loaded.config.merge(config);
+        loaded.files.push(global.to_path_buf());
+
+        let reference = normalize_reference(image_name);
+        for (pattern, mut rule) in rules {
+            if !glob_match(&pattern, &reference) {
+                continue;
+            }
+            tracing::info!("applying config for images matching `{}`", pattern);
+            warn_unknown(
+                &mut rule,
+                &format!("[image.\"{}\"] in `{}`", pattern, global.display()),
+            );
+            let rule: FileConfig = rule.try_into().map_err(|e| {
+                anyhow!(
+                    "invalid `[image.\"{}\"]` in `{}`: {}",
+                    pattern,
+                    global.display(),
+                    e
+                )
+            })?;
+            loaded.config.merge(rule);
+            loaded.image_rules.push(pattern);
+        }
+    }
+
+    if let Some(project) = find_project(working_dir) {
+        tracing::info!("using config `{}`", project.display());
+        let mut config = load_file(&project, &vars)?;
+        // a sandboxed runtime asked for globally stays
+        if loaded.config.runtime.is_some() && config.runtime.is_some() {
+            tracing::warn!(
+                "the global config sets the runtime, ignoring `runtime` in `{}`",
+                project.display()
+            );
+            config.runtime = None;
+        }
+        if config.verify.take().is_some() {
+            tracing::warn!(
+                "`verify` is only read from the global config, ignoring it in `{}`",
+                project.display()
+            );
+        }
+        // nor loosen the isolation the global config asks for
+        if config.network.take().is_some() {
+            tracing::warn!(
+                "`network` is only read from the global config, ignoring it in `{}`",
+                project.display()
+            );
+        }
+        // a project must not be able to redirect where images come from
+        if !std::mem::take(&mut config.registry_mirrors).is_empty()
+            | !std::mem::take(&mut config.registry_mirror_map).is_empty()
+        {
+            tracing::warn!(
+                "registry mirrors are only read from the global config, ignoring them in `{}`",
+                project.display()
+            );
+        }
+        if !config.image.is_empty() {
+            tracing::warn!(
+                "`[image]` rules are only read from the global config, ignoring them in `{}`",
+                project.display()
+            );
+        }
+        if config.audit.is_some() {
+            tracing::warn!(
+                "`[audit]` is only read from the global config, ignoring it in `{}`",
+                project.display()
+            );
+        }
+        loaded.config.merge(config);
+        loaded.files.push(project);
+    }
+    Ok(loaded)
+}
+
+/// the `[audit]` settings of the global config.
+pub fn audit() -> anyhow::Result<Option<Audit>> {
+    let global = global_config();
+    if !global.is_file() {
+        return Ok(None);
+    }
+    let project_dir = std::env::current_dir()?;
+    Ok(load_file(&global, &Vars::new(&project_dir))?.audit)
+}
+
+/// `name` and `name:tag` refer to `name:latest` and `name:tag`, so `[image."postgres:*"]` matches `postgres`.
+fn normalize_reference(image_name: &str) -> String {
+    let last = image_name.rsplit('/').next().unwrap_or(image_name);
+    match last.contains(':') || last.contains('@') {
+        true => image_name.to_string(),
+        false => format!("{}:latest", image_name),
+    }
+}
+
+/// matches `text` against a glob where `*` is any run of characters and `?` is any single character.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<char>>();
+    let text = text.chars().collect::<Vec<char>>();
+
+    let (mut p, mut t) = (0, 0);
+    // where the last `*` was, and how much of the text it has swallowed so far
+    let mut backtrack = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star, swallowed)) => {
+                    p = star + 1;
+                    t = swallowed + 1;
+                    backtrack = Some((star, swallowed + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+fn load_file(path: &Path, vars: &Vars) -> anyhow::Result<FileConfig> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| anyhow!("unable to read `{}`: {}", path.display(), e))?;
+    let mut value = contents
+        .parse::<toml::Value>()
+        .map_err(|e| anyhow!("invalid config `{}`: {}", path.display(), e))?;
+
+    interpolate_value(&mut value, "", vars)
+        .map_err(|e| anyhow!("{} in `{}`", e, path.display()))?;
+    warn_unknown(&mut value, &format!("`{}`", path.display()));
+
+    // `image = "node:20"` names the image, `[image."pattern"]` are rules
+    let image_name = match value.get("image") {
+        Some(toml::Value::String(_)) => value
+            .as_table_mut()
+            .and_then(|table| table.remove("image"))
+            .and_then(|image| image.as_str().map(String::from)),
+        _ => None,
+    };
+    let mut config: FileConfig = value
+        .try_into()
+        .map_err(|e| anyhow!("invalid config `{}`: {}", path.display(), e))?;
+    config.image_name = image_name;
+    Ok(config)
+}
+
+/// removes the keys quarantine doesn't know from a config table, with a warning, so a typo or a
+/// setting of a newer version doesn't make the whole file unusable.
+fn warn_unknown(value: &mut toml::Value, source: &str) {
+    if let Some(table) = value.as_table_mut() {
+        table.retain(|key, _| {
+            let known = KEYS.contains(&key);
+            if !known {
+                tracing::warn!("unknown key `{}` in {}, ignoring it", key, source);
+            }
+            known
+        });
+    }
+}
+
+/// the variables `${...}` can refer to. the built-ins take precedence over the host environment.
+pub struct Vars {
+    builtins: HashMap<&'static str, String>,
+}
+
+impl Vars {
+    pub fn new(project_dir: &Path) -> Self {
+        let mut builtins = HashMap::new();
+        builtins.insert("PWD", project_dir.display().to_string());
+        builtins.insert("QUARANTINE_CONFIG_DIR", config_dir().display().to_string());
+        if let Some(home) = std::env::var_os("HOME") {
+            builtins.insert("HOME", home.to_string_lossy().into_owned());
+        }
+        if let Some(user) = std::env::var_os("USER").or_else(|| std::env::var_os("USERNAME")) {
+            builtins.insert("USER", user.to_string_lossy().into_owned());
+        }
+        Self { builtins }
+    }
+
+    fn get(&self, name: &str) -> Option<String> {
+        self.builtins
+            .get(name)
+            .cloned()
+            .or_else(|| std::env::var(name).ok())
+    }
+}
+
+/// interpolates every string in the parsed toml. `key` is the path to `value`, for error messages.
+fn interpolate_value(value: &mut toml::Value, key: &str, vars: &Vars) -> anyhow::Result<()> {
+    match value {
+        toml::Value::String(s) => {
+            *s =
+                interpolate(s, |name| vars.get(name)).map_err(|e| anyhow!("{} in `{}`", e, key))?;
+        }
+        toml::Value::Array(values) => {
+            for (i, value) in values.iter_mut().enumerate() {
+                interpolate_value(value, &format!("{}[{}]", key, i), vars)?;
+            }
+        }
+        toml::Value::Table(table) => {
+            for (name, value) in table.iter_mut() {
+                let key = match key.is_empty() {
+                    true => name.clone(),
+                    false => format!("{}.{}", key, name),
+                };
+                interpolate_value(value, &key, vars)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// replaces `${VAR}` with the value `lookup` returns for it. `$${` is a literal `${`,
+/// and a `$` that isn't followed by `{` is left as it is. undefined variables are an error.
+pub fn interpolate(s: &str, lookup: impl Fn(&str) -> Option<String>) -> anyhow::Result<String> {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+
+    while let Some(i) = rest.find('$') {
+        out.push_str(&rest[..i]);
+        rest = &rest[i..];
+
+        if let Some(after) = rest.strip_prefix("$${") {
+            out.push_str("${");
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("${") {
+            let end = after
+                .find('}')
+                .ok_or_else(|| anyhow!("unterminated `${{`"))?;
+            let name = &after[..end];
+            if name.is_empty() {
+                return Err(anyhow!("empty variable name `${{}}`"));
+            }
+            let value = lookup(name).ok_or_else(|| anyhow!("undefined variable `{}`", name))?;
+            out.push_str(&value);
+            rest = &after[end + 1..];
+        } else {
+            out.push('$');
+            rest = &rest[1..];
+        }
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "HOME" => Some("/home/me".to_string()),
+            "EMPTY" => Some(String::new()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn interpolates_variables() {
+        assert_eq!(
+            interpolate("${HOME}/.cache", lookup).unwrap(),
+            "/home/me/.cache"
+        );
+        assert_eq!(
+            interpolate("${HOME}:${HOME}", lookup).unwrap(),
+            "/home/me:/home/me"
+        );
+        assert_eq!(interpolate("a${EMPTY}b", lookup).unwrap(), "ab");
+        assert_eq!(interpolate("no variables", lookup).unwrap(), "no variables");
+        assert_eq!(interpolate("", lookup).unwrap(), "");
+    }
+
+    #[test]
+    fn leaves_escapes_and_lone_dollars() {
+        assert_eq!(interpolate("$${HOME}", lookup).unwrap(), "${HOME}");
+        assert_eq!(
+            interpolate("$${HOME} is ${HOME}", lookup).unwrap(),
+            "${HOME} is /home/me"
+        );
+        // the escape is not interpolated again, even when the name is undefined
+        assert_eq!(interpolate("$${UNSET}", lookup).unwrap(), "${UNSET}");
+        assert_eq!(
+            interpolate("$HOME costs $5 $", lookup).unwrap(),
+            "$HOME costs $5 $"
+        );
+        assert_eq!(interpolate("$$", lookup).unwrap(), "$$");
+    }
+
+    #[test]
+    fn refuses_unset_and_malformed_variables() {
+        let e = interpolate("${UNSET}/x", lookup).unwrap_err();
+        assert_eq!(e.to_string(), "undefined variable `UNSET`");
+        let e = interpolate("${HOME", lookup).unwrap_err();
+        assert_eq!(e.to_string(), "unterminated `${`");
+        let e = interpolate("${}", lookup).unwrap_err();
+        assert_eq!(e.to_string(), "empty variable name `${}`");
+    }
+
+    #[test]
+    fn interpolates_every_string_of_a_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let vars = Vars::new(Path::new("/work/project"));
+
+        let path = dir.path().join(PROJECT_CONFIG);
+        std::fs::write(
+            &path,
+            "mounts = [\"${PWD}/cache:/cache\"]\n\
+             on_start = [\"echo $${PWD}\"]\n\
+             [env]\n\
+             PROJECT = \"${PWD}\"\n",
+        )
+        .unwrap();
+        let config = load_file(&path, &vars).unwrap();
+        assert_eq!(config.mounts, ["/work/project/cache:/cache"]);
+        assert_eq!(config.on_start, ["echo ${PWD}"]);
+        assert_eq!(config.env["PROJECT"], "/work/project");
+
+        // the error says which key and which file
+        std::fs::write(
+            &path,
+            "[env]\nTOKEN = \"${QUARANTINE_TEST_UNSET_VARIABLE}\"\n",
+        )
+        .unwrap();
+        let e = load_file(&path, &vars).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            format!(
+                "undefined variable `QUARANTINE_TEST_UNSET_VARIABLE` in `env.TOKEN` in `{}`",
+                path.display()
+            )
+        );
+    }
+
+    /// a global config and a project below it, in a fresh directory.
+    struct Files {
+        dir: tempfile::TempDir,
+    }
+
+    impl Files {
+        fn new(global: &str, project: &str) -> Self {
+            let dir = tempfile::tempdir().unwrap();
+            std::fs::write(dir.path().join("config.toml"), global).unwrap();
+            std::fs::create_dir_all(dir.path().join("project/src")).unwrap();
+            std::fs::write(dir.path().join("project").join(PROJECT_CONFIG), project).unwrap();
+            Self { dir }
+        }
+
+        fn load(&self, working_dir: &str, image_name: &str) -> Loaded {
+            let project = self.dir.path().join("project");
+            load_from(
+                &self.dir.path().join("config.toml"),
+                &project.join(working_dir),
+                &project,
+                image_name,
+            )
+            .unwrap()
+        }
+    }
+
+    #[test]
+    fn later_configs_take_precedence() {
+        let mut config = FileConfig {
+            image_name: Some("alpine".to_string()),
+            persist: Some(true),
+            shm_size: Some("1g".to_string()),
+            env: BTreeMap::from([
+                ("A".to_string(), "global".to_string()),
+                ("B".to_string(), "global".to_string()),
+            ]),
+            mounts: vec!["/a:/a".to_string()],
+            auto: vec![AutoRule {
+                marker: "global".to_string(),
+                image: "alpine".to_string(),
+            }],
+            ..Default::default()
+        };
+        config.merge(FileConfig {
+            image_name: Some("node:20".to_string()),
+            persist: None,
+            env: BTreeMap::from([("B".to_string(), "project".to_string())]),
+            mounts: vec!["/b:/b".to_string()],
+            auto: vec![AutoRule {
+                marker: "project".to_string(),
+                image: "node:20".to_string(),
+            }],
+            ..Default::default()
+        });
+
+        assert_eq!(config.image_name.as_deref(), Some("node:20"));
+        // unset settings don't reset what came before
+        assert_eq!(config.persist, Some(true));
+        assert_eq!(config.shm_size.as_deref(), Some("1g"));
+        assert_eq!(config.env["A"], "global");
+        assert_eq!(config.env["B"], "project");
+        assert_eq!(config.mounts, ["/a:/a", "/b:/b"]);
+        let markers = config
+            .auto
+            .iter()
+            .map(|rule| rule.marker.as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(markers, ["project", "global"]);
+    }
+
+    #[test]
+    fn loads_the_global_config_then_the_rules_then_the_project() {
+        let files = Files::new(
+            "shm_size = \"1g\"\n\
+             on_start = [\"global\"]\n\
+             [env]\n\
+             LEVEL = \"global\"\n\
+             [image.\"python:*\"]\n\
+             shm_size = \"2g\"\n\
+             on_start = [\"python\"]\n\
+             env = { LEVEL = \"python\" }\n\
+             [image.\"node:*\"]\n\
+             shm_size = \"4g\"\n",
+            "on_start = [\"project\"]\n[env]\nLEVEL = \"project\"\n",
+        );
+
+        let loaded = files.load("", "python");
+        assert_eq!(loaded.image_rules, ["python:*"]);
+        assert_eq!(loaded.files.len(), 2);
+        assert_eq!(loaded.config.shm_size.as_deref(), Some("2g"));
+        assert_eq!(loaded.config.on_start, ["global", "python", "project"]);
+        assert_eq!(loaded.config.env["LEVEL"], "project");
+
+        let loaded = files.load("", "alpine:3");
+        assert!(loaded.image_rules.is_empty());
+        assert_eq!(loaded.config.shm_size.as_deref(), Some("1g"));
+    }
+
+    #[test]
+    fn finds_the_project_config_in_a_parent() {
+        let files = Files::new("", "image = \"node:20\"\n");
+        let loaded = files.load("src", "node:20");
+        assert_eq!(
+            loaded.files.last(),
+            Some(&files.dir.path().join("project").join(PROJECT_CONFIG))
+        );
+        assert_eq!(loaded.config.image_name.as_deref(), Some("node:20"));
+    }
+
+    #[test]
+    fn a_project_cannot_loosen_the_global_config() {
+        let files = Files::new(
+            "runtime = \"runsc\"\nnetwork = \"none\"\n",
+            "runtime = \"runc\"\n\
+             network = \"host\"\n\
+             registry_mirrors = [\"mirror.example.com\"]\n\
+             [verify]\n\
+             key = \"cosign.pub\"\n\
+             [audit]\n\
+             enabled = false\n",
+        );
+        let config = files.load("", "alpine").config;
+        assert_eq!(config.runtime.as_deref(), Some("runsc"));
+        assert_eq!(config.network.as_deref(), Some("none"));
+        assert!(config.registry_mirrors.is_empty());
+        assert!(config.verify.is_none());
+        assert!(config.audit.is_none());
+
+        // a project may pick the runtime when the global config doesn't
+        let files = Files::new("", "runtime = \"runc\"\n");
+        assert_eq!(
+            files.load("", "alpine").config.runtime.as_deref(),
+            Some("runc")
+        );
+    }
+
+    #[test]
+    fn ignores_unknown_keys() {
+        let files = Files::new(
+            "shm_sise = \"1g\"\n",
+            "[evn]\nA = \"1\"\n[env]\nB = \"2\"\n",
+        );
+        let config = files.load("", "alpine").config;
+        assert!(config.shm_size.is_none());
+        assert_eq!(
+            config.env,
+            BTreeMap::from([("B".to_string(), "2".to_string())])
+        );
+    }
+
+    #[test]
+    fn loads_nothing_without_config_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let loaded = load_from(
+            &dir.path().join("config.toml"),
+            dir.path(),
+            dir.path(),
+            "alpine",
+        )
+        .unwrap();
+        assert!(loaded.files.is_empty());
+        assert!(loaded.config.env.is_empty());
+    }
+
+    #[test]
+    fn matches_globs() {
+        assert!(glob_match("*", ""));
+        assert!(glob_match("*", "anything"));
+        assert!(glob_match("python:*", "python:3.12"));
+        assert!(glob_match("python:3.1?", "python:3.12"));
+        assert!(!glob_match("python:3.1?", "python:3.1"));
+        assert!(glob_match("*/node:*", "ghcr.io/me/node:20"));
+        assert!(glob_match("a*b*c", "aXXbYYbc"));
+        assert!(glob_match("a**", "a"));
+        assert!(!glob_match("python:*", "pythonista:1"));
+        assert!(!glob_match("node", "node:20"));
+        assert!(!glob_match("", "a"));
+    }
+
+    #[test]
+    fn normalizes_references_for_the_rules() {
+        assert_eq!(normalize_reference("postgres"), "postgres:latest");
+        assert_eq!(normalize_reference("postgres:16"), "postgres:16");
+        assert_eq!(
+            normalize_reference("localhost:5000/postgres"),
+            "localhost:5000/postgres:latest"
+        );
+        assert_eq!(
+            normalize_reference("postgres@sha256:abc"),
+            "postgres@sha256:abc"
+        );
+    }
+}
@@ -0,0 +1,921 @@
+//! `--copy-in` and `--copy-out`: the working directory is copied into the container instead of being mounted,
+//! so nothing the sandbox does reaches the host unless it is copied back at the end.
+
+use anyhow::anyhow;
+use bollard::container::{DownloadFromContainerOptions, UploadToContainerOptions};
+use bollard::Docker;
+use futures::StreamExt;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+const BLOCK: usize = 512;
+
+/// what is copied, and where it is copied back to.
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// the working directory
+    pub dir: PathBuf,
+    /// globs of `.gitignore` syntax that are not copied, in addition to `.gitignore` and `.dockerignore`
+    pub exclude: Vec<String>,
+    /// where the files are copied back to at the end of the session, none without `--copy-out`
+    pub out: Option<PathBuf>,
+}
+
+/// uploads the working directory to `target`, a directory that exists in the container.
+pub async fn copy_in(
+    docker: &Docker,
+    container: &str,
+    target: &str,
+    options: &Options,
+) -> anyhow::Result<()> {
+    let filter = filter(&options.dir, &options.exclude);
+    let dir = options.dir.clone();
+    let (archive, files) = tokio::task::spawn_blocking(move || archive(&dir, &filter)).await??;
+    tracing::info!(
+        "copying {} files ({:.1} MB) into `{}`",
+        files,
+        archive.len() as f64 / 1e6,
+        target
+    );
+    docker
+        .upload_to_container(
+            container,
+            Some(UploadToContainerOptions {
+                path: target,
+                no_overwrite_dir_non_dir: "",
+            }),
+            archive.into(),
+        )
+        .await
+        .map_err(|e| {
+            anyhow!(
+                "unable to copy the working directory into the container: {}",
+                e
+            )
+        })
+}
+
+/// downloads `source` from the container and writes it over `options.out`. excluded paths and `.git` are
+/// skipped, so the container can't plant hooks or config that would run on the host. files removed in the
+/// container are not removed on the host.
+pub async fn copy_out(
+    docker: &Docker,
+    container: &str,
+    source: &str,
+    options: &Options,
+) -> anyhow::Result<()> {
+    let Some(out) = options.out.clone() else {
+        return Ok(());
+    };
+    let mut stream = docker.download_from_container(
+        container,
+        Some(DownloadFromContainerOptions { path: source }),
+    );
+    let mut archive = vec![];
+    while let Some(chunk) = stream.next().await {
+        archive.extend_from_slice(&chunk?);
+    }
+    // the rules of the working directory decide, wherever the files are copied to
+    let filter = filter(&options.dir, &options.exclude);
+    let written =
+        tokio::task::spawn_blocking(move || extract(&archive, &out, &filter).map(|n| (n, out)))
+            .await??;
+    tracing::info!(
+        "copied {} files back to `{}`",
+        written.0,
+        written.1.display()
+    );
+    Ok(())
+}
+
+/// `.gitignore`, `.dockerignore` and the `--exclude` globs of `dir`.
+fn filter(dir: &Path, exclude: &[String]) -> Gitignore {
+    let files = [".gitignore", ".dockerignore"].map(|name| dir.join(name));
+    rules(dir, &files, exclude)
+}
+
+/// the rules of the ignore `files` that exist, then `lines`, matched relative to `root`.
+pub fn rules(root: &Path, files: &[PathBuf], lines: &[String]) -> Gitignore {
+    let mut builder = GitignoreBuilder::new(root);
+    for path in files.iter().filter(|path| path.is_file()) {
+        if let Some(e) = builder.add(path) {
+            tracing::warn!("unable to read `{}`: {}", path.display(), e);
+        }
+    }
+    for line in lines {
+        if let Err(e) = builder.add_line(None, line) {
+            tracing::warn!("invalid ignore rule `{}`: {}", line, e);
+        }
+    }
+    builder.build().unwrap_or_else(|e| {
+        tracing::warn!("invalid ignore rules, copying everything: {}", e);
+        Gitignore::empty()
+    })
+}
+
+/// a tar archive of `dir`, without the paths `filter` ignores, and the number of files in it.
+pub fn archive(dir: &Path, filter: &Gitignore) -> anyhow::Result<(Vec<u8>, usize)> {
+    let mut archive = vec![];
+    let mut files = 0;
+    let walk = ignore::WalkBuilder::new(dir)
+        .standard_filters(false)
+        .filter_entry({
+            let filter = filter.clone();
+            move |entry| {
+                let is_dir = entry.file_type().is_some_and(|kind| kind.is_dir());
+                !filter.matched(entry.path(), is_dir).is_ignore()
+            }
+        })
+        .build();
+    for entry in walk {
+        let entry = entry?;
+        let relative = entry.path().strip_prefix(dir)?;
+        if relative.as_os_str().is_empty() {
+            continue;
+        }
+        let name = relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        let metadata = std::fs::symlink_metadata(entry.path())?;
+        let mtime = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .unwrap_or_default()
+            .as_secs();
+        let (mode, uid, gid) = ownership(&metadata);
+
+        if metadata.is_symlink() {
+            let link = std::fs::read_link(entry.path())?;
+            let entry = Entry::new(&name, b'2', mode, uid, gid, 0, mtime);
+            append(&mut archive, &entry.with_link(&link.to_string_lossy()), &[]);
+        } else if metadata.is_dir() {
+            let entry = Entry::new(&format!("{}/", name), b'5', mode, uid, gid, 0, mtime);
+            append(&mut archive, &entry, &[]);
+        } else if metadata.is_file() {
+            let contents = std::fs::read(entry.path())?;
+            let entry = Entry::new(&name, b'0', mode, uid, gid, contents.len() as u64, mtime);
+            append(&mut archive, &entry, &contents);
+            files += 1;
+        } else {
+            tracing::debug!("not copying `{}`, it is not a regular file", name);
+        }
+    }
+    archive.extend_from_slice(&[0; 2 * BLOCK]);
+    Ok((archive, files))
+}
+
+#[cfg(unix)]
+fn ownership(metadata: &std::fs::Metadata) -> (u32, u32, u32) {
+    use std::os::unix::fs::MetadataExt;
+    (metadata.mode() & 0o7777, metadata.uid(), metadata.gid())
+}
+
+#[cfg(not(unix))]
+fn ownership(metadata: &std::fs::Metadata) -> (u32, u32, u32) {
+    match metadata.is_dir() {
+        true => (0o755, 0, 0),
+        false => (0o644, 0, 0),
+    }
+}
+
+/// a tar header.
+struct Entry {
+    name: String,
+    kind: u8,
+    mode: u32,
+    uid: u32,
+    gid: u32,
+    size: u64,
+    mtime: u64,
+    link: String,
+}
+
+impl Entry {
+    fn new(name: &str, kind: u8, mode: u32, uid: u32, gid: u32, size: u64, mtime: u64) -> Self {
+        Self {
+            name: name.to_string(),
+            kind,
+            mode,
+            uid,
+            gid,
+            size,
+            mtime,
+            link: String::new(),
+        }
+    }
+
+    fn with_link(mut self, link: &str) -> Self {
+        self.link = link.to_string();
+        self
+    }
+}
+
+/// appends a ustar header (after a pax header when the names are too long for it) and the contents.
+fn append(archive: &mut Vec<u8>, entry: &Entry, contents: &[u8]) {
+    let mut records = String::new();
+    for (key, value) in [("path", &entry.name), ("linkpath", &entry.link)] {
+        if value.len() >= 100 {
+            records.push_str(&pax_record(key, value));
+        }
+    }
+    if !records.is_empty() {
+        let pax = Entry::new("././@PaxHeader", b'x', 0o644, 0, 0, records.len() as u64, 0);
+        archive.extend_from_slice(&header(&pax));
+        pad(archive, records.as_bytes());
+    }
+    archive.extend_from_slice(&header(entry));
+    pad(archive, contents);
+}
+
+/// `<length> key=value\n`, where the length counts itself.
+fn pax_record(key: &str, value: &str) -> String {
+    let rest = format!(" {}={}\n", key, value);
+    let mut length = rest.len() + 1;
+    while (length.to_string().len() + rest.len()) != length {
+        length += 1;
+    }
+    format!("{}{}", length, rest)
+}
+
+fn header(entry: &Entry) -> [u8; BLOCK] {
+    let mut block = [0; BLOCK];
+    let field = |block: &mut [u8; BLOCK], at: usize, len: usize, value: &[u8]| {
+        let n = value.len().min(len);
+        block[at..at + n].copy_from_slice(&value[..n]);
+    };
+    field(&mut block, 0, 100, entry.name.as_bytes());
+    field(
+        &mut block,
+        100,
+        8,
+        format!("{:07o}\0", entry.mode).as_bytes(),
+    );
+    field(
+        &mut block,
+        108,
+        8,
+        format!("{:07o}\0", entry.uid.min(0o7777777)).as_bytes(),
+    );
+    field(
+        &mut block,
+        116,
+        8,
+        format!("{:07o}\0", entry.gid.min(0o7777777)).as_bytes(),
+    );
+    field(&mut block, 124, 12, &size_field(entry.size));
+    field(
+        &mut block,
+        136,
+        12,
+        format!("{:011o}\0", entry.mtime).as_bytes(),
+    );
+    field(&mut block, 148, 8, b"        ");
+    block[156] = entry.kind;
+    field(&mut block, 157, 100, entry.link.as_bytes());
+    field(&mut block, 257, 8, b"ustar\x0000");
+    let checksum = block.iter().map(|&b| b as u32).sum::<u32>();
+    field(
+        &mut block,
+        148,
+        8,
+        format!("{:06o}\0 ", checksum).as_bytes(),
+    );
+    block
+}
+
+/// octal, or base-256 for files of 8 GiB and more.
+fn size_field(size: u64) -> [u8; 12] {
+    let mut field = [0; 12];
+    match size < 0o77777777777 {
+        true => field[..12].copy_from_slice(format!("{:011o}\0", size).as_bytes()),
+        false => {
+            field[4..].copy_from_slice(&size.to_be_bytes());
+            field[0] = 0x80;
+        }
+    }
+    field
+}
+
+/// appends `data` padded to a whole number of blocks.
+fn pad(archive: &mut Vec<u8>, data: &[u8]) {
+    archive.extend_from_slice(data);
+    let rest = data.len() % BLOCK;
+    if rest != 0 {
+        archive.resize(archive.len() + BLOCK - rest, 0);
+    }
+}
+
+fn number(field: &[u8]) -> u64 {
+    if field.first().is_some_and(|&b| b & 0x80 != 0) {
+        return field[1..].iter().fold(0, |n, &b| (n << 8) | b as u64);
+    }
+    let digits = String::from_utf8_lossy(field);
+    u64::from_str_radix(digits.trim_matches(|c: char| c == '\0' || c == ' '), 8).unwrap_or(0)
+}
+
+fn text(field: &[u8]) -> String {
+    let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+/// an entry of a tar archive, with the long names of its pax or gnu headers applied.
+struct Read<'a> {
+    name: String,
+    kind: u8,
+    link: String,
+    mode: u32,
+    mtime: u64,
+    data: &'a [u8],
+}
+
+/// the entries of a tar archive, up to the end-of-archive block.
+fn entries(archive: &[u8]) -> anyhow::Result<Vec<Read<'_>>> {
+    let mut entries = vec![];
+    let mut at = 0;
+    // names of the next entry from a pax or gnu long name header
+    let (mut long_name, mut long_link) = (None, None);
+    while at + BLOCK <= archive.len() {
+        let block = &archive[at..at + BLOCK];
+        if block.iter().all(|&b| b == 0) {
+            break;
+        }
+        let size = number(&block[124..136]) as usize;
+        let kind = block[156];
+        let start = at + BLOCK;
+        let end = start
+            .checked_add(size)
+            .filter(|&end| end <= archive.len())
+            .ok_or_else(|| anyhow!("the archive of the container is truncated"))?;
+        let data = &archive[start..end];
+        at = start + size.div_ceil(BLOCK) * BLOCK;
+
+        match kind {
+            b'x' => {
+                for (key, value) in pax_records(data)? {
+                    match key.as_str() {
+                        "path" => long_name = Some(value),
+                        "linkpath" => long_link = Some(value),
+                        _ => {}
+                    }
+                }
+                continue;
+            }
+            b'L' => {
+                long_name = Some(text(data));
+                continue;
+            }
+            b'K' => {
+                long_link = Some(text(data));
+                continue;
+            }
+            b'g' => continue,
+            _ => {}
+        }
+
+        let name = long_name.take().unwrap_or_else(|| {
+            let prefix = text(&block[345..500]);
+            let name = text(&block[..100]);
+            match prefix.is_empty() {
+                true => name,
+                false => format!("{}/{}", prefix, name),
+            }
+        });
+        entries.push(Read {
+            name,
+            kind,
+            link: long_link.take().unwrap_or_else(|| text(&block[157..257])),
+            mode: number(&block[100..108]) as u32 & 0o777,
+            mtime: number(&block[136..148]),
+            data,
+        });
+    }
+    Ok(entries)
+}
+
+/// writes the entries of a `docker cp` archive into `out`, below the directory the archive was made of.
+/// returns the number of files written.
+fn extract(archive: &[u8], out: &Path, filter: &Gitignore) -> anyhow::Result<usize> {
+    std::fs::create_dir_all(out)
+        .map_err(|e| anyhow!("unable to create `{}`: {}", out.display(), e))?;
+    let mut written = 0;
+    for Read {
+        name,
+        kind,
+        link,
+        mode,
+        mtime,
+        data,
+    } in entries(archive)?
+    {
+        // the first component is the copied directory itself
+        let Some(relative) = relative(&name) else {
+            continue;
+        };
+        if relative.as_os_str().is_empty() {
+            continue;
+        }
+        let path = out.join(&relative);
+        let is_dir = kind == b'5';
+        if relative.starts_with(".git")
+            || filter
+                .matched_path_or_any_parents(filter.path().join(&relative), is_dir)
+                .is_ignore()
+        {
+            continue;
+        }
+        if !inside(out, &relative) {
+            tracing::warn!(
+                "not copying back `{}`, it is below a symlink",
+                relative.display()
+            );
+            continue;
+        }
+        let existing = std::fs::symlink_metadata(&path).ok();
+        if existing.as_ref().is_some_and(|m| m.is_dir()) && !is_dir {
+            tracing::warn!(
+                "not copying back `{}`, it is a directory on the host",
+                relative.display()
+            );
+            continue;
+        }
+        // never written through, a symlink on the host is replaced
+        if existing
+            .as_ref()
+            .is_some_and(|m| m.is_symlink() || (m.is_file() && kind == b'2'))
+        {
+            std::fs::remove_file(&path)?;
+        }
+
+        match kind {
+            b'5' => {
+                std::fs::create_dir_all(&path)?;
+                set_mode(&path, mode)?;
+            }
+            b'2' => symlink(&link, &path)?,
+            b'0' | b'\0' | b'7' | b'1' => {
+                let contents = match kind {
+                    b'1' => {
+                        let Some(target) = relative_link(&link).filter(|target| {
+                            inside(out, target)
+                                && std::fs::symlink_metadata(out.join(target))
+                                    .is_ok_and(|m| m.is_file())
+                        }) else {
+                            tracing::warn!(
+                                "not copying back the hard link `{}`",
+                                relative.display()
+                            );
+                            continue;
+                        };
+                        std::fs::read(out.join(target))?
+                    }
+                    _ => data.to_vec(),
+                };
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::write(&path, contents)
+                    .map_err(|e| anyhow!("unable to write `{}`: {}", path.display(), e))?;
+                set_mode(&path, mode)?;
+                if let Ok(file) = std::fs::File::options().write(true).open(&path) {
+                    let _ = file.set_modified(UNIX_EPOCH + Duration::from_secs(mtime));
+                }
+                written += 1;
+            }
+            _ => tracing::debug!(
+                "not copying back `{}`, it is not a regular file",
+                relative.display()
+            ),
+        }
+    }
+    Ok(written)
+}
+
+/// the records of a pax header, each `<length> key=value\n`. the length counts the whole record,
+/// so a value can contain newlines.
+fn pax_records(data: &[u8]) -> anyhow::Result<Vec<(String, String)>> {
+    let invalid = || anyhow!("the archive of the container has an invalid pax header");
+    let mut records = vec![];
+    let mut rest = data;
+    while !rest.is_empty() {
+        let space = rest.iter().position(|&b| b == b' ').ok_or_else(invalid)?;
+        let length = std::str::from_utf8(&rest[..space])
+            .ok()
+            .and_then(|length| length.parse::<usize>().ok())
+            .filter(|&length| length > space + 1 && length <= rest.len())
+            .ok_or_else(invalid)?;
+        let record = rest[space + 1..length]
+            .strip_suffix(b"\n")
+            .ok_or_else(invalid)?;
+        let equals = record.iter().position(|&b| b == b'=').ok_or_else(invalid)?;
+        records.push((
+            String::from_utf8_lossy(&record[..equals]).into_owned(),
+            String::from_utf8_lossy(&record[equals + 1..]).into_owned(),
+        ));
+        rest = &rest[length..];
+    }
+    Ok(records)
+}
+
+/// the name without the copied directory, or none when it would leave it, eg: with `..`.
+fn relative(name: &str) -> Option<PathBuf> {
+    let mut components = Path::new(name).components();
+    components
+        .next()
+        .filter(|first| matches!(first, Component::Normal(_)))?;
+    let relative = components.as_path().to_path_buf();
+    relative
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+        .then_some(relative)
+}
+
+/// a hard link names its target from the root of the archive.
+fn relative_link(link: &str) -> Option<PathBuf> {
+    relative(link).filter(|target| !target.as_os_str().is_empty())
+}
+
+/// that no directory between `out` and `relative` is a symlink, so a write can't end up outside of `out`.
+fn inside(out: &Path, relative: &Path) -> bool {
+    let mut path = out.to_path_buf();
+    let parents = relative
+        .parent()
+        .map(|parent| parent.components().count())
+        .unwrap_or(0);
+    for component in relative.components().take(parents) {
+        path.push(component);
+        if std::fs::symlink_metadata(&path).is_ok_and(|m| m.is_symlink()) {
+            return false;
+        }
+    }
+    true
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> anyhow::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: u32) -> anyhow::Result<()> {
+    Ok(())
+}
+
+#[cfg(unix)]
+fn symlink(link: &str, path: &Path) -> anyhow::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::os::unix::fs::symlink(link, path)
+        .map_err(|e| anyhow!("unable to create the symlink `{}`: {}", path.display(), e))
+}
+
+#[cfg(not(unix))]
+fn symlink(_link: &str, path: &Path) -> anyhow::Result<()> {
+    tracing::warn!("not copying back the symlink `{}`", path.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(name: &str, contents: &[u8]) -> (Entry, Vec<u8>) {
+        let entry = Entry::new(name, b'0', 0o644, 0, 0, contents.len() as u64, 0);
+        (entry, contents.to_vec())
+    }
+
+    fn link(name: &str, kind: u8, target: &str) -> (Entry, Vec<u8>) {
+        (
+            Entry::new(name, kind, 0o777, 0, 0, 0, 0).with_link(target),
+            vec![],
+        )
+    }
+
+    /// an archive like `docker cp` makes of a `project` directory.
+    fn tar(entries: Vec<(Entry, Vec<u8>)>) -> Vec<u8> {
+        let mut archive = vec![];
+        append(
+            &mut archive,
+            &Entry::new("project/", b'5', 0o755, 0, 0, 0, 0),
+            &[],
+        );
+        for (entry, contents) in entries {
+            append(&mut archive, &entry, &contents);
+        }
+        archive.extend_from_slice(&[0; 2 * BLOCK]);
+        archive
+    }
+
+    fn extract_all(archive: &[u8], out: &Path) -> usize {
+        extract(archive, out, &rules(out, &[], &[])).unwrap()
+    }
+
+    #[test]
+    fn archives_a_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.txt"), "a").unwrap();
+        std::fs::create_dir(dir.path().join("sub")).unwrap();
+        std::fs::write(dir.path().join("sub/b.txt"), "bb").unwrap();
+        std::fs::write(dir.path().join("skipped.log"), "").unwrap();
+        std::fs::create_dir(dir.path().join("target")).unwrap();
+        std::fs::write(dir.path().join("target/out"), "").unwrap();
+
+        let filter = rules(
+            dir.path(),
+            &[],
+            &["*.log".to_string(), "target/".to_string()],
+        );
+        let (archive, files) = archive(dir.path(), &filter).unwrap();
+        assert_eq!(files, 2);
+        assert_eq!(archive.len() % BLOCK, 0);
+
+        let mut entries = entries(&archive)
+            .unwrap()
+            .into_iter()
+            .map(|entry| (entry.name, entry.kind, entry.data.to_vec()))
+            .collect::<Vec<_>>();
+        entries.sort();
+        assert_eq!(
+            entries,
+            [
+                ("a.txt".to_string(), b'0', b"a".to_vec()),
+                ("sub/".to_string(), b'5', vec![]),
+                ("sub/b.txt".to_string(), b'0', b"bb".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn archives_long_names_in_pax_headers() {
+        let dir = tempfile::tempdir().unwrap();
+        let long = "d".repeat(120);
+        std::fs::create_dir(dir.path().join(&long)).unwrap();
+        let name = format!("{}/{}.txt", long, "f".repeat(150));
+        std::fs::write(dir.path().join(&name), "long").unwrap();
+
+        let (archive, files) = archive(dir.path(), &Gitignore::empty()).unwrap();
+        assert_eq!(files, 1);
+        let entries = entries(&archive).unwrap();
+        assert!(entries
+            .iter()
+            .any(|entry| entry.name == format!("{}/", long)));
+        let entry = entries.iter().find(|entry| entry.kind == b'0').unwrap();
+        assert_eq!(entry.name, name);
+        assert_eq!(entry.data, b"long");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn archives_symlinks_as_links() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = format!("/{}", "t".repeat(130));
+        std::os::unix::fs::symlink("a.txt", dir.path().join("short")).unwrap();
+        std::os::unix::fs::symlink(&target, dir.path().join("long")).unwrap();
+
+        let (archive, files) = archive(dir.path(), &Gitignore::empty()).unwrap();
+        assert_eq!(files, 0);
+        let mut links = entries(&archive)
+            .unwrap()
+            .into_iter()
+            .map(|entry| (entry.name, entry.kind, entry.link))
+            .collect::<Vec<_>>();
+        links.sort();
+        assert_eq!(
+            links,
+            [
+                ("long".to_string(), b'2', target),
+                ("short".to_string(), b'2', "a.txt".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_pax_records_by_their_length() {
+        let records = [
+            pax_record("path", "a\nb=c"),
+            pax_record("linkpath", "x"),
+            pax_record("mtime", "1.5"),
+        ]
+        .concat();
+        assert_eq!(
+            pax_records(records.as_bytes()).unwrap(),
+            [
+                ("path".to_string(), "a\nb=c".to_string()),
+                ("linkpath".to_string(), "x".to_string()),
+                ("mtime".to_string(), "1.5".to_string()),
+            ]
+        );
+        // the length counts itself, also when that adds a digit
+        assert_eq!(pax_record("path", "x"), "9 path=x\n");
+        assert_eq!(pax_record("path", &"x".repeat(91)).len(), 101);
+        assert!(pax_records(b"").unwrap().is_empty());
+    }
+
+    #[test]
+    fn refuses_invalid_pax_records() {
+        for invalid in [
+            &b"9 path=xy\n"[..],
+            b"11 path=x\n",
+            b"11 path=x!",
+            b"8 pathx\n",
+            b"x path=x\n",
+            b"path=x\n",
+            b"2 \n",
+        ] {
+            assert!(
+                pax_records(invalid).is_err(),
+                "{}",
+                String::from_utf8_lossy(invalid)
+            );
+        }
+    }
+
+    #[test]
+    fn extracts_files_below_the_copied_directory() {
+        let out = tempfile::tempdir().unwrap();
+        let long = format!("project/{}/{}", "d".repeat(120), "f".repeat(120));
+        let archive = tar(vec![
+            (Entry::new("project/sub/", b'5', 0o755, 0, 0, 0, 0), vec![]),
+            file("project/sub/a.txt", b"a"),
+            file(&long, b"long"),
+        ]);
+        assert_eq!(extract_all(&archive, out.path()), 2);
+        assert_eq!(std::fs::read(out.path().join("sub/a.txt")).unwrap(), b"a");
+        assert_eq!(
+            std::fs::read(out.path().join(long.strip_prefix("project/").unwrap())).unwrap(),
+            b"long"
+        );
+    }
+
+    #[test]
+    fn extracts_gnu_long_names() {
+        let out = tempfile::tempdir().unwrap();
+        let name = format!("project/{}", "n".repeat(200));
+        let mut archive = vec![];
+        let long_name = Entry::new("././@LongLink", b'L', 0, 0, 0, name.len() as u64 + 1, 0);
+        append(&mut archive, &long_name, format!("{}\0", name).as_bytes());
+        let (entry, contents) = file("project/truncated", b"gnu");
+        append(&mut archive, &entry, &contents);
+        archive.extend_from_slice(&[0; 2 * BLOCK]);
+
+        assert_eq!(extract_all(&archive, out.path()), 1);
+        assert_eq!(
+            std::fs::read(out.path().join("n".repeat(200))).unwrap(),
+            b"gnu"
+        );
+        assert!(!out.path().join("truncated").exists());
+    }
+
+    #[test]
+    fn refuses_to_leave_the_copied_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("out");
+        let archive = tar(vec![
+            file("project/../escaped", b"x"),
+            file("project/sub/../../escaped", b"x"),
+            file("/escaped", b"x"),
+            file("../escaped", b"x"),
+            file("project/ok", b"ok"),
+        ]);
+        assert_eq!(extract_all(&archive, &out), 1);
+        let mut names = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, ["out"]);
+        assert!(!Path::new("/escaped").exists());
+    }
+
+    #[test]
+    fn skips_git_and_ignored_paths() {
+        let out = tempfile::tempdir().unwrap();
+        let archive = tar(vec![
+            file("project/.git/hooks/pre-commit", b"#!/bin/sh"),
+            file("project/.git/config", b"[core]"),
+            file("project/.github/workflow.yml", b"on: push"),
+            file("project/build/out.o", b"o"),
+            file("project/main.rs", b"fn main() {}"),
+        ]);
+        let filter = rules(out.path(), &[], &["build/".to_string()]);
+        assert_eq!(extract(&archive, out.path(), &filter).unwrap(), 2);
+        assert!(!out.path().join(".git").exists());
+        assert!(!out.path().join("build").exists());
+        assert!(out.path().join(".github/workflow.yml").is_file());
+        assert!(out.path().join("main.rs").is_file());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn never_writes_through_symlinks() {
+        let dir = tempfile::tempdir().unwrap();
+        let outside = dir.path().join("outside");
+        std::fs::create_dir(&outside).unwrap();
+        std::fs::write(outside.join("secret"), "host").unwrap();
+        let out = dir.path().join("out");
+        std::fs::create_dir(&out).unwrap();
+        // a symlink that is already on the host
+        std::os::unix::fs::symlink(outside.join("secret"), out.join("existing")).unwrap();
+
+        let outside_link = outside.to_str().unwrap();
+        let archive = tar(vec![
+            // a symlink from the container, then a file below it
+            link("project/escape", b'2', outside_link),
+            file("project/escape/planted", b"x"),
+            file("project/existing", b"replaced"),
+        ]);
+        assert_eq!(extract_all(&archive, &out), 1);
+
+        assert!(!outside.join("planted").exists());
+        assert_eq!(std::fs::read(outside.join("secret")).unwrap(), b"host");
+        // the host symlink is replaced by the file, not written through
+        let existing = std::fs::symlink_metadata(out.join("existing")).unwrap();
+        assert!(existing.is_file());
+        assert_eq!(std::fs::read(out.join("existing")).unwrap(), b"replaced");
+        // the symlink itself is copied back
+        assert_eq!(
+            std::fs::read_link(out.join("escape")).unwrap(),
+            Path::new(outside_link)
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn copies_hard_links_only_within_the_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("passwd"), "host").unwrap();
+        let out = dir.path().join("out");
+        let outside = dir.path().join("passwd");
+        let archive = tar(vec![
+            file("project/a", b"a"),
+            link("project/b", b'1', "project/a"),
+            link("project/up", b'1', "project/../passwd"),
+            link("project/absolute", b'1', outside.to_str().unwrap()),
+            link("project/symlink", b'2', "a"),
+            link("project/via-symlink", b'1', "project/symlink"),
+            link("project/missing", b'1', "project/nothing"),
+        ]);
+        assert_eq!(extract_all(&archive, &out), 2);
+        assert_eq!(std::fs::read(out.join("b")).unwrap(), b"a");
+        // a copy, not a link to the same inode
+        std::fs::write(out.join("b"), "changed").unwrap();
+        assert_eq!(std::fs::read(out.join("a")).unwrap(), b"a");
+        for skipped in ["up", "absolute", "via-symlink", "missing"] {
+            assert!(!out.join(skipped).exists(), "{}", skipped);
+        }
+        assert_eq!(std::fs::read(&outside).unwrap(), b"host");
+    }
+
+    #[test]
+    fn keeps_directories_on_the_host() {
+        let out = tempfile::tempdir().unwrap();
+        std::fs::create_dir(out.path().join("src")).unwrap();
+        std::fs::write(out.path().join("src/lib.rs"), "kept").unwrap();
+        let archive = tar(vec![file("project/src", b"a file now")]);
+        assert_eq!(extract_all(&archive, out.path()), 0);
+        assert_eq!(
+            std::fs::read(out.path().join("src/lib.rs")).unwrap(),
+            b"kept"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn drops_special_mode_bits() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let out = tempfile::tempdir().unwrap();
+        let contents = b"#!/bin/sh".to_vec();
+        let entry = Entry::new("project/run", b'0', 0o4755, 0, 0, contents.len() as u64, 0);
+        let archive = tar(vec![(entry, contents)]);
+        assert_eq!(extract_all(&archive, out.path()), 1);
+        let mode = std::fs::metadata(out.path().join("run"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o7777, 0o755);
+    }
+
+    #[test]
+    fn refuses_truncated_archives() {
+        let archive = tar(vec![file("project/a", &[b'a'; 2000])]);
+        let out = tempfile::tempdir().unwrap();
+        let e = extract(&archive[..BLOCK * 3], out.path(), &Gitignore::empty()).unwrap_err();
+        assert_eq!(e.to_string(), "the archive of the container is truncated");
+    }
+
+    #[test]
+    fn writes_large_sizes_in_base_256() {
+        assert_eq!(number(&size_field(1234)), 1234);
+        let large = 10 << 30;
+        let field = size_field(large);
+        assert_eq!(field[0], 0x80);
+        assert_eq!(number(&field), large);
+    }
+}
@@ -0,0 +1,242 @@
+//! `--auto`: picks an image from the marker files in the project, e.g. `Cargo.toml` or `go.mod`.
+
+use anyhow::anyhow;
+use std::io::{BufRead, IsTerminal, Write};
+use std::path::Path;
+
+use crate::config::AutoRule;
+
+/// an image inferred from the project, and why.
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    pub image: String,
+    pub reason: String,
+}
+
+/// reads the version the project asks for and builds the candidate from it.
+type Detector = fn(&Path) -> Candidate;
+
+/// the marker files quarantine knows about, each with its detector.
+const BUILTIN: [(&str, Detector); 5] = [
+    ("Cargo.toml", rust),
+    ("package.json", node),
+    ("pyproject.toml", python),
+    ("requirements.txt", python),
+    ("go.mod", golang),
+];
+
+/// the images the project's marker files point to. rules from the config come first, and replace
+/// the built-in rule for the same marker.
+pub fn candidates(dir: &Path, rules: &[AutoRule]) -> Vec<Candidate> {
+    let mut candidates = vec![];
+    for rule in rules {
+        if dir.join(&rule.marker).exists() {
+            candidates.push(Candidate {
+                image: rule.image.clone(),
+                reason: format!("found `{}` (rule from the config)", rule.marker),
+            });
+        }
+    }
+    for (marker, detect) in BUILTIN {
+        let overridden = rules.iter().any(|rule| rule.marker == marker);
+        if !overridden && dir.join(marker).exists() {
+            let candidate = detect(dir);
+            // `pyproject.toml` and `requirements.txt` both point to python
+            if !candidates
+                .iter()
+                .any(|existing: &Candidate| existing.image == candidate.image)
+            {
+                candidates.push(candidate);
+            }
+        }
+    }
+    candidates
+}
+
+/// picks the image for `--auto`: asks which one when several markers were found, and asks for confirmation
+/// before it is pulled unless `yes` is set.
+pub fn choose(dir: &Path, rules: &[AutoRule], yes: bool) -> anyhow::Result<String> {
+    let candidates = candidates(dir, rules);
+    let candidate = match candidates.as_slice() {
+        [] => {
+            return Err(anyhow!(
+                "--auto found nothing to infer an image from in `{}`. pass one with `-i`",
+                dir.display()
+            ))
+        }
+        [candidate] => candidate.clone(),
+        candidates => {
+            eprintln!("--auto found several kinds of project:");
+            for (i, candidate) in candidates.iter().enumerate() {
+                eprintln!("  {}) {} :: {}", i + 1, candidate.image, candidate.reason);
+            }
+            let answer = ask(
+                &format!("which image? [1-{}] ", candidates.len()),
+                "--auto needs a terminal to pick one of them. pass the image with `-i`",
+            )?;
+            answer
+                .parse::<usize>()
+                .ok()
+                .and_then(|n| n.checked_sub(1))
+                .and_then(|i| candidates.get(i))
+                .cloned()
+                .ok_or_else(|| anyhow!("no image was picked"))?
+        }
+    };
+
+    tracing::info!(
+        "--auto picked `{}` :: {}",
+        candidate.image,
+        candidate.reason
+    );
+    if !yes {
+        let answer = ask(
+            &format!("use `{}`? [Y/n] ", candidate.image),
+            "--auto needs a terminal to confirm the image. pass `--yes` (or the image with `-i`)",
+        )?;
+        if !matches!(answer.to_lowercase().as_str(), "" | "y" | "yes") {
+            return Err(anyhow!("aborted, pass the image with `-i`"));
+        }
+    }
+    Ok(candidate.image)
+}
+
+/// asks on the terminal. `no_terminal` is the error when there is no terminal to ask on.
+fn ask(prompt: &str, no_terminal: &str) -> anyhow::Result<String> {
+    if !std::io::stdin().is_terminal() {
+        return Err(anyhow!("{}", no_terminal));
+    }
+    let mut stderr = std::io::stderr();
+    write!(stderr, "{}", prompt)?;
+    stderr.flush()?;
+
+    let mut answer = String::new();
+    std::io::stdin().lock().read_line(&mut answer)?;
+    Ok(answer.trim().to_string())
+}
+
+fn rust(dir: &Path) -> Candidate {
+    // `rust-toolchain.toml` has `[toolchain] channel = "..."`, the legacy `rust-toolchain` just the channel
+    let channel = std::fs::read_to_string(dir.join("rust-toolchain.toml"))
+        .ok()
+        .and_then(|contents| contents.parse::<toml::Table>().ok())
+        .and_then(|table| {
+            table
+                .get("toolchain")?
+                .get("channel")?
+                .as_str()
+                .map(String::from)
+        })
+        .or_else(|| {
+            std::fs::read_to_string(dir.join("rust-toolchain"))
+                .ok()
+                .map(|contents| contents.trim().to_string())
+        });
+
+    match channel.filter(|channel| channel.starts_with(|c: char| c.is_ascii_digit())) {
+        Some(version) => Candidate {
+            image: format!("rust:{}", version),
+            reason: format!("found `Cargo.toml` and the `{}` toolchain", version),
+        },
+        None => Candidate {
+            image: "rust:latest".to_string(),
+            reason: "found `Cargo.toml`, using the stable toolchain".to_string(),
+        },
+    }
+}
+
+fn node(dir: &Path) -> Candidate {
+    let engines = std::fs::read_to_string(dir.join("package.json"))
+        .ok()
+        .and_then(|contents| serde_json::from_str::<serde_json::Value>(&contents).ok())
+        .and_then(|package| package["engines"]["node"].as_str().map(String::from));
+
+    match engines.as_deref().and_then(major_version) {
+        Some(major) => Candidate {
+            image: format!("node:{}", major),
+            reason: format!(
+                "found `package.json` requiring node `{}`",
+                engines.unwrap_or_default()
+            ),
+        },
+        None => Candidate {
+            image: "node:lts".to_string(),
+            reason: "found `package.json`, using the lts release".to_string(),
+        },
+    }
+}
+
+fn python(dir: &Path) -> Candidate {
+    let requires = std::fs::read_to_string(dir.join("pyproject.toml"))
+        .ok()
+        .and_then(|contents| contents.parse::<toml::Table>().ok())
+        .and_then(|table| {
+            table
+                .get("project")?
+                .get("requires-python")?
+                .as_str()
+                .map(String::from)
+        });
+
+    match requires.as_deref().and_then(minor_version) {
+        Some(version) => Candidate {
+            image: format!("python:{}", version),
+            reason: format!(
+                "found `pyproject.toml` requiring python `{}`",
+                requires.unwrap_or_default()
+            ),
+        },
+        None => Candidate {
+            image: "python:3".to_string(),
+            reason: "found a python project, using the latest python 3".to_string(),
+        },
+    }
+}
+
+fn golang(dir: &Path) -> Candidate {
+    let version = std::fs::read_to_string(dir.join("go.mod"))
+        .ok()
+        .and_then(|contents| {
+            contents.lines().find_map(|line| {
+                line.trim()
+                    .strip_prefix("go ")
+                    .map(|version| version.trim().to_string())
+            })
+        });
+
+    match version {
+        Some(version) => Candidate {
+            image: format!("golang:{}", version),
+            reason: format!("found `go.mod` with the `go {}` directive", version),
+        },
+        None => Candidate {
+            image: "golang:latest".to_string(),
+            reason: "found `go.mod`".to_string(),
+        },
+    }
+}
+
+/// the first number in a version requirement like `>=18` or `^20.1`.
+fn major_version(requirement: &str) -> Option<String> {
+    let start = requirement.find(|c: char| c.is_ascii_digit())?;
+    let digits = requirement[start..]
+        .chars()
+        .take_while(|c| c.is_ascii_digit())
+        .collect::<String>();
+    Some(digits)
+}
+
+/// the first `major.minor` in a version requirement like `>=3.11`.
+fn minor_version(requirement: &str) -> Option<String> {
+    let start = requirement.find(|c: char| c.is_ascii_digit())?;
+    let version = requirement[start..]
+        .chars()
+        .take_while(|c| c.is_ascii_digit() || *c == '.')
+        .collect::<String>();
+    let mut parts = version.split('.');
+    match (parts.next(), parts.next()) {
+        (Some(major), Some(minor)) if !minor.is_empty() => Some(format!("{}.{}", major, minor)),
+        (Some(major), _) => Some(major.to_string()),
+        _ => None,
+    }
+}
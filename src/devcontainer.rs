@@ -0,0 +1,275 @@
+//! `--devcontainer`: runs the sandbox from the common subset of `.devcontainer/devcontainer.json`.
+
+use anyhow::anyhow;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+use crate::config::FileConfig;
+
+/// the settings of a `devcontainer.json` that quarantine can map.
+#[derive(Debug, Default)]
+pub struct Devcontainer {
+    pub image: Option<String>,
+    /// `containerEnv`, `mounts`, `postCreateCommand` and `--shm-size` from `runArgs`
+    pub config: FileConfig,
+    /// `--memory` from `runArgs`
+    pub memory: Option<i64>,
+    /// `remoteUser`
+    pub user: Option<String>,
+}
+
+/// properties that are understood, so they aren't reported as ignored.
+const MAPPED: [&str; 8] = [
+    "name",
+    "image",
+    "containerEnv",
+    "mounts",
+    "runArgs",
+    "postCreateCommand",
+    "remoteUser",
+    "forwardPorts",
+];
+
+/// the `devcontainer.json` of the project, if it has one.
+pub fn find(dir: &Path) -> Option<PathBuf> {
+    [
+        dir.join(".devcontainer").join("devcontainer.json"),
+        dir.join(".devcontainer.json"),
+    ]
+    .into_iter()
+    .find(|path| path.is_file())
+}
+
+pub fn load(path: &Path) -> anyhow::Result<Devcontainer> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| anyhow!("unable to read `{}`: {}", path.display(), e))?;
+    let value = serde_json::from_str::<Value>(&strip_jsonc(&contents))
+        .map_err(|e| anyhow!("invalid `{}`: {}", path.display(), e))?;
+    let Value::Object(properties) = value else {
+        return Err(anyhow!("invalid `{}`: expected an object", path.display()));
+    };
+
+    if properties.contains_key("build") || properties.contains_key("dockerFile") {
+        return Err(anyhow!(
+            "`{}` builds its image from a Dockerfile, which quarantine can't do. build it and pass it with `-i`",
+            path.display()
+        ));
+    }
+    let ignored = properties
+        .keys()
+        .filter(|key| !MAPPED.contains(&key.as_str()))
+        .map(|key| format!("`{}`", key))
+        .collect::<Vec<String>>();
+    if !ignored.is_empty() {
+        tracing::warn!(
+            "ignoring these properties of `{}`: {}",
+            path.display(),
+            ignored.join(", ")
+        );
+    }
+    if properties.contains_key("forwardPorts") {
+        tracing::warn!("ignoring `forwardPorts`, quarantine doesn't publish ports");
+    }
+
+    let mut devcontainer = Devcontainer {
+        image: properties
+            .get("image")
+            .and_then(Value::as_str)
+            .map(String::from),
+        user: properties
+            .get("remoteUser")
+            .and_then(Value::as_str)
+            .map(String::from),
+        ..Default::default()
+    };
+
+    if let Some(Value::Object(env)) = properties.get("containerEnv") {
+        for (key, value) in env {
+            if let Some(value) = value.as_str() {
+                devcontainer
+                    .config
+                    .env
+                    .insert(key.clone(), value.to_string());
+            }
+        }
+    }
+
+    for mount in properties
+        .get("mounts")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+    {
+        match mount_to_bind(mount) {
+            Some(bind) => devcontainer.config.mounts.push(bind),
+            None => tracing::warn!("ignoring the mount `{}`", mount),
+        }
+    }
+
+    match properties.get("postCreateCommand") {
+        Some(Value::String(command)) => devcontainer.config.on_start.push(command.clone()),
+        Some(Value::Array(args)) => devcontainer.config.on_start.push(
+            args.iter()
+                .filter_map(Value::as_str)
+                .map(shell_quote)
+                .collect::<Vec<String>>()
+                .join(" "),
+        ),
+        // named commands run in parallel in devcontainers, here they run one after the other
+        Some(Value::Object(commands)) => {
+            for command in commands.values() {
+                if let Some(command) = command.as_str() {
+                    devcontainer.config.on_start.push(command.to_string());
+                }
+            }
+        }
+        _ => {}
+    }
+
+    let run_args = properties
+        .get("runArgs")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+        .collect::<Vec<&str>>();
+    let mut run_args = run_args.iter().peekable();
+    while let Some(arg) = run_args.next() {
+        let (flag, inline) = match arg.split_once('=') {
+            Some((flag, value)) => (flag, Some(value.to_string())),
+            None => (*arg, None),
+        };
+        match flag {
+            "--memory" | "-m" | "--shm-size" => {
+                let Some(value) = inline.or_else(|| run_args.next().map(|value| value.to_string()))
+                else {
+                    continue;
+                };
+                match flag {
+                    "--shm-size" => devcontainer.config.shm_size = Some(value),
+                    _ => devcontainer.memory = Some(crate::parse_size(&value)?),
+                }
+            }
+            _ => tracing::warn!("ignoring `{}` in `runArgs`", arg),
+        }
+    }
+
+    Ok(devcontainer)
+}
+
+/// `source=...,target=...,type=bind` (or the same as an object) as a `SRC:DEST[:ro]` bind.
+/// volumes map to named volume binds.
+fn mount_to_bind(mount: &Value) -> Option<String> {
+    let field = |name: &str| -> Option<String> {
+        match mount {
+            Value::String(spec) => spec.split(',').find_map(|part| {
+                let (key, value) = part.split_once('=')?;
+                let matches = match name {
+                    "source" => matches!(key, "source" | "src"),
+                    "target" => matches!(key, "target" | "destination" | "dst"),
+                    _ => key == name,
+                };
+                matches.then(|| value.to_string())
+            }),
+            Value::Object(object) => object.get(name)?.as_str().map(String::from),
+            _ => None,
+        }
+    };
+    let read_only = match mount {
+        Value::String(spec) => spec
+            .split(',')
+            .any(|part| part == "readonly" || part == "ro" || part == "readonly=true"),
+        _ => false,
+    };
+
+    let typ = field("type").unwrap_or_else(|| "volume".to_string());
+    if typ != "bind" && typ != "volume" {
+        return None;
+    }
+    let bind = format!("{}:{}", field("source")?, field("target")?);
+    Some(match read_only {
+        true => format!("{}:ro", bind),
+        false => bind,
+    })
+}
+
+fn shell_quote(word: &str) -> String {
+    match word
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || "-_./:=".contains(c))
+    {
+        true => word.to_string(),
+        false => format!("'{}'", word.replace('\'', r"'\''")),
+    }
+}
+
+/// drops `//` and `/* */` comments and trailing commas, which `devcontainer.json` allows.
+pub fn strip_jsonc(s: &str) -> String {
+    strip_trailing_commas(&strip_comments(s))
+}
+
+fn strip_comments(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    let mut in_string = false;
+
+    while let Some(c) = chars.next() {
+        if in_string {
+            out.push(c);
+            match c {
+                '\\' => out.extend(chars.next()),
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match (c, chars.peek()) {
+            ('/', Some('/')) => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        out.push('\n');
+                        break;
+                    }
+                }
+            }
+            ('/', Some('*')) => {
+                chars.next();
+                let mut prev = None;
+                for c in chars.by_ref() {
+                    if prev == Some('*') && c == '/' {
+                        break;
+                    }
+                    prev = Some(c);
+                }
+            }
+            _ => {
+                in_string = c == '"';
+                out.push(c);
+            }
+        }
+    }
+    out
+}
+
+fn strip_trailing_commas(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for (i, c) in s.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+        } else if c == '"' {
+            in_string = true;
+        } else if c == ',' && s[i + 1..].trim_start().starts_with(['}', ']']) {
+            continue;
+        }
+        out.push(c);
+    }
+    out
+}
@@ -0,0 +1,35 @@
+use bollard::API_DEFAULT_VERSION;
+use std::path::Path;
+
+use crate::{engine, logging};
+
+/// prints a short report about the environment quarantine runs in.
+pub async fn doctor(log_file: Option<&Path>) -> anyhow::Result<()> {
+    println!("quarantine {}", env!("CARGO_PKG_VERSION"));
+
+    match log_file {
+        Some(path) => println!("logs: {}", path.display()),
+        None => println!(
+            "logs: not enabled (`--log-file` writes to {})",
+            logging::default_log_file().display()
+        ),
+    }
+
+    println!("docker api (client): {}", API_DEFAULT_VERSION);
+    match engine::connect().await {
+        Ok(docker) => {
+            println!("docker api (negotiated): {}", docker.client_version());
+            match docker.version().await {
+                Ok(version) => println!(
+                    "docker: connected :: version {} :: max api {}",
+                    version.version.unwrap_or_default(),
+                    version.api_version.unwrap_or_default()
+                ),
+                Err(e) => println!("docker: unreachable :: {}", e),
+            }
+        }
+        Err(e) => println!("docker: unable to connect :: {}", e),
+    }
+
+    Ok(())
+}
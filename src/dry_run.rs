@@ -0,0 +1,186 @@
+use bollard::container::Config;
+use bollard::secret::{HostConfigIsolationEnum, MountTypeEnum};
+
+/// prints an equivalent `docker run` command line and the raw create-container body,
+/// in place of creating anything.
+pub fn print(
+    container_name: &str,
+    config: &Config<String>,
+    shell: &[String],
+) -> anyhow::Result<()> {
+    println!("{}", docker_run_command(container_name, config, shell));
+    println!();
+    println!("{}", serde_json::to_string_pretty(config)?);
+    Ok(())
+}
+
+/// the `docker run` command line that starts an equivalent interactive session, quoted for a posix shell.
+pub fn docker_run_command(
+    container_name: &str,
+    config: &Config<String>,
+    shell: &[String],
+) -> String {
+    let mut args = vec!["docker", "run", "--rm", "-it", "--name", container_name]
+        .into_iter()
+        .map(String::from)
+        .collect::<Vec<String>>();
+    let mut arg = |flag: &str, value: String| {
+        args.push(flag.to_string());
+        args.push(value);
+    };
+
+    if let Some(working_dir) = &config.working_dir {
+        arg("--workdir", working_dir.clone());
+    }
+    for env in config.env.iter().flatten() {
+        arg("--env", env.clone());
+    }
+    let mut labels = config.labels.iter().flatten().collect::<Vec<_>>();
+    labels.sort();
+    for (key, value) in labels {
+        arg("--label", format!("{}={}", key, value));
+    }
+
+    if let Some(host_config) = &config.host_config {
+        if let Some(runtime) = &host_config.runtime {
+            arg("--runtime", runtime.clone());
+        }
+        for mount in host_config.mounts.iter().flatten() {
+            let typ = match mount.typ {
+                Some(MountTypeEnum::VOLUME) => "volume",
+                Some(MountTypeEnum::TMPFS) => "tmpfs",
+                _ => "bind",
+            };
+            let mut spec = format!("type={}", typ);
+            if let Some(source) = &mount.source {
+                spec.push_str(&format!(",source={}", source));
+            }
+            if let Some(target) = &mount.target {
+                spec.push_str(&format!(",target={}", target));
+            }
+            if mount.read_only == Some(true) {
+                spec.push_str(",readonly");
+            }
+            arg("--mount", spec);
+        }
+        for bind in host_config.binds.iter().flatten() {
+            arg("--volume", bind.clone());
+        }
+        if let Some(network_mode) = &host_config.network_mode {
+            arg("--network", network_mode.clone());
+        }
+        let mut port_bindings = host_config
+            .port_bindings
+            .iter()
+            .flatten()
+            .collect::<Vec<_>>();
+        port_bindings.sort_by_key(|(container_port, _)| *container_port);
+        for (container_port, bindings) in port_bindings {
+            for binding in bindings.iter().flatten() {
+                let host_port = binding.host_port.as_deref().unwrap_or_default();
+                arg(
+                    "--publish",
+                    match binding.host_ip.as_deref() {
+                        Some(host_ip) => format!("{}:{}:{}", host_ip, host_port, container_port),
+                        None if host_port.is_empty() => container_port.clone(),
+                        None => format!("{}:{}", host_port, container_port),
+                    },
+                );
+            }
+        }
+        if let Some(ipc_mode) = &host_config.ipc_mode {
+            arg("--ipc", ipc_mode.clone());
+        }
+        if let Some(uts_mode) = host_config
+            .uts_mode
+            .as_ref()
+            .filter(|mode| !mode.is_empty())
+        {
+            arg("--uts", uts_mode.clone());
+        }
+        for container in host_config.volumes_from.iter().flatten() {
+            arg("--volumes-from", container.clone());
+        }
+        if let Some(memory) = host_config.memory {
+            arg("--memory", memory.to_string());
+        }
+        if let Some(memory_reservation) = host_config.memory_reservation {
+            arg("--memory-reservation", memory_reservation.to_string());
+        }
+        if let Some(memory_swap) = host_config.memory_swap {
+            arg("--memory-swap", memory_swap.to_string());
+        }
+        if let Some(nano_cpus) = host_config.nano_cpus {
+            arg("--cpus", (nano_cpus as f64 / 1e9).to_string());
+        }
+        if let Some(pids_limit) = host_config.pids_limit {
+            arg("--pids-limit", pids_limit.to_string());
+        }
+        for host in host_config.extra_hosts.iter().flatten() {
+            arg("--add-host", host.clone());
+        }
+        if let Some(userns_mode) = &host_config.userns_mode {
+            arg("--userns", userns_mode.clone());
+        }
+        for group in host_config.group_add.iter().flatten() {
+            arg("--group-add", group.clone());
+        }
+        for cap in host_config.cap_drop.iter().flatten() {
+            arg("--cap-drop", cap.clone());
+        }
+        for cap in host_config.cap_add.iter().flatten() {
+            arg("--cap-add", cap.clone());
+        }
+        let mut tmpfs = host_config.tmpfs.iter().flatten().collect::<Vec<_>>();
+        tmpfs.sort();
+        for (target, options) in tmpfs {
+            arg(
+                "--tmpfs",
+                match options.is_empty() {
+                    true => target.clone(),
+                    false => format!("{}:{}", target, options),
+                },
+            );
+        }
+        for option in host_config.security_opt.iter().flatten() {
+            arg("--security-opt", option.clone());
+        }
+        match host_config.isolation {
+            Some(HostConfigIsolationEnum::PROCESS) => arg("--isolation", "process".into()),
+            Some(HostConfigIsolationEnum::HYPERV) => arg("--isolation", "hyperv".into()),
+            _ => {}
+        }
+        for request in host_config.device_requests.iter().flatten() {
+            let gpus = match (request.count, &request.device_ids) {
+                (Some(-1), _) => "all".to_string(),
+                (Some(count), _) => count.to_string(),
+                (None, Some(ids)) => format!("\"device={}\"", ids.join(",")),
+                (None, None) => continue,
+            };
+            arg("--gpus", gpus);
+        }
+        if host_config.privileged == Some(true) {
+            args.push("--privileged".into());
+        }
+        if host_config.readonly_rootfs == Some(true) {
+            args.push("--read-only".into());
+        }
+    }
+
+    args.extend(config.image.clone());
+    args.extend(shell.iter().cloned());
+
+    args.iter()
+        .map(|arg| quote(arg))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// quotes a word for a posix shell, leaving words that need no quoting as they are.
+fn quote(word: &str) -> String {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "-_./:=,@%+".contains(c);
+    match !word.is_empty() && word.chars().all(safe) {
+        true => word.to_string(),
+        false => format!("'{}'", word.replace('\'', r"'\''")),
+    }
+}
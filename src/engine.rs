@@ -0,0 +1,197 @@
+use anyhow::anyhow;
+use bollard::{ClientVersion, Docker, API_DEFAULT_VERSION};
+use std::sync::{Once, OnceLock};
+
+/// seconds a request to the daemon may take.
+const TIMEOUT: u64 = 120;
+
+/// where `connect_with_local_defaults` connects to.
+#[cfg(unix)]
+const LOCAL_DEFAULT: &str = "unix:///var/run/docker.sock";
+#[cfg(windows)]
+const LOCAL_DEFAULT: &str = "npipe:////./pipe/docker_engine";
+
+/// how to reach a daemon quarantine can't connect to directly.
+#[cfg(windows)]
+const FORWARD_HINT: &str = "forward the daemon's socket instead, eg: `ssh -NL /tmp/docker.sock:/var/run/docker.sock HOST` \
+     and pass `unix:///tmp/docker.sock`";
+
+/// the `--docker-host` of this invocation.
+static HOST: OnceLock<String> = OnceLock::new();
+
+/// makes every later `connect` use `host` (eg: `unix:///run/user/1000/podman/podman.sock` or
+/// `tcp://build-box:2375`) instead of `DOCKER_HOST` or the local default socket.
+pub fn set_host(host: String) {
+    let _ = HOST.set(host);
+}
+
+/// the `--docker-host`, or else `DOCKER_HOST`. none for the local default socket.
+pub fn host() -> Option<String> {
+    HOST.get().cloned().or_else(|| {
+        std::env::var("DOCKER_HOST")
+            .ok()
+            .filter(|host| !host.is_empty())
+    })
+}
+
+/// whether the daemon is on another machine, so paths of this one mean nothing to it. only a tcp host
+/// other than this machine is known to be remote, a forwarded socket looks local.
+pub fn is_remote() -> bool {
+    let Some(host) = host() else {
+        return false;
+    };
+    match host.split_once("://") {
+        Some(("tcp" | "http" | "https" | "ssh", address)) => {
+            let address = address
+                .rsplit_once('@')
+                .map_or(address, |(_, address)| address);
+            let name = match address.strip_prefix('[') {
+                Some(ipv6) => ipv6.split_once(']').map_or(ipv6, |(name, _)| name),
+                None => address.split([':', '/']).next().unwrap_or(address),
+            };
+            !matches!(name, "localhost" | "127.0.0.1" | "::1" | "")
+        }
+        _ => false,
+    }
+}
+
+/// connects to the daemon and negotiates the api version down to what the daemon supports,
+/// so older daemons don't reject every call with "client version is too new".
+pub async fn connect() -> anyhow::Result<Docker> {
+    let host = host().or_else(rootless_socket);
+    let endpoint = host.as_deref().unwrap_or(LOCAL_DEFAULT).to_string();
+    let docker = match &host {
+        Some(host) => connect_to(host)?,
+        // through the proxy that logs the requests
+        None if crate::trace::enabled() => connect_to(LOCAL_DEFAULT)?,
+        None => Docker::connect_with_local_defaults().map_err(|e| unreachable(&endpoint, e))?,
+    }
+    // asks the daemon for its version, the first request that has to reach it
+    .negotiate_version()
+    .await
+    .map_err(|e| unreachable(&endpoint, e))?;
+    static CONNECTED: Once = Once::new();
+    CONNECTED.call_once(|| tracing::info!("connected to `{}`", endpoint));
+    tracing::debug!(
+        "docker api :: client {} :: negotiated {}",
+        API_DEFAULT_VERSION,
+        docker.client_version()
+    );
+    Ok(docker)
+}
+
+/// the error of a daemon that doesn't answer at `endpoint`.
+fn unreachable(endpoint: &str, e: bollard::errors::Error) -> anyhow::Error {
+    anyhow!(
+        "unable to reach the docker daemon at `{}`: {}. is it running? pass --docker-host (or set DOCKER_HOST) \
+         to use another one",
+        endpoint,
+        e
+    )
+}
+
+/// the socket of a rootless docker or podman when the default socket doesn't exist, eg: `$XDG_RUNTIME_DIR/podman/podman.sock`.
+#[cfg(unix)]
+fn rootless_socket() -> Option<String> {
+    if std::path::Path::new(LOCAL_DEFAULT.trim_start_matches("unix://")).exists() {
+        return None;
+    }
+    let runtime_dir = std::path::PathBuf::from(std::env::var_os("XDG_RUNTIME_DIR")?);
+    let socket = ["docker.sock", "podman/podman.sock"]
+        .into_iter()
+        .map(|path| runtime_dir.join(path))
+        .find(|path| path.exists())?;
+    tracing::debug!(
+        "`{}` doesn't exist, using `{}`",
+        LOCAL_DEFAULT,
+        socket.display()
+    );
+    Some(format!("unix://{}", socket.display()))
+}
+
+#[cfg(windows)]
+fn rootless_socket() -> Option<String> {
+    None
+}
+
+/// picks the transport by the scheme of `host`.
+fn connect_to(host: &str) -> anyhow::Result<Docker> {
+    tracing::debug!("connecting to `{}`", host);
+    let tls_verify = std::env::var("DOCKER_TLS_VERIFY").is_ok_and(|verify| !verify.is_empty());
+    #[cfg(unix)]
+    if crate::trace::enabled() {
+        return proxied(host, tls_verify);
+    }
+    let docker = match host.split_once("://") {
+        #[cfg(unix)]
+        Some(("unix", _)) => Docker::connect_with_unix(host, TIMEOUT, API_DEFAULT_VERSION)?,
+        #[cfg(windows)]
+        Some(("npipe", _)) => Docker::connect_with_named_pipe(host, TIMEOUT, API_DEFAULT_VERSION)?,
+        #[cfg(unix)]
+        Some(("tcp", _)) if tls_verify => proxied(host, tls_verify)?,
+        #[cfg(windows)]
+        Some(("tcp", _)) if tls_verify => {
+            return Err(anyhow!(
+                "`{}`: DOCKER_TLS_VERIFY is set, but tls connections are not supported on windows. {}",
+                host,
+                FORWARD_HINT
+            ))
+        }
+        Some(("tcp" | "http", _)) => Docker::connect_with_http(host, TIMEOUT, API_DEFAULT_VERSION)?,
+        #[cfg(unix)]
+        Some(("https" | "ssh", _)) => proxied(host, tls_verify)?,
+        #[cfg(windows)]
+        Some(("https" | "ssh", _)) => {
+            return Err(anyhow!(
+                "`{}`: tls and ssh connections are not supported on windows. {}",
+                host,
+                FORWARD_HINT
+            ))
+        }
+        _ => {
+            return Err(anyhow!(
+                "unsupported docker host `{}`. expected unix://PATH, npipe://PATH, tcp://HOST:PORT, https://HOST:PORT \
+                 or ssh://[USER@]HOST[:PORT]",
+                host
+            ))
+        }
+    };
+    Ok(docker)
+}
+
+/// connects through the proxy, which bollard can't do without for tls and ssh, and which logs the requests
+/// for `--trace-docker`.
+#[cfg(unix)]
+fn proxied(host: &str, tls_verify: bool) -> anyhow::Result<Docker> {
+    let upstream = crate::proxy::Upstream::parse(host, tls_verify, &crate::proxy::cert_path())?;
+    let socket = crate::proxy::socket(upstream)?;
+    Ok(Docker::connect_with_unix(
+        &format!("unix://{}", socket.display()),
+        TIMEOUT,
+        API_DEFAULT_VERSION,
+    )?)
+}
+
+/// fails with a readable error when `feature` needs a newer api than the one negotiated with the daemon.
+pub fn require_api_version(
+    docker: &Docker,
+    major_version: usize,
+    minor_version: usize,
+    feature: &str,
+) -> anyhow::Result<()> {
+    let required = ClientVersion {
+        major_version,
+        minor_version,
+    };
+    let negotiated = docker.client_version();
+
+    match negotiated < required {
+        true => Err(anyhow!(
+            "{} requires Docker API >= {}, but the daemon only supports {}",
+            feature,
+            required,
+            negotiated
+        )),
+        false => Ok(()),
+    }
+}
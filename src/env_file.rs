@@ -0,0 +1,201 @@
+//! `--env-file` and `--env`: the variables given on the command line, merged in the order docker applies them.
+
+use anyhow::anyhow;
+use std::path::{Path, PathBuf};
+
+/// the variables of the env files (in order), then of `--env`. a later definition of a variable replaces
+/// an earlier one. `KEY` without a value takes the value from the host environment, and is left out when
+/// the host doesn't have it either.
+pub fn merge(env_files: &[PathBuf], envs: &[String]) -> anyhow::Result<Vec<String>> {
+    let mut merged = Vec::<(String, String)>::new();
+    let mut set = |key: &str, value: String| {
+        merged.retain(|(existing, _)| existing != key);
+        merged.push((key.to_string(), value));
+    };
+
+    for path in env_files {
+        for (key, value) in parse(path)? {
+            set(&key, value);
+        }
+    }
+    for entry in envs {
+        match entry.split_once('=') {
+            Some((key, value)) => set(key, value.to_string()),
+            None => match std::env::var(entry) {
+                Ok(value) => set(entry, value),
+                Err(_) => tracing::debug!("`{}` is not set on the host, not passing it on", entry),
+            },
+        }
+    }
+
+    Ok(merged
+        .into_iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect())
+}
+
+/// reads a dotenv style file: `KEY=VALUE` per line, blank lines and `#` comments are skipped.
+/// a value in matching single or double quotes is unquoted, otherwise values are taken literally.
+/// nothing is expanded.
+pub fn parse(path: &Path) -> anyhow::Result<Vec<(String, String)>> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| anyhow!("unable to read the env file `{}`: {}", path.display(), e))?;
+
+    let mut vars = vec![];
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim_start();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (key, value) = line
+            .split_once('=')
+            .filter(|(key, _)| valid_key(key))
+            .ok_or_else(|| {
+                anyhow!(
+                    "`{}` line {}: expected `KEY=VALUE`, got `{}`",
+                    path.display(),
+                    number + 1,
+                    line
+                )
+            })?;
+        vars.push((key.to_string(), unquote(value).to_string()));
+    }
+    Ok(vars)
+}
+
+/// `"value"` or `'value'` without the quotes.
+fn unquote(value: &str) -> &str {
+    for quote in ['"', '\''] {
+        if let Some(inner) = value
+            .strip_prefix(quote)
+            .and_then(|value| value.strip_suffix(quote))
+        {
+            return inner;
+        }
+    }
+    value
+}
+
+/// a variable name must be non-empty and may contain neither `=` nor whitespace.
+pub fn valid_key(key: &str) -> bool {
+    !key.is_empty() && !key.contains(|c: char| c == '=' || c.is_whitespace())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env_file(dir: &tempfile::TempDir, name: &str, content: &str) -> PathBuf {
+        let path = dir.path().join(name);
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn parses_env_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = env_file(
+            &dir,
+            ".env",
+            "# a comment\n\nA=1\n  B=two words\nC=\nD=x=y\nE=$HOME #not a comment\r\n",
+        );
+        assert_eq!(
+            parse(&path).unwrap(),
+            [
+                ("A".to_string(), "1".to_string()),
+                ("B".to_string(), "two words".to_string()),
+                ("C".to_string(), String::new()),
+                ("D".to_string(), "x=y".to_string()),
+                ("E".to_string(), "$HOME #not a comment".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn refuses_lines_without_a_key() {
+        let dir = tempfile::tempdir().unwrap();
+        for line in ["NO_VALUE", "=1", "A B=1", "export A=1"] {
+            let path = env_file(&dir, ".env", &format!("OK=1\n{}\n", line));
+            let e = parse(&path).unwrap_err();
+            assert_eq!(
+                e.to_string(),
+                format!(
+                    "`{}` line 2: expected `KEY=VALUE`, got `{}`",
+                    path.display(),
+                    line
+                )
+            );
+        }
+
+        let e = parse(&dir.path().join("missing")).unwrap_err();
+        assert!(e.to_string().starts_with("unable to read the env file"));
+    }
+
+    #[test]
+    fn later_definitions_take_precedence() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = env_file(&dir, "first.env", "A=first\nB=first\nC=first\n");
+        let second = env_file(&dir, "second.env", "B=second\n");
+        let merged = merge(
+            &[first, second],
+            &["C=flag".to_string(), "D=flag".to_string()],
+        )
+        .unwrap();
+        assert_eq!(merged, ["A=first", "B=second", "C=flag", "D=flag"]);
+
+        // a repeated variable moves to where it was defined last
+        let merged = merge(
+            &[],
+            &["A=1".to_string(), "B=2".to_string(), "A=3".to_string()],
+        )
+        .unwrap();
+        assert_eq!(merged, ["B=2", "A=3"]);
+    }
+
+    #[test]
+    fn passes_on_host_variables_without_a_value() {
+        let path = std::env::var("PATH").unwrap();
+        let merged = merge(
+            &[],
+            &[
+                "PATH".to_string(),
+                "QUARANTINE_TEST_UNSET_VARIABLE".to_string(),
+                "EMPTY=".to_string(),
+            ],
+        )
+        .unwrap();
+        assert_eq!(merged, [format!("PATH={}", path), "EMPTY=".to_string()]);
+    }
+
+    #[test]
+    fn unquotes_quoted_values() {
+        assert_eq!(unquote("\"a b\""), "a b");
+        assert_eq!(unquote("'a b'"), "a b");
+        assert_eq!(unquote("\"\""), "");
+        assert_eq!(unquote("''"), "");
+        // the inner quotes and escapes are taken literally
+        assert_eq!(unquote("\"it's\""), "it's");
+        assert_eq!(unquote("'say \"hi\"'"), "say \"hi\"");
+        assert_eq!(unquote("\"a\\nb\""), "a\\nb");
+        // unless they match
+        assert_eq!(unquote("\"a'"), "\"a'");
+        assert_eq!(unquote("\"a"), "\"a");
+        assert_eq!(unquote("a\""), "a\"");
+        assert_eq!(unquote("\""), "\"");
+        assert_eq!(unquote("a \"b\""), "a \"b\"");
+    }
+
+    #[test]
+    fn unquotes_values_of_env_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = env_file(&dir, ".env", "A=\"a # b\"\nB='$HOME'\nC=\" padded \"\n");
+        assert_eq!(
+            parse(&path).unwrap(),
+            [
+                ("A".to_string(), "a # b".to_string()),
+                ("B".to_string(), "$HOME".to_string()),
+                ("C".to_string(), " padded ".to_string()),
+            ]
+        );
+    }
+}
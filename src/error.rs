@@ -0,0 +1,69 @@
+//! the errors of the `Session` api, so embedding programs can tell a failed pull from a missing runtime.
+
+/// what went wrong in a `Session`.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// `SessionBuilder::build` without an `image`
+    #[error("no image to run, set one with `image`")]
+    NoImage,
+
+    /// a mount whose host path is not valid unicode, or whose container path is not absolute
+    #[error("unable to mount `{0}`, the container path must be absolute")]
+    InvalidMount(String),
+
+    /// a mount that exposes the docker or podman socket, see
+    /// `SessionBuilder::dangerously_expose_engine_socket`
+    #[error("`{}` is the container engine's socket, mounting it gives the container control of the host", .0.display())]
+    EngineSocket(std::path::PathBuf),
+
+    /// the daemon can't be reached
+    #[error("unable to connect to the docker daemon")]
+    Connect(#[source] Box<dyn std::error::Error + Send + Sync>),
+
+    /// the registry has no such image or tag
+    #[error("image `{0}` not found, check the image name and tag")]
+    ImageNotFound(String),
+
+    /// the image could not be pulled
+    #[error("unable to pull `{image}`")]
+    Pull {
+        image: String,
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    /// the daemon doesn't have the requested runtime
+    #[error("runtime `{runtime}` not found, the daemon has: {}", available.join(", "))]
+    RuntimeNotFound {
+        runtime: String,
+        available: Vec<String>,
+    },
+
+    /// the container could not be created or started
+    #[error("unable to start the container")]
+    Start(#[source] Box<dyn std::error::Error + Send + Sync>),
+
+    /// the command could not be run in the container
+    #[error("unable to run the command in the container")]
+    Exec(#[source] bollard::errors::Error),
+
+    /// copying the streams to or from the container failed
+    #[error("unable to attach to the container")]
+    Attach(#[source] std::io::Error),
+
+    /// the daemon started the command detached, so there are no streams to attach to
+    #[error("the daemon started the command detached")]
+    Detached,
+
+    /// the daemon didn't report the exit code of the command
+    #[error("the daemon reported no exit code for the command")]
+    NoExitCode,
+
+    /// `attach` or `exec` before `start`
+    #[error("the session has not been started")]
+    NotStarted,
+
+    /// any other request to the daemon failed
+    #[error(transparent)]
+    Docker(#[from] bollard::errors::Error),
+}
@@ -0,0 +1,180 @@
+//! a docker daemon for tests: answers the requests it has a route for with canned json, and records all of them.
+
+use bollard::Docker;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+/// what the engine answers to `method path`. the path is without the api version, eg: `/containers/json`.
+pub struct Route {
+    pub method: &'static str,
+    pub path: String,
+    pub status: u16,
+    pub body: String,
+}
+
+impl Route {
+    pub fn new(method: &'static str, path: impl Into<String>, body: serde_json::Value) -> Self {
+        Route {
+            method,
+            path: path.into(),
+            status: 200,
+            body: body.to_string(),
+        }
+    }
+
+    pub fn status(mut self, status: u16) -> Self {
+        self.status = status;
+        self
+    }
+}
+
+/// a request the engine got.
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: String,
+    /// without the api version and the query
+    pub path: String,
+    /// percent decoded
+    pub query: String,
+    /// with lowercase names
+    pub headers: Vec<(String, String)>,
+}
+
+pub struct FakeEngine {
+    pub docker: Docker,
+    /// `HOST:PORT`
+    pub address: String,
+    requests: Arc<Mutex<Vec<Request>>>,
+}
+
+impl FakeEngine {
+    pub async fn start(routes: Vec<Route>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let requests = Arc::new(Mutex::new(vec![]));
+        let routes = Arc::new(routes);
+        let recorded = requests.clone();
+        tokio::spawn(async move {
+            loop {
+                let Ok((mut stream, _)) = listener.accept().await else {
+                    return;
+                };
+                let (routes, recorded) = (routes.clone(), recorded.clone());
+                tokio::spawn(async move {
+                    let Some(request) = read_request(&mut stream).await else {
+                        return;
+                    };
+                    let (status, body) = routes
+                        .iter()
+                        .find(|route| route.method == request.method && route.path == request.path)
+                        .map(|route| (route.status, route.body.clone()))
+                        .unwrap_or_else(|| (404, r#"{"message":"no such route"}"#.to_string()));
+                    recorded.lock().unwrap().push(request);
+                    let response = format!(
+                        "HTTP/1.1 {} \r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                        status,
+                        body.len(),
+                        body
+                    );
+                    let _ = stream.write_all(response.as_bytes()).await;
+                    let _ = stream.shutdown().await;
+                });
+            }
+        });
+        let docker = Docker::connect_with_http(
+            &format!("http://{}", address),
+            5,
+            bollard::API_DEFAULT_VERSION,
+        )
+        .unwrap();
+        FakeEngine {
+            docker,
+            address: address.to_string(),
+            requests,
+        }
+    }
+
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// the requests with this method and path.
+    pub fn requested(&self, method: &str, path: &str) -> Vec<Request> {
+        self.requests()
+            .into_iter()
+            .filter(|request| request.method == method && request.path == path)
+            .collect()
+    }
+}
+
+async fn read_request(stream: &mut tokio::net::TcpStream) -> Option<Request> {
+    let mut buffer = vec![];
+    let mut chunk = [0; 4096];
+    let end = loop {
+        if let Some(end) = buffer.windows(4).position(|window| window == b"\r\n\r\n") {
+            break end + 4;
+        }
+        let read = stream.read(&mut chunk).await.ok()?;
+        if read == 0 {
+            return None;
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    };
+    let head = String::from_utf8_lossy(&buffer[..end]).into_owned();
+    let length = head
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+        .unwrap_or_default();
+    while buffer.len() < end + length {
+        let read = stream.read(&mut chunk).await.ok()?;
+        if read == 0 {
+            break;
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    }
+
+    let mut request_line = head.lines().next()?.split(' ');
+    let method = request_line.next()?.to_string();
+    let target = request_line.next()?;
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    // `/v1.41/containers/json` -> `/containers/json`
+    let path = match path
+        .strip_prefix("/v")
+        .and_then(|rest| rest.split_once('/'))
+    {
+        Some((version, rest)) if version.contains('.') => format!("/{}", rest),
+        _ => path.to_string(),
+    };
+    let headers = head
+        .lines()
+        .skip(1)
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_lowercase(), value.trim().to_string()))
+        .collect();
+    Some(Request {
+        method,
+        path,
+        query: percent_decode(query),
+        headers,
+    })
+}
+
+fn percent_decode(s: &str) -> String {
+    let mut decoded = vec![];
+    let mut bytes = s.bytes();
+    while let Some(byte) = bytes.next() {
+        match byte {
+            b'%' => {
+                let hex = [bytes.next().unwrap_or(b'0'), bytes.next().unwrap_or(b'0')];
+                let hex = std::str::from_utf8(&hex).unwrap_or("00");
+                decoded.push(u8::from_str_radix(hex, 16).unwrap_or(b'%'));
+            }
+            b'+' => decoded.push(b' '),
+            byte => decoded.push(byte),
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
@@ -0,0 +1,90 @@
+use anyhow::anyhow;
+use bollard::exec::{CreateExecOptions, StartExecResults};
+use bollard::Docker;
+use futures::StreamExt;
+use std::time::Duration;
+
+/// `on_stop` hooks that take longer than this are abandoned so they can't block the cleanup.
+pub const ON_STOP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// runs the `on_start` hooks in order, failing on the first one that doesn't exit 0.
+pub async fn on_start(docker: &Docker, container: &str, commands: &[String]) -> anyhow::Result<()> {
+    for command in commands {
+        match run_hook(docker, container, "on_start", command).await? {
+            0 => {}
+            exit_code => {
+                return Err(anyhow!(
+                    "on_start hook `{}` exited with {}",
+                    command,
+                    exit_code
+                ))
+            }
+        }
+    }
+    Ok(())
+}
+
+/// runs the `on_stop` hooks in order. failures are reported and the remaining hooks still run.
+pub async fn on_stop(docker: &Docker, container: &str, commands: &[String]) {
+    for command in commands {
+        match tokio::time::timeout(
+            ON_STOP_TIMEOUT,
+            run_hook(docker, container, "on_stop", command),
+        )
+        .await
+        {
+            Ok(Ok(0)) => {}
+            Ok(Ok(exit_code)) => {
+                tracing::warn!("on_stop hook `{}` exited with {}", command, exit_code)
+            }
+            Ok(Err(e)) => tracing::warn!("on_stop hook `{}` failed: {}", command, e),
+            Err(_) => tracing::warn!(
+                "on_stop hook `{}` did not finish within {:?}, abandoning it",
+                command,
+                ON_STOP_TIMEOUT
+            ),
+        }
+    }
+}
+
+/// runs `command` with `sh -c` in the container, logging its output line by line prefixed with the hook's name.
+async fn run_hook(
+    docker: &Docker,
+    container: &str,
+    hook: &str,
+    command: &str,
+) -> anyhow::Result<i64> {
+    tracing::info!("[{}] $ {}", hook, command);
+    let exec = docker
+        .create_exec(
+            container,
+            CreateExecOptions {
+                attach_stdout: Some(true),
+                attach_stderr: Some(true),
+                cmd: Some(vec!["sh", "-c", command]),
+                ..Default::default()
+            },
+        )
+        .await?;
+
+    if let StartExecResults::Attached { mut output, .. } = docker.start_exec(&exec.id, None).await?
+    {
+        let mut pending = String::new();
+        while let Some(output) = output.next().await {
+            pending.push_str(&output?.to_string());
+            while let Some(newline) = pending.find('\n') {
+                tracing::info!("[{}] {}", hook, pending[..newline].trim_end_matches('\r'));
+                pending.drain(..=newline);
+            }
+        }
+        if !pending.is_empty() {
+            tracing::info!("[{}] {}", hook, pending);
+        }
+    }
+
+    docker
+        .inspect_exec(&exec.id)
+        .await?
+        .exit_code
+        .ok_or_else(|| anyhow!("exec `{}` did not report an exit code", exec.id))
+}
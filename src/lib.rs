@@ -0,0 +1,207 @@
+//! quarantine as a library. `Session` runs a hardened container, attaches streams to it and removes it again,
+//! for programs that embed quarantine. the command line does much more on top of it (config files, compose,
+//! pools, signatures, ..), its modules are not part of the api.
+
+use anyhow::anyhow;
+use bollard::exec::{CreateExecOptions, StartExecResults};
+use bollard::Docker;
+use futures::StreamExt;
+
+// the building blocks of the command line. they are public for the `quarantine` binary and are not part of the
+// library's api, they change without notice.
+#[doc(hidden)]
+pub mod audit;
+mod auth;
+#[doc(hidden)]
+pub mod build;
+#[doc(hidden)]
+pub mod cache;
+#[doc(hidden)]
+pub mod compose;
+#[doc(hidden)]
+pub mod config;
+#[doc(hidden)]
+pub mod copy;
+#[doc(hidden)]
+pub mod detect;
+#[doc(hidden)]
+pub mod devcontainer;
+#[doc(hidden)]
+pub mod dry_run;
+#[doc(hidden)]
+pub mod engine;
+#[doc(hidden)]
+pub mod env_file;
+mod error;
+#[cfg(test)]
+mod fake_engine;
+#[doc(hidden)]
+pub mod hooks;
+#[doc(hidden)]
+pub mod load;
+#[doc(hidden)]
+pub mod logging;
+#[doc(hidden)]
+pub mod nested;
+#[doc(hidden)]
+pub mod platform;
+#[doc(hidden)]
+pub mod pool;
+#[cfg(unix)]
+mod proxy;
+#[doc(hidden)]
+pub mod pull;
+mod sandbox;
+#[doc(hidden)]
+pub mod save;
+#[doc(hidden)]
+pub mod session;
+#[doc(hidden)]
+pub mod session_log;
+#[doc(hidden)]
+pub mod signature;
+#[doc(hidden)]
+pub mod snapshot;
+#[doc(hidden)]
+pub mod socket;
+#[doc(hidden)]
+pub mod terminal;
+#[doc(hidden)]
+pub mod trace;
+#[doc(hidden)]
+pub mod volumes_from;
+#[doc(hidden)]
+pub mod wait_for;
+#[doc(hidden)]
+pub mod watch;
+
+pub use error::Error;
+pub use sandbox::{ExecResult, Session, SessionBuilder};
+
+/// set on every container quarantine creates, with the version that created it.
+/// containers without it are never removed automatically.
+pub const MANAGED_LABEL: &str = "quarantine.version";
+
+/// the namespaces shared with the host (eg: `ipc,uts`), see `--ipc` and `--uts`.
+pub const HOST_NAMESPACES_LABEL: &str = "quarantine.host-namespaces";
+
+/// the capabilities kept by default, so root inside the container can write to the project owned by the host user.
+pub const PROJECT_CAPABILITIES: [&str; 3] = ["CHOWN", "DAC_OVERRIDE", "FOWNER"];
+
+/// the mount options of the `/tmp` tmpfs on a read-only root filesystem. executable, so builds can run there.
+pub const TMP_OPTIONS: &str = "rw,exec,nosuid,nodev";
+
+/// set on containers that have the engine socket mounted, see `--dangerously-expose-engine-socket`.
+pub const ENGINE_SOCKET_LABEL: &str = "quarantine.engine-socket";
+
+/// container names are `[a-zA-Z0-9][a-zA-Z0-9_.-]*`, so only `.` means something in a regex.
+#[doc(hidden)]
+pub fn regex_escape(name: &str) -> String {
+    name.replace('.', "\\.")
+}
+
+/// runs a command inside the container without attaching to it and returns its exit code.
+#[doc(hidden)]
+pub async fn exec_status(docker: &Docker, container: &str, cmd: Vec<&str>) -> anyhow::Result<i64> {
+    let (exit_code, output) = exec_output(docker, container, cmd).await?;
+    tracing::debug!("{}", output);
+    Ok(exit_code)
+}
+
+/// runs a command inside the container without attaching to it and returns its exit code
+/// along with everything it wrote to stdout and stderr.
+#[doc(hidden)]
+pub async fn exec_output(
+    docker: &Docker,
+    container: &str,
+    cmd: Vec<&str>,
+) -> anyhow::Result<(i64, String)> {
+    let exec = docker
+        .create_exec(
+            container,
+            CreateExecOptions {
+                attach_stdout: Some(true),
+                attach_stderr: Some(true),
+                cmd: Some(cmd),
+                ..Default::default()
+            },
+        )
+        .await?;
+
+    let mut collected = String::new();
+    if let StartExecResults::Attached { mut output, .. } = docker.start_exec(&exec.id, None).await?
+    {
+        while let Some(output) = output.next().await {
+            collected.push_str(&output?.to_string());
+        }
+    }
+
+    let inspect = docker.inspect_exec(&exec.id).await?;
+    let exit_code = inspect
+        .exit_code
+        .ok_or_else(|| anyhow!("exec `{}` did not report an exit code", exec.id))?;
+    Ok((exit_code, collected))
+}
+
+/// parses a docker style size like `1024`, `512k`, `256m` or `2g` (case insensitive) into bytes.
+#[doc(hidden)]
+pub fn parse_size(s: &str) -> anyhow::Result<i64> {
+    let s = s.trim();
+    let lower = s.to_lowercase();
+    let lower = lower.strip_suffix('b').unwrap_or(&lower);
+
+    let (digits, multiplier) = match lower.chars().last() {
+        Some('k') => (&lower[..lower.len() - 1], 1 << 10),
+        Some('m') => (&lower[..lower.len() - 1], 1 << 20),
+        Some('g') => (&lower[..lower.len() - 1], 1 << 30),
+        Some('t') => (&lower[..lower.len() - 1], 1 << 40),
+        _ => (lower, 1),
+    };
+
+    let value = digits.parse::<i64>().map_err(|_| {
+        anyhow!(
+            "invalid size `{}`. expected a number with an optional k/m/g/t suffix (eg: `512m`)",
+            s
+        )
+    })?;
+
+    value
+        .checked_mul(multiplier)
+        .ok_or_else(|| anyhow!("size `{}` is too large", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sizes() {
+        assert_eq!(parse_size("1024").unwrap(), 1024);
+        assert_eq!(parse_size("512k").unwrap(), 512 << 10);
+        assert_eq!(parse_size("256m").unwrap(), 256 << 20);
+        assert_eq!(parse_size("2g").unwrap(), 2 << 30);
+        assert_eq!(parse_size("1t").unwrap(), 1 << 40);
+        assert_eq!(parse_size(" 2GB ").unwrap(), 2 << 30);
+        assert_eq!(parse_size("64Mb").unwrap(), 64 << 20);
+        assert_eq!(parse_size("100b").unwrap(), 100);
+        // unlimited swap
+        assert_eq!(parse_size("-1").unwrap(), -1);
+    }
+
+    #[test]
+    fn refuses_invalid_sizes() {
+        for invalid in ["", "m", "12x", "1.5g", "g2", "2 g", "0x10"] {
+            let e = parse_size(invalid).unwrap_err();
+            assert!(e.to_string().starts_with("invalid size"), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn refuses_sizes_that_overflow() {
+        let e = parse_size("9999999999t").unwrap_err();
+        assert_eq!(e.to_string(), "size `9999999999t` is too large");
+        assert!(parse_size(&format!("{}k", i64::MAX)).is_err());
+        assert_eq!(parse_size(&i64::MAX.to_string()).unwrap(), i64::MAX);
+        assert!(parse_size("99999999999999999999").is_err());
+    }
+}
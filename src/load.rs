@@ -0,0 +1,150 @@
+//! `--load`: runs the sandbox from a `docker save` archive instead of pulling the image.
+
+use anyhow::anyhow;
+use bollard::image::{ImportImageOptions, TagImageOptions};
+use bollard::Docker;
+use bytes::Bytes;
+use futures::StreamExt;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// how much of the archive is read at a time while streaming it to the daemon.
+const CHUNK: usize = 1024 * 1024;
+
+/// what the daemon reported it loaded.
+#[derive(Debug, Default, PartialEq)]
+pub struct Loaded {
+    pub references: Vec<String>,
+    /// images without a tag in the archive
+    pub ids: Vec<String>,
+}
+
+/// streams the archive (plain or gzipped, the daemon detects which) to the daemon and returns the image to run.
+/// `tag` picks one of several images, or names an untagged one.
+pub async fn load(docker: &Docker, archive: &Path, tag: Option<&str>) -> anyhow::Result<String> {
+    let mut file = std::fs::File::open(archive)
+        .map_err(|e| anyhow!("unable to open `{}`: {}", archive.display(), e))?;
+    if !is_archive(&mut file)? {
+        return Err(anyhow!(
+            "`{}` is neither a tar nor a gzipped tar archive",
+            archive.display()
+        ));
+    }
+    tracing::info!(
+        "loading `{}` ({} bytes)",
+        archive.display(),
+        file.metadata()?.len()
+    );
+
+    let path = archive.display().to_string();
+    let chunks = futures::stream::poll_fn(move |_| {
+        let mut chunk = vec![0; CHUNK];
+        std::task::Poll::Ready(match file.read(&mut chunk) {
+            Ok(0) => None,
+            Ok(n) => {
+                chunk.truncate(n);
+                Some(Bytes::from(chunk))
+            }
+            // the daemon sees a truncated archive and rejects it
+            Err(e) => {
+                tracing::error!("unable to read `{}`: {}", path, e);
+                None
+            }
+        })
+    });
+
+    let mut stream = docker.import_image_stream(ImportImageOptions { quiet: true }, chunks, None);
+    let mut messages = vec![];
+    while let Some(info) = stream.next().await {
+        let info = info.map_err(|e| {
+            anyhow!(
+                "`{}` could not be loaded, is it an archive made by `docker save`? {}",
+                archive.display(),
+                e
+            )
+        })?;
+        messages.extend(info.stream);
+    }
+
+    let loaded = parse(&messages);
+    for reference in &loaded.references {
+        tracing::info!("loaded image: {}", reference);
+    }
+    for id in &loaded.ids {
+        tracing::info!("loaded untagged image: {}", id);
+    }
+
+    let (image, untagged) = choose(&loaded, tag)?;
+    if let Some(id) = untagged {
+        let (repo, tag) = split_reference(&image);
+        docker
+            .tag_image(id, Some(TagImageOptions { repo, tag }))
+            .await?;
+        tracing::info!("tagged {} as {}", id, image);
+    }
+    Ok(image)
+}
+
+/// looks for the gzip magic bytes or the `ustar` magic of a tar header, and rewinds the file.
+fn is_archive(file: &mut std::fs::File) -> anyhow::Result<bool> {
+    let mut header = vec![];
+    file.by_ref().take(262).read_to_end(&mut header)?;
+    file.seek(SeekFrom::Start(0))?;
+    Ok(header.starts_with(&[0x1f, 0x8b]) || header.get(257..262) == Some(b"ustar"))
+}
+
+/// collects the `Loaded image: REF` and `Loaded image ID: ID` lines of the load response.
+pub fn parse(messages: &[String]) -> Loaded {
+    let mut loaded = Loaded::default();
+    for line in messages.iter().flat_map(|message| message.lines()) {
+        if let Some(id) = line.strip_prefix("Loaded image ID: ") {
+            loaded.ids.push(id.trim().to_string());
+        } else if let Some(reference) = line.strip_prefix("Loaded image: ") {
+            loaded.references.push(reference.trim().to_string());
+        }
+    }
+    loaded
+}
+
+/// the image to run and, when `tag` names an untagged image, the id it has to be tagged from.
+pub fn choose<'a>(
+    loaded: &'a Loaded,
+    tag: Option<&str>,
+) -> anyhow::Result<(String, Option<&'a str>)> {
+    let everything = || {
+        loaded
+            .references
+            .iter()
+            .chain(&loaded.ids)
+            .map(|image| format!("`{}`", image))
+            .collect::<Vec<String>>()
+            .join(" ")
+    };
+
+    match (loaded.references.as_slice(), loaded.ids.as_slice(), tag) {
+        ([], [], _) => Err(anyhow!("the archive does not contain any image")),
+        (references, _, Some(tag)) if references.iter().any(|reference| reference == tag) => {
+            Ok((tag.to_string(), None))
+        }
+        ([], [id], Some(tag)) => Ok((tag.to_string(), Some(id.as_str()))),
+        (_, _, Some(tag)) => Err(anyhow!(
+            "`{}` is not in the archive. it contains {}",
+            tag,
+            everything()
+        )),
+        ([reference], [], None) => Ok((reference.clone(), None)),
+        ([], [id], None) => Ok((id.clone(), None)),
+        (_, _, None) => Err(anyhow!(
+            "the archive contains several images ({}). pick one with --load-tag",
+            everything()
+        )),
+    }
+}
+
+/// `registry:5000/repo:tag` as (`registry:5000/repo`, `tag`), `latest` when there is no tag.
+fn split_reference(reference: &str) -> (&str, &str) {
+    match reference.rsplit_once(':') {
+        Some((repo, tag)) if !tag.contains('/') => (repo, tag),
+        _ => (reference, "latest"),
+    }
+}
@@ -0,0 +1,158 @@
+use clap::ColorChoice;
+use std::fs::{File, OpenOptions};
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use tracing_subscriber::filter::{LevelFilter, Targets};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::Layer;
+
+/// the log file is rotated once it grows past this size.
+const MAX_LOG_FILE_BYTES: u64 = 10 * 1024 * 1024;
+
+/// how many rotated log files (`quarantine.log.1` .. `quarantine.log.N`) are kept around.
+const KEEP_LOG_FILES: usize = 5;
+
+static COLOR: OnceLock<bool> = OnceLock::new();
+
+/// sets up the terminal output on stderr at info level and, when a log file is given,
+/// a second layer that writes everything at debug level to that file. `trace_docker` adds the docker api
+/// requests to the file.
+pub fn init(log_file: Option<&Path>, color: ColorChoice, trace_docker: bool) -> anyhow::Result<()> {
+    let color = *COLOR.get_or_init(|| use_color(color));
+    let terminal = tracing_subscriber::fmt::layer()
+        .with_writer(std::io::stderr)
+        .with_ansi(color)
+        .with_filter(LevelFilter::INFO);
+
+    let file = match log_file {
+        Some(path) => Some(
+            tracing_subscriber::fmt::layer()
+                .with_ansi(false)
+                .with_writer(Mutex::new(RotatingFile::open(path)?))
+                .with_filter(Targets::new().with_default(LevelFilter::DEBUG).with_target(
+                    crate::trace::TARGET,
+                    match trace_docker {
+                        true => LevelFilter::TRACE,
+                        false => LevelFilter::OFF,
+                    },
+                )),
+        ),
+        None => None,
+    };
+
+    tracing_subscriber::registry()
+        .with(terminal)
+        .with(file)
+        .try_init()?;
+
+    Ok(())
+}
+
+/// whether quarantine's own output should be colored.
+/// `auto` follows the `NO_COLOR` convention and disables colors when stderr is not a terminal.
+pub fn use_color(color: ColorChoice) -> bool {
+    match color {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => {
+            std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+                && std::io::stderr().is_terminal()
+        }
+    }
+}
+
+/// prints a warning that must not be missed on stderr, in red when colors are enabled.
+pub fn alert(message: &str) {
+    match COLOR.get().copied().unwrap_or_default() {
+        true => eprintln!("\x1b[1;31m{}\x1b[0m", message),
+        false => eprintln!("{}", message),
+    }
+    tracing::debug!("{}", message);
+}
+
+/// `$XDG_STATE_HOME/quarantine/logs/quarantine.log`, falling back to `~/.local/state`.
+pub fn default_log_file() -> PathBuf {
+    log_dir().join("quarantine.log")
+}
+
+pub fn log_dir() -> PathBuf {
+    state_dir().join("logs")
+}
+
+/// `$XDG_STATE_HOME/quarantine`, falling back to `~/.local/state`.
+pub fn state_dir() -> PathBuf {
+    let state_home = std::env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state")))
+        .unwrap_or_else(std::env::temp_dir);
+
+    state_home.join("quarantine")
+}
+
+/// identifies one invocation of quarantine, so interleaved sessions in the same log file can be told apart.
+pub fn session_id() -> String {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.subsec_nanos())
+        .unwrap_or_default();
+    format!("{:x}-{:08x}", std::process::id(), nanos)
+}
+
+/// a log file that is rotated by size, keeping the last `KEEP_LOG_FILES` rotations.
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    written: u64,
+}
+
+impl RotatingFile {
+    fn open(path: &Path) -> std::io::Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let written = file.metadata()?.len();
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            written,
+        })
+    }
+
+    fn rotated(&self, n: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", n));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&mut self) -> std::io::Result<()> {
+        for n in (1..KEEP_LOG_FILES).rev() {
+            let from = self.rotated(n);
+            if from.exists() {
+                std::fs::rename(&from, self.rotated(n + 1))?;
+            }
+        }
+        std::fs::rename(&self.path, self.rotated(1))?;
+
+        *self = Self::open(&self.path)?;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.written > 0 && self.written + buf.len() as u64 > MAX_LOG_FILE_BYTES {
+            self.rotate()?;
+        }
+        let n = self.file.write(buf)?;
+        self.written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
@@ -1,270 +1,4200 @@
 use anyhow::anyhow;
+use bollard::auth::DockerCredentials;
 use bollard::container::{
-    Config, CreateContainerOptions, ListContainersOptions, LogOutput, StartContainerOptions,
+    Config, CreateContainerOptions, LogOutput, RemoveContainerOptions, StartContainerOptions,
+    StopContainerOptions,
 };
 use bollard::exec::{CreateExecOptions, StartExecOptions, StartExecResults};
-use bollard::image::CreateImageOptions;
-use bollard::secret::{ErrorDetail, HostConfig};
+use bollard::network::ListNetworksOptions;
+use bollard::secret::{
+    DeviceRequest, ExecInspectResponse, HostConfig, HostConfigIsolationEnum, ImageInspect, Mount,
+    MountTypeEnum, MountVolumeOptions,
+};
 use bollard::Docker;
-use clap::Parser;
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use clap_complete::{ArgValueCompleter, CompleteEnv};
 use futures::StreamExt;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tracing::Instrument;
+
+use quarantine::{
+    audit, build, cache, compose, config, copy, detect, devcontainer, dry_run, engine, env_file,
+    exec_output, exec_status, hooks, load, logging, nested, parse_size, platform, pool, pull, save,
+    session, session_log, signature, snapshot, socket, terminal, trace, volumes_from, wait_for,
+    watch, ENGINE_SOCKET_LABEL, HOST_NAMESPACES_LABEL, MANAGED_LABEL,
+};
+
+mod completion;
+mod doctor;
+mod manage;
+mod self_test;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    tracing_subscriber::fmt::init();
+    CompleteEnv::with_factory(Quarantine::command).complete();
 
+    let quarantine = Quarantine::parse();
+    if let Err(e) = quarantine.check() {
+        e.exit();
+    }
     let Quarantine {
+        command,
+        log_file,
+        color,
+        trace_docker,
+        docker_host,
+        run: run_args,
+    } = quarantine;
+    if let Some(docker_host) = docker_host {
+        engine::set_host(docker_host);
+    }
+
+    let log_file = match (log_file, trace_docker) {
+        (Some(path), _) => Some(path.unwrap_or_else(logging::default_log_file)),
+        (None, true) => Some(logging::default_log_file()),
+        (None, false) => None,
+    };
+    logging::init(log_file.as_deref(), color, trace_docker)?;
+    if trace_docker {
+        trace::init()?;
+        if let Some(log_file) = &log_file {
+            tracing::info!("tracing docker api requests to `{}`", log_file.display());
+        }
+    }
+
+    match (command, run_args) {
+        (Some(Command::Doctor), _) => doctor::doctor(log_file.as_deref()).await,
+        (Some(Command::SelfTest), _) => self_test::self_test().await,
+        (Some(Command::Warm { image_name, count }), _) => warm(image_name, count).await,
+        (Some(Command::Ls { pool, caches }), _) => manage::ls(pool, caches).await,
+        (
+            Some(Command::Save {
+                image_name,
+                output,
+                gzip,
+                force,
+            }),
+            _,
+        ) => save::save(&image_name, &output, gzip, force).await,
+        (Some(Command::Audit { action, query }), _) => match action {
+            Some(AuditAction::Verify) => audit_verify(),
+            None => audit_query(query),
+        },
+        (
+            Some(Command::Clean {
+                pool,
+                dangling_volumes,
+                caches,
+                container,
+                force,
+            }),
+            _,
+        ) => manage::clean(pool, dangling_volumes, caches, container, force).await,
+        (Some(Command::Kill { name }), _) => manage::kill(&name).await,
+        (Some(Command::Prune { force }), _) => manage::prune(force).await,
+        (Some(Command::Run(args)), _) => session(*args).await,
+        (None, Some(args)) => session(args).await,
+        // no arguments at all, the config may name the image
+        (None, None) => match Quarantine::parse_from(["quarantine", "run"]).command {
+            Some(Command::Run(args)) => session(*args).await,
+            _ => unreachable!("`run` always parses to the run command"),
+        },
+    }
+}
+
+async fn session(args: RunArgs) -> anyhow::Result<()> {
+    let span = tracing::info_span!(
+        "session",
+        id = %logging::session_id(),
+        container = tracing::field::Empty
+    );
+    run(args).instrument(span).await
+}
+
+async fn warm(image_name: String, count: usize) -> anyhow::Result<()> {
+    // resolve the config exactly like `quarantine -i IMAGE` would, so sessions can claim the members
+    let Some(args) = Quarantine::parse_from(["quarantine", "--image-name", &image_name]).run else {
+        unreachable!("the run arguments are always present when no subcommand is given")
+    };
+    let Plan {
+        docker,
+        container_name,
+        config,
         image_name,
-        runtime,
-    } = Quarantine::parse();
+        daemon_arch,
+        docker_desktop,
+        pull,
+        platform,
+        ..
+    } = plan(args).await?;
+    prepare_image(
+        &docker,
+        &image_name,
+        pull.as_ref(),
+        platform.as_deref(),
+        daemon_arch.as_deref(),
+        docker_desktop,
+    )
+    .await?;
 
-    let docker = Docker::connect_with_local_defaults()?;
-    let info = docker.info().await?;
-    let default_runtime = info.default_runtime.unwrap_or_default();
-    let available_runtimes = info.runtimes.unwrap_or_default();
+    let created = pool::warm(&docker, config, &container_name, count).await?;
+    tracing::info!("{} container(s) added to the pool", created.len());
+    Ok(())
+}
+
+/// everything `run` resolved before creating the container.
+struct Plan {
+    docker: Docker,
+    container_name: String,
+    config: Config<String>,
+    env: Vec<String>,
+    image_name: String,
+    /// the `--platform` of the image and the container
+    platform: Option<String>,
+    daemon_arch: Option<String>,
+    windows: bool,
+    docker_desktop: bool,
+    host_gateway: bool,
+    privileged: bool,
+    exposes_engine_socket: bool,
+    wait_for: Vec<wait_for::Probe>,
+    wait_for_timeout: std::time::Duration,
+    /// see `--timeout`
+    timeout: Option<std::time::Duration>,
+    on_start: Vec<String>,
+    on_stop: Vec<String>,
+    force_cleanup: bool,
+    /// leave the container running at the end of the session (or on detach) and print its name
+    persist: bool,
+    /// recreate a kept container instead of entering it again
+    fresh: bool,
+    /// the command to rerun on changes
+    watch: Option<Vec<String>>,
+    /// the command to run instead of the shell
+    command: Option<Vec<String>>,
+    /// the interactive shell and its arguments. when unset, `bash` or else `sh` (powershell or else cmd.exe
+    /// on windows), whichever the image has
+    shell: Option<Vec<String>>,
+    /// where and how the output of the session is recorded
+    session_log: Option<(PathBuf, session_log::Format)>,
+    /// the image the container is committed to at the end of the session
+    snapshot: Option<String>,
+    /// `--after-commit`: where the snapshot is saved to as an archive
+    after_commit: Option<PathBuf>,
+    /// `--copy-in`: the working directory is copied in instead of mounted
+    copy: Option<copy::Options>,
+    /// the `--cache` volumes, created before the container
+    caches: Vec<cache::Cache>,
+    /// the missing `--volume` sources that `--create-missing` would create, left alone by `--dry-run`
+    would_create: Vec<PathBuf>,
+    /// the keys that detach from the shell
+    detach_keys: terminal::DetachKeys,
+    /// the flags that undo the hardened defaults in effect, suggested when the container doesn't start
+    opt_outs: Vec<&'static str>,
+    /// the ledger sessions are recorded in
+    audit: Option<PathBuf>,
+    /// the namespaces shared with the host
+    host_namespaces: Vec<&'static str>,
+    /// who the shell runs as, the image's user when unset
+    user: Option<String>,
+    /// the `--with-deps` services
+    sidecars: Vec<compose::Sidecar>,
+    /// `--offline`: the network shared with the sidecars doesn't reach outside either
+    offline: bool,
+    /// how images are pulled. `None` with `--offline`, `--pull never` and `--load`, the local ones are used then
+    pull: Option<pull::Options>,
+    /// the signature the image must have
+    verify: Option<signature::Policy>,
+}
+
+/// the `requested` runtime when the daemon has it, else the daemon's default.
+fn choose_runtime<'a>(
+    requested: Option<String>,
+    default: String,
+    available: impl Iterator<Item = &'a String>,
+) -> String {
+    let Some(requested) = requested else {
+        tracing::info!("using default runtime `{}`", default);
+        return default;
+    };
+    let available = available.collect::<Vec<&String>>();
+    if available.contains(&&requested) {
+        tracing::info!("using runtime `{}`", requested);
+        return requested;
+    }
+    tracing::warn!(
+        "runtime `{}` not found! reverting to the default `{}`",
+        requested,
+        default
+    );
+    tracing::warn!(
+        "available runtimes are {}",
+        available
+            .iter()
+            .map(|runtime| format!("`{}`", runtime))
+            .collect::<Vec<String>>()
+            .join(" ")
+    );
+    default
+}
 
-    let runtime = match runtime {
-        Some(runtime) => match available_runtimes.contains_key(&runtime) {
-            true => {
-                tracing::info!("using runtime `{}`", runtime);
-                runtime
+/// validates the arguments, connects to the daemon and resolves the config of the container
+/// without pulling or creating anything.
+async fn plan(args: RunArgs) -> anyhow::Result<Plan> {
+    let RunArgs {
+        image_name,
+        envs,
+        env_file,
+        runtime,
+        memory,
+        memory_reservation,
+        memory_swap,
+        cpus,
+        pids_limit,
+        host_gateway,
+        host_port,
+        publish,
+        publish_all,
+        userns,
+        user_map,
+        as_root,
+        run_as,
+        group_add,
+        gpus,
+        cap_drop,
+        cap_add,
+        hardened,
+        writable_rootfs,
+        allow_privilege_escalation,
+        security_opt,
+        tmpfs,
+        nested,
+        no_resolve_symlinks,
+        isolation,
+        privileged,
+        privileged_i_accept_the_risk,
+        dangerously_expose_engine_socket,
+        dry_run,
+        no_mount,
+        no_default_mount,
+        copy_in,
+        remote,
+        copy_out,
+        copy_out_dir,
+        exclude,
+        cache,
+        cache_dir,
+        volumes,
+        create_missing,
+        read_only,
+        volumes_from,
+        wait_for,
+        wait_for_timeout,
+        timeout,
+        show_config: _,
+        no_config,
+        force_cleanup,
+        persist,
+        fresh,
+        name,
+        watch,
+        command,
+        share_net_with,
+        network,
+        shell,
+        session_log,
+        record,
+        record_format,
+        snapshot,
+        after_commit,
+        detach_keys,
+        ipc,
+        uts,
+        auto: _,
+        yes: _,
+        devcontainer,
+        compose_service,
+        compose_file,
+        with_deps,
+        load,
+        load_tag: _,
+        dockerfile,
+        build,
+        build_arg: _,
+        offline,
+        pull: pull_policy,
+        platform,
+        no_pull,
+        registry_user,
+        registry_password_stdin: _,
+        verify_signature,
+        cosign_key,
+        certificate_identity,
+        certificate_oidc_issuer,
+        pull_retries,
+        registry_mirror,
+        pull_timeout,
+        pull_stall_timeout,
+    } = args;
+    let working_dir = std::env::current_dir()?;
+    let mut targets = match no_mount || no_default_mount {
+        true => vec![],
+        false => vec![PROJECT_MOUNT],
+    };
+    for volume in &volumes {
+        if targets.contains(&volume.container.as_str()) {
+            return Err(anyhow!(
+                "`{}` is mounted more than once. each --volume needs its own container path{}",
+                volume.container,
+                match volume.container == PROJECT_MOUNT {
+                    true => ", the working directory is mounted there (pass --no-default-mount to leave it out)",
+                    false => "",
+                }
+            ));
+        }
+        targets.push(&volume.container);
+    }
+    let volume_targets = volumes
+        .iter()
+        .map(|volume| volume.container.clone())
+        .collect::<Vec<String>>();
+    let mut would_create = vec![];
+    let volumes = volumes
+        .iter()
+        .map(|volume| {
+            if volume.ensure_host(create_missing, dry_run)? {
+                would_create.push(volume.host.clone());
             }
-            false => {
-                tracing::warn!(
-                    "runtime `{}` not found! reverting to the default `{}`",
-                    runtime,
-                    default_runtime
-                );
-                tracing::warn!(
-                    "available runtimes are {}",
-                    available_runtimes
-                        .keys()
-                        .into_iter()
-                        .map(|key| format!("`{}`", key))
-                        .collect::<Vec<String>>()
-                        .join(" ")
-                );
-                default_runtime
+            volume.bind()
+        })
+        .collect::<anyhow::Result<Vec<String>>>()?;
+    let devcontainer = match devcontainer {
+        true => {
+            let path = devcontainer::find(&working_dir).ok_or_else(|| {
+                anyhow!("no devcontainer.json found in `{}`", working_dir.display())
+            })?;
+            tracing::info!("reading `{}`", path.display());
+            Some(devcontainer::load(&path)?)
+        }
+        false => {
+            if let Some(path) = devcontainer::find(&working_dir) {
+                tracing::info!("found `{}`, pass --devcontainer to use it", path.display());
             }
-        },
-        None => {
-            tracing::info!("using default runtime `{}`", default_runtime);
-            default_runtime
+            None
+        }
+    };
+    let compose = match &compose_service {
+        Some(service) => {
+            let path = compose::find(&working_dir, compose_file.as_deref())?;
+            tracing::info!("reading the service `{}` of `{}`", service, path.display());
+            Some(compose::load(&path, service, with_deps)?)
         }
+        None => None,
     };
+    let image_name = image_name
+        .or_else(|| devcontainer.as_ref().and_then(|dc| dc.image.clone()))
+        .or_else(|| {
+            compose
+                .as_ref()
+                .and_then(|compose| compose.service.image.clone())
+        })
+        .map(Ok)
+        .or_else(|| match no_config {
+            true => None,
+            false => config::image(&working_dir, &working_dir).transpose(),
+        })
+        .transpose()?
+        .ok_or_else(|| no_image(&working_dir, no_config))?;
+    // the command line wins over devcontainer.json
+    let memory = memory.or(devcontainer.as_ref().and_then(|dc| dc.memory));
+    let host_gateway = host_gateway || host_port.is_some();
 
-    // pull image
-    {
-        let mut stream = docker.create_image(
-            Some(CreateImageOptions {
-                from_image: image_name.as_str(),
-                ..Default::default()
-            }),
-            None,
-            None,
-        );
+    check_memory(memory, memory_swap, memory_reservation)?;
 
-        tracing::info!("pulling image: {}", image_name);
-        while let Some(Ok(pull_result)) = stream.next().await {
-            if let Some(error) = pull_result.error {
-                tracing::error!("{}", error);
-                if let Some(ErrorDetail {
-                    code: Some(code),
-                    message: Some(message),
-                }) = pull_result.error_detail
-                {
-                    tracing::error!("{} :: {}", code, message);
-                }
-            } else {
-                tracing::info!(
-                    "{} {} {}",
-                    pull_result.id.unwrap_or_default(),
-                    pull_result.status.unwrap_or_default(),
-                    pull_result.progress.unwrap_or_default(),
-                );
+    let group_add = group_add
+        .into_iter()
+        .map(|group| resolve_group(&group))
+        .collect::<Vec<String>>();
+
+    // sharing them with the host undoes the isolation the hardened defaults are for
+    for (host, flag) in [
+        (ipc.as_deref() == Some("host"), "--ipc host"),
+        (uts == Some(Uts::Host), "--uts host"),
+    ] {
+        if host && !privileged {
+            return Err(anyhow!(
+                "`{}` is refused by the hardened sandbox. pass --privileged to turn the hardening off",
+                flag
+            ));
+        }
+    }
+    if privileged && !privileged_i_accept_the_risk {
+        confirm_privileged()?;
+    }
+
+    let docker = engine::connect().await?;
+    if host_gateway {
+        // the `host-gateway` magic value in extra_hosts was added in docker 20.10
+        engine::require_api_version(&docker, 1, 41, "--host-gateway")?;
+    }
+    let info = docker.info().await?;
+    if let (Some(cpus), Some(available)) = (cpus, info.ncpu) {
+        if cpus > available as f64 {
+            return Err(anyhow!(
+                "--cpus {} is more than the {} cpus the daemon has",
+                cpus,
+                available
+            ));
+        }
+    }
+    let docker_desktop = info
+        .operating_system
+        .as_deref()
+        .is_some_and(|os| os.contains("Docker Desktop"));
+    let windows = info.os_type.as_deref() == Some("windows");
+    let project_mount = match windows {
+        true => WINDOWS_PROJECT_MOUNT,
+        false => PROJECT_MOUNT,
+    };
+    if windows {
+        tracing::info!("the daemon runs windows containers");
+        for (set, flag) in [
+            (userns.is_some(), "--userns"),
+            (!group_add.is_empty(), "--group-add"),
+            (gpus.is_some(), "--gpus"),
+            (
+                !(cap_drop.is_empty() && cap_add.is_empty()),
+                "--cap-drop/--cap-add",
+            ),
+            (memory_reservation.is_some(), "--memory-reservation"),
+            (memory_swap.is_some(), "--memory-swap"),
+            (pids_limit.is_some(), "--pids-limit"),
+            (!tmpfs.is_empty(), "--tmpfs"),
+            (!security_opt.is_empty(), "--security-opt"),
+            (host_gateway, "--host-gateway"),
+            (privileged, "--privileged"),
+            (hardened, "--hardened"),
+            (!wait_for.is_empty(), "--wait-for"),
+            (watch, "--watch"),
+            (ipc.is_some(), "--ipc"),
+            (uts.is_some(), "--uts"),
+        ] {
+            if set {
+                return Err(anyhow!(
+                    "`{}` is only supported for linux containers, but the daemon runs windows containers",
+                    flag
+                ));
             }
         }
+    } else if isolation.is_some() {
+        return Err(anyhow!(
+            "`--isolation` is only supported for windows containers"
+        ));
     }
 
-    let container_name = format!("quarantine-{}", image_name.replace(":", "-"));
+    let podman = docker
+        .version()
+        .await?
+        .components
+        .unwrap_or_default()
+        .iter()
+        .any(|component| component.name.contains("Podman"));
+    let rootless = info
+        .security_options
+        .as_deref()
+        .unwrap_or_default()
+        .iter()
+        .any(|option| option.contains("name=rootless"));
+    tracing::info!(
+        "connected to {} {}",
+        if podman { "podman" } else { "docker" },
+        if rootless { "(rootless)" } else { "(rootful)" }
+    );
 
-    // stop and remove any previously running containers
-    {
-        let list_containers_options: ListContainersOptions<String> = ListContainersOptions {
-            all: true,
-            ..Default::default()
-        };
+    let userns_mode = match userns {
+        None => None,
+        Some(Userns::Host) => {
+            if rootless {
+                tracing::warn!("the engine is rootless. `--userns host` still runs inside the engine's own user namespace");
+            }
+            Some("host".to_string())
+        }
+        Some(mode @ (Userns::KeepId | Userns::Auto)) if !podman => {
+            return Err(anyhow!(
+                "`--userns {}` is only supported by podman. docker only supports `--userns host`",
+                mode.to_possible_value()
+                    .map(|value| value.get_name().to_string())
+                    .unwrap_or_default()
+            ));
+        }
+        // podman maps the invoking user to the same uid/gid inside the container, so files written
+        // to the bind mount stay owned by the host user
+        Some(Userns::KeepId) => Some("keep-id".to_string()),
+        Some(Userns::Auto) => Some("auto".to_string()),
+    };
 
-        let containers = docker
-            .list_containers(Some(list_containers_options))
-            .await?;
+    let shared = volumes_from::resolve(&docker, &volumes_from).await?;
 
+    if let Some(target) = &share_net_with {
+        let running = docker
+            .inspect_container(target, None)
+            .await
+            .map_err(|e| {
+                anyhow!(
+                    "unable to find the --share-net-with container `{}`: {}",
+                    target,
+                    e
+                )
+            })?
+            .state
+            .and_then(|state| state.running)
+            .unwrap_or_default();
+        if !running {
+            return Err(anyhow!(
+                "the --share-net-with container `{}` is not running",
+                target
+            ));
+        }
         tracing::info!(
-            "checking for any previously running containers with the name: {}",
-            container_name
+            "sharing the network of `{}`. `localhost` inside the sandbox is `{}`",
+            target,
+            target
         );
-        for container in containers {
-            for name in container.names.unwrap_or_default() {
-                if name.trim_start_matches("/") == container_name {
-                    if let Some(state) = &container.state {
-                        if state.to_lowercase() == "running" {
-                            tracing::info!("stopping running container: {}", &container_name);
-                            docker.stop_container(&container_name, None).await?;
-                        }
-                        tracing::info!("removing container: {}", &container_name);
-                        docker.remove_container(&container_name, None).await?;
-                    }
+    }
+
+    // a remote daemon would mount the path of its own machine, which is empty or something else
+    let remote = remote || engine::is_remote();
+    let copy_in = match remote && !copy_in && !no_mount && !no_default_mount {
+        true if watch => {
+            return Err(anyhow!(
+                "the daemon is remote, it can't mount the working directory that --watch needs. pass --no-mount to run without it"
+            ))
+        }
+        true => {
+            tracing::warn!(
+                "the daemon is remote, the working directory is copied into the container instead of mounted. \
+                 pass --copy-in --copy-out to copy the changes back"
+            );
+            true
+        }
+        false => copy_in,
+    };
+    if remote && !volume_targets.is_empty() {
+        tracing::warn!("the daemon is remote, the host paths of --volume are paths on its machine");
+    }
+    let project_dir = match (no_mount, no_default_mount) {
+        (true, _) => {
+            tracing::info!("no host files are mounted");
+            None
+        }
+        (_, true) => {
+            tracing::info!("the working directory is not mounted");
+            None
+        }
+        // read by quarantine itself, the daemon never sees the path
+        (false, false) if copy_in => {
+            if windows {
+                return Err(anyhow!("--copy-in is not supported for windows containers"));
+            }
+            Some(project_dir(!no_resolve_symlinks)?)
+        }
+        (false, false) => Some(mounted_project_dir(&docker, !no_resolve_symlinks, nested).await?),
+    };
+    let copy = match (copy_in, &project_dir) {
+        (true, Some(dir)) => {
+            tracing::info!("the working directory is copied into the container, not mounted");
+            Some(copy::Options {
+                dir: dir.clone(),
+                exclude,
+                out: copy_out.then(|| copy_out_dir.unwrap_or_else(|| dir.clone())),
+            })
+        }
+        _ => None,
+    };
+    if read_only && copy.is_none() && project_dir.is_some() {
+        tracing::info!("the working directory is mounted read-only");
+    }
+    if hardened && !read_only && copy.is_none() && project_dir.is_some() {
+        tracing::warn!(
+            "--hardened keeps the root filesystem read-only, but the working directory is still mounted writable. \
+             pass --read-only to protect it too"
+        );
+    }
+
+    let mut file_config = match no_config {
+        true => {
+            tracing::info!("--no-config: ignoring the config files");
+            config::FileConfig::default()
+        }
+        false => {
+            config::load(
+                &working_dir,
+                project_dir.as_deref().unwrap_or(&working_dir),
+                &image_name,
+            )?
+            .config
+        }
+    };
+    let persist =
+        persist || (!force_cleanup && !with_deps && file_config.persist.unwrap_or_default());
+    let user = match devcontainer {
+        Some(devcontainer) => {
+            file_config.merge(devcontainer.config);
+            devcontainer.user
+        }
+        None => None,
+    };
+    let mapped_user = match run_as {
+        Some(run_as) => {
+            tracing::info!("running as {}", run_as);
+            Some(run_as)
+        }
+        None if userns.is_some() || !wants_user_map(user_map, as_root, file_config.user_map) => {
+            None
+        }
+        None => match mapped_user(host_ids(), windows, docker_desktop, rootless) {
+            Ok(mapped) => {
+                tracing::info!(
+                    "running as the host user {}, pass --as-root to run as root",
+                    mapped
+                );
+                Some(mapped)
+            }
+            // only worth mentioning when it was asked for
+            Err(reason) if user_map => {
+                tracing::info!("{}, ignoring --user-map", reason);
+                None
+            }
+            Err(reason) => {
+                tracing::debug!("{}, running as the image's user", reason);
+                None
+            }
+        },
+    };
+    if let (Some(mapped), Some(user)) = (&mapped_user, &user) {
+        tracing::info!("running the shell as {} instead of `{}`", mapped, user);
+    }
+    let user = mapped_user.clone().or(user);
+    let (mut ports, dependencies) = match compose {
+        Some(compose) => {
+            file_config.merge(compose.service.config);
+            (compose.service.ports, compose.dependencies)
+        }
+        None => (vec![], vec![]),
+    };
+    ports.extend(publish);
+    let mut published = std::collections::HashSet::new();
+    for port in &ports {
+        let protocol = port
+            .container_port
+            .split_once('/')
+            .map(|(_, protocol)| protocol);
+        // 0 picks a free port, any number of times
+        if let Some(host_port) = port
+            .host_port
+            .as_deref()
+            .filter(|host_port| *host_port != "0")
+        {
+            if !published.insert((port.host_ip.clone(), host_port, protocol)) {
+                return Err(anyhow!(
+                    "the host port {} is published more than once",
+                    host_port
+                ));
+            }
+        }
+    }
+    let shm_size = file_config
+        .shm_size
+        .as_deref()
+        .map(parse_size)
+        .transpose()?;
+    if windows && !(file_config.on_start.is_empty() && file_config.on_stop.is_empty()) {
+        return Err(anyhow!(
+            "`on_start` and `on_stop` hooks are only supported for linux containers"
+        ));
+    }
+    let (on_start, on_stop) = (file_config.on_start, file_config.on_stop);
+    let audit = audit::ledger(file_config.audit.as_ref());
+    // a loaded or built image may not exist in any registry
+    let no_registry = offline || no_pull || pull_policy == PullPolicy::Never;
+    let local = load.is_some() || dockerfile.is_some() || build.is_some();
+    let pull = (!no_registry && !local).then(|| {
+        let mut mirrors = std::mem::take(&mut file_config.registry_mirror_map);
+        mirrors.entry("docker.io".to_string()).or_default().splice(
+            0..0,
+            registry_mirror
+                .into_iter()
+                .chain(std::mem::take(&mut file_config.registry_mirrors)),
+        );
+        mirrors.retain(|_, mirrors| !mirrors.is_empty());
+        pull::Options {
+            retries: pull_retries,
+            mirrors,
+            timeout: pull_timeout,
+            stall_timeout: pull_stall_timeout,
+            always: pull_policy == PullPolicy::Always,
+            credentials: BTreeMap::new(),
+            platform: platform.clone(),
+        }
+    });
+    let pull = match (pull, registry_user) {
+        (Some(mut pull), Some(username)) => {
+            let registry = pull::Reference::parse(&image_name).registry;
+            pull.credentials
+                .insert(registry.clone(), registry_login(username, &registry)?);
+            Some(pull)
+        }
+        (pull, _) => pull,
+    };
+    let verify = signature::policy(
+        verify_signature.then_some(config::Verify {
+            key: cosign_key,
+            certificate_identity,
+            certificate_oidc_issuer,
+        }),
+        file_config.verify.as_ref(),
+    )?;
+    if verify.is_some() && pull.is_none() {
+        return Err(anyhow!(
+            "the signature of `{}` has to be verified against its registry, which --offline, --pull never, --load and --dockerfile don't contact",
+            image_name
+        ));
+    }
+    let binds = match no_mount {
+        true => {
+            if !file_config.mounts.is_empty() {
+                tracing::info!("ignoring the mounts from the config because of --no-mount");
+            }
+            vec![]
+        }
+        false => file_config.mounts.into_iter().chain(volumes).collect(),
+    };
+
+    let available_runtimes = info.runtimes.unwrap_or_default();
+    let runtime = choose_runtime(
+        runtime.or(file_config.runtime.take()),
+        info.default_runtime.unwrap_or_default(),
+        available_runtimes.keys(),
+    );
+    if gpus.is_some() && runtime.contains("runsc") {
+        tracing::warn!(
+            "gpus usually don't work with the `{}` runtime, it needs its nvproxy enabled",
+            runtime
+        );
+    }
+
+    // the config's default gives way to the other ways of choosing a network
+    let network = match (network, file_config.network.take()) {
+        (Some(network), _) => Some(network),
+        // the dependencies share their own network, it is cut off in `compose::up`
+        (None, _) if offline && dependencies.is_empty() => Some("none".to_string()),
+        (None, Some(network)) if share_net_with.is_none() && dependencies.is_empty() => {
+            Some(parse_network(&network).map_err(|e| anyhow!("`network` in the config: {}", e))?)
+        }
+        (None, _) => None,
+    };
+    let network = match network {
+        Some(network) if !matches!(network.as_str(), "none" | "bridge" | "host") => {
+            let networks = docker
+                .list_networks(Some(ListNetworksOptions {
+                    filters: HashMap::from([("name", vec![network.as_str()])]),
+                }))
+                .await?;
+            // the name filter matches substrings too
+            match networks.iter().any(|candidate| {
+                candidate.name.as_ref() == Some(&network) || candidate.id.as_ref() == Some(&network)
+            }) {
+                true => {
+                    tracing::info!("using network `{}`", network);
+                    Some(network)
+                }
+                false => {
+                    tracing::warn!(
+                        "network `{}` not found! reverting to the default network",
+                        network
+                    );
+                    None
                 }
             }
         }
+        network => network,
+    };
+    if offline || network.as_deref() == Some("none") {
+        if publish_all {
+            return Err(anyhow!(
+                "ports can't be published without a network, `--publish-all` needs one"
+            ));
+        }
+        if !ports.is_empty() {
+            return Err(anyhow!(
+                "ports can't be published without a network. {} would be published, but the sandbox has none",
+                ports
+                    .iter()
+                    .map(|port| port.container_port.as_str())
+                    .collect::<Vec<&str>>()
+                    .join(", ")
+            ));
+        }
+        if host_gateway {
+            return Err(anyhow!(
+                "the host can't be reached without a network, `--host-gateway` and `--host-port` need one"
+            ));
+        }
+        tracing::info!("the sandbox has no network access, downloads like `pip install` will fail");
     }
 
-    // start container
+    let container_name = match name {
+        Some(name) => name,
+        None => {
+            let base = session::default_name(&image_name, Some(&working_dir));
+            session::free_name(&docker, &base).await?
+        }
+    };
+    tracing::Span::current().record("container", container_name.as_str());
+    let sidecars = dependencies
+        .into_iter()
+        .map(|(name, service)| compose::sidecar(&container_name, &name, service))
+        .collect::<anyhow::Result<Vec<compose::Sidecar>>>()?;
+    let (exposed_ports, port_bindings) = compose::publish(&ports);
+    for port in &ports {
+        tracing::info!(
+            "publishing {} on {}:{}",
+            port.container_port,
+            port.host_ip.as_deref().unwrap_or("0.0.0.0"),
+            port.host_port.as_deref().unwrap_or("<random>")
+        );
+    }
+    let mut caches = vec![];
+    let mut env = marker_env(&image_name, project_dir.as_ref().map(|_| project_mount));
+    for tool in &cache {
+        let project = project_dir.as_deref().map(|dir| (dir, project_mount));
+        let (volumes, variables) = cache::preset(tool, project);
+        caches.extend(volumes);
+        env.extend(variables);
+    }
+    caches.extend(cache_dir);
+    if windows && !caches.is_empty() {
+        return Err(anyhow!(
+            "--cache and --cache-dir are not supported for windows containers"
+        ));
+    }
+    let mut targets = project_dir
+        .iter()
+        .map(|_| project_mount.to_string())
+        .chain(volume_targets.iter().cloned())
+        .collect::<Vec<String>>();
+    for cache in &caches {
+        if targets.contains(&cache.target) {
+            return Err(anyhow!(
+                "`{}` is mounted more than once, the cache `{}` can't be mounted there",
+                cache.target,
+                cache.volume
+            ));
+        }
+        targets.push(cache.target.clone());
+    }
+    // a numeric user usually has no entry, and so no home, in the image
+    if mapped_user
+        .as_deref()
+        .is_some_and(|user| user.starts_with(|c: char| c.is_ascii_digit()))
     {
-        let options = Some(CreateContainerOptions {
-            name: container_name.as_str(),
-            ..Default::default()
-        });
-
-        let mut volumes = HashMap::new();
-        volumes.insert("/quarantine".to_string(), HashMap::new());
+        env.push("HOME=/tmp".to_string());
+    }
+    if let Some(host_port) = host_port {
+        env.push(format!(
+            "QUARANTINE_HOST_URL=http://{}:{}",
+            HOST_GATEWAY_NAME, host_port
+        ));
+    }
+    env.extend(
+        file_config
+            .env
+            .into_iter()
+            .map(|(key, value)| format!("{}={}", key, value)),
+    );
+    // the last occurrence of a variable wins
+    let envs = env_file::merge(&env_file, &envs)?;
+    for entry in &envs {
+        tracing::debug!("env from the command line: {}", entry);
+    }
+    env.extend(envs);
 
-        let current_dir = std::env::current_dir()?
-            .into_os_string()
-            .into_string()
-            .map_err(|_| anyhow!("current working directory path is not valid unicode"))?;
+    let mut volumes = HashMap::new();
+    if project_dir.is_some() {
+        volumes.insert(project_mount.to_string(), HashMap::new());
+    }
+    for target in volume_targets {
+        volumes.insert(target, HashMap::new());
+    }
 
-        let host_config = HostConfig {
-            runtime: Some(runtime),
-            binds: Some(vec![format!("{}:/quarantine", current_dir)]),
-            ..Default::default()
-        };
+    // swap counts against the limit, unless it was raised explicitly. windows containers have no swap setting
+    let memory_swap = memory_swap.or(memory.filter(|_| !windows));
+    if let Some(memory) = memory {
+        tracing::info!("memory limit: {} bytes", memory);
+    }
+    match memory_swap {
+        Some(-1) => tracing::info!("swap: unlimited"),
+        Some(memory_swap) if Some(memory_swap) == memory => tracing::info!("swap: disabled"),
+        Some(memory_swap) => tracing::info!("memory and swap limit: {} bytes", memory_swap),
+        None => {}
+    }
+    if let Some(memory_reservation) = memory_reservation {
+        tracing::info!("memory reservation: {} bytes", memory_reservation);
+    }
+    if let Some(cpus) = cpus {
+        tracing::info!("cpu limit: {}", cpus);
+    }
+    if let Some(pids_limit) = pids_limit {
+        tracing::info!("process limit: {}", pids_limit);
+    }
 
-        let config = Config {
-            image: Some(image_name),
-            tty: Some(true),
-            working_dir: Some("/quarantine".into()),
-            volumes: Some(volumes),
-            host_config: Some(host_config),
+    let base = Config {
+        // without a mount, the image's own working directory is used
+        working_dir: project_dir.as_ref().map(|_| project_mount.into()),
+        env: Some(env.clone()),
+        volumes: Some(volumes),
+        exposed_ports: (!exposed_ports.is_empty()).then_some(exposed_ports),
+        networking_config: compose_service
+            .filter(|_| !sidecars.is_empty())
+            .map(|service| compose::networking_config(&container_name, &service)),
+        host_config: Some(HostConfig {
+            mounts: Some(caches.iter().map(|cache| cache.mount()).collect()),
+            binds: (!binds.is_empty()).then_some(binds),
+            memory,
+            memory_reservation,
+            memory_swap,
+            // some hosts disable it, a container at its limit would hang instead of having a process killed
+            oom_kill_disable: memory.map(|_| false),
+            nano_cpus: cpus.map(|cpus| (cpus * 1e9) as i64),
+            pids_limit,
+            shm_size,
+            // docker desktop resolves `host.docker.internal` on its own. plain linux daemons need the mapping
+            extra_hosts: (host_gateway && !docker_desktop)
+                .then(|| vec![format!("{}:host-gateway", HOST_GATEWAY_NAME)]),
+            userns_mode,
+            group_add: (!group_add.is_empty()).then_some(group_add),
+            device_requests: gpus.map(|gpus| vec![gpus]),
+            isolation: isolation.map(|isolation| match isolation {
+                Isolation::Process => HostConfigIsolationEnum::PROCESS,
+                Isolation::Hyperv => HostConfigIsolationEnum::HYPERV,
+            }),
+            volumes_from: (!volumes_from.is_empty()).then_some(volumes_from),
+            network_mode: match sidecars.is_empty() {
+                true => share_net_with
+                    .map(|target| format!("container:{}", target))
+                    .or_else(|| network.clone()),
+                false => Some(container_name.clone()),
+            },
+            port_bindings: (!port_bindings.is_empty()).then_some(port_bindings),
+            publish_all_ports: publish_all.then_some(true),
+            ipc_mode: ipc.clone(),
+            uts_mode: uts.map(|uts| match uts {
+                Uts::Private => String::new(),
+                Uts::Host => "host".to_string(),
+            }),
             ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let explicit_cap_drop = !cap_drop.is_empty();
+    let explicit_no_new_privileges = security_opt
+        .iter()
+        .any(|option| option.starts_with("no-new-privileges"));
+    let mut sandbox = quarantine::Session::builder()
+        .image(&image_name)
+        .runtime(runtime)
+        .base(base)
+        // windows containers have none of the hardened defaults
+        .hardened(!windows)
+        .privileged(privileged)
+        // root needs these to write to the project, the files are owned by the host user
+        .file_capabilities(
+            !hardened && project_dir.is_some() && !read_only && mapped_user.is_none(),
+        )
+        .writable_rootfs(writable_rootfs)
+        .allow_privilege_escalation(allow_privilege_escalation)
+        .dangerously_expose_engine_socket(dangerously_expose_engine_socket);
+    if let Some(user) = &mapped_user {
+        sandbox = sandbox.user(user);
+    }
+    if let Some(project_dir) = project_dir.as_ref().filter(|_| copy.is_none()) {
+        sandbox = match read_only {
+            true => sandbox.mount_read_only(project_dir, project_mount),
+            false => sandbox.mount(project_dir, project_mount),
         };
+    }
+    for cap in cap_drop {
+        sandbox = sandbox.cap_drop(cap);
+    }
+    for cap in cap_add {
+        sandbox = sandbox.cap_add(cap);
+    }
+    for option in security_opt {
+        sandbox = sandbox.security_opt(option);
+    }
+    for (path, options) in tmpfs {
+        sandbox = sandbox.tmpfs(path, options);
+    }
+    let mut config = sandbox.config().map_err(|e| match e {
+        quarantine::Error::EngineSocket(socket) => anyhow!(
+            "the mounts expose the engine socket `{}`, which gives the container full control of the host. \
+            pass `--dangerously-expose-engine-socket` if that is really what you want",
+            socket.display()
+        ),
+        e => e.into(),
+    })?;
 
-        let container = docker.create_container(options, config).await?;
+    let host_config = config.host_config.as_ref().cloned().unwrap_or_default();
+    let mut opt_outs = vec![];
+    if host_config.cap_drop.as_deref() == Some(&["ALL".to_string()]) && !explicit_cap_drop {
+        opt_outs.push("--cap-add CAP");
         tracing::info!(
-            "starting new container: {} :: name: {}",
-            container.id,
-            container_name
+            "capabilities: {}",
+            match &host_config.cap_add {
+                Some(added) => added.join(", "),
+                None => "none".to_string(),
+            }
         );
-        docker
-            .start_container(&container.id, None::<StartContainerOptions<String>>)
-            .await?;
-        tracing::info!(
-            "container started: {} :: name: {}",
-            container.id,
-            container_name
+    }
+    if host_config.readonly_rootfs == Some(true) {
+        opt_outs.push("--writable-rootfs");
+        tracing::info!("the root filesystem is read-only, /tmp is writable");
+    }
+    let no_new_privileges = host_config
+        .security_opt
+        .iter()
+        .flatten()
+        .any(|option| option == "no-new-privileges");
+    if no_new_privileges && !explicit_no_new_privileges {
+        opt_outs.push("--allow-privilege-escalation");
+    }
+    if privileged {
+        tracing::warn!("the session is privileged, the container has full access to the host");
+        tracing::warn!(
+            "--privileged turns off the hardened defaults: the capabilities are not dropped, the root filesystem \
+             is writable and privilege escalation is allowed"
         );
+    }
+    let labels = config.labels.get_or_insert_with(HashMap::new);
+    let exposes_engine_socket = match labels
+        .contains_key(ENGINE_SOCKET_LABEL)
+        .then(|| socket::exposed(&host_config))
+        .flatten()
+        .or_else(|| {
+            volumes_from::bind_sources(&shared).find_map(|source| socket::exposed_by(&source))
+        })
+        .or_else(|| {
+            sidecars
+                .iter()
+                .filter_map(|sidecar| sidecar.config.host_config.as_ref())
+                .find_map(socket::exposed)
+        }) {
+        Some(socket) if !dangerously_expose_engine_socket => {
+            return Err(anyhow!(
+                "the mounts expose the engine socket `{}`, which gives the container full control of the host. \
+                pass `--dangerously-expose-engine-socket` if that is really what you want",
+                socket.display()
+            ));
+        }
+        Some(socket) => {
+            logging::alert(&format!(
+                "WARNING: the engine socket `{}` is mounted into the container. anything inside can take over the host",
+                socket.display()
+            ));
+            true
+        }
+        None => false,
     };
+    let host_namespaces = [
+        ("ipc", ipc.as_deref() == Some("host")),
+        ("uts", uts == Some(Uts::Host)),
+        ("network", network.as_deref() == Some("host")),
+    ]
+    .into_iter()
+    .filter(|(_, host)| *host)
+    .map(|(namespace, _)| namespace)
+    .collect::<Vec<&'static str>>();
+    for namespace in &host_namespaces {
+        tracing::warn!(
+            "the sandbox shares the host's {} namespace, it is not isolated from the host there",
+            namespace
+        );
+    }
 
-    {
-        tracing::info!("creating an exec instance to run a shell in the container");
-        let create_exec = docker
-            .create_exec(
-                &container_name,
-                CreateExecOptions {
-                    attach_stdin: Some(true),
-                    attach_stdout: Some(true),
-                    attach_stderr: Some(true),
-                    tty: Some(true),
-                    cmd: Some(vec!["sh", "-c", "stty -echo; exec sh"]),
-                    ..Default::default()
-                },
-            )
-            .await?;
+    labels.extend(
+        [
+            (PRIVILEGED_LABEL, privileged),
+            (ENGINE_SOCKET_LABEL, exposes_engine_socket),
+            (session::PERSIST_LABEL, persist),
+        ]
+        .into_iter()
+        .filter(|(_, set)| *set)
+        .map(|(label, _)| (label.to_string(), "true".to_string()))
+        .chain([(
+            session::CWD_LABEL.to_string(),
+            working_dir.to_string_lossy().into_owned(),
+        )]),
+    );
+    if !host_namespaces.is_empty() {
+        labels.insert(HOST_NAMESPACES_LABEL.to_string(), host_namespaces.join(","));
+    }
 
-        let start_exec = docker
-            .start_exec(
-                &create_exec.id,
-                Some(StartExecOptions {
-                    detach: false,
-                    tty: true,
-                    output_capacity: None,
-                }),
-            )
-            .await?;
+    let snapshot = snapshot.map(|name| name.unwrap_or_else(|| snapshot::default_name(&image_name)));
+    // refused before the session rather than after it
+    if let Some(path) = after_commit.as_ref().filter(|path| path.exists()) {
+        return Err(anyhow!(
+            "`{}` already exists, --after-commit does not overwrite it",
+            path.display()
+        ));
+    }
 
-        let StartExecResults::Attached {
-            mut output,
-            mut input,
-        } = start_exec
-        else {
-            return Err(anyhow!("failed to execute shell inside container"));
-        };
+    Ok(Plan {
+        docker,
+        container_name,
+        config,
+        env,
+        image_name,
+        platform,
+        daemon_arch: info.architecture,
+        windows,
+        docker_desktop,
+        host_gateway,
+        privileged,
+        exposes_engine_socket,
+        wait_for,
+        wait_for_timeout,
+        timeout,
+        on_start,
+        on_stop,
+        force_cleanup,
+        persist,
+        fresh,
+        watch: watch.then(|| command.clone()),
+        command: (!watch && !command.is_empty()).then_some(command),
+        // the words were checked by `parse_shell`
+        shell: shell.map(|shell| shlex::split(&shell).unwrap_or_default()),
+        session_log: session_log
+            .map(|path| (path, session_log::Format::Log))
+            .or(record.map(|path| (path, record_format))),
+        snapshot,
+        after_commit,
+        detach_keys: detach_keys.unwrap_or_default(),
+        copy,
+        caches,
+        would_create,
+        opt_outs,
+        audit,
+        host_namespaces,
+        user,
+        sidecars,
+        offline,
+        pull,
+        verify,
+    })
+}
 
-        let mut stdin = tokio::io::stdin();
-        let mut stdout = tokio::io::stdout();
-        let mut stderr = tokio::io::stderr();
+/// verifies the signature of the image in the registry and checks that the pulled image is the one that was signed.
+async fn verify_image(
+    docker: &Docker,
+    image_name: &str,
+    policy: &signature::Policy,
+) -> anyhow::Result<signature::Verified> {
+    let verified = signature::verify(image_name, policy).await?;
+    let pulled = docker
+        .inspect_image(image_name)
+        .await?
+        .repo_digests
+        .unwrap_or_default();
+    let suffix = format!("@{}", verified.digest);
+    match pulled.iter().any(|digest| digest.ends_with(&suffix)) {
+        true => Ok(verified),
+        false => Err(anyhow!(
+            "the pulled `{}` ({}) is not the signed image {}",
+            image_name,
+            pulled.join(", "),
+            verified.digest
+        )),
+    }
+}
 
-        tracing::info!("redirecting inputs and outputs");
+/// pulls the image (unless `pull` is false, then it must already be there) and checks that it can run
+/// on the daemon's architecture.
+async fn prepare_image(
+    docker: &Docker,
+    image_name: &str,
+    pull: Option<&pull::Options>,
+    platform: Option<&str>,
+    daemon_arch: Option<&str>,
+    docker_desktop: bool,
+) -> anyhow::Result<ImageInspect> {
+    if let Some(options) = pull {
+        pull::pull_image(docker, image_name, options).await?;
+    }
 
-        let input_fut = async {
-            // copy stdin to container input
-            let mut input_buffer = vec![0; 1024];
-            loop {
-                let bytes_read = stdin.read(&mut input_buffer).await?;
-                if bytes_read == 0 {
-                    tracing::info!("EOF reached on stdin");
-                    break;
-                }
-                input.write_all(&input_buffer[..bytes_read]).await?;
+    let image = match docker.inspect_image(image_name).await {
+        Ok(image) => image,
+        Err(bollard::errors::Error::DockerResponseServerError {
+            status_code: 404, ..
+        }) if pull.is_none() => {
+            return Err(anyhow!(
+                "the image `{}` is not available locally. it is not pulled with --offline, --no-pull or --pull never",
+                image_name
+            ))
+        }
+        Err(e) => return Err(e.into()),
+    };
+    if let Some(platform) = platform.filter(|platform| !pull::matches_platform(&image, platform)) {
+        return Err(anyhow!(
+            "the image `{}` is for `{}/{}`, not `{}`{}",
+            image_name,
+            image.os.as_deref().unwrap_or("unknown"),
+            image.architecture.as_deref().unwrap_or("unknown"),
+            platform,
+            match pull {
+                Some(_) => "",
+                None => ". it is not pulled with --offline, --no-pull or --pull never",
             }
-            Ok::<_, bollard::errors::Error>(())
-        };
+        ));
+    }
+    if pull.is_none() {
+        tracing::info!("using the local image `{}`", image_name);
+    }
+    if let (Some(image_arch), Some(daemon_arch)) = (&image.architecture, daemon_arch) {
+        platform::check_emulation(image_arch, daemon_arch, docker_desktop)?;
+    }
+    Ok(image)
+}
 
-        let output_fut = async {
-            // copy container output to stdout
-            while let Some(output) = output.next().await {
-                match output {
-                    Ok(LogOutput::StdOut { message }) => stdout.write_all(&message).await?,
-                    Ok(LogOutput::StdErr { message }) => stderr.write_all(&message).await?,
-                    Ok(LogOutput::Console { message }) => stdout.write_all(&message).await?,
-                    Err(e) => tracing::error!("error reading output: {:?}", e),
-                    other => tracing::info!("{:?}", other),
-                }
-                stdout.flush().await?;
-                stderr.flush().await?;
-            }
-            Ok::<_, bollard::errors::Error>(())
-        };
+/// labelled anonymous volumes for the paths of `VOLUME`s, so `clean --dangling-volumes` can tell the leftovers
+/// of quarantine from those of other tools. paths that are mounted from elsewhere are left alone.
+fn anonymous_volumes(image: &ImageInspect, config: &Config<String>) -> Vec<Mount> {
+    let host_config = config.host_config.clone().unwrap_or_default();
+    let mounted = host_config
+        .mounts
+        .iter()
+        .flatten()
+        .filter_map(|mount| mount.target.clone())
+        .chain(
+            host_config
+                .binds
+                .iter()
+                .flatten()
+                .filter_map(|bind| bind.split(':').nth(1).map(String::from)),
+        )
+        .chain(host_config.tmpfs.unwrap_or_default().into_keys())
+        .collect::<std::collections::HashSet<String>>();
+    let declared = image
+        .config
+        .as_ref()
+        .and_then(|config| config.volumes.as_ref())
+        .into_iter()
+        .flat_map(|volumes| volumes.keys())
+        .chain(config.volumes.iter().flat_map(|volumes| volumes.keys()))
+        .collect::<std::collections::BTreeSet<&String>>();
+    declared
+        .into_iter()
+        .filter(|target| !mounted.contains(*target))
+        .map(|target| Mount {
+            target: Some(target.clone()),
+            typ: Some(MountTypeEnum::VOLUME),
+            volume_options: Some(MountVolumeOptions {
+                labels: Some(HashMap::from([(
+                    MANAGED_LABEL.to_string(),
+                    env!("CARGO_PKG_VERSION").to_string(),
+                )])),
+                ..Default::default()
+            }),
+            ..Default::default()
+        })
+        .collect()
+}
 
-        tokio::select! {
-            _ = tokio::signal::ctrl_c() => { /* catch ctrl_c */  }
-            result = input_fut => { result? }
-            result = output_fut => { result? }
-        };
+fn audit_ledger() -> anyhow::Result<PathBuf> {
+    audit::ledger(config::audit()?.as_ref()).ok_or_else(|| {
+        anyhow!(
+            "auditing is not enabled. add `[audit] enabled = true` to `{}`",
+            config::config_dir().join("config.toml").display()
+        )
+    })
+}
+
+fn audit_query(query: AuditQuery) -> anyhow::Result<()> {
+    let dir = query.dir.map(std::path::absolute).transpose()?;
+    for record in audit::query(&audit_ledger()?, query.since, query.until, dir.as_deref())? {
+        println!(
+            "{}\t{}\t{}\t{}\t{}",
+            audit::format_time(record.time),
+            record.user,
+            record.project,
+            record.image,
+            record.weakened.join(",")
+        );
     }
+    Ok(())
+}
 
-    // Stop and clean up the container after use
-    {
-        tracing::info!("stopping container: {}", container_name);
-        docker.stop_container(&container_name, None).await?;
+fn audit_verify() -> anyhow::Result<()> {
+    let ledger = audit_ledger()?;
+    let count = audit::verify(&ledger)?;
+    println!("`{}` is intact :: {} record(s)", ledger.display(), count);
+    Ok(())
+}
 
-        tracing::info!("removing container: {}", container_name);
-        docker.remove_container(&container_name, None).await?;
+/// the error for a session without an image, with every place one could have come from.
+fn no_image(working_dir: &std::path::Path, no_config: bool) -> anyhow::Error {
+    let mut consulted = vec!["-i/--image-name".to_string()];
+    match no_config {
+        true => consulted.push("no config files (--no-config)".to_string()),
+        false => {
+            consulted.push(format!(
+                "`image` in `{}` of `{}` or its parents",
+                config::PROJECT_CONFIG,
+                working_dir.display()
+            ));
+            consulted.push(format!(
+                "`image` in `{}`",
+                config::global_config().display()
+            ));
+        }
+    }
+    anyhow!(
+        "no image was given. looked at: {}. pass one with `-i`, eg: `quarantine -i python:3.12`",
+        consulted.join(", ")
+    )
+}
+
+/// prints the merged config files for the session, without connecting to the daemon.
+fn show_config(args: &RunArgs) -> anyhow::Result<()> {
+    if args.no_config {
+        println!("# --no-config: the config files are ignored");
+        return Ok(());
+    }
+    let working_dir = std::env::current_dir()?;
+    let project_dir = project_dir(!args.no_resolve_symlinks)?;
+    let image_name = match &args.image_name {
+        Some(image_name) => Some(image_name.clone()),
+        None => config::image(&working_dir, &project_dir)?,
+    };
+    let loaded = config::load(
+        &working_dir,
+        &project_dir,
+        image_name.as_deref().unwrap_or_default(),
+    )?;
+
+    match loaded.files.is_empty() {
+        true => println!("# no config files found"),
+        false => {
+            for file in &loaded.files {
+                println!("# read `{}`", file.display());
+            }
+        }
+    }
+    for pattern in &loaded.image_rules {
+        println!("# applied [image.\"{}\"]", pattern);
     }
+    print!("{}", toml::to_string_pretty(&loaded.config)?);
+    Ok(())
+}
 
-    tracing::info!("done");
+/// the end of a session: the container is torn down, or left running with `--persist`. the teardown listens for
+/// ctrl-c: a second one skips the hooks and the graceful stop, a third gives up on the cleanup and exits right
+/// away. `force` (`--force-cleanup`) goes straight to killing. the container is addressed by the id it was
+/// created with, the name is for the messages and the sidecars.
+async fn finish(
+    docker: &Docker,
+    (container_name, container_id): (&str, &str),
+    on_stop: &[String],
+    force: bool,
+    persist: bool,
+) -> anyhow::Result<()> {
+    if !persist {
+        return teardown(docker, (container_name, container_id), on_stop, force).await;
+    }
+    // the `on_stop` hooks are for a container that stops, this one keeps running
+    tracing::info!(
+        "kept `{}` running. the next session with the same image continues in it, pass --fresh to start over. \
+         `docker stop {}` stops it",
+        container_name,
+        container_name
+    );
+    println!("{}", container_name);
     Ok(())
 }
 
-#[derive(Parser, Debug)]
-struct Quarantine {
-    /// image name with (optional)tag. eg: `python:latest` or `golang` or `node:20.17.0` or `node:20.17.0-alpine3.19`
-    #[arg(short, long)]
-    image_name: String,
+async fn teardown(
+    docker: &Docker,
+    (container_name, container_id): (&str, &str),
+    on_stop: &[String],
+    force: bool,
+) -> anyhow::Result<()> {
+    let removed = remove_session(docker, (container_name, container_id), on_stop, force).await;
+    if let Err(e) = compose::down(docker, container_name).await {
+        tracing::warn!(
+            "unable to remove the sidecars of `{}`: {}",
+            container_name,
+            e
+        );
+    }
+    removed
+}
 
-    /// which container runtime to use (eg: `runsc`). will revert to the default runtime if the one specified is not found.
-    #[arg(short, long)]
-    runtime: Option<String>,
+async fn remove_session(
+    docker: &Docker,
+    (container_name, container_id): (&str, &str),
+    on_stop: &[String],
+    force: bool,
+) -> anyhow::Result<()> {
+    if force {
+        return force_remove(docker, container_id).await;
+    }
+    let graceful = cleanup(docker, (container_name, container_id), on_stop);
+    tokio::pin!(graceful);
+    tokio::select! {
+        result = &mut graceful => return result,
+        _ = tokio::signal::ctrl_c() => {}
+    }
+
+    tracing::warn!(
+        "ctrl-c during cleanup. killing and force removing `{}` (press ctrl-c again to give up)",
+        container_name
+    );
+    tokio::select! {
+        result = force_remove(docker, container_id) => result,
+        _ = tokio::signal::ctrl_c() => {
+            tracing::warn!(
+                "giving up on the cleanup. the container `{}` may be left behind, remove it with `docker rm -f {}`",
+                container_name,
+                container_name
+            );
+            std::process::exit(130);
+        }
+    }
+}
+
+/// stops the container of a session that reached its `--timeout`. it gets `TIMEOUT_GRACE` to exit after SIGTERM
+/// before it is killed.
+async fn stop_expired(
+    docker: &Docker,
+    (container_name, container_id): (&str, &str),
+    timeout: std::time::Duration,
+) -> anyhow::Result<()> {
+    tracing::warn!(
+        "the session reached its --timeout of {:?}, stopping `{}`",
+        timeout,
+        container_name
+    );
+    docker
+        .stop_container(
+            container_id,
+            Some(StopContainerOptions {
+                t: TIMEOUT_GRACE.as_secs() as i64,
+            }),
+        )
+        .await?;
+    Ok(())
+}
+
+async fn force_remove(docker: &Docker, container_name: &str) -> anyhow::Result<()> {
+    docker
+        .remove_container(
+            container_name,
+            Some(RemoveContainerOptions {
+                force: true,
+                v: true,
+                link: false,
+            }),
+        )
+        .await?;
+    tracing::info!("removed container: {}", container_name);
+    Ok(())
+}
+
+async fn cleanup(
+    docker: &Docker,
+    (container_name, container_id): (&str, &str),
+    on_stop: &[String],
+) -> anyhow::Result<()> {
+    hooks::on_stop(docker, container_id, on_stop).await;
+
+    tracing::info!("stopping container: {}", container_name);
+    docker.stop_container(container_id, None).await?;
+
+    tracing::info!("removing container: {}", container_name);
+    docker
+        .remove_container(container_id, Some(REMOVE_WITH_ANONYMOUS_VOLUMES))
+        .await?;
+    Ok(())
+}
+
+/// creates the session container. when a stale container of an earlier session still holds the name
+/// (eg: created but never started, or still being removed) it is removed and the creation retried once.
+async fn create_container(
+    docker: &Docker,
+    options: Option<CreateContainerOptions<&str>>,
+    config: Config<String>,
+) -> anyhow::Result<String> {
+    let name = options
+        .as_ref()
+        .map(|options| options.name)
+        .unwrap_or_default();
+    match docker
+        .create_container(options.clone(), config.clone())
+        .await
+    {
+        Ok(response) => return Ok(response.id),
+        Err(bollard::errors::Error::DockerResponseServerError {
+            status_code: 409, ..
+        }) => {}
+        Err(e) => return Err(e.into()),
+    }
+
+    let existing = docker.inspect_container(name, None).await?;
+    let existing_id = existing.id;
+    let labels = existing.config.and_then(|config| config.labels);
+    let managed = labels
+        .as_ref()
+        .is_some_and(|labels| labels.contains_key(MANAGED_LABEL));
+    if !managed {
+        return Err(anyhow!(
+            "a container named `{}` already exists and was not created by quarantine. \
+             pick a different name with `--name`",
+            name
+        ));
+    }
+
+    let state = existing
+        .state
+        .and_then(|state| state.status)
+        .map(|status| status.to_string())
+        .unwrap_or_default();
+    if session::in_use(existing_id.as_deref(), Some(&state), labels.as_ref()) {
+        return Err(session::in_use_error(name));
+    }
+    match state.as_str() {
+        "removing" => {
+            tracing::info!(
+                "stale container `{}` is being removed, waiting for it",
+                name
+            );
+            let start = std::time::Instant::now();
+            while docker.inspect_container(name, None).await.is_ok() {
+                if start.elapsed() > REMOVAL_WAIT {
+                    return Err(anyhow!(
+                        "the stale container `{}` is still being removed after {:?}. try again later or pick a different name with `--name`",
+                        name,
+                        REMOVAL_WAIT
+                    ));
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(250)).await;
+            }
+        }
+        _ => {
+            tracing::info!(
+                "removing stale quarantine container `{}` ({}) and retrying",
+                name,
+                state
+            );
+            force_remove(docker, name).await?;
+        }
+    }
+
+    Ok(docker.create_container(options, config).await?.id)
+}
+
+async fn run(mut args: RunArgs) -> anyhow::Result<()> {
+    if args.auto {
+        let working_dir = std::env::current_dir()?;
+        let rules = match args.no_config {
+            true => vec![],
+            false => config::load(&working_dir, &working_dir, "")?.config.auto,
+        };
+        args.image_name = Some(detect::choose(&working_dir, &rules, args.yes)?);
+    }
+    if args.show_config {
+        return show_config(&args);
+    }
+    if let Some(archive) = &args.load {
+        let docker = engine::connect().await?;
+        args.image_name = Some(load::load(&docker, archive, args.load_tag.as_deref()).await?);
+    }
+    if args.dockerfile.is_some() || args.build.is_some() {
+        let context = build::Context::new(args.dockerfile.as_deref(), args.build.as_deref())?;
+        // a dry run only shows the tag the build would get
+        args.image_name = Some(match args.dry_run {
+            true => context.tag(),
+            false => build::build(&engine::connect().await?, &context, &args.build_arg).await?,
+        });
+    } else if !args.build_arg.is_empty() {
+        return Err(anyhow!("--build-arg needs --dockerfile or --build"));
+    }
+    let dry_run = args.dry_run;
+    let planned = plan(args).await?;
+    if dry_run {
+        // there is no container to look for a shell in
+        let shell = match (&planned.shell, planned.windows) {
+            (Some(shell), _) => shell.clone(),
+            (None, true) => vec!["cmd.exe".to_string()],
+            (None, false) => vec!["sh".to_string()],
+        };
+        for path in &planned.would_create {
+            println!("# would create `{}` for --volume", path.display());
+        }
+        return dry_run::print(&planned.container_name, &planned.config, &shell);
+    }
+
+    let Plan {
+        docker,
+        container_name,
+        mut config,
+        env,
+        image_name,
+        platform,
+        daemon_arch,
+        windows,
+        docker_desktop,
+        host_gateway,
+        privileged,
+        exposes_engine_socket,
+        wait_for,
+        wait_for_timeout,
+        timeout,
+        on_start,
+        on_stop,
+        force_cleanup,
+        mut persist,
+        fresh,
+        watch,
+        command,
+        shell,
+        session_log,
+        snapshot,
+        after_commit,
+        detach_keys,
+        copy,
+        caches,
+        would_create: _,
+        opt_outs,
+        audit,
+        host_namespaces,
+        user,
+        sidecars,
+        offline,
+        pull,
+        verify,
+    } = planned;
+
+    // opened before anything is created, so an unwritable path fails early
+    let recording = match &session_log {
+        Some((path, format)) => {
+            Some(session_log::SessionLog::open(path, *format, &image_name, &container_name).await?)
+        }
+        None => None,
+    };
+
+    let mut signed = None;
+    let kept_container;
+    let image_volumes;
+    // the image and any stale container are independent of each other
+    match tokio::join!(
+        prepare_image(
+            &docker,
+            &image_name,
+            pull.as_ref(),
+            platform.as_deref(),
+            daemon_arch.as_deref(),
+            docker_desktop
+        ),
+        session::remove_stale(&docker, &container_name, fresh)
+    ) {
+        (Ok(image), Ok(kept)) => {
+            let image_id = image.id.clone().unwrap_or_default();
+            image_volumes = anonymous_volumes(&image, &config);
+            kept_container = match kept {
+                Some(kept) if kept.image_id == image_id => Some(kept.id),
+                // the signed image must be the one that runs
+                Some(kept) if verify.is_some() => {
+                    tracing::warn!(
+                        "the kept container `{}` was created from another image than `{}` is now. recreating it",
+                        container_name,
+                        image_name
+                    );
+                    force_remove(&docker, &kept.id).await?;
+                    None
+                }
+                Some(kept) => {
+                    tracing::warn!(
+                        "the kept container `{}` was created from another image than `{}` is now. \
+                         continuing in it, pass --recreate to start from the new image",
+                        container_name,
+                        image_name
+                    );
+                    Some(kept.id)
+                }
+                None => None,
+            };
+            if let Some(policy) = &verify {
+                let verified = verify_image(&docker, &image_name, policy).await?;
+                let labels = config.labels.get_or_insert_with(HashMap::new);
+                labels.insert(signature::DIGEST_LABEL.to_string(), verified.digest.clone());
+                labels.insert(
+                    signature::SUBJECT_LABEL.to_string(),
+                    verified.subject.clone(),
+                );
+                if let Some(issuer) = &verified.issuer {
+                    labels.insert(signature::ISSUER_LABEL.to_string(), issuer.clone());
+                }
+                signed = Some(verified);
+            }
+            if let Some(ledger) = &audit {
+                audit::append(ledger, &std::env::current_dir()?, &config, &image_id)?;
+            }
+        }
+        (Err(e), Ok(_)) | (Ok(_), Err(e)) => return Err(e),
+        (Err(image), Err(stale)) => {
+            return Err(anyhow!(
+                "{:#}\nadditionally, removing the stale container failed: {:#}",
+                image,
+                stale
+            ))
+        }
+    }
+
+    if !sidecars.is_empty() {
+        compose::up(&docker, &container_name, &sidecars, pull.as_ref(), offline).await?;
+    }
+
+    cache::ensure(&docker, &caches).await?;
+
+    // start container
+    let (container_id, deadline, lease) = {
+        let options = Some(CreateContainerOptions {
+            name: container_name.as_str(),
+            platform: platform.as_deref(),
+        });
+
+        let entered_again = kept_container.is_some();
+        let container_id = match kept_container {
+            Some(id) => {
+                tracing::info!("entering the kept container again: {}", container_name);
+                // a kept container is a persisted one, even when --persist isn't given this time
+                persist = true;
+                if copy.is_some() {
+                    tracing::info!("the kept container has its own copy of the working directory, not copying it again");
+                }
+                id
+            }
+            // prewarmed containers are created for the daemon's own platform
+            None => match match platform {
+                Some(_) => None,
+                None => pool::claim(&docker, &config, &container_name).await?,
+            } {
+                Some(id) => {
+                    tracing::info!("claimed prewarmed container: {}", id);
+                    id
+                }
+                None => {
+                    // not part of the planned config, the pool members are matched by it
+                    config
+                        .labels
+                        .get_or_insert_with(HashMap::new)
+                        .extend(session::owner());
+                    config
+                        .host_config
+                        .get_or_insert_with(HostConfig::default)
+                        .mounts
+                        .get_or_insert_with(Vec::new)
+                        .extend(image_volumes);
+                    create_container(&docker, options, config)
+                        .await
+                        .map_err(gpu_hint)?
+                }
+            },
+        };
+        // the claimed and entered containers have no owner labels of this process, the lease stands in
+        let Some(lease) = session::Lease::take(&container_id)? else {
+            return Err(session::in_use_error(&container_name));
+        };
+        if let (Some(copy), false) = (&copy, entered_again) {
+            if let Err(e) = copy::copy_in(&docker, &container_id, PROJECT_MOUNT, copy).await {
+                finish(
+                    &docker,
+                    (&container_name, &container_id),
+                    &[],
+                    force_cleanup,
+                    persist,
+                )
+                .await?;
+                return Err(e);
+            }
+        }
+        let running = docker
+            .inspect_container(&container_id, None)
+            .await?
+            .state
+            .and_then(|state| state.running)
+            .unwrap_or_default();
+        if !running {
+            tracing::info!(
+                "starting container: {} :: name: {}",
+                container_id,
+                container_name
+            );
+            docker
+                .start_container(&container_id, None::<StartContainerOptions<String>>)
+                .await
+                .map_err(|e| {
+                    match opt_outs.is_empty() || e.to_string().contains(GPU_DRIVER_ERROR) {
+                        true => gpu_hint(e.into()),
+                        false => anyhow!(
+                        "{}. some images don't start with quarantine's hardened defaults, try again with {}",
+                        e,
+                        opt_outs.join(" or ")
+                    ),
+                    }
+                })?;
+        }
+        tracing::info!(
+            "container started: {} :: name: {}",
+            container_id,
+            container_name
+        );
+        // counted from here, a slow pull doesn't take from it
+        // a timeout beyond what the clock can represent never ends
+        let deadline = timeout.and_then(|timeout| tokio::time::Instant::now().checked_add(timeout));
+
+        let inspect = docker.inspect_container(&container_id, None).await?;
+        if let Some(host_config) = inspect.host_config {
+            tracing::info!(
+                "applied limits :: memory: {} :: memory reservation: {} :: cpus: {} :: pids: {} :: privileged: {}",
+                host_config.memory.unwrap_or_default(),
+                host_config.memory_reservation.unwrap_or_default(),
+                host_config.nano_cpus.unwrap_or_default() as f64 / 1e9,
+                host_config.pids_limit.unwrap_or_default(),
+                host_config.privileged.unwrap_or_default(),
+            );
+        }
+        let mut published = inspect
+            .network_settings
+            .and_then(|settings| settings.ports)
+            .unwrap_or_default()
+            .into_iter()
+            .flat_map(|(container_port, bindings)| {
+                bindings
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(move |binding| (container_port.clone(), binding))
+            })
+            .collect::<Vec<_>>();
+        published.sort_by(|(a, _), (b, _)| a.cmp(b));
+        for (container_port, binding) in published {
+            tracing::info!(
+                "{} is published on {}:{}",
+                container_port,
+                binding.host_ip.as_deref().unwrap_or("0.0.0.0"),
+                binding.host_port.unwrap_or_default()
+            );
+        }
+
+        if host_gateway {
+            if docker_desktop {
+                let resolves = exec_status(
+                    &docker,
+                    &container_id,
+                    vec![
+                        "sh",
+                        "-c",
+                        "getent hosts host.docker.internal || nslookup host.docker.internal",
+                    ],
+                )
+                .await?;
+                if resolves != 0 {
+                    tracing::warn!(
+                        "`{}` does not resolve inside the container",
+                        HOST_GATEWAY_NAME
+                    );
+                }
+            }
+            tracing::info!(
+                "services on the host are reachable at `{}`",
+                HOST_GATEWAY_NAME
+            );
+        }
+
+        if !wait_for.is_empty() {
+            if let Err(e) =
+                wait_for::wait(&docker, &container_id, &wait_for, wait_for_timeout).await
+            {
+                finish(
+                    &docker,
+                    (&container_name, &container_id),
+                    &[],
+                    force_cleanup,
+                    persist,
+                )
+                .await?;
+                return Err(e);
+            }
+        }
+
+        if let Err(e) = hooks::on_start(&docker, &container_id, &on_start).await {
+            finish(
+                &docker,
+                (&container_name, &container_id),
+                &on_stop,
+                force_cleanup,
+                persist,
+            )
+            .await?;
+            return Err(e);
+        }
+        (container_id, deadline, lease)
+    };
+    let expired = async {
+        match deadline {
+            Some(deadline) => tokio::time::sleep_until(deadline).await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::pin!(expired);
+    let mut timed_out = false;
+
+    if let Some(command) = watch {
+        let working_dir = std::env::current_dir()?;
+        let watched = tokio::select! {
+            watched = watch::watch(&docker, &container_name, &command, &working_dir) => Some(watched),
+            _ = &mut expired => None,
+        };
+        if let (None, Some(timeout)) = (&watched, timeout) {
+            stop_expired(&docker, (&container_name, &container_id), timeout).await?;
+        }
+        finish(
+            &docker,
+            (&container_name, &container_id),
+            match watched {
+                Some(_) => &on_stop,
+                None => &[],
+            },
+            force_cleanup,
+            persist,
+        )
+        .await?;
+        lease.release();
+        return match watched {
+            Some(watched) => watched,
+            None => std::process::exit(TIMEOUT_EXIT_CODE),
+        };
+    }
+
+    let (detached, exit_code) = match command {
+        Some(command) => {
+            let exit_code = tokio::select! {
+                exit_code = run_command(
+                    &docker,
+                    &container_name,
+                    &command,
+                    &env,
+                    user.as_deref(),
+                    recording,
+                ) => exit_code?,
+                _ = &mut expired => {
+                    timed_out = true;
+                    TIMEOUT_EXIT_CODE.into()
+                }
+            };
+            (false, Some(exit_code))
+        }
+        None => {
+            let raw_mode = terminal::RawMode::enable()?;
+            let shell = match (shell, windows) {
+                (Some(shell), _) => shell,
+                (None, true) => match exec_status(
+                    &docker,
+                    &container_name,
+                    vec!["powershell", "-Command", "exit 0"],
+                )
+                .await
+                {
+                    Ok(0) => vec!["powershell".to_string()],
+                    _ => vec!["cmd.exe".to_string()],
+                },
+                (None, false) => match find_shell(&docker, &container_name).await {
+                    Some(shell) => vec![shell.to_string()],
+                    None => {
+                        finish(
+                            &docker,
+                            (&container_name, &container_id),
+                            &on_stop,
+                            force_cleanup,
+                            persist,
+                        )
+                        .await?;
+                        return Err(anyhow!(
+                            "`{}` has no usable shell, neither `bash` nor `sh` could be run in it. pass one with --shell, eg: `--shell /busybox/sh`, or a command after `--`",
+                            image_name
+                        ));
+                    }
+                },
+            };
+            // piped input gets no tty, so nothing is echoed back and stdout and stderr stay apart
+            let tty = raw_mode.is_some();
+
+            tracing::info!("creating an exec instance to run a shell in the container");
+            let create_exec = docker
+                .create_exec(
+                    &container_name,
+                    CreateExecOptions {
+                        attach_stdin: Some(true),
+                        attach_stdout: Some(true),
+                        attach_stderr: Some(true),
+                        tty: Some(tty),
+                        cmd: Some(shell.iter().map(String::as_str).collect()),
+                        env: Some(env.iter().map(String::as_str).collect()),
+                        user: user.as_deref(),
+                        ..Default::default()
+                    },
+                )
+                .await?;
+
+            let start_exec = docker
+                .start_exec(
+                    &create_exec.id,
+                    Some(StartExecOptions {
+                        detach: false,
+                        tty,
+                        output_capacity: None,
+                    }),
+                )
+                .await?;
+
+            let StartExecResults::Attached {
+                mut output,
+                mut input,
+            } = start_exec
+            else {
+                return Err(anyhow!("failed to execute shell inside container"));
+            };
+
+            tracing::info!("redirecting inputs and outputs");
+            match (raw_mode.is_some(), persist) {
+                (true, true) => tracing::info!(
+                    "press {} to detach and leave the container running",
+                    detach_keys
+                ),
+                (true, false) => tracing::info!(
+                    "press {} to detach. the container is only left running with --persist",
+                    detach_keys
+                ),
+                (false, _) => {}
+            }
+            let resizes = tokio::spawn({
+                let (docker, exec_id) = (docker.clone(), create_exec.id.clone());
+                async move {
+                    if tty {
+                        terminal::forward_resizes(docker, exec_id).await;
+                    }
+                }
+            });
+
+            // the pumps run as tasks so that one side ending never cancels the other mid-write
+            let (shutdown, mut shutdown_requested) = tokio::sync::watch::channel(false);
+
+            let raw = raw_mode.is_some();
+            let mut input_task = tokio::spawn(async move {
+                // copy stdin to container input. whether the user detached
+                let mut stdin = tokio::io::stdin();
+                let mut input_buffer = vec![0; 1024];
+                let mut detach = terminal::Detach::new(detach_keys);
+                loop {
+                    let bytes_read = tokio::select! {
+                        _ = shutdown_requested.changed() => break,
+                        bytes_read = stdin.read(&mut input_buffer) => bytes_read?,
+                    };
+                    if bytes_read == 0 {
+                        tracing::info!("EOF reached on stdin");
+                        // half-close, so the container sees EOF but can still finish writing its output
+                        input.shutdown().await?;
+                        break;
+                    }
+                    let (forward, detached) = match raw {
+                        true => detach.feed(&input_buffer[..bytes_read]),
+                        false => (input_buffer[..bytes_read].to_vec(), false),
+                    };
+                    input.write_all(&forward).await?;
+                    if detached {
+                        return Ok(true);
+                    }
+                }
+                Ok::<_, std::io::Error>(false)
+            });
+
+            let mut recording = recording;
+            let mut output_task = tokio::spawn(async move {
+                // copy container output to stdout
+                let mut stdout = tokio::io::stdout();
+                let mut stderr = tokio::io::stderr();
+                while let Some(output) = output.next().await {
+                    match output {
+                        Ok(LogOutput::StdOut { message }) => {
+                            record(&mut recording, "stdout", &message).await;
+                            stdout.write_all(&message).await?
+                        }
+                        Ok(LogOutput::StdErr { message }) => {
+                            record(&mut recording, "stderr", &message).await;
+                            stderr.write_all(&message).await?
+                        }
+                        Ok(LogOutput::Console { message }) => {
+                            record(&mut recording, "console", &message).await;
+                            stdout.write_all(&message).await?
+                        }
+                        Err(e) => tracing::error!("error reading output: {:?}", e),
+                        other => tracing::info!("{:?}", other),
+                    }
+                    stdout.flush().await?;
+                    stderr.flush().await?;
+                }
+                Ok::<_, std::io::Error>(())
+            });
+
+            // in raw mode ctrl-c is a key for the container, not a signal
+            let ctrl_c = async {
+                match raw {
+                    true => std::future::pending().await,
+                    false => tokio::signal::ctrl_c().await,
+                }
+            };
+            let detached = tokio::select! {
+                _ = ctrl_c => { /* catch ctrl_c */ false }
+                _ = &mut expired => { timed_out = true; false }
+                result = &mut output_task => { result??; false }
+                result = &mut input_task => match result?? {
+                    true => true,
+                    false => {
+                        // stdin is done, but the container may still be writing
+                        match tokio::time::timeout(OUTPUT_DRAIN_TIMEOUT, &mut output_task).await {
+                            Ok(result) => result??,
+                            Err(_) => tracing::warn!(
+                                "container output did not end within {:?} of stdin closing",
+                                OUTPUT_DRAIN_TIMEOUT
+                            ),
+                        }
+                        false
+                    }
+                }
+            };
+
+            let _ = shutdown.send(true);
+            output_task.abort();
+            resizes.abort();
+            let _ = input_task.await;
+            // the shell's exit status. none when the session ended some other way, eg: on ctrl-c
+            let exit_code = match (detached, timed_out) {
+                (true, _) => None,
+                (false, true) => Some(TIMEOUT_EXIT_CODE.into()),
+                (false, false) => docker.inspect_exec(&create_exec.id).await?.exit_code,
+            };
+            if let (Some(code @ (126 | 127)), Some(user)) = (exit_code, &user) {
+                tracing::warn!(
+                    "the shell exited with {}, it may not be usable as `{}`. pass another --shell or run as the image's user",
+                    code,
+                    user
+                );
+            }
+            (detached, exit_code)
+        }
+    };
+
+    // stopped before the files are copied back or the snapshot is taken, so nothing changes underneath them
+    if let (true, Some(timeout)) = (timed_out, timeout) {
+        stop_expired(&docker, (&container_name, &container_id), timeout).await?;
+    }
+
+    // without --persist, detaching ends the session like the shell exiting does
+    match (detached, persist) {
+        (true, true) => {
+            if snapshot.is_some() {
+                tracing::warn!("no snapshot was saved, the session was detached from");
+            }
+            if copy.as_ref().is_some_and(|copy| copy.out.is_some()) {
+                tracing::warn!("no files were copied back, the session was detached from");
+            }
+            // kept like at the end of a persisted session, the next one enters it again
+            lease.release();
+            tracing::info!(
+                "detached from `{}`, it keeps running. the next session with the same image continues in it, \
+                 `docker exec -it {} sh` enters it too, `quarantine kill {}` removes it",
+                container_name,
+                container_name,
+                container_name
+            );
+            println!("{}", container_name);
+            return Ok(());
+        }
+        (true, false) => tracing::info!(
+            "detached from `{}`, ending the session. pass --persist to leave the container running",
+            container_name
+        ),
+        (false, _) => {}
+    }
+
+    report_oom(&docker, &container_name, exit_code).await;
+
+    if let Some(copy) = copy.as_ref().filter(|copy| copy.out.is_some()) {
+        match persist {
+            true => tracing::warn!("the container is kept, no files were copied back"),
+            false => {
+                if let Err(e) = copy::copy_out(&docker, &container_name, PROJECT_MOUNT, copy).await
+                {
+                    // the files in the container are not lost, it is only stopped
+                    docker.stop_container(&container_name, None).await?;
+                    return Err(anyhow!(
+                        "unable to copy the files back: {}. the container was kept, retry with \
+                         `docker cp {}:{} .` and remove it with `docker rm {}`",
+                        e,
+                        container_name,
+                        PROJECT_MOUNT,
+                        container_name
+                    ));
+                }
+            }
+        }
+    }
+
+    if let Some(name) = &snapshot {
+        if let Err(e) = snapshot::commit(&docker, &container_name, name, &image_name).await {
+            // the work in the container is not lost, it is only stopped
+            docker.stop_container(&container_name, None).await?;
+            return Err(anyhow!(
+                "unable to save the snapshot `{}`: {}. the container was kept, retry with \
+                 `docker commit {} {}` and remove it with `docker rm {}`",
+                name,
+                e,
+                container_name,
+                name,
+                container_name
+            ));
+        }
+    }
+
+    // the snapshot holds the work, so the container is removed even when the archive can't be written
+    let saved = match (&snapshot, &after_commit) {
+        (Some(name), Some(path)) => save::save(name, path, false, false).await.map_err(|e| {
+            anyhow!(
+                "unable to save the snapshot `{}` to `{}`: {}. retry with `quarantine save {} -o {}`",
+                name,
+                path.display(),
+                e,
+                name,
+                path.display()
+            )
+        }),
+        _ => Ok(()),
+    };
+
+    // Stop and clean up the container after use, unless it is kept
+    finish(
+        &docker,
+        (&container_name, &container_id),
+        // the hooks can't run in the stopped container
+        match timed_out {
+            true => &[],
+            false => &on_stop,
+        },
+        force_cleanup,
+        persist,
+    )
+    .await?;
+    // before the exit with the shell's status, which skips the drop
+    lease.release();
+    saved?;
+
+    let mut notes = [
+        (privileged, "the session was privileged"),
+        (exposes_engine_socket, "the engine socket was exposed"),
+    ]
+    .into_iter()
+    .filter(|(set, _)| *set)
+    .map(|(_, note)| note.to_string())
+    .collect::<Vec<String>>();
+    for namespace in host_namespaces {
+        notes.push(format!("the host's {} namespace was shared", namespace));
+    }
+    if let Some(signed) = signed {
+        notes.push(format!(
+            "the image was signed by {}{} ({})",
+            signed.subject,
+            signed
+                .issuer
+                .map(|issuer| format!(" via {}", issuer))
+                .unwrap_or_default(),
+            signed.digest
+        ));
+    }
+    match notes.is_empty() {
+        true => tracing::info!("done"),
+        false => tracing::info!("done :: {}", notes.join(" :: ")),
+    }
+    match exit_code {
+        Some(exit_code) if exit_code != 0 => std::process::exit(exit_code as i32),
+        _ => Ok(()),
+    }
+}
+
+#[derive(Parser, Debug)]
+#[command(
+    version,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
+struct Quarantine {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// also write debug level diagnostics to this file, regardless of the terminal verbosity.
+    /// defaults to `~/.local/state/quarantine/logs/quarantine.log` when no path is given.
+    /// the file is rotated when it grows too big and the last few rotations are kept.
+    #[arg(long, global = true, value_name = "PATH")]
+    log_file: Option<Option<PathBuf>>,
+
+    /// when to color quarantine's own output. `auto` disables colors when `NO_COLOR` is set or stderr
+    /// is not a terminal. output from inside the container is always passed through untouched.
+    #[arg(long, global = true, value_enum, default_value_t = clap::ColorChoice::Auto)]
+    color: clap::ColorChoice,
+
+    /// log every docker api request (url and body, with secrets redacted) to the log file, along with
+    /// its status and how long it took. uses the default `--log-file` when none is given.
+    #[arg(long, global = true)]
+    trace_docker: bool,
+
+    /// the daemon to talk to, eg: `unix:///run/user/1000/podman/podman.sock`, `tcp://build-box:2375`,
+    /// `https://build-box:2376` (with the certificates in `DOCKER_CERT_PATH`) or `ssh://me@build-box`.
+    /// defaults to `DOCKER_HOST`, then the local socket.
+    #[arg(short = 'H', long, global = true, value_name = "URL", value_parser = parse_docker_host)]
+    docker_host: Option<String>,
+
+    #[command(flatten)]
+    run: Option<RunArgs>,
+}
+
+impl Quarantine {
+    /// what clap can't check on its own: the `--security-opt`s that undo `--hardened`.
+    fn check(&self) -> Result<(), clap::Error> {
+        let run = match &self.command {
+            Some(Command::Run(args)) => Some(&**args),
+            _ => self.run.as_ref(),
+        };
+        let Some(run) = run.filter(|run| run.hardened) else {
+            return Ok(());
+        };
+        match run
+            .security_opt
+            .iter()
+            .find(|option| loosens_hardening(option))
+        {
+            Some(option) => Err(Quarantine::command().error(
+                clap::error::ErrorKind::ArgumentConflict,
+                format!(
+                    "the argument '--hardened' cannot be used with '--security-opt {}'",
+                    option
+                ),
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// start a session, the same as `quarantine` with the arguments given after `run`
+    Run(Box<RunArgs>),
+
+    /// check the docker connection and print where quarantine keeps its files
+    Doctor,
+
+    /// run a short end-to-end session against `busybox` to verify that quarantine works on this machine
+    SelfTest,
+
+    /// pre-create stopped containers for IMAGE in the current directory, so sessions started
+    /// here with default options start faster
+    Warm {
+        #[arg(add = ArgValueCompleter::new(completion::images))]
+        image_name: String,
+
+        /// how many containers to pre-create
+        #[arg(long, default_value_t = 1)]
+        count: usize,
+    },
+
+    /// list quarantine containers: name, image, state and when they were created
+    #[command(visible_alias = "list")]
+    Ls {
+        /// only list prewarmed pool members
+        #[arg(long)]
+        pool: bool,
+
+        /// list the `--cache` volumes instead
+        #[arg(long, conflicts_with = "pool")]
+        caches: bool,
+    },
+
+    /// write IMAGE to an archive that `--load` (or `docker load`) can read
+    Save {
+        #[arg(add = ArgValueCompleter::new(completion::images))]
+        image_name: String,
+
+        /// where to write the archive
+        #[arg(short, long, value_name = "FILE")]
+        output: PathBuf,
+
+        /// gzip the archive. implied when FILE ends in `.gz`
+        #[arg(long)]
+        gzip: bool,
+
+        /// overwrite FILE if it exists
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// search the ledger of started sessions (enable it with `[audit] enabled = true` in the global config)
+    #[command(args_conflicts_with_subcommands = true)]
+    Audit {
+        #[command(subcommand)]
+        action: Option<AuditAction>,
+
+        #[command(flatten)]
+        query: AuditQuery,
+    },
+
+    /// remove resources quarantine left behind
+    #[command(group = clap::ArgGroup::new("what").required(true).multiple(true))]
+    Clean {
+        /// remove prewarmed pool members
+        #[arg(long, group = "what")]
+        pool: bool,
+
+        /// remove the anonymous volumes of quarantine containers that are no longer attached to any container,
+        /// eg: of a session that crashed. volumes of other tools are never touched
+        #[arg(long, group = "what")]
+        dangling_volumes: bool,
+
+        /// remove the `--cache` volumes that no container uses
+        #[arg(long, group = "what")]
+        caches: bool,
+
+        /// remove a session container (and its anonymous volumes), eg: when the normal cleanup got stuck
+        #[arg(long, group = "what", value_name = "NAME")]
+        container: Option<String>,
+
+        /// kill the `--container` instead of stopping it gracefully
+        #[arg(long, requires = "container")]
+        force: bool,
+    },
+
+    /// stop and remove a quarantine container, eg: one kept with `--persist`
+    #[command(visible_alias = "rm")]
+    Kill {
+        /// the container's name, or the image of a container with the default name
+        name: String,
+    },
+
+    /// remove the stopped quarantine containers. prewarmed ones are left to `clean --pool`
+    Prune {
+        /// also stop and remove the running ones
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum AuditAction {
+    /// check that no record of the ledger was edited or removed
+    Verify,
+}
+
+#[derive(Args, Debug)]
+struct AuditQuery {
+    /// only sessions started at or after this time (`YYYY-MM-DD`, `YYYY-MM-DDTHH:MM:SS` in utc, `24h` or `7d` ago)
+    #[arg(long, value_name = "TIME", value_parser = audit::parse_time)]
+    since: Option<u64>,
+
+    /// only sessions started at or before this time
+    #[arg(long, value_name = "TIME", value_parser = audit::parse_time)]
+    until: Option<u64>,
+
+    /// only sessions of this directory or the ones below it
+    #[arg(long, value_name = "PATH")]
+    dir: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct RunArgs {
+    /// image name with (optional)tag. eg: `python:latest` or `golang` or `node:20.17.0` or `node:20.17.0-alpine3.19`.
+    /// defaults to the `image` of the config
+    #[arg(short, long, add = ArgValueCompleter::new(completion::images))]
+    image_name: Option<String>,
+
+    /// set an environment variable in the container (eg: `-e AWS_PROFILE=dev`), or pass one on from the
+    /// host with `-e KEY`. can be repeated, later ones win. wins over `--env-file`, the config's `env` and the
+    /// `QUARANTINE*` variables, `-e QUARANTINE=` clears one of those
+    #[arg(short, long = "env", value_name = "KEY[=VALUE]", value_parser = parse_env)]
+    envs: Vec<String>,
+
+    /// read environment variables from a file with a `KEY=VALUE` per line. `#` starts a comment line,
+    /// values are taken literally. can be repeated
+    #[arg(long, value_name = "PATH")]
+    env_file: Vec<PathBuf>,
+
+    /// infer the image from the project (`Cargo.toml`, `package.json`, `pyproject.toml`, `requirements.txt`
+    /// or `go.mod`) instead of passing it with `-i`. the `auto` rules of the config add more.
+    #[arg(long, conflicts_with = "image_name")]
+    auto: bool,
+
+    /// don't ask to confirm the image `--auto` picked
+    #[arg(long, requires = "auto")]
+    yes: bool,
+
+    /// configure the session from `.devcontainer/devcontainer.json`. its `image`, `containerEnv`, `mounts`,
+    /// `postCreateCommand`, `remoteUser` and the `--memory`/`--shm-size` of `runArgs` are used, everything else
+    /// is reported and ignored. `-i` and `--memory` take precedence.
+    #[arg(long, conflicts_with = "auto")]
+    devcontainer: bool,
+
+    /// load the image from an archive made by `docker save` (`.tar` or `.tar.gz`) and run it, instead of `-i`
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["image_name", "auto"])]
+    load: Option<PathBuf>,
+
+    /// the image of the `--load` archive to run when it contains several, or the name to give an untagged one
+    #[arg(long, value_name = "IMAGE", requires = "load")]
+    load_tag: Option<String>,
+
+    /// build the image from this Dockerfile and run it, instead of `-i`. it is built in its own directory
+    /// unless `--build` names another, with the `.dockerignore` next to it
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["image_name", "auto", "load"])]
+    dockerfile: Option<PathBuf>,
+
+    /// build the image in this directory and run it, instead of `-i`. its `Dockerfile` is built unless
+    /// `--dockerfile` names another
+    #[arg(long, value_name = "DIR", conflicts_with_all = ["image_name", "auto", "load"])]
+    build: Option<PathBuf>,
+
+    /// a build argument for `--dockerfile` or `--build`, eg: `--build-arg VERSION=1.2`. can be given multiple times
+    #[arg(long, value_name = "KEY=VALUE", value_parser = parse_build_arg)]
+    build_arg: Vec<(String, String)>,
+
+    /// refuse to run the image unless it has a valid cosign signature, checked against `--cosign-key` or
+    /// `--certificate-identity` and `--certificate-oidc-issuer`. the `verify` setting of the config can require this
+    #[arg(long, conflicts_with_all = ["offline", "load", "dockerfile", "build"])]
+    verify_signature: bool,
+
+    /// the public key the image must be signed with
+    #[arg(
+        long,
+        value_name = "PATH",
+        requires = "verify_signature",
+        conflicts_with = "certificate_identity"
+    )]
+    cosign_key: Option<PathBuf>,
+
+    /// the email or url of the keyless signer (eg: a github workflow)
+    #[arg(long, value_name = "IDENTITY", requires_all = ["verify_signature", "certificate_oidc_issuer"])]
+    certificate_identity: Option<String>,
+
+    /// the oidc issuer of the keyless signer (eg: `https://token.actions.githubusercontent.com`)
+    #[arg(long, value_name = "URL", requires = "certificate_identity")]
+    certificate_oidc_issuer: Option<String>,
+
+    /// how often a pull that failed because of the connection (a reset, a timeout, a 5xx from the registry)
+    /// is retried. a missing image or denied access fails right away
+    #[arg(long, value_name = "N", default_value_t = pull::Options::default().retries)]
+    pull_retries: u32,
+
+    /// give up on a pull (with all its retries) that takes longer than this (eg: `10m`)
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    pull_timeout: Option<std::time::Duration>,
+
+    /// abort a pull attempt when the daemon reports nothing (no bytes, no layer moving on to extracting or
+    /// done) for this long. it counts as a transient failure and is retried
+    #[arg(long, value_name = "DURATION", default_value = "60s", value_parser = parse_duration)]
+    pull_stall_timeout: std::time::Duration,
+
+    /// a pull-through cache for docker hub (eg: `mirror.internal:5000`), tried before the mirrors of the config
+    /// and docker hub itself. can be repeated
+    #[arg(long, value_name = "URL")]
+    registry_mirror: Vec<String>,
+
+    /// log in to the registry of the image as this user. the password is read from stdin with
+    /// `--registry-password-stdin`. without it, the docker cli's `config.json` and credential helpers are used
+    #[arg(long, value_name = "USER", requires = "registry_password_stdin")]
+    registry_user: Option<String>,
+
+    /// read the password (or token) for `--registry-user` from the first line of stdin,
+    /// eg: `echo $TOKEN | quarantine ...`. the session then has no stdin left to read
+    #[arg(long, requires = "registry_user")]
+    registry_password_stdin: bool,
+
+    /// never contact a registry, and run the sandbox without a network like `--network none`. the image
+    /// (and those of `--with-deps`) must already be present. the dependencies can still be reached, but
+    /// nothing outside of them
+    #[arg(long, conflicts_with_all = ["network", "share_net_with"])]
+    offline: bool,
+
+    /// when to pull the image
+    #[arg(long, value_enum, default_value_t = PullPolicy::Missing, conflicts_with = "offline")]
+    pull: PullPolicy,
+
+    /// pull and run the image for this platform, eg: `linux/amd64` on an arm machine. foreign platforms
+    /// run under emulation
+    #[arg(long, value_name = "OS/ARCH[/VARIANT]", value_parser = parse_platform)]
+    platform: Option<String>,
+
+    /// use the local image and fail when there is none, the same as `--pull never`
+    #[arg(long, conflicts_with_all = ["offline", "pull"])]
+    no_pull: bool,
+
+    /// configure the session from a service of the compose file: its `image`, `environment`, `volumes`
+    /// (relative to the compose file) and `ports`. `-i` takes precedence over the image.
+    #[arg(long, value_name = "NAME", conflicts_with_all = ["auto", "devcontainer"])]
+    compose_service: Option<String>,
+
+    /// the compose file to read, instead of `compose.yaml` or `docker-compose.yml` in the working directory
+    #[arg(long, value_name = "PATH", requires = "compose_service")]
+    compose_file: Option<PathBuf>,
+
+    /// also start the services the `--compose-service` depends on. they share a network with the sandbox
+    /// and are reachable by their service name.
+    #[arg(long, requires = "compose_service", conflicts_with = "share_net_with")]
+    with_deps: bool,
+
+    /// which container runtime to use (eg: `runsc`). will revert to the default runtime if the one specified is not found.
+    #[arg(short, long, add = ArgValueCompleter::new(completion::runtimes))]
+    runtime: Option<String>,
+
+    /// hard memory limit (eg: `512m`, `2g`). the container is OOM-killed if it tries to use more than this.
+    #[arg(long, value_parser = parse_size)]
+    memory: Option<i64>,
+
+    /// soft memory limit (eg: `256m`). the container may use more than this, but the kernel reclaims memory
+    /// down to it when the host is under memory pressure instead of killing the container. must be <= `--memory`.
+    #[arg(long, value_parser = parse_size)]
+    memory_reservation: Option<i64>,
+
+    /// memory plus swap the container may use (eg: `1g`), `-1` for unlimited swap. defaults to `--memory`,
+    /// which disables swap, so the memory limit can't be dodged by swapping.
+    #[arg(long, allow_hyphen_values = true, value_parser = parse_size)]
+    memory_swap: Option<i64>,
+
+    /// how many cpus the container may use (eg: `1.5`). at most the number of cpus of the daemon.
+    #[arg(long, value_parser = parse_cpus)]
+    cpus: Option<f64>,
+
+    /// how many processes the container may run at once, which stops fork bombs.
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(i64).range(1..))]
+    pids_limit: Option<i64>,
+
+    /// make services running on the host reachable from the container at `host.docker.internal`.
+    #[arg(long)]
+    host_gateway: bool,
+
+    /// same as `--host-gateway`, and also exports `QUARANTINE_HOST_URL=http://host.docker.internal:PORT`.
+    #[arg(long, value_name = "PORT")]
+    host_port: Option<u16>,
+
+    /// user namespace mode. `host` disables remapping, `keep-id` makes files written to the mount owned by
+    /// your host user, `auto` uses a private range of subordinate ids. `keep-id` and `auto` require podman.
+    #[arg(long, value_enum)]
+    userns: Option<Userns>,
+
+    /// run as your host uid:gid instead of the image's user, so files created in the mount are owned by you.
+    /// this is the default on linux, the flag overrides `user_map = false` in the config. `HOME` is set to
+    /// `/tmp`, as the user usually has no home in the image. not needed with docker desktop or a rootless
+    /// engine, which map ownership themselves.
+    #[arg(long, visible_alias = "as-me", conflicts_with = "userns")]
+    user_map: bool,
+
+    /// run as the image's user (usually root) instead of your host user, eg: to install packages.
+    /// `user_map = false` in the config makes this the default
+    #[arg(long, conflicts_with_all = ["user_map", "run_as"])]
+    as_root: bool,
+
+    /// run as this user instead of the image's, eg: `1000:1000` or `node`. `HOME` is set to `/tmp` for a
+    /// numeric uid
+    #[arg(short = 'u', long = "user", value_name = "UID[:GID]", value_parser = parse_user, conflicts_with_all = ["user_map", "userns"])]
+    run_as: Option<String>,
+
+    /// add a supplementary group to the container user (eg: `kvm` or `994`). can be repeated.
+    /// group names are resolved to gids on the host so they match the owners of passed-through devices.
+    #[arg(long, value_name = "NAME|GID")]
+    group_add: Vec<String>,
+
+    /// give the container nvidia gpus, like `docker run --gpus`: `all`, a count (`2` or `count=2`) or the
+    /// devices (`device=0,1` or `0,1`). needs the nvidia container toolkit on the daemon's machine
+    #[arg(long, value_name = "all|COUNT|DEVICES", value_parser = parse_gpus)]
+    gpus: Option<DeviceRequest>,
+
+    /// drop a linux capability (eg: `NET_RAW`, or `ALL`). can be repeated. by default every capability is
+    /// dropped except CHOWN, DAC_OVERRIDE and FOWNER (so root can write to the project). passing `--cap-drop`
+    /// replaces that default, the container starts from docker's default set then
+    #[arg(long, value_name = "CAP", value_parser = parse_capability)]
+    cap_drop: Vec<String>,
+
+    /// add a linux capability (eg: `NET_BIND_SERVICE`). can be repeated.
+    #[arg(long, value_name = "CAP", value_parser = parse_capability)]
+    cap_add: Vec<String>,
+
+    /// the strictest sandbox: the hardened defaults without their opt-outs, and not even the capabilities root
+    /// keeps to write to the project. pair it with `--read-only`, or the project stays writable. refuses
+    /// `--cap-add`, `--privileged` and the `--security-opt`s that loosen it (eg: `seccomp=unconfined`)
+    #[arg(
+        long,
+        conflicts_with_all = ["cap_drop", "cap_add", "writable_rootfs", "allow_privilege_escalation", "privileged"]
+    )]
+    hardened: bool,
+
+    /// keep the root filesystem of the container writable. by default it is read-only, with a tmpfs at
+    /// `/tmp`; the project and `--volume`s are writable either way. needed to install packages with eg: apt
+    #[arg(long)]
+    writable_rootfs: bool,
+
+    /// allow processes to gain privileges, eg: through `sudo` or setuid binaries. by default the container
+    /// runs with `no-new-privileges`
+    #[arg(long)]
+    allow_privilege_escalation: bool,
+
+    /// a security option for the container: `seccomp=PROFILE.json`, `seccomp=unconfined`, `apparmor=PROFILE`,
+    /// `label=LABEL`, `systempaths=unconfined` or `no-new-privileges[:true|false]`. the seccomp profile is read
+    /// from the file, like `docker run` does. can be repeated
+    #[arg(long, value_name = "OPTION", value_parser = parse_security_opt)]
+    security_opt: Vec<String>,
+
+    /// mount an empty, in-memory filesystem, eg: `/run` or `/tmp:size=1g`. the options are those of
+    /// `mount -t tmpfs`. can be repeated. replaces the default `/tmp` tmpfs when given for `/tmp`
+    #[arg(long, value_name = "PATH[:OPTIONS]", value_parser = parse_tmpfs)]
+    tmpfs: Vec<(String, String)>,
+
+    /// allow running quarantine from inside a container (with the docker socket mounted).
+    /// the working directory is translated to the matching path on the host using the outer container's mounts.
+    #[arg(long)]
+    nested: bool,
+
+    /// mount the working directory by the path you see (`$PWD`) instead of resolving symlinks to the real path.
+    #[arg(long)]
+    no_resolve_symlinks: bool,
+
+    /// isolation technology for windows containers.
+    #[arg(long, value_enum)]
+    isolation: Option<Isolation>,
+
+    /// run the container in privileged mode. this disables nearly all of the isolation quarantine exists for,
+    /// the hardened defaults included, so you are asked to confirm before the container is created.
+    #[arg(long, conflicts_with = "hardened")]
+    privileged: bool,
+
+    /// skip the `--privileged` confirmation, for scripts.
+    #[arg(long, requires = "privileged")]
+    privileged_i_accept_the_risk: bool,
+
+    /// allow mounts that expose the docker or podman socket. whoever controls the socket controls the host,
+    /// so this hands the sandbox the keys to everything.
+    #[arg(long)]
+    dangerously_expose_engine_socket: bool,
+
+    /// resolve everything, but instead of starting a session print an equivalent `docker run` command
+    /// and the create-container request that would have been sent. nothing is pulled or created.
+    #[arg(long)]
+    dry_run: bool,
+
+    /// don't mount the working directory. the shell starts in the image's default working directory.
+    #[arg(long, conflicts_with = "no_resolve_symlinks")]
+    no_mount: bool,
+
+    /// don't mount the working directory, but still mount the `--volume`s and the mounts of the config.
+    #[arg(long, conflicts_with_all = ["no_mount", "no_resolve_symlinks", "read_only"])]
+    no_default_mount: bool,
+
+    /// copy the working directory into the container instead of mounting it, so the sandbox can't change
+    /// the files on the host. paths matched by `.gitignore`, `.dockerignore` or `--exclude` are left out
+    #[arg(long, conflicts_with_all = ["no_mount", "no_default_mount", "read_only", "watch"])]
+    copy_in: bool,
+
+    /// the daemon is on another machine, which doesn't have the working directory: it is copied in (see
+    /// `--copy-in`) instead of mounted. known without this for a tcp `--docker-host` other than localhost
+    #[arg(long)]
+    remote: bool,
+
+    /// at the end of a `--copy-in` session, copy the files back over the working directory (or into
+    /// `--copy-out-dir`). `.git` and the excluded paths are not copied back, and removed files stay on the host
+    #[arg(long, requires = "copy_in", conflicts_with = "persist")]
+    copy_out: bool,
+
+    /// where `--copy-out` writes the files instead of the working directory
+    #[arg(long, value_name = "DIR", requires = "copy_out")]
+    copy_out_dir: Option<PathBuf>,
+
+    /// a glob of `.gitignore` syntax that `--copy-in` leaves out, eg: `target/`. can be repeated
+    #[arg(long, value_name = "GLOB", requires = "copy_in")]
+    exclude: Vec<String>,
+
+    /// keep the downloads of a package manager in a named volume, so the next sessions don't fetch them
+    /// again. `cargo` also keeps the project's `target` directory. can be repeated
+    #[arg(long, value_name = "TOOL", value_parser = clap::builder::PossibleValuesParser::new(cache::PRESETS))]
+    cache: Vec<String>,
+
+    /// keep a directory in the named volume `quarantine-cache-NAME`, eg: `--cache-dir gradle:/root/.gradle`.
+    /// can be repeated
+    #[arg(long, value_name = "NAME:PATH", value_parser = parse_cache_dir)]
+    cache_dir: Vec<cache::Cache>,
+
+    /// bind mount a host path into the container (eg: `-v ~/.cache/pip:/root/.cache/pip`), read-write unless
+    /// `:ro` is given. relative host paths are relative to the working directory, `~` is your home directory.
+    /// can be repeated.
+    #[arg(
+        short,
+        long = "volume",
+        visible_alias = "mount",
+        value_name = "HOST:CONTAINER[:ro]",
+        value_parser = parse_volume,
+        conflicts_with = "no_mount"
+    )]
+    volumes: Vec<Volume>,
+
+    /// create the missing host directories of `--volume` instead of refusing to start. `--dry-run` only lists them
+    #[arg(long, requires = "volumes")]
+    create_missing: bool,
+
+    /// mount the working directory read-only, so the code in the sandbox can read the project but not change it.
+    #[arg(long, conflicts_with = "no_mount")]
+    read_only: bool,
+
+    /// mount all volumes of an existing container, read-write unless `:ro` is given. can be repeated.
+    #[arg(long, value_name = "NAME[:ro]")]
+    volumes_from: Vec<String>,
+
+    /// wait until a dependency is reachable from inside the container before starting the shell.
+    /// `tcp://HOST:PORT` waits for a connection, `http(s)://...` for a 2xx response. can be repeated.
+    #[arg(long, value_name = "URL", value_parser = wait_for::parse_probe)]
+    wait_for: Vec<wait_for::Probe>,
+
+    /// how long to wait for the `--wait-for` dependencies (eg: `90s`, `2m`).
+    #[arg(long, value_name = "DURATION", default_value = "60s", value_parser = parse_duration)]
+    wait_for_timeout: std::time::Duration,
+
+    /// end the session after this long (eg: `30s`, `10m`, `2h`), counted from the start of the container. the
+    /// container is stopped, killed when it doesn't stop within 10s, and quarantine exits with 124 like `timeout`
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    timeout: Option<std::time::Duration>,
+
+    /// print the settings read from the config files (and which `[image."pattern"]` rules matched), then exit.
+    #[arg(long)]
+    show_config: bool,
+
+    /// ignore `quarantine.toml` and the global config, eg: to reproduce a bug with the built-in defaults
+    #[arg(long)]
+    no_config: bool,
+
+    /// at the end of the session kill and remove the container right away, without the `on_stop` hooks
+    /// or a graceful stop.
+    #[arg(long)]
+    force_cleanup: bool,
+
+    /// leave the container running at the end of the session instead of removing it, so packages installed
+    /// in it survive. detaching leaves it running too. the next session with the same name and image enters it
+    /// again (starting it when it was stopped) instead of creating a new one. a `--timeout` still stops it, the
+    /// `on_stop` hooks don't run. its name is printed on exit.
+    #[arg(long, conflicts_with_all = ["force_cleanup", "with_deps"])]
+    persist: bool,
+
+    /// remove a container kept with `--persist` and start from a new one, eg: after the image was updated.
+    #[arg(long, visible_alias = "recreate")]
+    fresh: bool,
+
+    /// name of the container. defaults to `quarantine-<image>-<hash of the working directory>`, with `-2`, `-3`..
+    /// appended while other sessions use that name.
+    #[arg(long)]
+    name: Option<String>,
+
+    /// run the command given after `--`, and run it again whenever a file in the working directory changes.
+    /// files matched by `.gitignore` are not watched.
+    #[arg(long, requires = "command", conflicts_with_all = ["no_mount", "no_default_mount"])]
+    watch: bool,
+
+    /// the command for `--watch`, or else a command to run instead of the shell. its output is not sent
+    /// through a tty, so stdout and stderr stay apart, and quarantine exits with its exit code
+    #[arg(last = true, value_name = "COMMAND")]
+    command: Vec<String>,
+
+    /// the interactive program to attach to, eg: `bash` or `python3`, split into words like a posix shell
+    /// would, eg: `"bash --noprofile"`. defaults to `bash`, or `sh` when the image has no bash
+    #[arg(short, long, value_parser = parse_shell, conflicts_with_all = ["command", "watch"])]
+    shell: Option<String>,
+
+    /// append everything the container prints to this file, each chunk prefixed with a utc timestamp and
+    /// every session after a header line. not to be confused with `--log-file`, quarantine's own log
+    #[arg(short = 'l', long, value_name = "PATH", conflicts_with = "watch")]
+    session_log: Option<PathBuf>,
+
+    /// record everything the container prints to this file, as an asciinema v2 cast that `asciinema play`
+    /// replays. what is typed is not recorded
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["watch", "session_log"])]
+    record: Option<PathBuf>,
+
+    /// `cast`, or `raw` for the bytes as they were printed
+    #[arg(long, value_enum, default_value_t = session_log::Format::Cast, requires = "record")]
+    record_format: session_log::Format,
+
+    /// when the session ends, save the container's filesystem as a local image before it is removed, so
+    /// the next session can start from it with `-i`. defaults to `quarantine/<image>-snapshot:latest`.
+    /// the container is kept when the snapshot fails. the image is labelled `quarantine.source-image`
+    #[arg(
+        long,
+        visible_alias = "commit-as",
+        value_name = "NAME:TAG",
+        value_parser = parse_snapshot,
+        conflicts_with = "watch"
+    )]
+    snapshot: Option<Option<String>>,
+
+    /// once the snapshot is taken, also write it to this `quarantine save` archive (gzipped when it ends in
+    /// `.gz`), to hand someone the environment the session ended with. an existing file is not overwritten
+    #[arg(long, value_name = "PATH", requires = "snapshot")]
+    after_commit: Option<PathBuf>,
+
+    /// the keys that detach from the shell, eg: `ctrl-x,x`. with `--persist` the container is left running,
+    /// otherwise it is removed as when the shell exits. separated by commas, each a single character or
+    /// `ctrl-<key>`. defaults to `ctrl-p,ctrl-q`
+    #[arg(long, value_name = "KEYS", conflicts_with_all = ["command", "watch"])]
+    detach_keys: Option<terminal::DetachKeys>,
+
+    /// publish a port of the container on the host, eg: `3000` (the same port on the host), `8080:80`,
+    /// `127.0.0.1:5432:5432` or `0:53/udp` (any free host port). can be repeated
+    #[arg(short, long, value_name = "[HOST_IP:][HOST:]CONTAINER[/PROTO]", value_parser = parse_publish)]
+    publish: Vec<compose::Port>,
+
+    /// publish every port the image exposes on free host ports.
+    #[arg(long)]
+    publish_all: bool,
+
+    /// join the network namespace of a running container, so `localhost` reaches the servers it runs.
+    #[arg(
+        long,
+        value_name = "NAME",
+        conflicts_with_all = ["host_gateway", "host_port", "publish", "publish_all"]
+    )]
+    share_net_with: Option<String>,
+
+    /// the network of the container: `none`, `bridge`, `host` or the name of a docker network. defaults to
+    /// the daemon's default (`bridge` on linux). `none` gives the strongest isolation, the container gets
+    /// nothing but a loopback interface
+    #[arg(
+        short,
+        long,
+        value_name = "MODE",
+        value_parser = parse_network,
+        conflicts_with_all = ["share_net_with", "with_deps"]
+    )]
+    network: Option<String>,
+
+    /// ipc namespace. `host` shares shared memory and semaphores with the host, `shareable` lets other
+    /// containers join this one with `container:NAME`. `host` needs `--privileged`, the hardened sandbox refuses it
+    #[arg(long, value_name = "private|host|shareable|container:NAME", value_parser = parse_ipc)]
+    ipc: Option<String>,
+
+    /// uts namespace. `host` shares the hostname of the host. it needs `--privileged`, the hardened sandbox refuses
+    /// it
+    #[arg(long, value_enum)]
+    uts: Option<Uts>,
+}
+
+/// when `--pull` pulls the image.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum PullPolicy {
+    /// every time, to get the newest image for the tag
+    Always,
+    /// only when it is not present locally
+    Missing,
+    /// never, like `--offline`
+    Never,
+}
+
+/// uts namespace modes accepted by `--uts`.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Uts {
+    Private,
+    Host,
+}
+
+fn parse_env(s: &str) -> anyhow::Result<String> {
+    let key = s.split_once('=').map_or(s, |(key, _)| key);
+    match env_file::valid_key(key) {
+        true => Ok(s.to_string()),
+        false => Err(anyhow!(
+            "expected `KEY=VALUE` or `KEY`, without whitespace in KEY"
+        )),
+    }
+}
+
+fn parse_build_arg(s: &str) -> anyhow::Result<(String, String)> {
+    match s.split_once('=') {
+        Some((key, value)) if env_file::valid_key(key) => Ok((key.to_string(), value.to_string())),
+        _ => Err(anyhow!("expected `KEY=VALUE`, without whitespace in KEY")),
+    }
+}
+
+fn parse_publish(s: &str) -> anyhow::Result<compose::Port> {
+    let (spec, _) = s.rsplit_once('/').unwrap_or((s, ""));
+    // a bare port is published on the same port of the host
+    let s = match spec.contains(':') {
+        true => s.to_string(),
+        false => format!("{}:{}", spec, s),
+    };
+    compose::parse_port(&serde_yaml::Value::String(s))
+        .filter(|port| !port.container_port.starts_with("0/"))
+        .ok_or_else(|| {
+            anyhow!("expected `[HOST_IP:][HOST:]CONTAINER[/PROTO]` with ports from 1 to 65535 (0 for any free host port), eg: `3000` or `8080:80`")
+        })
+}
+
+fn parse_user(s: &str) -> anyhow::Result<String> {
+    let (user, group) = s.split_once(':').unwrap_or((s, "x"));
+    match !user.is_empty() && !group.is_empty() && !s.contains(char::is_whitespace) {
+        true => Ok(s.to_string()),
+        false => Err(anyhow!(
+            "expected `UID[:GID]` or a user name, eg: `1000:1000` or `node`"
+        )),
+    }
+}
+
+fn parse_shell(s: &str) -> anyhow::Result<String> {
+    match shlex::split(s) {
+        Some(words) if !words.is_empty() => Ok(s.to_string()),
+        Some(_) => Err(anyhow!(
+            "expected a program, eg: `bash` or `bash --noprofile`"
+        )),
+        None => Err(anyhow!("unbalanced quotes or a trailing `\\`")),
+    }
+}
+
+fn parse_cache_dir(s: &str) -> anyhow::Result<cache::Cache> {
+    match s.split_once(':') {
+        Some((name, path))
+            if !name.is_empty()
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c))
+                && path.starts_with('/') =>
+        {
+            Ok(cache::Cache::new(name, path))
+        }
+        _ => Err(anyhow!(
+            "expected `NAME:PATH` with a NAME of letters, digits, `-`, `_` or `.` and an absolute PATH, eg: `gradle:/root/.gradle`"
+        )),
+    }
+}
+
+/// the daemon's error for `--gpus` without the nvidia container toolkit.
+const GPU_DRIVER_ERROR: &str = "could not select device driver";
+
+/// explains how to get `--gpus` working when the daemon has no gpu driver, other errors are left as they are.
+fn gpu_hint(e: anyhow::Error) -> anyhow::Error {
+    match e.to_string().contains(GPU_DRIVER_ERROR) {
+        true => anyhow!(
+            "the daemon can't give the container gpus: {}. install the nvidia container toolkit \
+             (https://docs.nvidia.com/datacenter/cloud-native/container-toolkit/latest/install-guide.html), \
+             run `nvidia-ctk runtime configure --runtime=docker` and restart the daemon",
+            e
+        ),
+        false => e,
+    }
+}
+
+/// `all`, `N`, `count=N|all`, `device=ID,...` or `ID,...`, as `docker run --gpus` takes them.
+fn parse_gpus(s: &str) -> anyhow::Result<DeviceRequest> {
+    let request = |count: Option<i64>, device_ids: Option<Vec<String>>| DeviceRequest {
+        driver: Some("nvidia".to_string()),
+        count,
+        device_ids,
+        capabilities: Some(vec![vec!["gpu".to_string()]]),
+        ..Default::default()
+    };
+    let count = s.strip_prefix("count=").unwrap_or(s);
+    if count == "all" {
+        return Ok(request(Some(-1), None));
+    }
+    // a single number is a count, like docker takes it. `device=1` is the second gpu
+    if let Ok(count) = count.parse::<i64>() {
+        return match count > 0 {
+            true => Ok(request(Some(count), None)),
+            false => Err(anyhow!("expected at least one gpu, eg: `1` or `all`")),
+        };
+    }
+    let devices = s.strip_prefix("device=").unwrap_or(s);
+    let ids = devices
+        .split(',')
+        .map(String::from)
+        .collect::<Vec<String>>();
+    match !s.starts_with("count=") && ids.iter().all(|id| !id.is_empty() && !id.contains('=')) {
+        true => Ok(request(None, Some(ids))),
+        false => Err(anyhow!(
+            "expected `all`, a count (eg: `2`) or device ids (eg: `device=0,1`)"
+        )),
+    }
+}
+
+fn parse_platform(s: &str) -> anyhow::Result<String> {
+    let parts = s.split('/').collect::<Vec<&str>>();
+    match (2..=3).contains(&parts.len()) && parts.iter().all(|part| !part.is_empty()) {
+        true => Ok(s.to_string()),
+        false => Err(anyhow!(
+            "expected `OS/ARCH[/VARIANT]`, eg: `linux/amd64` or `linux/arm/v7`"
+        )),
+    }
+}
+
+fn parse_tmpfs(s: &str) -> anyhow::Result<(String, String)> {
+    let (path, options) = s.split_once(':').unwrap_or((s, ""));
+    match path.starts_with('/') {
+        true => Ok((path.to_string(), options.to_string())),
+        false => Err(anyhow!(
+            "expected an absolute path in the container, eg: `/run` or `/tmp:size=1g`"
+        )),
+    }
+}
+
+/// the options of `docker run --security-opt`. a seccomp profile is passed as its contents, the daemon can't
+/// read the file.
+fn parse_security_opt(s: &str) -> anyhow::Result<String> {
+    let (key, value) = s.split_once(['=', ':']).unwrap_or((s, ""));
+    match (key, value) {
+        ("no-new-privileges", "" | "true" | "false") => Ok(s.to_string()),
+        ("seccomp", "unconfined") | ("systempaths", "unconfined") => Ok(s.to_string()),
+        ("seccomp", path) if !path.is_empty() => {
+            let profile = std::fs::read_to_string(path)
+                .map_err(|e| anyhow!("unable to read the seccomp profile `{}`: {}", path, e))?;
+            serde_json::from_str::<serde_json::Value>(&profile)
+                .map_err(|e| anyhow!("the seccomp profile `{}` is not valid json: {}", path, e))?;
+            Ok(format!("seccomp={}", profile))
+        }
+        ("apparmor" | "label", value) if !value.is_empty() => Ok(s.to_string()),
+        _ => Err(anyhow!(
+            "expected `seccomp=PROFILE.json`, `seccomp=unconfined`, `apparmor=PROFILE`, `label=LABEL`, \
+             `systempaths=unconfined` or `no-new-privileges[:true|false]`"
+        )),
+    }
+}
+
+/// whether a parsed `--security-opt` undoes part of the hardened defaults, which `--hardened` refuses.
+fn loosens_hardening(option: &str) -> bool {
+    let (key, value) = option.split_once(['=', ':']).unwrap_or((option, ""));
+    matches!(
+        (key, value),
+        ("no-new-privileges", "false")
+            | ("seccomp" | "systempaths" | "apparmor", "unconfined")
+            | ("label", "disable")
+    )
+}
+
+/// the well known modes, or the name of a user-defined network.
+fn parse_network(s: &str) -> anyhow::Result<String> {
+    match s {
+        "none" | "bridge" | "host" => Ok(s.to_string()),
+        "" => Err(anyhow!(
+            "expected `none`, `bridge`, `host` or a network name"
+        )),
+        _ if s.starts_with("container:") => Err(anyhow!(
+            "use --share-net-with to join the network of a container"
+        )),
+        _ => Ok(s.to_string()),
+    }
+}
+
+/// a `--volume`.
+#[derive(Debug, Clone, PartialEq)]
+struct Volume {
+    /// absolute
+    host: PathBuf,
+    container: String,
+    /// `ro` or `rw`, passed on as given
+    mode: Option<String>,
+}
+
+impl Volume {
+    /// makes sure the host path exists, creating it as a directory when `create` is set. a dry run doesn't touch
+    /// the host, it tells whether the directory would be created instead.
+    fn ensure_host(&self, create: bool, dry_run: bool) -> anyhow::Result<bool> {
+        match (self.host.exists(), create) {
+            (true, _) => Ok(false),
+            (false, true) if dry_run => Ok(true),
+            (false, true) => {
+                tracing::info!("creating `{}` for --volume", self.host.display());
+                std::fs::create_dir_all(&self.host)?;
+                Ok(false)
+            }
+            (false, false) => Err(anyhow!(
+                "the --volume source `{}` does not exist. pass --create-missing to create it",
+                self.host.display()
+            )),
+        }
+    }
+
+    /// as a bind string, `HOST:CONTAINER[:MODE]`.
+    fn bind(&self) -> anyhow::Result<String> {
+        let host = self
+            .host
+            .to_str()
+            .ok_or_else(|| anyhow!("`{}` is not valid unicode", self.host.display()))?;
+        Ok(match &self.mode {
+            Some(mode) => format!("{}:{}:{}", host, self.container, mode),
+            None => format!("{}:{}", host, self.container),
+        })
+    }
+}
+
+/// `HOST:CONTAINER[:ro|:rw]`, with `~` expanded and the host path made absolute against the working directory.
+fn parse_volume(s: &str) -> anyhow::Result<Volume> {
+    let (spec, mode) = match s.rsplit_once(':') {
+        Some((spec, mode @ ("ro" | "rw"))) => (spec, Some(mode.to_string())),
+        _ => (s, None),
+    };
+    let (host, container) = spec
+        .rsplit_once(':')
+        .filter(|(host, container)| !host.is_empty() && container.starts_with('/'))
+        .ok_or_else(|| anyhow!("expected `HOST:CONTAINER[:ro]` with an absolute CONTAINER path"))?;
+    let host = match (host.strip_prefix('~'), std::env::var_os("HOME")) {
+        (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with('/') => {
+            PathBuf::from(home).join(rest.trim_start_matches('/'))
+        }
+        _ => PathBuf::from(host),
+    };
+    Ok(Volume {
+        host: std::path::absolute(host)?,
+        container: container.to_string(),
+        mode,
+    })
+}
+
+/// a `--docker-host` with a scheme quarantine can connect with.
+fn parse_docker_host(s: &str) -> anyhow::Result<String> {
+    match s.split_once("://") {
+        Some(("unix" | "npipe" | "tcp" | "http" | "https" | "ssh", address)) if !address.is_empty() => {
+            Ok(s.to_string())
+        }
+        _ => Err(anyhow!(
+            "expected unix://PATH, npipe://PATH, tcp://HOST:PORT, https://HOST:PORT or ssh://[USER@]HOST[:PORT]"
+        )),
+    }
+}
+
+/// a capability name as docker takes it, eg: `NET_RAW` or `CAP_NET_RAW`.
+fn parse_capability(s: &str) -> anyhow::Result<String> {
+    match !s.is_empty()
+        && s.chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_')
+    {
+        true => Ok(s.to_string()),
+        false => Err(anyhow!(
+            "expected an uppercase capability name, eg: `NET_RAW` or `ALL`"
+        )),
+    }
+}
+
+/// an image name for `docker commit`, eg: `my-python` or `registry.example.com/me/python:tools`.
+fn parse_snapshot(s: &str) -> anyhow::Result<String> {
+    let repository = match s.rsplit_once(':') {
+        Some((repository, tag)) if !tag.contains('/') => repository,
+        _ => s,
+    };
+    match !repository.is_empty() && !s.contains('@') && repository == repository.to_lowercase() {
+        true => Ok(s.to_string()),
+        false => Err(anyhow!(
+            "expected a lowercase image name with an optional tag, eg: `my-python` or `my-python:tools`"
+        )),
+    }
+}
+
+fn parse_ipc(s: &str) -> anyhow::Result<String> {
+    match s {
+        "private" | "host" | "shareable" => Ok(s.to_string()),
+        _ if s
+            .strip_prefix("container:")
+            .is_some_and(|name| !name.is_empty()) =>
+        {
+            Ok(s.to_string())
+        }
+        _ => Err(anyhow!(
+            "expected `private`, `host`, `shareable` or `container:NAME`"
+        )),
+    }
+}
+
+/// asks on the terminal before starting a privileged container. without a terminal to ask on, refuses.
+fn confirm_privileged() -> anyhow::Result<()> {
+    use std::io::{BufRead, IsTerminal, Write};
+
+    if !std::io::stdin().is_terminal() {
+        return Err(anyhow!(
+            "`--privileged` needs an interactive confirmation. pass `--privileged-i-accept-the-risk` to skip it"
+        ));
+    }
+
+    let mut stderr = std::io::stderr();
+    writeln!(
+        stderr,
+        "--privileged gives the container full access to the host. it disables:
+  - capability dropping (the container gets every capability, including CAP_SYS_ADMIN)
+  - the seccomp and apparmor/selinux profiles
+  - device isolation (every device under /dev on the host is available)
+  - read-only /proc and /sys paths (the container can change kernel parameters and load modules)
+  - quarantine's hardened defaults (the read-only root filesystem and no-new-privileges)
+anything running inside can take over the host. nothing about the session is quarantined."
+    )?;
+    write!(stderr, "start a privileged container? [y/N] ")?;
+    stderr.flush()?;
+
+    let mut answer = String::new();
+    std::io::stdin().lock().read_line(&mut answer)?;
+    match answer.trim().to_lowercase().as_str() {
+        "y" | "yes" => Ok(()),
+        _ => Err(anyhow!("aborted, the privileged container was not started")),
+    }
+}
+
+/// resolves a group name to its gid using the host's `/etc/group`. numeric gids are passed through as-is.
+/// names that don't exist on the host are passed through for the container to resolve.
+fn resolve_group(group: &str) -> String {
+    if group.parse::<u32>().is_ok() {
+        return group.to_string();
+    }
+
+    let gid = std::fs::read_to_string("/etc/group")
+        .unwrap_or_default()
+        .lines()
+        .map(|line| line.split(':').collect::<Vec<&str>>())
+        .find(|fields| fields.first() == Some(&group))
+        .and_then(|fields| fields.get(2).map(|gid| gid.to_string()));
+
+    match gid {
+        Some(gid) => {
+            tracing::info!("resolved group `{}` to gid {}", group, gid);
+            gid
+        }
+        None => {
+            tracing::warn!(
+                "group `{}` does not exist on the host. passing the name to the container as-is",
+                group
+            );
+            group.to_string()
+        }
+    }
+}
+
+/// user namespace modes accepted by `--userns`.
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Userns {
+    Host,
+    KeepId,
+    Auto,
+}
+
+/// where the working directory is mounted inside the container.
+const PROJECT_MOUNT: &str = "/quarantine";
+
+/// where the working directory is mounted inside windows containers.
+const WINDOWS_PROJECT_MOUNT: &str = r"C:\quarantine";
+
+/// isolation technologies accepted by `--isolation`.
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Isolation {
+    Process,
+    Hyperv,
+}
+
+/// removes the anonymous volumes the image declared with `VOLUME` along with the container.
+/// named volumes are never removed this way.
+const REMOVE_WITH_ANONYMOUS_VOLUMES: RemoveContainerOptions = RemoveContainerOptions {
+    v: true,
+    force: false,
+    link: false,
+};
+
+/// the smallest memory limit docker accepts.
+const MIN_MEMORY: i64 = 6 * 1024 * 1024;
+
+/// how long to wait for a container that is already being removed to disappear.
+const REMOVAL_WAIT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// set on containers started with `--privileged`.
+const PRIVILEGED_LABEL: &str = "quarantine.privileged";
+
+/// the exit code of a session that reached its `--timeout`, the one of coreutils' `timeout`.
+const TIMEOUT_EXIT_CODE: i32 = 124;
+
+/// how long a container that reached the `--timeout` has to stop before it is killed.
+const TIMEOUT_GRACE: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// how long to keep copying container output after stdin reached EOF.
+const OUTPUT_DRAIN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// how often a command whose stdin ended is checked for having exited.
+const EXIT_POLL: std::time::Duration = std::time::Duration::from_secs(1);
+
+const HOST_GATEWAY_NAME: &str = "host.docker.internal";
+
+/// runs the command given after `--` without a tty, feeds it our stdin, streams its stdout and stderr to ours and
+/// returns its exit code. ctrl-c stops waiting for it, like a shell would (exit code 130).
+async fn run_command(
+    docker: &Docker,
+    container: &str,
+    command: &[String],
+    env: &[String],
+    user: Option<&str>,
+    mut recording: Option<session_log::SessionLog>,
+) -> anyhow::Result<i64> {
+    tracing::info!("running `{}`", command.join(" "));
+    let exec = docker
+        .create_exec(
+            container,
+            CreateExecOptions {
+                attach_stdin: Some(true),
+                attach_stdout: Some(true),
+                attach_stderr: Some(true),
+                tty: Some(false),
+                cmd: Some(command.iter().map(String::as_str).collect()),
+                env: Some(env.iter().map(String::as_str).collect()),
+                user,
+                ..Default::default()
+            },
+        )
+        .await?;
+
+    let StartExecResults::Attached {
+        mut output,
+        mut input,
+    } = docker.start_exec(&exec.id, None).await?
+    else {
+        return Err(anyhow!(
+            "failed to run `{}` inside the container",
+            command.join(" ")
+        ));
+    };
+
+    let (shutdown, mut shutdown_requested) = tokio::sync::watch::channel(false);
+    let mut input_task = tokio::spawn(async move {
+        // copy stdin to the command's input
+        let mut stdin = read_stdin();
+        loop {
+            let chunk = tokio::select! {
+                _ = shutdown_requested.changed() => break,
+                chunk = stdin.recv() => chunk,
+            };
+            match chunk.transpose()? {
+                Some(chunk) => input.write_all(&chunk).await?,
+                None => {
+                    tracing::info!("EOF reached on stdin");
+                    // half-close, so the command sees EOF but can still finish writing its output
+                    input.shutdown().await?;
+                    break;
+                }
+            }
+        }
+        Ok::<_, std::io::Error>(())
+    });
+    // the output ends when the command exits, unless something it left running in the background still holds it
+    let drained = async {
+        // a command that doesn't read its input may close it early, that is not an error
+        let _ = (&mut input_task).await;
+        while let Ok(ExecInspectResponse {
+            running: Some(true),
+            ..
+        }) = docker.inspect_exec(&exec.id).await
+        {
+            tokio::time::sleep(EXIT_POLL).await;
+        }
+        tokio::time::sleep(OUTPUT_DRAIN_TIMEOUT).await;
+    };
+
+    let copy = async {
+        let mut stdout = tokio::io::stdout();
+        let mut stderr = tokio::io::stderr();
+        while let Some(output) = output.next().await {
+            match output? {
+                LogOutput::StdErr { message } => {
+                    record(&mut recording, "stderr", &message).await;
+                    stderr.write_all(&message).await?
+                }
+                LogOutput::StdOut { message } => {
+                    record(&mut recording, "stdout", &message).await;
+                    stdout.write_all(&message).await?
+                }
+                LogOutput::Console { message } => {
+                    record(&mut recording, "console", &message).await;
+                    stdout.write_all(&message).await?
+                }
+                LogOutput::StdIn { .. } => {}
+            }
+            stdout.flush().await?;
+            stderr.flush().await?;
+        }
+        Ok::<_, anyhow::Error>(())
+    };
+    let interrupted = tokio::select! {
+        copied = copy => { copied?; false }
+        _ = drained => {
+            tracing::warn!(
+                "the output of `{}` did not end within {:?} of it exiting",
+                command.join(" "),
+                OUTPUT_DRAIN_TIMEOUT
+            );
+            false
+        }
+        _ = tokio::signal::ctrl_c() => {
+            tracing::warn!("interrupted `{}`", command.join(" "));
+            true
+        }
+    };
+    let _ = shutdown.send(true);
+    input_task.abort();
+    if interrupted {
+        return Ok(130);
+    }
+
+    docker
+        .inspect_exec(&exec.id)
+        .await?
+        .exit_code
+        .ok_or_else(|| anyhow!("`{}` did not report an exit code", command.join(" ")))
+}
+
+/// reads stdin in chunks on a thread of its own. a read blocked in `tokio::io::stdin` would keep the runtime from
+/// shutting down once the command exited, a plain thread just ends with the process.
+fn read_stdin() -> tokio::sync::mpsc::Receiver<std::io::Result<Vec<u8>>> {
+    let (chunks, received) = tokio::sync::mpsc::channel(16);
+    std::thread::spawn(move || {
+        let mut stdin = std::io::stdin().lock();
+        let mut buffer = vec![0; 8192];
+        loop {
+            let chunk = match std::io::Read::read(&mut stdin, &mut buffer) {
+                // EOF, the receiver sees the channel close
+                Ok(0) => return,
+                Ok(read) => Ok(buffer[..read].to_vec()),
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => Err(e),
+            };
+            let failed = chunk.is_err();
+            if chunks.blocking_send(chunk).is_err() || failed {
+                return;
+            }
+        }
+    });
+    received
+}
+
+/// tells why the shell vanished when it was killed for using more memory than `--memory` allows.
+async fn report_oom(docker: &Docker, container_name: &str, exit_code: Option<i64>) {
+    let Ok(inspect) = docker.inspect_container(container_name, None).await else {
+        return;
+    };
+    let Some(memory) = inspect
+        .host_config
+        .and_then(|host_config| host_config.memory)
+        .filter(|memory| *memory > 0)
+    else {
+        return;
+    };
+    let oom_killed = inspect
+        .state
+        .and_then(|state| state.oom_killed)
+        .unwrap_or_default();
+    // a process killed by the oom killer exits with 128 + SIGKILL
+    if oom_killed {
+        tracing::warn!(
+            "out of memory: a process was killed for going over the memory limit of {} bytes. raise it with --memory",
+            memory
+        );
+    } else if exit_code == Some(137) {
+        tracing::warn!(
+            "the shell was killed (exit code 137), likely for going over the memory limit of {} bytes. raise it with --memory",
+            memory
+        );
+    }
+}
+
+/// appends output to the `--session-log`, if there is one.
+async fn record(recording: &mut Option<session_log::SessionLog>, stream: &str, chunk: &[u8]) {
+    if let Some(recording) = recording {
+        recording.write(stream, chunk).await;
+    }
+}
+
+/// the first of `bash` and `sh` that runs in the container.
+async fn find_shell(docker: &Docker, container: &str) -> Option<&'static str> {
+    for shell in ["bash", "sh"] {
+        // not `command -v`, that needs a shell to begin with
+        if let Ok(0) = exec_status(docker, container, vec![shell, "-c", "exit 0"]).await {
+            tracing::debug!("found `{}` in the container", shell);
+            return Some(shell);
+        }
+    }
+    None
+}
+
+/// the directory to mount into the sandbox.
+/// `getcwd` already resolves symlinks on most platforms, so the literal path comes from `$PWD`.
+fn project_dir(resolve_symlinks: bool) -> anyhow::Result<std::path::PathBuf> {
+    let current_dir = std::env::current_dir()?;
+    let literal = std::env::var_os("PWD")
+        .map(std::path::PathBuf::from)
+        .filter(|pwd| std::fs::canonicalize(pwd).ok() == std::fs::canonicalize(&current_dir).ok())
+        .unwrap_or(current_dir);
+
+    if !resolve_symlinks {
+        return Ok(literal);
+    }
+
+    match std::fs::canonicalize(&literal) {
+        Ok(resolved) => {
+            if resolved != literal {
+                tracing::info!(
+                    "resolved symlinked working directory `{}` to `{}`",
+                    literal.display(),
+                    resolved.display()
+                );
+            }
+            Ok(resolved)
+        }
+        Err(e) => {
+            tracing::warn!(
+                "unable to resolve symlinks in `{}` ({}). mounting it as-is",
+                literal.display(),
+                e
+            );
+            Ok(literal)
+        }
+    }
+}
+
+/// the directory to mount as the project. when quarantine itself runs in a container (and `--nested` allows it),
+/// that is the matching directory on the host.
+async fn mounted_project_dir(
+    docker: &Docker,
+    resolve_symlinks: bool,
+    nested: bool,
+) -> anyhow::Result<std::path::PathBuf> {
+    let project_dir = project_dir(resolve_symlinks)?;
+    if nested::running_in_container() {
+        if !nested {
+            return Err(anyhow!(
+                "quarantine seems to be running inside a container. the working directory `{}` only \
+                 exists inside this container, so the sandbox would see an empty directory. \
+                 pass `--nested` to mount the matching path from the host instead",
+                project_dir.display()
+            ));
+        }
+        let host_dir = nested::host_path(docker, &project_dir).await?;
+        tracing::info!(
+            "running nested. mounting `{}` from the host for `{}`",
+            host_dir.display(),
+            project_dir.display()
+        );
+        return Ok(host_dir);
+    }
+    Ok(project_dir)
+}
+
+/// the credentials of `--registry-user`, with the password from the first line of stdin.
+fn registry_login(username: String, registry: &str) -> anyhow::Result<DockerCredentials> {
+    let mut password = String::new();
+    std::io::stdin().read_line(&mut password)?;
+    let password = password.trim_end_matches(['\r', '\n']);
+    if password.is_empty() {
+        return Err(anyhow!(
+            "--registry-password-stdin read an empty password from stdin"
+        ));
+    }
+    tracing::info!("logging in to `{}` as `{}`", registry, username);
+    Ok(DockerCredentials {
+        username: Some(username),
+        password: Some(password.to_string()),
+        serveraddress: Some(registry.to_string()),
+        ..Default::default()
+    })
+}
+
+/// whether to run as the host user: by default, unless `--as-root` or `user_map = false` in the config opts out.
+/// `--user-map` wins over the config.
+fn wants_user_map(user_map: bool, as_root: bool, config: Option<bool>) -> bool {
+    !as_root && (user_map || config.unwrap_or(true))
+}
+
+/// the uid and gid of the host user, `None` where the daemon runs in a vm that maps file ownership itself.
+fn host_ids() -> Option<(u32, u32)> {
+    #[cfg(target_os = "linux")]
+    {
+        // SAFETY: getuid and getgid cannot fail and have no preconditions
+        Some(unsafe { (libc::getuid(), libc::getgid()) })
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        None
+    }
+}
+
+/// `uid:gid` to run as for `--user-map`, or why the mapping doesn't apply.
+fn mapped_user(
+    ids: Option<(u32, u32)>,
+    windows: bool,
+    docker_desktop: bool,
+    rootless: bool,
+) -> Result<String, &'static str> {
+    match ids {
+        _ if windows => Err("the host user does not apply to windows containers"),
+        _ if docker_desktop => Err("docker desktop maps the ownership of mounted files itself"),
+        // root in the container already is the host user, the host uid would be a subordinate id
+        _ if rootless => Err("the rootless engine maps its root to the host user"),
+        Some((uid, gid)) => Ok(format!("{}:{}", uid, gid)),
+        None => Err("the daemon runs in a vm that maps file ownership"),
+    }
+}
+
+/// environment variables that let scripts inside the sandbox detect that they are running in quarantine.
+/// `QUARANTINE_PROJECT` is left out when nothing is mounted.
+fn marker_env(image_name: &str, project: Option<&str>) -> Vec<String> {
+    let mut env = vec![
+        "QUARANTINE=1".to_string(),
+        format!("QUARANTINE_VERSION={}", env!("CARGO_PKG_VERSION")),
+        format!("QUARANTINE_IMAGE={}", image_name),
+    ];
+    if let Some(project) = project {
+        env.push(format!("QUARANTINE_PROJECT={}", project));
+    }
+    env
+}
+
+/// refuses memory limits docker would refuse, before anything is created.
+fn check_memory(
+    memory: Option<i64>,
+    memory_swap: Option<i64>,
+    memory_reservation: Option<i64>,
+) -> anyhow::Result<()> {
+    if let Some(memory) = memory.filter(|memory| *memory < MIN_MEMORY) {
+        return Err(anyhow!(
+            "--memory ({} bytes) must be at least 6m, docker refuses less",
+            memory
+        ));
+    }
+    match (memory, memory_swap) {
+        (None, Some(_)) => return Err(anyhow!("--memory-swap needs a --memory limit")),
+        (Some(memory), Some(memory_swap)) if memory_swap != -1 && memory_swap < memory => {
+            return Err(anyhow!(
+                "--memory-swap ({} bytes) must be -1 or at least --memory ({} bytes)",
+                memory_swap,
+                memory
+            ))
+        }
+        _ => {}
+    }
+    if let (Some(memory), Some(memory_reservation)) = (memory, memory_reservation) {
+        if memory_reservation > memory {
+            return Err(anyhow!(
+                "--memory-reservation ({} bytes) must not be greater than --memory ({} bytes)",
+                memory_reservation,
+                memory
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// parses a fractional number of cpus like `0.5` or `2`. docker's granularity is a hundredth of a cpu.
+fn parse_cpus(s: &str) -> anyhow::Result<f64> {
+    let cpus = s
+        .trim()
+        .parse::<f64>()
+        .map_err(|_| anyhow!("invalid number of cpus `{}`. expected eg: `1.5`", s))?;
+    match cpus.is_finite() && cpus >= 0.01 {
+        true => Ok(cpus),
+        false => Err(anyhow!("the number of cpus must be at least 0.01")),
+    }
+}
+
+/// parses a duration like `30`, `30s`, `5m` or `1h`. plain numbers are seconds.
+fn parse_duration(s: &str) -> anyhow::Result<std::time::Duration> {
+    let s = s.trim();
+    let (digits, multiplier) = match s.chars().last() {
+        Some('s') => (&s[..s.len() - 1], 1),
+        Some('m') => (&s[..s.len() - 1], 60),
+        Some('h') => (&s[..s.len() - 1], 60 * 60),
+        _ => (s, 1),
+    };
+
+    let value = digits.parse::<u64>().map_err(|_| {
+        anyhow!(
+            "invalid duration `{}`. expected a number with an optional s/m/h suffix (eg: `90s`)",
+            s
+        )
+    })?;
+    let duration = value
+        .checked_mul(multiplier)
+        .map(std::time::Duration::from_secs)
+        // it is added to the current time for deadlines
+        .filter(|duration| std::time::Instant::now().checked_add(*duration).is_some())
+        .ok_or_else(|| anyhow!("the duration `{}` is too long", s))?;
+    Ok(duration)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs_the_default_runtime_unless_asked() {
+        let available = ["runc".to_string(), "runsc".to_string()];
+        assert_eq!(
+            choose_runtime(None, "runc".to_string(), available.iter()),
+            "runc"
+        );
+    }
+
+    #[test]
+    fn runs_the_requested_runtime() {
+        let available = ["runc".to_string(), "runsc".to_string()];
+        assert_eq!(
+            choose_runtime(
+                Some("runsc".to_string()),
+                "runc".to_string(),
+                available.iter()
+            ),
+            "runsc"
+        );
+    }
+
+    #[test]
+    fn falls_back_to_the_default_runtime() {
+        let available = ["runc".to_string()];
+        assert_eq!(
+            choose_runtime(
+                Some("runsc".to_string()),
+                "runc".to_string(),
+                available.iter()
+            ),
+            "runc"
+        );
+    }
+
+    fn labelled(mount: &Mount) -> bool {
+        mount
+            .volume_options
+            .as_ref()
+            .and_then(|options| options.labels.as_ref())
+            .is_some_and(|labels| labels.contains_key(MANAGED_LABEL))
+    }
+
+    #[test]
+    fn labels_the_anonymous_volumes() {
+        let image = ImageInspect {
+            config: Some(bollard::secret::ContainerConfig {
+                volumes: Some(HashMap::from([
+                    ("/var/lib/postgresql/data".to_string(), HashMap::new()),
+                    ("/quarantine".to_string(), HashMap::new()),
+                    ("/tmp".to_string(), HashMap::new()),
+                    ("/data".to_string(), HashMap::new()),
+                ])),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let config = Config {
+            volumes: Some(HashMap::from([("/scratch".to_string(), HashMap::new())])),
+            host_config: Some(HostConfig {
+                mounts: Some(vec![Mount {
+                    source: Some("/project".to_string()),
+                    target: Some("/quarantine".to_string()),
+                    typ: Some(MountTypeEnum::BIND),
+                    ..Default::default()
+                }]),
+                binds: Some(vec!["/srv/data:/data:ro".to_string()]),
+                tmpfs: Some(HashMap::from([("/tmp".to_string(), String::new())])),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let volumes = anonymous_volumes(&image, &config);
+        let targets = volumes
+            .iter()
+            .map(|mount| mount.target.as_deref().unwrap())
+            .collect::<Vec<&str>>();
+        assert_eq!(targets, ["/scratch", "/var/lib/postgresql/data"]);
+        for volume in &volumes {
+            assert_eq!(volume.typ, Some(MountTypeEnum::VOLUME));
+            assert_eq!(volume.source, None);
+            assert!(labelled(volume));
+        }
+    }
+
+    #[test]
+    fn parses_durations() {
+        let secs = std::time::Duration::from_secs;
+        assert_eq!(parse_duration("30").unwrap(), secs(30));
+        assert_eq!(parse_duration("90s").unwrap(), secs(90));
+        assert_eq!(parse_duration(" 5m ").unwrap(), secs(300));
+        assert_eq!(parse_duration("2h").unwrap(), secs(7200));
+        assert_eq!(parse_duration("0").unwrap(), secs(0));
+        for invalid in ["", "s", "1.5m", "-1", "5d", "m5"] {
+            assert!(parse_duration(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn refuses_durations_out_of_range() {
+        let e = parse_duration(&format!("{}h", u64::MAX / 60)).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            format!("the duration `{}h` is too long", u64::MAX / 60)
+        );
+        // fits a u64, but not a deadline
+        assert!(parse_duration(&u64::MAX.to_string()).is_err());
+        let too_long = format!("{}m", u64::MAX);
+        let e = Quarantine::try_parse_from(["quarantine", "-i", "alpine", "--timeout", &too_long])
+            .unwrap_err();
+        assert_eq!(e.kind(), clap::error::ErrorKind::ValueValidation);
+    }
+
+    #[test]
+    fn marks_the_sandbox() {
+        assert_eq!(
+            marker_env("python:3.12", Some("/quarantine")),
+            [
+                "QUARANTINE=1".to_string(),
+                format!("QUARANTINE_VERSION={}", env!("CARGO_PKG_VERSION")),
+                "QUARANTINE_IMAGE=python:3.12".to_string(),
+                "QUARANTINE_PROJECT=/quarantine".to_string(),
+            ]
+        );
+        assert!(!marker_env("python:3.12", None)
+            .iter()
+            .any(|var| var.starts_with("QUARANTINE_PROJECT=")));
+    }
+
+    #[test]
+    fn refuses_memory_docker_refuses() {
+        let mib = 1024 * 1024;
+        assert!(check_memory(None, None, None).is_ok());
+        assert!(check_memory(Some(MIN_MEMORY), None, None).is_ok());
+        let e = check_memory(Some(MIN_MEMORY - 1), None, None).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            format!(
+                "--memory ({} bytes) must be at least 6m, docker refuses less",
+                MIN_MEMORY - 1
+            )
+        );
+
+        assert!(check_memory(None, Some(512 * mib), None).is_err());
+        assert!(check_memory(Some(512 * mib), Some(-1), None).is_ok());
+        assert!(check_memory(Some(512 * mib), Some(512 * mib), None).is_ok());
+        assert!(check_memory(Some(512 * mib), Some(256 * mib), None).is_err());
+
+        assert!(check_memory(Some(512 * mib), None, Some(256 * mib)).is_ok());
+        assert!(check_memory(Some(512 * mib), None, Some(1024 * mib)).is_err());
+        assert!(check_memory(None, None, Some(1024 * mib)).is_ok());
+    }
+
+    #[test]
+    fn parses_volumes() {
+        let volume = parse_volume("/data:/mnt/data").unwrap();
+        assert_eq!(
+            volume,
+            Volume {
+                host: PathBuf::from("/data"),
+                container: "/mnt/data".to_string(),
+                mode: None,
+            }
+        );
+        assert_eq!(volume.bind().unwrap(), "/data:/mnt/data");
+
+        let volume = parse_volume("/data:/mnt/data:ro").unwrap();
+        assert_eq!(volume.mode.as_deref(), Some("ro"));
+        assert_eq!(volume.bind().unwrap(), "/data:/mnt/data:ro");
+        let volume = parse_volume("/data:/mnt/data:rw").unwrap();
+        assert_eq!(volume.bind().unwrap(), "/data:/mnt/data:rw");
+
+        // relative host paths are made absolute against the working directory
+        let volume = parse_volume("data:/mnt/data").unwrap();
+        assert_eq!(volume.host, std::env::current_dir().unwrap().join("data"));
+
+        for invalid in [
+            "/data",
+            "/data:mnt",
+            ":/mnt/data",
+            "/data:ro",
+            "/data:/mnt:ro:rw",
+        ] {
+            assert!(parse_volume(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn creates_missing_volume_sources_only_when_asked() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("a/b");
+        let volume = parse_volume(&format!("{}:/mnt", missing.display())).unwrap();
+
+        let e = volume.ensure_host(false, false).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            format!(
+                "the --volume source `{}` does not exist. pass --create-missing to create it",
+                missing.display()
+            )
+        );
+        assert!(!missing.exists());
+        // a dry run only tells
+        assert!(volume.ensure_host(false, true).is_err());
+        assert!(volume.ensure_host(true, true).unwrap());
+        assert!(!missing.exists());
+
+        assert!(!volume.ensure_host(true, false).unwrap());
+        assert!(missing.is_dir());
+        // existing sources are fine either way
+        for (create, dry_run) in [(false, false), (true, false), (false, true), (true, true)] {
+            assert!(!volume.ensure_host(create, dry_run).unwrap());
+        }
+    }
+
+    #[test]
+    fn maps_the_host_user_unless_opted_out() {
+        assert!(wants_user_map(false, false, None));
+        assert!(wants_user_map(true, false, None));
+        assert!(!wants_user_map(false, true, None));
+        assert!(!wants_user_map(false, false, Some(false)));
+        assert!(wants_user_map(false, false, Some(true)));
+        // the flag wins over the config
+        assert!(wants_user_map(true, false, Some(false)));
+    }
+
+    #[test]
+    fn maps_the_host_uid_and_gid() {
+        assert_eq!(
+            mapped_user(Some((1000, 100)), false, false, false).as_deref(),
+            Ok("1000:100")
+        );
+        assert!(mapped_user(Some((1000, 100)), true, false, false).is_err());
+        assert!(mapped_user(Some((1000, 100)), false, true, false).is_err());
+        assert!(mapped_user(Some((1000, 100)), false, false, true).is_err());
+        assert_eq!(
+            mapped_user(None, false, false, false),
+            Err("the daemon runs in a vm that maps file ownership")
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn reads_the_host_ids_on_linux() {
+        let (uid, gid) = host_ids().unwrap();
+        // SAFETY: getuid and getgid cannot fail and have no preconditions
+        assert_eq!((uid, gid), unsafe { (libc::getuid(), libc::getgid()) });
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    #[test]
+    fn leaves_the_ids_to_the_vm_elsewhere() {
+        assert_eq!(host_ids(), None);
+    }
+
+    #[test]
+    fn as_root_conflicts_with_choosing_a_user() {
+        for args in [
+            ["quarantine", "--as-root", "--user-map"],
+            ["quarantine", "--as-root", "-u1000"],
+        ] {
+            let e = Quarantine::try_parse_from(args).unwrap_err();
+            assert_eq!(e.kind(), clap::error::ErrorKind::ArgumentConflict);
+        }
+        assert!(Quarantine::try_parse_from(["quarantine", "--as-root"]).is_ok());
+    }
+
+    /// parses like `main`, with the checks clap can't do on its own.
+    fn parse(args: &[&str]) -> Result<Quarantine, clap::Error> {
+        let quarantine = Quarantine::try_parse_from(args)?;
+        quarantine.check()?;
+        Ok(quarantine)
+    }
+
+    #[test]
+    fn hardened_refuses_what_undoes_it() {
+        for loosening in [
+            "--cap-add=SYS_ADMIN",
+            "--privileged",
+            "--writable-rootfs",
+            "--security-opt=no-new-privileges:false",
+            "--security-opt=no-new-privileges=false",
+            "--security-opt=seccomp=unconfined",
+            "--security-opt=systempaths=unconfined",
+            "--security-opt=apparmor=unconfined",
+            "--security-opt=label=disable",
+        ] {
+            for args in [
+                vec!["quarantine", "--hardened", loosening],
+                vec!["quarantine", "run", "--hardened", loosening],
+            ] {
+                let e = parse(&args).unwrap_err();
+                assert_eq!(
+                    e.kind(),
+                    clap::error::ErrorKind::ArgumentConflict,
+                    "{:?}",
+                    args
+                );
+            }
+        }
+        for tightening in [
+            "--security-opt=no-new-privileges",
+            "--security-opt=no-new-privileges:true",
+            "--security-opt=apparmor=docker-default",
+        ] {
+            assert!(parse(&["quarantine", "--hardened", tightening]).is_ok());
+        }
+        // without --hardened they are only opt-outs
+        assert!(parse(&["quarantine", "--security-opt=seccomp=unconfined"]).is_ok());
+    }
+
+    #[test]
+    fn offline_is_its_own_network() {
+        for args in [
+            ["quarantine", "--offline", "--network=bridge"],
+            ["quarantine", "--offline", "--share-net-with=db"],
+        ] {
+            let e = Quarantine::try_parse_from(args).unwrap_err();
+            assert_eq!(e.kind(), clap::error::ErrorKind::ArgumentConflict);
+        }
+    }
+
+    #[test]
+    fn after_commit_needs_a_snapshot() {
+        let e =
+            Quarantine::try_parse_from(["quarantine", "--after-commit", "env.tar"]).unwrap_err();
+        assert_eq!(e.kind(), clap::error::ErrorKind::MissingRequiredArgument);
+        for snapshot in ["--snapshot", "--commit-as=me/env:1"] {
+            assert!(Quarantine::try_parse_from([
+                "quarantine",
+                snapshot,
+                "--after-commit",
+                "env.tar"
+            ])
+            .is_ok());
+        }
+    }
+
+    #[test]
+    fn parses_docker_hosts() {
+        for host in [
+            "unix:///var/run/docker.sock",
+            "tcp://build-box:2375",
+            "https://build-box:2376",
+            "ssh://me@build-box",
+        ] {
+            assert_eq!(parse_docker_host(host).unwrap(), host);
+        }
+        for invalid in ["build-box:2375", "ftp://build-box", "tcp://", ""] {
+            assert!(parse_docker_host(invalid).is_err(), "{}", invalid);
+        }
+    }
 }
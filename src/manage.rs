@@ -0,0 +1,306 @@
+use anyhow::anyhow;
+use bollard::container::{ListContainersOptions, RemoveContainerOptions};
+use bollard::secret::{ContainerSummary, MountPointTypeEnum, Volume};
+use bollard::volume::ListVolumesOptions;
+use bollard::Docker;
+use std::collections::HashMap;
+
+use crate::{audit, cache, engine, pool, session, MANAGED_LABEL};
+
+pub async fn ls(pool: bool, caches: bool) -> anyhow::Result<()> {
+    let docker = engine::connect().await?;
+    if caches {
+        let caches = cache::list(&docker).await?;
+        if caches.is_empty() {
+            println!("no cache volumes. `--cache TOOL` creates them");
+        }
+        for (name, mountpoint) in caches {
+            println!("{}\t{}", name, mountpoint);
+        }
+        return Ok(());
+    }
+
+    let containers = match pool {
+        true => pool::members(&docker, None).await?,
+        false => sessions(&docker).await?,
+    };
+    if containers.is_empty() {
+        match pool {
+            true => println!("no prewarmed containers. `quarantine warm IMAGE` creates some"),
+            false => println!("no quarantine containers"),
+        }
+        return Ok(());
+    }
+
+    let rows = containers
+        .into_iter()
+        .map(|container| {
+            [
+                name(&container),
+                container.image.unwrap_or_default(),
+                container.state.unwrap_or_default(),
+                audit::format_time(container.created.unwrap_or_default().max(0) as u64),
+            ]
+        })
+        .collect::<Vec<[String; 4]>>();
+    let header = ["NAME", "IMAGE", "STATE", "CREATED"].map(String::from);
+    let widths = (0..4)
+        .map(|column| {
+            std::iter::once(&header)
+                .chain(&rows)
+                .map(|row| row[column].len())
+                .max()
+                .unwrap_or_default()
+        })
+        .collect::<Vec<usize>>();
+    for row in std::iter::once(&header).chain(&rows) {
+        println!(
+            "{:<w0$}   {:<w1$}   {:<w2$}   {}",
+            row[0],
+            row[1],
+            row[2],
+            row[3],
+            w0 = widths[0],
+            w1 = widths[1],
+            w2 = widths[2]
+        );
+    }
+    Ok(())
+}
+
+/// `quarantine kill` (or `rm`): stops and removes a session container, given its name or the image it was
+/// started from. unlike `clean --container`, a container that doesn't exist is an error.
+pub async fn kill(name: &str) -> anyhow::Result<()> {
+    let docker = engine::connect().await?;
+    let working_dir = std::env::current_dir()?;
+    // the image's session in this directory, or one of a quarantine that named containers after the image only
+    let candidates = [
+        name.to_string(),
+        session::default_name(name, Some(&working_dir)),
+        session::default_name(name, None),
+    ];
+    for candidate in candidates {
+        match docker.inspect_container(&candidate, None).await {
+            Ok(inspect) => {
+                let managed = inspect
+                    .config
+                    .and_then(|config| config.labels)
+                    .is_some_and(|labels| labels.contains_key(MANAGED_LABEL))
+                    // containers of versions from before the label
+                    || candidate.starts_with("quarantine-");
+                if !managed {
+                    return Err(anyhow!(
+                        "`{}` was not created by quarantine, remove it with `docker rm` instead",
+                        candidate
+                    ));
+                }
+                return remove_container(&docker, &candidate, false).await;
+            }
+            Err(e) if is_not_found(&e) => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Err(anyhow!(
+        "container `{}` not found. `quarantine ls` lists the quarantine containers",
+        name
+    ))
+}
+
+/// `quarantine prune`: removes the stopped session containers, and with `force` the running ones too.
+/// unclaimed prewarmed containers are left to `clean --pool`, the claimed ones are sessions like any other.
+pub async fn prune(force: bool) -> anyhow::Result<()> {
+    let docker = engine::connect().await?;
+
+    let mut removed = 0;
+    let mut skipped = 0;
+    for container in sessions(&docker).await? {
+        let pooled = container
+            .labels
+            .as_ref()
+            .is_some_and(|labels| labels.contains_key(pool::POOL_LABEL));
+        if pooled && pool::unclaimed(&container) {
+            continue;
+        }
+        let name = name(&container);
+        if container.state.as_deref() == Some("running") && !force {
+            tracing::info!("`{}` is running, pass --force to remove it too", name);
+            skipped += 1;
+            continue;
+        }
+        remove_container(&docker, &name, false).await?;
+        removed += 1;
+    }
+    tracing::info!("removed {} container(s)", removed);
+    if skipped > 0 {
+        tracing::info!("left {} running container(s)", skipped);
+    }
+    Ok(())
+}
+
+/// the containers quarantine created, running or not.
+async fn sessions(docker: &Docker) -> anyhow::Result<Vec<ContainerSummary>> {
+    Ok(docker
+        .list_containers(Some(ListContainersOptions::<String> {
+            all: true,
+            ..Default::default()
+        }))
+        .await?
+        .into_iter()
+        .filter(|container| {
+            container
+                .labels
+                .as_ref()
+                .is_some_and(|labels| labels.contains_key(MANAGED_LABEL))
+                // containers of versions from before the label
+                || container
+                    .names
+                    .iter()
+                    .flatten()
+                    .any(|name| name.trim_start_matches('/').starts_with("quarantine-"))
+        })
+        .collect())
+}
+
+fn name(container: &ContainerSummary) -> String {
+    container
+        .names
+        .iter()
+        .flatten()
+        .map(|name| name.trim_start_matches('/'))
+        .collect::<Vec<&str>>()
+        .join(",")
+}
+
+pub async fn clean(
+    pool: bool,
+    dangling_volumes: bool,
+    caches: bool,
+    container: Option<String>,
+    force: bool,
+) -> anyhow::Result<()> {
+    let docker = engine::connect().await?;
+    if let Some(container) = container {
+        remove_container(&docker, &container, force).await?;
+    }
+    if pool {
+        let removed = pool::clean(&docker).await?;
+        tracing::info!("removed {} pool member(s)", removed);
+    }
+    if dangling_volumes {
+        let removed = remove_dangling_volumes(&docker).await?;
+        tracing::info!("removed {} dangling volume(s)", removed);
+    }
+    if caches {
+        let removed = cache::clean(&docker).await?;
+        tracing::info!("removed {} cache volume(s)", removed);
+    }
+    Ok(())
+}
+
+/// removes a (possibly wedged) session container and its anonymous volumes, reporting each resource.
+/// a container that doesn't exist is not an error. `force` skips the graceful stop and kills the container.
+async fn remove_container(docker: &Docker, name: &str, force: bool) -> anyhow::Result<()> {
+    let inspect = match docker.inspect_container(name, None).await {
+        Ok(inspect) => inspect,
+        Err(e) if is_not_found(&e) => {
+            tracing::info!("container `{}` does not exist, nothing to remove", name);
+            return Ok(());
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let volumes = inspect
+        .mounts
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|mount| mount.typ == Some(MountPointTypeEnum::VOLUME))
+        .filter_map(|mount| mount.name)
+        .filter(|name| name.len() == 64 && name.chars().all(|c| c.is_ascii_hexdigit()))
+        .collect::<Vec<String>>();
+
+    let running = inspect
+        .state
+        .and_then(|state| state.running)
+        .unwrap_or_default();
+    if running && !force {
+        tracing::info!("stopping container: {}", name);
+        if let Err(e) = docker.stop_container(name, None).await {
+            tracing::warn!(
+                "unable to stop `{}` ({}). pass --force to kill it instead",
+                name,
+                e
+            );
+            return Err(anyhow!("container `{}` could not be removed", name));
+        }
+    }
+
+    let removed = docker
+        .remove_container(
+            name,
+            Some(RemoveContainerOptions {
+                force,
+                v: true,
+                link: false,
+            }),
+        )
+        .await;
+    match removed {
+        Ok(()) => {
+            tracing::info!("removed container: {}", name);
+            for volume in volumes {
+                tracing::info!("removed volume: {}", volume);
+            }
+            Ok(())
+        }
+        Err(e) if is_not_found(&e) => {
+            tracing::info!("removed container: {}", name);
+            Ok(())
+        }
+        Err(e) => {
+            tracing::warn!("unable to remove container `{}`: {}", name, e);
+            for volume in volumes {
+                tracing::warn!("volume `{}` was not removed", volume);
+            }
+            Err(anyhow!("container `{}` could not be removed", name))
+        }
+    }
+}
+
+fn is_not_found(e: &bollard::errors::Error) -> bool {
+    matches!(
+        e,
+        bollard::errors::Error::DockerResponseServerError {
+            status_code: 404,
+            ..
+        }
+    )
+}
+
+/// anonymous volumes get a random 64 character hex name, and docker 23+ also labels them.
+fn is_anonymous(volume: &Volume) -> bool {
+    volume.labels.contains_key("com.docker.volume.anonymous")
+        || (volume.name.len() == 64 && volume.name.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+/// removes the anonymous volumes quarantine labelled at creation that no container uses anymore. docker doesn't
+/// record which container created a volume, so unlabelled ones may be another tool's and are left alone.
+async fn remove_dangling_volumes(docker: &Docker) -> anyhow::Result<usize> {
+    let volumes = docker
+        .list_volumes(Some(ListVolumesOptions {
+            filters: HashMap::from([("dangling", vec!["true"]), ("label", vec![MANAGED_LABEL])]),
+        }))
+        .await?
+        .volumes
+        .unwrap_or_default();
+
+    let mut removed = 0;
+    // older daemons may ignore filters they don't know
+    let removable = volumes
+        .iter()
+        .filter(|volume| is_anonymous(volume) && volume.labels.contains_key(MANAGED_LABEL));
+    for volume in removable {
+        docker.remove_volume(&volume.name, None).await?;
+        tracing::info!("removed dangling volume: {}", volume.name);
+        removed += 1;
+    }
+    Ok(removed)
+}
@@ -0,0 +1,75 @@
+use anyhow::anyhow;
+use bollard::Docker;
+use std::path::{Path, PathBuf};
+
+/// whether quarantine itself is running inside a container (a devcontainer, a CI job, ...).
+pub fn running_in_container() -> bool {
+    if Path::new("/.dockerenv").exists() || Path::new("/run/.containerenv").exists() {
+        return true;
+    }
+
+    std::fs::read_to_string("/proc/1/cgroup")
+        .unwrap_or_default()
+        .lines()
+        .any(|line| {
+            ["docker", "kubepods", "containerd", "libpod", "lxc"]
+                .iter()
+                .any(|marker| line.contains(marker))
+        })
+}
+
+/// translates a path inside the container quarantine is running in to the matching path on the host,
+/// using the mounts of that outer container.
+pub async fn host_path(docker: &Docker, path: &Path) -> anyhow::Result<PathBuf> {
+    let id = own_container_id().ok_or_else(|| {
+        anyhow!("unable to determine the id of the container quarantine is running in")
+    })?;
+
+    let outer = docker.inspect_container(&id, None).await.map_err(|e| {
+        anyhow!(
+            "unable to inspect the container quarantine is running in (`{}`): {}",
+            id,
+            e
+        )
+    })?;
+
+    let (source, remainder) = outer
+        .mounts
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|mount| {
+            let source = PathBuf::from(mount.source?);
+            let destination = PathBuf::from(mount.destination?);
+            let remainder = path.strip_prefix(&destination).ok()?.to_path_buf();
+            Some((source, remainder, destination.components().count()))
+        })
+        // the most specific mount wins
+        .max_by_key(|(_, _, depth)| *depth)
+        .map(|(source, remainder, _)| (source, remainder))
+        .ok_or_else(|| {
+            anyhow!(
+                "`{}` is not inside any mount of the outer container `{}`, so it does not exist on the host",
+                path.display(),
+                id
+            )
+        })?;
+
+    Ok(source.join(remainder))
+}
+
+fn own_container_id() -> Option<String> {
+    // docker bind mounts /etc/hostname etc. from `/var/lib/docker/containers/<id>/`
+    let mountinfo = std::fs::read_to_string("/proc/self/mountinfo").unwrap_or_default();
+    let from_mountinfo = mountinfo.split_whitespace().find_map(|field| {
+        let (_, rest) = field.split_once("/containers/")?;
+        let id = rest.split('/').next()?;
+        (id.len() == 64 && id.chars().all(|c| c.is_ascii_hexdigit())).then(|| id.to_string())
+    });
+
+    from_mountinfo.or_else(|| {
+        std::fs::read_to_string("/etc/hostname")
+            .ok()
+            .map(|hostname| hostname.trim().to_string())
+            .filter(|hostname| !hostname.is_empty())
+    })
+}
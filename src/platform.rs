@@ -0,0 +1,65 @@
+use anyhow::anyhow;
+use std::path::Path;
+
+/// normalizes the architecture names used by the daemon (`uname -m` style, eg: `x86_64`)
+/// and by images (go style, eg: `amd64`) to the go style.
+pub fn normalize_arch(arch: &str) -> &str {
+    match arch {
+        "x86_64" | "amd64" => "amd64",
+        "aarch64" | "arm64" => "arm64",
+        "armv7l" | "armv6l" | "arm" => "arm",
+        "i386" | "i686" | "386" => "386",
+        other => other,
+    }
+}
+
+/// the name qemu-user-static registers its binfmt handler under for a go style architecture.
+fn qemu_arch(arch: &str) -> &str {
+    match arch {
+        "amd64" => "x86_64",
+        "arm64" => "aarch64",
+        "386" => "i386",
+        other => other,
+    }
+}
+
+/// checks that an image built for `image_arch` can run on a daemon running on `daemon_arch`.
+/// foreign images need binfmt emulation, which docker desktop ships with and plain linux hosts must install.
+pub fn check_emulation(
+    image_arch: &str,
+    daemon_arch: &str,
+    docker_desktop: bool,
+) -> anyhow::Result<()> {
+    let (image_arch, daemon_arch) = (normalize_arch(image_arch), normalize_arch(daemon_arch));
+    if image_arch == daemon_arch {
+        return Ok(());
+    }
+
+    if docker_desktop || binfmt_registered(image_arch) {
+        tracing::warn!(
+            "the image is built for `{}` but the daemon runs on `{}`. it will run under emulation, which is much slower",
+            image_arch,
+            daemon_arch
+        );
+        return Ok(());
+    }
+
+    Err(anyhow!(
+        "the image is built for `{}` but the daemon runs on `{}`, and no binfmt emulation is registered for `{}`. \
+         the shell would die with `exec format error`. install emulators with \
+         `docker run --privileged --rm tonistiigi/binfmt --install {}` or use an image built for `{}`",
+        image_arch,
+        daemon_arch,
+        image_arch,
+        image_arch,
+        daemon_arch
+    ))
+}
+
+fn binfmt_registered(arch: &str) -> bool {
+    let binfmt_misc = Path::new("/proc/sys/fs/binfmt_misc");
+    binfmt_misc
+        .join(format!("qemu-{}", qemu_arch(arch)))
+        .exists()
+        || (arch == "amd64" && binfmt_misc.join("rosetta").exists())
+}
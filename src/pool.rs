@@ -0,0 +1,183 @@
+use bollard::container::{
+    Config, CreateContainerOptions, ListContainersOptions, RemoveContainerOptions,
+    RenameContainerOptions,
+};
+use bollard::secret::ContainerSummary;
+use bollard::Docker;
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+/// pool members carry this label. its value is the hash of the config they were created with,
+/// so a session only ever claims a container that is identical to the one it would have created.
+pub const POOL_LABEL: &str = "quarantine.pool";
+
+/// the hash of a container config. keys are sorted so the same config always hashes the same.
+pub fn config_hash(config: &Config<String>) -> anyhow::Result<String> {
+    // round tripping through `Value` sorts the keys of the `HashMap`s in the config
+    let canonical = serde_json::to_string(&serde_json::to_value(config)?)?;
+
+    let mut hasher = DefaultHasher::new();
+    env!("CARGO_PKG_VERSION").hash(&mut hasher);
+    canonical.hash(&mut hasher);
+    Ok(format!("{:016x}", hasher.finish()))
+}
+
+/// pre-creates `count` stopped containers with `config`, so later sessions can skip the create call.
+pub async fn warm(
+    docker: &Docker,
+    mut config: Config<String>,
+    container_name: &str,
+    count: usize,
+) -> anyhow::Result<Vec<String>> {
+    let hash = config_hash(&config)?;
+    config
+        .labels
+        .get_or_insert_with(HashMap::new)
+        .insert(POOL_LABEL.to_string(), hash.clone());
+
+    let mut created = vec![];
+    for n in 0..count {
+        let name = format!(
+            "{}-pool-{}-{}",
+            container_name,
+            &hash[..8],
+            unique_suffix(n)
+        );
+        docker
+            .create_container(
+                Some(CreateContainerOptions {
+                    name: name.as_str(),
+                    platform: None,
+                }),
+                config.clone(),
+            )
+            .await?;
+        tracing::info!("created pool member: {}", name);
+        created.push(name);
+    }
+    Ok(created)
+}
+
+/// claims a pool member created with the same config and names it `container_name`.
+/// renaming is atomic on the daemon side, so when several sessions race for the same member
+/// exactly one rename succeeds and the others move on to the next member.
+/// returns the id of the claimed container, or `None` when the pool has nothing suitable.
+pub async fn claim(
+    docker: &Docker,
+    config: &Config<String>,
+    container_name: &str,
+) -> anyhow::Result<Option<String>> {
+    let hash = config_hash(config)?;
+
+    for member in members(docker, Some(&hash)).await? {
+        let Some(id) = member.id else { continue };
+        match docker
+            .rename_container(
+                &id,
+                RenameContainerOptions {
+                    name: container_name,
+                },
+            )
+            .await
+        {
+            Ok(()) => return Ok(Some(id)),
+            Err(e) => tracing::debug!("unable to claim pool member {} :: {}", id, e),
+        }
+    }
+
+    Ok(None)
+}
+
+/// unclaimed pool members, optionally only the ones created with the config hashing to `hash`.
+pub async fn members(docker: &Docker, hash: Option<&str>) -> anyhow::Result<Vec<ContainerSummary>> {
+    let label = match hash {
+        Some(hash) => format!("{}={}", POOL_LABEL, hash),
+        None => POOL_LABEL.to_string(),
+    };
+
+    let containers = docker
+        .list_containers(Some(ListContainersOptions {
+            all: true,
+            filters: HashMap::from([("label".to_string(), vec![label])]),
+            ..Default::default()
+        }))
+        .await?;
+
+    Ok(containers.into_iter().filter(unclaimed).collect())
+}
+
+/// whether a container with the `POOL_LABEL` is still waiting to be claimed. a claimed member keeps its label
+/// (labels are immutable) but loses its pool name, and is started.
+pub fn unclaimed(container: &ContainerSummary) -> bool {
+    container.state.as_deref() == Some("created")
+        && container
+            .names
+            .iter()
+            .flatten()
+            .any(|name| name.contains("-pool-"))
+}
+
+/// removes every unclaimed pool member.
+pub async fn clean(docker: &Docker) -> anyhow::Result<usize> {
+    remove(docker, members(docker, None).await?).await
+}
+
+/// removes the unclaimed pool members created with `config`.
+pub async fn clean_matching(docker: &Docker, config: &Config<String>) -> anyhow::Result<usize> {
+    let hash = config_hash(config)?;
+    remove(docker, members(docker, Some(&hash)).await?).await
+}
+
+async fn remove(docker: &Docker, members: Vec<ContainerSummary>) -> anyhow::Result<usize> {
+    let count = members.len();
+    for member in members {
+        let Some(id) = member.id else { continue };
+        docker
+            .remove_container(
+                &id,
+                Some(RemoveContainerOptions {
+                    force: true,
+                    ..Default::default()
+                }),
+            )
+            .await?;
+        tracing::info!(
+            "removed pool member: {}",
+            member.names.unwrap_or_default().join(" ")
+        );
+    }
+    Ok(count)
+}
+
+fn unique_suffix(n: usize) -> String {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.subsec_nanos())
+        .unwrap_or_default();
+    format!("{:x}{:x}{}", std::process::id(), nanos, n)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn member(name: &str, state: &str) -> ContainerSummary {
+        ContainerSummary {
+            names: Some(vec![format!("/{}", name)]),
+            state: Some(state.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn claimed_members_are_sessions() {
+        assert!(unclaimed(&member("quarantine-python-pool-1a2b", "created")));
+        // renamed by the claim, then started
+        assert!(!unclaimed(&member("quarantine-python", "created")));
+        assert!(!unclaimed(&member("quarantine-python", "exited")));
+        assert!(!unclaimed(&member(
+            "quarantine-python-pool-1a2b",
+            "running"
+        )));
+    }
+}
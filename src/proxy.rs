@@ -0,0 +1,825 @@
+//! the daemons bollard can't connect to itself: tls (`https://`, or `tcp://` with `DOCKER_TLS_VERIFY`) and
+//! `ssh://`. quarantine listens on a unix socket in a private directory and forwards every connection bollard
+//! opens there to the daemon. with `--trace-docker` every daemon is reached through it, so each request is
+//! logged with its status and timing.
+
+use anyhow::anyhow;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use rustls::{ClientConfig, RootCertStore};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
+use tokio::net::{TcpStream, UnixListener, UnixStream};
+use tokio::process::{Child, ChildStdin, ChildStdout};
+
+/// the port of a tls daemon without one in its address.
+const TLS_PORT: u16 = 2376;
+
+/// the port of a plain tcp daemon without one in its address.
+const TCP_PORT: u16 = 2375;
+
+/// the largest request head that is traced. docker's own are far smaller.
+const MAX_HEAD: usize = 64 * 1024;
+
+/// the proxy of this invocation, there is only ever one daemon.
+static SOCKET: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// the directories of the sockets, removed when quarantine exits.
+static DIRS: Mutex<Vec<PathBuf>> = Mutex::new(vec![]);
+
+/// where the daemon is, and how to get there.
+#[derive(Clone)]
+pub enum Upstream {
+    Unix(PathBuf),
+    /// `HOST:PORT`
+    Tcp(String),
+    Tls {
+        /// `HOST:PORT`
+        address: String,
+        server_name: ServerName<'static>,
+        config: Arc<ClientConfig>,
+    },
+    /// `docker system dial-stdio` on the other machine, one ssh per connection
+    Ssh {
+        command: Vec<String>,
+    },
+}
+
+impl std::fmt::Debug for Upstream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unix(path) => write!(f, "unix://{}", path.display()),
+            Self::Tcp(address) => write!(f, "tcp://{}", address),
+            Self::Tls { address, .. } => write!(f, "tls://{}", address),
+            Self::Ssh { command } => write!(f, "{}", command.join(" ")),
+        }
+    }
+}
+
+impl Upstream {
+    /// the upstream of a `unix://`, `tcp://`, `https://` or `ssh://` host. tls verifies the daemon with `ca.pem`
+    /// in `cert_path`, and presents `cert.pem` and `key.pem` from there when they exist.
+    pub fn parse(host: &str, tls_verify: bool, cert_path: &Path) -> anyhow::Result<Self> {
+        match host.split_once("://") {
+            Some(("unix", path)) if !path.is_empty() => Ok(Self::Unix(PathBuf::from(path))),
+            Some(("tcp", address)) if tls_verify => Self::tls(address, cert_path),
+            Some(("https", address)) => Self::tls(address, cert_path),
+            Some(("tcp" | "http", address)) => {
+                let (name, port) = address_of(address, TCP_PORT)?;
+                Ok(Self::Tcp(join_port(name, port)))
+            }
+            Some(("ssh", address)) => {
+                let address = address.trim_end_matches('/');
+                let (user, address) = match address.rsplit_once('@') {
+                    Some((user, address)) => (Some(user), address),
+                    None => (None, address),
+                };
+                let (name, port) = split_port(address);
+                if name.is_empty() || user.is_some_and(str::is_empty) {
+                    return Err(anyhow!(
+                        "expected `ssh://[USER@]HOST[:PORT]`, got `{}`",
+                        host
+                    ));
+                }
+                let destination = match user {
+                    Some(user) => format!("{}@{}", user, name),
+                    None => name.to_string(),
+                };
+                let mut command = vec!["ssh".to_string()];
+                if let Some(port) = port {
+                    command.extend(["-p".to_string(), port.to_string()]);
+                }
+                command.extend(
+                    ["--", &destination, "docker", "system", "dial-stdio"].map(String::from),
+                );
+                Ok(Self::Ssh { command })
+            }
+            _ => Err(anyhow!("unsupported docker host `{}`", host)),
+        }
+    }
+
+    fn tls(address: &str, cert_path: &Path) -> anyhow::Result<Self> {
+        let (name, port) = address_of(address, TLS_PORT)?;
+        let server_name = ServerName::try_from(name.to_string())
+            .map_err(|_| anyhow!("`{}` is not a valid host name", name))?;
+        Ok(Self::Tls {
+            address: join_port(name, port),
+            server_name,
+            config: Arc::new(tls_config(cert_path)?),
+        })
+    }
+
+    async fn connect(&self) -> anyhow::Result<Box<dyn Stream>> {
+        match self {
+            Self::Unix(path) => {
+                Ok(Box::new(UnixStream::connect(path).await.map_err(|e| {
+                    anyhow!("unable to connect to `{}`: {}", path.display(), e)
+                })?))
+            }
+            Self::Tcp(address) => {
+                Ok(Box::new(TcpStream::connect(address).await.map_err(
+                    |e| anyhow!("unable to connect to `{}`: {}", address, e),
+                )?))
+            }
+            Self::Tls {
+                address,
+                server_name,
+                config,
+            } => {
+                let tcp = TcpStream::connect(address)
+                    .await
+                    .map_err(|e| anyhow!("unable to connect to `{}`: {}", address, e))?;
+                let tls = tokio_rustls::TlsConnector::from(config.clone())
+                    .connect(server_name.clone(), tcp)
+                    .await
+                    .map_err(|e| anyhow!("tls handshake with `{}` failed: {}", address, e))?;
+                Ok(Box::new(tls))
+            }
+            Self::Ssh { command } => {
+                let mut child = tokio::process::Command::new(&command[0])
+                    .args(&command[1..])
+                    .stdin(std::process::Stdio::piped())
+                    .stdout(std::process::Stdio::piped())
+                    .stderr(std::process::Stdio::piped())
+                    .kill_on_drop(true)
+                    .spawn()
+                    .map_err(|e| anyhow!("unable to run `{}`: {}", command[0], e))?;
+                if let Some(stderr) = child.stderr.take() {
+                    tokio::spawn(async move {
+                        let mut lines = tokio::io::BufReader::new(stderr).lines();
+                        while let Ok(Some(line)) = lines.next_line().await {
+                            tracing::warn!("ssh: {}", line);
+                        }
+                    });
+                }
+                let (Some(stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) else {
+                    return Err(anyhow!("`{}` has no stdio", command[0]));
+                };
+                Ok(Box::new(Stdio {
+                    _child: child,
+                    stdin,
+                    stdout,
+                }))
+            }
+        }
+    }
+}
+
+/// `DOCKER_CERT_PATH`, or else `~/.docker`, like the docker cli.
+pub fn cert_path() -> PathBuf {
+    std::env::var_os("DOCKER_CERT_PATH")
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".docker")))
+        .unwrap_or_default()
+}
+
+/// trusts only `ca.pem`: the daemon's certificate is always verified, with or without `DOCKER_TLS_VERIFY`.
+fn tls_config(cert_path: &Path) -> anyhow::Result<ClientConfig> {
+    let pem_error = |path: &Path, e: rustls::pki_types::pem::Error| {
+        anyhow!("unable to read `{}`: {}", path.display(), e)
+    };
+    let ca = cert_path.join("ca.pem");
+    let mut roots = RootCertStore::empty();
+    for cert in CertificateDer::pem_file_iter(&ca).map_err(|e| pem_error(&ca, e))? {
+        roots
+            .add(cert.map_err(|e| pem_error(&ca, e))?)
+            .map_err(|e| anyhow!("invalid certificate in `{}`: {}", ca.display(), e))?;
+    }
+    if roots.is_empty() {
+        return Err(anyhow!("`{}` has no certificates", ca.display()));
+    }
+
+    let provider = Arc::new(rustls::crypto::aws_lc_rs::default_provider());
+    let builder = ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()?
+        .with_root_certificates(roots);
+    let (cert, key) = (cert_path.join("cert.pem"), cert_path.join("key.pem"));
+    match cert.is_file() && key.is_file() {
+        true => {
+            let chain = CertificateDer::pem_file_iter(&cert)
+                .map_err(|e| pem_error(&cert, e))?
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| pem_error(&cert, e))?;
+            let private_key = PrivateKeyDer::from_pem_file(&key).map_err(|e| pem_error(&key, e))?;
+            builder
+                .with_client_auth_cert(chain, private_key)
+                .map_err(|e| anyhow!("unable to use `{}`: {}", cert.display(), e))
+        }
+        false => Ok(builder.with_no_client_auth()),
+    }
+}
+
+/// the host and port of `address`, with `default` when it has none.
+fn address_of(address: &str, default: u16) -> anyhow::Result<(&str, u16)> {
+    let address = address.trim_end_matches('/');
+    let (name, port) = split_port(address);
+    let port = match port {
+        Some(port) => port
+            .parse::<u16>()
+            .map_err(|_| anyhow!("invalid port in `{}`", address))?,
+        None => default,
+    };
+    match name.is_empty() {
+        true => Err(anyhow!("`{}` has no host", address)),
+        false => Ok((name, port)),
+    }
+}
+
+/// `name:port`, with an ipv6 address in brackets.
+fn join_port(name: &str, port: u16) -> String {
+    match name.contains(':') {
+        true => format!("[{}]:{}", name, port),
+        false => format!("{}:{}", name, port),
+    }
+}
+
+/// `host:port`, `[v6]:port` or a host without a port.
+fn split_port(address: &str) -> (&str, Option<&str>) {
+    if let Some(rest) = address.strip_prefix('[') {
+        if let Some((name, after)) = rest.split_once(']') {
+            return (name, after.strip_prefix(':'));
+        }
+    }
+    match address.rsplit_once(':') {
+        // a bare ipv6 address has no port
+        Some((name, port)) if !name.contains(':') => (name, Some(port)),
+        _ => (address, None),
+    }
+}
+
+/// anything a connection can be forwarded to.
+trait Stream: AsyncRead + AsyncWrite + Send + Unpin {}
+
+impl<T: AsyncRead + AsyncWrite + Send + Unpin> Stream for T {}
+
+/// the stdin and stdout of `ssh` as one stream. ssh is killed with it.
+struct Stdio {
+    _child: Child,
+    stdin: ChildStdin,
+    stdout: ChildStdout,
+}
+
+impl AsyncRead for Stdio {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.stdout).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for Stdio {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        Pin::new(&mut self.stdin).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.stdin).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.stdin).poll_shutdown(cx)
+    }
+}
+
+/// the socket that forwards to `upstream`, started on first use.
+pub fn socket(upstream: Upstream) -> anyhow::Result<PathBuf> {
+    let mut socket = SOCKET.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(socket) = socket.as_ref() {
+        return Ok(socket.clone());
+    }
+    let started = start(upstream, crate::trace::enabled())?;
+    *socket = Some(started.clone());
+    Ok(started)
+}
+
+/// listens on a new socket and forwards its connections to `upstream`, on a thread of its own so the
+/// proxy outlives the runtime that asked for it. `trace` logs every request.
+fn start(upstream: Upstream, trace: bool) -> anyhow::Result<PathBuf> {
+    tracing::debug!("forwarding to `{:?}`", upstream);
+    let socket = private_dir()?.join("docker.sock");
+    let listener = std::os::unix::net::UnixListener::bind(&socket)
+        .map_err(|e| anyhow!("unable to listen on `{}`: {}", socket.display(), e))?;
+    listener.set_nonblocking(true)?;
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    std::thread::Builder::new()
+        .name("docker-proxy".to_string())
+        .spawn(move || {
+            runtime.block_on(async move {
+                match UnixListener::from_std(listener) {
+                    Ok(listener) => serve(listener, Arc::new(upstream), trace).await,
+                    Err(e) => tracing::warn!("unable to forward to the daemon: {}", e),
+                }
+            })
+        })?;
+    Ok(socket)
+}
+
+async fn serve(listener: UnixListener, upstream: Arc<Upstream>, trace: bool) {
+    loop {
+        let client = match listener.accept().await {
+            Ok((client, _)) => client,
+            Err(e) => {
+                tracing::warn!("the connection to the daemon failed: {}", e);
+                continue;
+            }
+        };
+        let upstream = upstream.clone();
+        tokio::spawn(async move {
+            if let Err(e) = forward(client, &upstream, trace).await {
+                tracing::debug!("forwarding to `{:?}`: {}", upstream, e);
+            }
+        });
+    }
+}
+
+async fn forward(mut client: UnixStream, upstream: &Upstream, trace: bool) -> anyhow::Result<()> {
+    let mut daemon = match upstream.connect().await {
+        Ok(daemon) => daemon,
+        Err(e) => {
+            // an answer, so the error reaches the user instead of a closed connection
+            let _ = client.read(&mut [0; 4096]).await;
+            let body = serde_json::json!({ "message": e.to_string() }).to_string();
+            let response = format!(
+                "HTTP/1.1 502 Bad Gateway\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+                 Connection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            client.write_all(response.as_bytes()).await?;
+            return Err(e);
+        }
+    };
+    match trace {
+        true => forward_traced(client, daemon).await,
+        false => {
+            tokio::io::copy_bidirectional(&mut client, &mut daemon).await?;
+            Ok(())
+        }
+    }
+}
+
+/// forwards one request and logs its method, path, status and how long the daemon took to answer. the
+/// request asks the daemon to close the connection after the response, so every connection carries one
+/// request and only the status line of the response has to be read.
+async fn forward_traced(client: UnixStream, daemon: Box<dyn Stream>) -> anyhow::Result<()> {
+    let (mut client_read, mut client_write) = tokio::io::split(client);
+    let (mut daemon_read, mut daemon_write) = tokio::io::split(daemon);
+
+    let mut buffer = vec![];
+    let mut chunk = [0; 8192];
+    let end = loop {
+        if let Some(end) = buffer.windows(4).position(|window| window == b"\r\n\r\n") {
+            break end + 4;
+        }
+        if buffer.len() > MAX_HEAD {
+            return Err(anyhow!(
+                "the request head is larger than {} bytes",
+                MAX_HEAD
+            ));
+        }
+        match client_read.read(&mut chunk).await? {
+            0 => return Ok(()),
+            read => buffer.extend_from_slice(&chunk[..read]),
+        }
+    };
+    let head = String::from_utf8_lossy(&buffer[..end]);
+    let (request, head) = close_after_response(&head);
+    let request = request.to_string();
+    daemon_write.write_all(head.as_bytes()).await?;
+    daemon_write.write_all(&buffer[end..]).await?;
+    let started = std::time::Instant::now();
+
+    let upload = async {
+        tokio::io::copy(&mut client_read, &mut daemon_write).await?;
+        daemon_write.shutdown().await
+    };
+    let download = async {
+        let mut response = Some(vec![]);
+        loop {
+            let read = daemon_read.read(&mut chunk).await?;
+            if let Some(line) = response.as_mut() {
+                line.extend_from_slice(&chunk[..read]);
+                if let Some(status) =
+                    status(line).or((read == 0 || line.len() > MAX_HEAD).then_some("?"))
+                {
+                    tracing::trace!(
+                        target: crate::trace::TARGET,
+                        "{} -> {} after {:.1?}",
+                        request,
+                        status,
+                        started.elapsed()
+                    );
+                    response = None;
+                }
+            }
+            if read == 0 {
+                break;
+            }
+            client_write.write_all(&chunk[..read]).await?;
+        }
+        client_write.shutdown().await
+    };
+    tokio::try_join!(upload, download)?;
+    Ok(())
+}
+
+/// the method and path of a request head, and the head with `Connection: close`. upgrades (attach, exec)
+/// are left as they are, their connection is the stream.
+fn close_after_response(head: &str) -> (&str, String) {
+    let request = head
+        .lines()
+        .next()
+        .map(|line| line.rsplit_once(' ').map_or(line, |(request, _)| request))
+        .unwrap_or_default();
+    let is = |line: &str, name: &str| {
+        line.split_once(':')
+            .is_some_and(|(header, _)| header.trim().eq_ignore_ascii_case(name))
+    };
+    if head.lines().any(|line| is(line, "upgrade")) {
+        return (request, head.to_string());
+    }
+    let mut rewritten = String::with_capacity(head.len() + 20);
+    for line in head
+        .trim_end_matches("\r\n")
+        .split("\r\n")
+        .filter(|line| !is(line, "connection") && !is(line, "keep-alive"))
+    {
+        rewritten.push_str(line);
+        rewritten.push_str("\r\n");
+    }
+    rewritten.push_str("Connection: close\r\n\r\n");
+    (request, rewritten)
+}
+
+/// the status code of a response, once its status line is complete.
+fn status(response: &[u8]) -> Option<&str> {
+    let end = response.windows(2).position(|window| window == b"\r\n")?;
+    std::str::from_utf8(&response[..end])
+        .ok()?
+        .split(' ')
+        .nth(1)
+        .or(Some("?"))
+}
+
+/// a new directory only this user can enter. `create` fails when the path exists, so a directory someone
+/// else made is never used.
+fn private_dir() -> anyhow::Result<PathBuf> {
+    use std::os::unix::fs::DirBuilderExt;
+
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .subsec_nanos();
+    let dir = std::env::temp_dir().join(format!("quarantine-{}-{}", std::process::id(), nanos));
+    std::fs::DirBuilder::new()
+        .mode(0o700)
+        .create(&dir)
+        .map_err(|e| anyhow!("unable to create `{}`: {}", dir.display(), e))?;
+
+    let mut dirs = DIRS.lock().unwrap_or_else(|e| e.into_inner());
+    if dirs.is_empty() {
+        // SAFETY: `remove_dirs` is a plain function that stays valid until the process exits
+        unsafe { libc::atexit(remove_dirs) };
+    }
+    dirs.push(dir.clone());
+    Ok(dir)
+}
+
+extern "C" fn remove_dirs() {
+    if let Ok(dirs) = DIRS.lock() {
+        for dir in dirs.iter() {
+            let _ = std::fs::remove_dir_all(dir);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake_engine::{FakeEngine, Route};
+    use bollard::{Docker, API_DEFAULT_VERSION};
+
+    const PING: &str = "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: 2\r\n\r\nOK";
+
+    fn fixtures() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/tls")
+    }
+
+    fn certs(name: &str) -> Vec<CertificateDer<'static>> {
+        CertificateDer::pem_file_iter(fixtures().join(name))
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap()
+    }
+
+    /// a daemon that answers every request with `PING`, with the certificate `name` and requiring a client
+    /// certificate of the test ca.
+    async fn tls_daemon(name: &str) -> String {
+        let provider = Arc::new(rustls::crypto::aws_lc_rs::default_provider());
+        let mut roots = RootCertStore::empty();
+        roots.add(certs("ca.pem").remove(0)).unwrap();
+        let verifier = rustls::server::WebPkiClientVerifier::builder_with_provider(
+            roots.into(),
+            provider.clone(),
+        )
+        .build()
+        .unwrap();
+        let key =
+            PrivateKeyDer::from_pem_file(fixtures().join(format!("{}-key.pem", name))).unwrap();
+        let config = rustls::ServerConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_client_cert_verifier(verifier)
+            .with_single_cert(certs(&format!("{}.pem", name)), key)
+            .unwrap();
+        let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(config));
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            while let Ok((tcp, _)) = listener.accept().await {
+                let acceptor = acceptor.clone();
+                tokio::spawn(async move {
+                    let Ok(mut tls) = acceptor.accept(tcp).await else {
+                        return;
+                    };
+                    let mut head = vec![];
+                    while !head.ends_with(b"\r\n\r\n") {
+                        let mut byte = [0];
+                        if tls.read(&mut byte).await.unwrap_or(0) == 0 {
+                            return;
+                        }
+                        head.push(byte[0]);
+                    }
+                    let _ = tls.write_all(PING.as_bytes()).await;
+                    let _ = tls.shutdown().await;
+                });
+            }
+        });
+        address
+    }
+
+    fn docker(socket: &Path) -> Docker {
+        Docker::connect_with_unix(
+            &format!("unix://{}", socket.display()),
+            10,
+            API_DEFAULT_VERSION,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn parses_tls_hosts() {
+        let upstream = Upstream::parse("tcp://build-box:2376", true, &fixtures()).unwrap();
+        assert_eq!(format!("{:?}", upstream), "tls://build-box:2376");
+        let upstream = Upstream::parse("https://build-box", false, &fixtures()).unwrap();
+        assert_eq!(format!("{:?}", upstream), "tls://build-box:2376");
+        let upstream = Upstream::parse("https://[::1]:3000/", false, &fixtures()).unwrap();
+        assert_eq!(format!("{:?}", upstream), "tls://[::1]:3000");
+        let upstream = Upstream::parse("tcp://10.0.0.5:2376", true, &fixtures()).unwrap();
+        assert_eq!(format!("{:?}", upstream), "tls://10.0.0.5:2376");
+
+        assert!(Upstream::parse("https://build-box:port", false, &fixtures()).is_err());
+    }
+
+    #[test]
+    fn parses_plain_hosts() {
+        let parse =
+            |host: &str| format!("{:?}", Upstream::parse(host, false, Path::new("")).unwrap());
+        assert_eq!(
+            parse("unix:///var/run/docker.sock"),
+            "unix:///var/run/docker.sock"
+        );
+        assert_eq!(parse("tcp://build-box"), "tcp://build-box:2375");
+        assert_eq!(parse("http://127.0.0.1:3000/"), "tcp://127.0.0.1:3000");
+        for invalid in [
+            "unix://",
+            "tcp://",
+            "tcp://:2375",
+            "npipe:////./pipe/docker_engine",
+        ] {
+            assert!(
+                Upstream::parse(invalid, false, Path::new("")).is_err(),
+                "{}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn parses_ssh_hosts() {
+        let command = |host: &str| match Upstream::parse(host, false, Path::new("")).unwrap() {
+            Upstream::Ssh { command } => command.join(" "),
+            upstream => panic!("{:?}", upstream),
+        };
+        assert_eq!(
+            command("ssh://build-box"),
+            "ssh -- build-box docker system dial-stdio"
+        );
+        assert_eq!(
+            command("ssh://me@build-box:2222"),
+            "ssh -p 2222 -- me@build-box docker system dial-stdio"
+        );
+        assert_eq!(
+            command("ssh://me@[fd00::1]:22/"),
+            "ssh -p 22 -- me@fd00::1 docker system dial-stdio"
+        );
+        for invalid in ["ssh://", "ssh://me@", "ssh://:22", "ssh://@build-box"] {
+            assert!(
+                Upstream::parse(invalid, false, Path::new("")).is_err(),
+                "{}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn needs_the_ca_of_the_daemon() {
+        let dir = tempfile::tempdir().unwrap();
+        let e = Upstream::parse("https://build-box", false, dir.path()).unwrap_err();
+        assert!(
+            e.to_string().starts_with(&format!(
+                "unable to read `{}`",
+                dir.path().join("ca.pem").display()
+            )),
+            "{}",
+            e
+        );
+
+        std::fs::write(dir.path().join("ca.pem"), "").unwrap();
+        let e = Upstream::parse("https://build-box", false, dir.path()).unwrap_err();
+        assert!(e.to_string().ends_with("has no certificates"), "{}", e);
+
+        // a client certificate is optional
+        std::fs::copy(fixtures().join("ca.pem"), dir.path().join("ca.pem")).unwrap();
+        assert!(Upstream::parse("https://build-box", false, dir.path()).is_ok());
+    }
+
+    #[tokio::test]
+    async fn forwards_to_a_tls_daemon() {
+        let address = tls_daemon("server").await;
+        let upstream =
+            Upstream::parse(&format!("https://{}", address), false, &fixtures()).unwrap();
+        let socket = start(upstream, false).unwrap();
+        assert_eq!(docker(&socket).ping().await.unwrap(), "OK");
+        // again, on a new connection
+        assert_eq!(docker(&socket).ping().await.unwrap(), "OK");
+    }
+
+    #[tokio::test]
+    async fn refuses_a_daemon_the_ca_did_not_sign() {
+        let address = tls_daemon("other").await;
+        let upstream =
+            Upstream::parse(&format!("https://{}", address), false, &fixtures()).unwrap();
+        let socket = start(upstream, false).unwrap();
+        let e = docker(&socket).ping().await.unwrap_err();
+        match e {
+            bollard::errors::Error::DockerResponseServerError {
+                status_code,
+                message,
+            } => {
+                assert_eq!(status_code, 502);
+                assert!(message.starts_with("tls handshake with"), "{}", message);
+            }
+            e => panic!("{}", e),
+        }
+    }
+
+    #[tokio::test]
+    async fn forwards_to_the_stdio_of_ssh() {
+        let response = PING.replace("\r\n", "\\r\\n");
+        let upstream = Upstream::Ssh {
+            command: ["sh", "-c", &format!("read -r line; printf '{}'", response)]
+                .map(String::from)
+                .to_vec(),
+        };
+        let socket = start(upstream, false).unwrap();
+        assert_eq!(docker(&socket).ping().await.unwrap(), "OK");
+    }
+
+    #[tokio::test]
+    async fn reports_an_ssh_that_does_not_run() {
+        let upstream = Upstream::Ssh {
+            command: vec!["/nonexistent/ssh".to_string()],
+        };
+        let socket = start(upstream, false).unwrap();
+        let e = docker(&socket).ping().await.unwrap_err();
+        assert!(
+            e.to_string().contains("unable to run `/nonexistent/ssh`"),
+            "{}",
+            e
+        );
+    }
+
+    #[test]
+    fn listens_in_a_private_directory() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = private_dir().unwrap();
+        let mode = std::fs::metadata(&dir).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+        assert!(DIRS.lock().unwrap().contains(&dir));
+    }
+
+    #[test]
+    fn splits_ports() {
+        assert_eq!(split_port("host:22"), ("host", Some("22")));
+        assert_eq!(split_port("host"), ("host", None));
+        assert_eq!(split_port("[::1]:22"), ("::1", Some("22")));
+        assert_eq!(split_port("[::1]"), ("::1", None));
+        assert_eq!(split_port("::1"), ("::1", None));
+    }
+
+    #[test]
+    fn asks_the_daemon_to_close_after_the_response() {
+        let (request, head) = close_after_response(
+            "GET /v1.45/containers/json?all=true HTTP/1.1\r\nhost: docker\r\nconnection: keep-alive\r\n\
+             Keep-Alive: timeout=5\r\n\r\n",
+        );
+        assert_eq!(request, "GET /v1.45/containers/json?all=true");
+        assert_eq!(
+            head,
+            "GET /v1.45/containers/json?all=true HTTP/1.1\r\nhost: docker\r\nConnection: close\r\n\r\n"
+        );
+
+        let (_, head) = close_after_response("GET /_ping HTTP/1.1\r\n\r\n");
+        assert_eq!(head, "GET /_ping HTTP/1.1\r\nConnection: close\r\n\r\n");
+
+        // the connection of an upgrade is the stream
+        let upgrade =
+            "POST /v1.45/containers/x/attach?stream=1 HTTP/1.1\r\nConnection: Upgrade\r\n\
+                       Upgrade: tcp\r\n\r\n";
+        let (request, head) = close_after_response(upgrade);
+        assert_eq!(request, "POST /v1.45/containers/x/attach?stream=1");
+        assert_eq!(head, upgrade);
+    }
+
+    #[test]
+    fn reads_the_status_once_the_line_is_complete() {
+        assert_eq!(status(b"HTTP/1.1 20"), None);
+        assert_eq!(status(b"HTTP/1.1 200 OK\r\n"), Some("200"));
+        assert_eq!(
+            status(b"HTTP/1.1 101 UPGRADED\r\nUpgrade: tcp"),
+            Some("101")
+        );
+        assert_eq!(status(b"garbage\r\n"), Some("?"));
+    }
+
+    #[tokio::test]
+    async fn traces_through_to_the_daemon() {
+        let engine = FakeEngine::start(vec![
+            Route::new("GET", "/containers/json", serde_json::json!([])),
+            Route::new(
+                "POST",
+                "/containers/create",
+                serde_json::json!({ "Id": "abc", "Warnings": [] }),
+            )
+            .status(201),
+        ])
+        .await;
+        let socket = start(Upstream::Tcp(engine.address.clone()), true).unwrap();
+        let docker = docker(&socket);
+
+        for _ in 0..2 {
+            let containers = docker.list_containers::<String>(None).await.unwrap();
+            assert!(containers.is_empty());
+        }
+        let created = docker
+            .create_container::<String, String>(
+                None,
+                bollard::container::Config {
+                    image: Some("alpine".to_string()),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(created.id, "abc");
+        let e = docker.inspect_container("missing", None).await.unwrap_err();
+        assert!(e.to_string().contains("no such route"), "{}", e);
+
+        let requests = engine.requests();
+        assert_eq!(requests.len(), 4);
+        for request in requests {
+            let connection = request
+                .headers
+                .iter()
+                .filter(|(name, _)| name == "connection")
+                .map(|(_, value)| value.as_str())
+                .collect::<Vec<&str>>();
+            assert_eq!(connection, ["close"], "{:?}", request);
+        }
+    }
+}
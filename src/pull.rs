@@ -0,0 +1,843 @@
+use anyhow::anyhow;
+use bollard::auth::DockerCredentials;
+use bollard::image::{CreateImageOptions, RemoveImageOptions, TagImageOptions};
+use bollard::secret::{CreateImageInfo, ErrorDetail, ImageInspect};
+use bollard::Docker;
+use futures::{Stream, StreamExt};
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+// tokio's clock, so tests can move it forward
+use tokio::time::Instant;
+
+use crate::auth;
+use crate::platform::normalize_arch;
+
+/// the delay before the first retry, doubled for every further one.
+const BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// how pulls deal with an unreliable connection. not `Debug`, it may hold passwords.
+#[derive(Clone)]
+pub struct Options {
+    /// how often a pull that failed with a transient error is retried
+    pub retries: u32,
+    /// pull-through caches to try before the registry itself, by registry (`docker.io` for docker hub)
+    pub mirrors: BTreeMap<String, Vec<String>>,
+    /// how long the whole pull, with its retries and mirrors, may take
+    pub timeout: Option<Duration>,
+    /// an attempt is aborted when the daemon reports nothing for this long
+    pub stall_timeout: Duration,
+    /// pull even when the image is present locally
+    pub always: bool,
+    /// credentials from the command line, by registry. they win over those of the docker cli's config
+    pub credentials: BTreeMap<String, DockerCredentials>,
+    /// the `os/arch[/variant]` to pull, the daemon's own when unset
+    pub platform: Option<String>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            retries: 3,
+            mirrors: BTreeMap::new(),
+            timeout: None,
+            stall_timeout: Duration::from_secs(60),
+            always: false,
+            credentials: BTreeMap::new(),
+            platform: None,
+        }
+    }
+}
+
+/// whether `image` is for `platform` (`os/arch[/variant]`). the variant is not compared.
+pub fn matches_platform(image: &ImageInspect, platform: &str) -> bool {
+    let mut parts = platform.split('/');
+    let os_matches = parts
+        .next()
+        .is_some_and(|os| image.os.as_deref() == Some(os));
+    let arch_matches = parts.next().is_none_or(|arch| {
+        image
+            .architecture
+            .as_deref()
+            .is_some_and(|image_arch| normalize_arch(image_arch) == normalize_arch(arch))
+    });
+    os_matches && arch_matches
+}
+
+/// an image reference split into its registry, repository and `:tag` or `@digest`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reference {
+    /// `docker.io` for docker hub
+    pub registry: String,
+    /// `library/python` for official docker hub images
+    pub repository: String,
+    pub suffix: String,
+}
+
+impl Reference {
+    pub fn parse(image: &str) -> Self {
+        let (name, digest) = match image.split_once('@') {
+            Some((name, digest)) => (name, Some(digest)),
+            None => (image, None),
+        };
+        let (name, tag) = match name.rsplit_once(':') {
+            Some((repo, tag)) if !tag.contains('/') => (repo, Some(tag)),
+            _ => (name, None),
+        };
+        let suffix = match (digest, tag) {
+            (Some(digest), _) => format!("@{}", digest),
+            (None, Some(tag)) => format!(":{}", tag),
+            (None, None) => ":latest".to_string(),
+        };
+
+        let (registry, repository) = match name.split_once('/') {
+            Some((first, rest))
+                if first.contains('.') || first.contains(':') || first == "localhost" =>
+            {
+                (first.to_string(), rest.to_string())
+            }
+            Some(_) => ("docker.io".to_string(), name.to_string()),
+            None => ("docker.io".to_string(), format!("library/{}", name)),
+        };
+        Self {
+            registry,
+            repository,
+            suffix,
+        }
+    }
+
+    /// the same image on `mirror`.
+    pub fn on(&self, mirror: &str) -> String {
+        format!(
+            "{}/{}{}",
+            mirror
+                .trim_start_matches("https://")
+                .trim_start_matches("http://")
+                .trim_end_matches('/'),
+            self.repository,
+            self.suffix
+        )
+    }
+}
+
+/// why a pull attempt failed.
+#[derive(Debug)]
+pub struct PullError {
+    pub message: String,
+    /// worth retrying, eg: a dropped connection. a missing image or denied access is not
+    pub transient: bool,
+}
+
+/// pulls the image, logging the progress reported by the daemon. the mirrors of its registry are tried first,
+/// and the image is tagged with the original reference when one of them has it.
+/// an image that is present locally is only pulled again with `always`.
+pub async fn pull_image(
+    docker: &Docker,
+    image_name: &str,
+    options: &Options,
+) -> anyhow::Result<()> {
+    if !options.always {
+        match docker.inspect_image(image_name).await {
+            Ok(image)
+                if options
+                    .platform
+                    .as_deref()
+                    .is_none_or(|platform| matches_platform(&image, platform)) =>
+            {
+                tracing::info!("using the local image `{}`", image_name);
+                return Ok(());
+            }
+            Ok(_) => tracing::info!(
+                "the local image `{}` is for another platform than `{}`",
+                image_name,
+                options.platform.as_deref().unwrap_or_default()
+            ),
+            Err(bollard::errors::Error::DockerResponseServerError {
+                status_code: 404, ..
+            }) => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    let deadline = options
+        .timeout
+        .and_then(|timeout| Instant::now().checked_add(timeout));
+    let reference = Reference::parse(image_name);
+    let mirrors = options
+        .mirrors
+        .get(&reference.registry)
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    if !mirrors.is_empty() && reference.suffix.starts_with('@') {
+        // a mirrored image can't be tagged with a digest reference
+        tracing::info!(
+            "not using a mirror for the digest reference `{}`",
+            image_name
+        );
+    } else {
+        for mirror in mirrors {
+            let mirrored = reference.on(mirror);
+            let credentials = auth::credentials(&Reference::parse(&mirrored).registry);
+            tracing::info!("pulling `{}` through the mirror `{}`", image_name, mirror);
+            match pull_from(docker, &mirrored, credentials, options, deadline).await {
+                Ok(()) => return retag(docker, &mirrored, image_name).await,
+                Err(e) => tracing::warn!("the mirror `{}` failed: {:#}", mirror, e),
+            }
+        }
+        if !mirrors.is_empty() {
+            tracing::info!("falling back to `{}`", reference.registry);
+        }
+    }
+
+    let credentials = options
+        .credentials
+        .get(&reference.registry)
+        .cloned()
+        .or_else(|| auth::credentials(&reference.registry));
+    let Err(e) = pull_from(docker, image_name, credentials, options, deadline).await else {
+        return Ok(());
+    };
+    // `always` pulls over a local image, which is still good enough when the registry can't be reached
+    match options.always {
+        true => match docker.inspect_image(image_name).await {
+            Ok(image)
+                if options
+                    .platform
+                    .as_deref()
+                    .is_none_or(|platform| matches_platform(&image, platform)) =>
+            {
+                tracing::warn!("{:#}. using the local image instead", e);
+                Ok(())
+            }
+            _ => Err(e),
+        },
+        false => Err(e),
+    }
+}
+
+/// tags the image pulled from a mirror with its original reference, and drops the mirror's name for it.
+async fn retag(docker: &Docker, mirrored: &str, image_name: &str) -> anyhow::Result<()> {
+    let (repo, tag) = match image_name.rsplit_once(':') {
+        Some((repo, tag)) if !tag.contains('/') => (repo, tag),
+        _ => (image_name, "latest"),
+    };
+    docker
+        .tag_image(mirrored, Some(TagImageOptions { repo, tag }))
+        .await?;
+    let untagged = docker
+        .remove_image(
+            mirrored,
+            Some(RemoveImageOptions {
+                noprune: true,
+                ..Default::default()
+            }),
+            None,
+        )
+        .await;
+    if let Err(e) = untagged {
+        tracing::debug!("unable to remove the tag `{}`: {}", mirrored, e);
+    }
+    Ok(())
+}
+
+/// pulls from exactly this reference. transient failures (including stalls) are retried with exponential backoff,
+/// as long as `deadline` leaves time for it. the daemon keeps the layers that were already downloaded,
+/// so a retry only fetches the rest.
+async fn pull_from(
+    docker: &Docker,
+    image_name: &str,
+    credentials: Option<DockerCredentials>,
+    options: &Options,
+    deadline: Option<Instant>,
+) -> anyhow::Result<()> {
+    let mut attempt = 0;
+    loop {
+        tracing::info!("pulling image: {}", image_name);
+        let stream = docker.create_image(
+            Some(CreateImageOptions {
+                from_image: image_name,
+                platform: options.platform.as_deref().unwrap_or_default(),
+                ..Default::default()
+            }),
+            None,
+            credentials.clone(),
+        );
+        let e = match consume_pull_stream(stream, options.stall_timeout, deadline).await {
+            Ok(digest) => {
+                if let Some(digest) = digest {
+                    tracing::info!("pulled `{}` :: digest: {}", image_name, digest);
+                }
+                return Ok(());
+            }
+            Err(e) => e,
+        };
+
+        attempt += 1;
+        let delay = backoff(attempt);
+        let out_of_time = deadline.is_some_and(|deadline| Instant::now() + delay >= deadline);
+        if !e.transient || attempt > options.retries || out_of_time {
+            let retried = match attempt - 1 {
+                0 => String::new(),
+                n => format!(" after {} retries", n),
+            };
+            return Err(anyhow!(
+                "unable to pull `{}`{}: {}{}",
+                image_name,
+                retried,
+                e.message,
+                hint(&e.message)
+                    .map(|hint| format!(". {}", hint))
+                    .unwrap_or_default()
+            ));
+        }
+        tracing::warn!(
+            "pulling `{}` failed ({}). retry {} of {} in {:.1?}",
+            image_name,
+            e.message,
+            attempt,
+            options.retries,
+            delay
+        );
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// the delay before retry `attempt` (1-based), with up to 50% random jitter so parallel pulls don't retry in lockstep.
+fn backoff(attempt: u32) -> Duration {
+    let delay = BACKOFF
+        .saturating_mul(2u32.saturating_pow(attempt - 1))
+        .min(MAX_BACKOFF);
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.subsec_nanos())
+        .unwrap_or_default();
+    delay + delay.mul_f64(f64::from(nanos % 500) / 1000.0)
+}
+
+/// the download progress of one attempt, to tell a slow pull from a stalled one.
+#[derive(Debug, Default)]
+struct Progress {
+    /// bytes downloaded and the size of each layer
+    layers: HashMap<String, (i64, i64)>,
+    /// the layer that received bytes last
+    last_layer: Option<String>,
+}
+
+impl Progress {
+    /// records the bytes of a progress message.
+    fn update(&mut self, info: &CreateImageInfo) {
+        let (Some(id), Some("Downloading"), Some(detail)) = (
+            info.id.as_ref(),
+            info.status.as_deref(),
+            info.progress_detail.as_ref(),
+        ) else {
+            return;
+        };
+        let current = detail.current.unwrap_or_default();
+        let layer = self.layers.entry(id.clone()).or_default();
+        if current > layer.0 {
+            self.last_layer = Some(id.clone());
+        }
+        *layer = (current.max(layer.0), detail.total.unwrap_or(layer.1));
+    }
+
+    fn downloaded(&self) -> String {
+        let bytes = self
+            .layers
+            .values()
+            .map(|(current, _)| current)
+            .sum::<i64>();
+        format!("{:.1} MiB downloaded", bytes as f64 / 1024.0 / 1024.0)
+    }
+
+    /// the unfinished layer that made progress last
+    fn stalled_layer(&self) -> String {
+        let unfinished = |id: &String| {
+            self.layers
+                .get(id)
+                .is_some_and(|(current, total)| current < total)
+        };
+        match self.last_layer.as_ref().filter(|id| unfinished(id)) {
+            Some(id) => {
+                let (current, total) = self.layers[id];
+                format!(
+                    "layer {} stalled at {:.1} of {:.1} MiB",
+                    id,
+                    current as f64 / 1024.0 / 1024.0,
+                    total as f64 / 1024.0 / 1024.0
+                )
+            }
+            None => "no layer was downloading".to_string(),
+        }
+    }
+}
+
+/// drives a pull to completion. a failing stream (dns, tls, dropped connection) aborts immediately,
+/// and errors reported inside the progress messages fail the pull once the daemon is done.
+/// the pull is aborted when the daemon reports nothing for `stall_timeout` (retryable), or at `deadline` (not
+/// retryable). any message counts, a layer that is being extracted or verified sends no bytes.
+/// the digest of the pulled image, when the daemon reported it.
+pub async fn consume_pull_stream<S>(
+    mut stream: S,
+    stall_timeout: Duration,
+    deadline: Option<Instant>,
+) -> Result<Option<String>, PullError>
+where
+    S: Stream<Item = Result<CreateImageInfo, bollard::errors::Error>> + Unpin,
+{
+    let mut errors = vec![];
+    let mut progress = Progress::default();
+    // the last status logged for each layer, so progress ticks are not logged one by one
+    let mut statuses = HashMap::<String, String>::new();
+    let mut digest = None;
+    let mut last_progress = Instant::now();
+
+    loop {
+        let stalls_at = last_progress + stall_timeout;
+        let wait_until = deadline.map_or(stalls_at, |deadline| deadline.min(stalls_at));
+        let next = tokio::time::timeout_at(wait_until, stream.next()).await;
+        let pull_result = match next {
+            Ok(Some(pull_result)) => pull_result,
+            Ok(None) => break,
+            Err(_) if deadline.is_some_and(|deadline| Instant::now() >= deadline) => {
+                return Err(PullError {
+                    message: format!(
+                        "the pull did not finish in time ({}, {})",
+                        progress.downloaded(),
+                        progress.stalled_layer()
+                    ),
+                    transient: false,
+                })
+            }
+            Err(_) => {
+                return Err(PullError {
+                    message: format!(
+                        "no progress for {:?} ({}, {})",
+                        stall_timeout,
+                        progress.downloaded(),
+                        progress.stalled_layer()
+                    ),
+                    transient: true,
+                })
+            }
+        };
+
+        let pull_result = pull_result.map_err(|e| PullError {
+            transient: is_transient_error(&e),
+            message: e.to_string(),
+        })?;
+        progress.update(&pull_result);
+        last_progress = Instant::now();
+        if let Some(error) = pull_result.error {
+            tracing::error!("{}", error);
+            if let Some(ErrorDetail {
+                code: Some(code),
+                message: Some(message),
+            }) = pull_result.error_detail
+            {
+                tracing::error!("{} :: {}", code, message);
+            }
+            errors.push(error);
+        } else {
+            let id = pull_result.id.unwrap_or_default();
+            let status = pull_result.status.unwrap_or_default();
+            if let Some(reported) = status.strip_prefix("Digest: ") {
+                digest = Some(reported.trim().to_string());
+            }
+            match statuses.get(&id) == Some(&status) {
+                true => tracing::debug!(
+                    "{} {} {}",
+                    id,
+                    status,
+                    pull_result.progress.unwrap_or_default()
+                ),
+                false => {
+                    tracing::info!("{} {}", id, status);
+                    statuses.insert(id, status);
+                }
+            }
+        }
+    }
+
+    match errors.is_empty() {
+        true => Ok(digest),
+        false => Err(PullError {
+            transient: errors.iter().all(|error| is_transient(None, error)),
+            message: errors.join(" :: "),
+        }),
+    }
+}
+
+fn is_transient_error(e: &bollard::errors::Error) -> bool {
+    use bollard::errors::Error;
+
+    match e {
+        Error::DockerResponseServerError {
+            status_code,
+            message,
+        } => is_transient(Some(*status_code), message),
+        Error::DockerStreamError { error } => is_transient(None, error),
+        // the connection to the daemon itself
+        Error::HyperResponseError { .. } | Error::IOError { .. } | Error::RequestTimeoutError => {
+            true
+        }
+        _ => false,
+    }
+}
+
+/// how registries say that there is no such image or tag.
+const NOT_FOUND: [&str; 3] = ["manifest unknown", "not found", "does not exist"];
+
+/// whether a pull failed because the registry has no such image or tag.
+pub fn is_not_found(e: &anyhow::Error) -> bool {
+    e.chain().any(|e| {
+        let message = e.to_string().to_lowercase();
+        NOT_FOUND.iter().any(|m| message.contains(m))
+    })
+}
+
+/// what to do about the usual permanent failures.
+fn hint(message: &str) -> Option<&'static str> {
+    let message = message.to_lowercase();
+    if NOT_FOUND.iter().any(|m| message.contains(m)) {
+        return Some("check the image name and tag");
+    }
+    if ["unauthorized", "denied", "authentication required"]
+        .iter()
+        .any(|m| message.contains(m))
+    {
+        return Some(
+            "the image may be private. log in with `docker login` or pass --registry-user and --registry-password-stdin",
+        );
+    }
+    if message.contains("no matching manifest") {
+        return Some("the image is not built for this platform, try --platform");
+    }
+    None
+}
+
+/// classifies a failed pull by the status code of the daemon and the error it relayed from the registry.
+/// unknown errors count as permanent, so they fail right away instead of being retried.
+pub fn is_transient(status_code: Option<u16>, message: &str) -> bool {
+    let message = message.to_lowercase();
+    let permanent = [
+        "unauthorized",
+        "denied",
+        "forbidden",
+        "not found",
+        "manifest unknown",
+        "invalid reference",
+        "manifest invalid",
+        "no matching manifest",
+        "no space left",
+    ];
+    if matches!(status_code, Some(400..=499)) || permanent.iter().any(|m| message.contains(m)) {
+        return false;
+    }
+    if matches!(status_code, Some(500..=599)) {
+        return true;
+    }
+
+    let transient = [
+        "connection reset",
+        "connection refused",
+        "broken pipe",
+        "unexpected eof",
+        "timeout",
+        "timed out",
+        "tls handshake",
+        "temporary failure",
+        "too many requests",
+        "toomanyrequests",
+        "502 bad gateway",
+        "503 service unavailable",
+        "504 gateway timeout",
+        "500 internal server error",
+        "request canceled",
+    ];
+    transient.iter().any(|m| message.contains(m))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bollard::secret::ProgressDetail;
+    use futures::stream;
+
+    const STALL: Duration = Duration::from_secs(60);
+
+    type Item = Result<CreateImageInfo, bollard::errors::Error>;
+
+    fn status(id: &str, status: &str) -> Item {
+        Ok(CreateImageInfo {
+            id: Some(id.to_string()),
+            status: Some(status.to_string()),
+            ..Default::default()
+        })
+    }
+
+    fn downloading(id: &str, current: i64, total: i64) -> Item {
+        Ok(CreateImageInfo {
+            id: Some(id.to_string()),
+            status: Some("Downloading".to_string()),
+            progress_detail: Some(ProgressDetail {
+                current: Some(current),
+                total: Some(total),
+            }),
+            ..Default::default()
+        })
+    }
+
+    fn failed(error: &str) -> Item {
+        Ok(CreateImageInfo {
+            error: Some(error.to_string()),
+            ..Default::default()
+        })
+    }
+
+    async fn consume(items: Vec<Item>) -> Result<Option<String>, PullError> {
+        consume_pull_stream(stream::iter(items), STALL, None).await
+    }
+
+    #[tokio::test]
+    async fn reports_the_digest() {
+        let pulled = consume(vec![
+            status("3.12", "Pulling from library/python"),
+            downloading("a1", 512, 1024),
+            downloading("a1", 1024, 1024),
+            status("a1", "Pull complete"),
+            status("", "Digest: sha256:0123"),
+            status("", "Status: Downloaded newer image for python:3.12"),
+        ])
+        .await;
+        assert_eq!(pulled.unwrap().as_deref(), Some("sha256:0123"));
+    }
+
+    #[tokio::test]
+    async fn fails_with_the_errors_of_the_messages() {
+        let e = consume(vec![
+            status("3.14", "Pulling from library/python"),
+            failed("manifest for python:3.14-nonexistent not found: manifest unknown"),
+        ])
+        .await
+        .unwrap_err();
+        assert_eq!(
+            e.message,
+            "manifest for python:3.14-nonexistent not found: manifest unknown"
+        );
+        assert!(!e.transient);
+
+        let e = consume(vec![
+            failed("read: connection reset by peer"),
+            failed("unexpected EOF"),
+        ])
+        .await
+        .unwrap_err();
+        assert_eq!(
+            e.message,
+            "read: connection reset by peer :: unexpected EOF"
+        );
+        assert!(e.transient);
+    }
+
+    #[tokio::test]
+    async fn fails_when_the_stream_breaks() {
+        let e = consume(vec![
+            downloading("a1", 512, 1024),
+            Err(bollard::errors::Error::RequestTimeoutError),
+            downloading("a1", 1024, 1024),
+        ])
+        .await
+        .unwrap_err();
+        assert!(e.transient);
+
+        let e = consume(vec![Err(
+            bollard::errors::Error::DockerResponseServerError {
+                status_code: 404,
+                message: "pull access denied for nonexistent, repository does not exist"
+                    .to_string(),
+            },
+        )])
+        .await
+        .unwrap_err();
+        assert!(e.message.contains("repository does not exist"));
+        assert!(!e.transient);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn aborts_stalled_pulls() {
+        let items = stream::iter(vec![
+            downloading("a1", 1024 * 1024, 4 * 1024 * 1024),
+            downloading("b2", 2 * 1024 * 1024, 2 * 1024 * 1024),
+        ])
+        .chain(stream::pending());
+        let e = consume_pull_stream(items, STALL, None).await.unwrap_err();
+        assert_eq!(
+            e.message,
+            "no progress for 60s (3.0 MiB downloaded, no layer was downloading)"
+        );
+        assert!(e.transient);
+
+        let items = stream::iter(vec![downloading("a1", 1024 * 1024, 4 * 1024 * 1024)])
+            .chain(stream::pending());
+        let e = consume_pull_stream(items, STALL, None).await.unwrap_err();
+        assert_eq!(
+            e.message,
+            "no progress for 60s (1.0 MiB downloaded, layer a1 stalled at 1.0 of 4.0 MiB)"
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn aborts_at_the_deadline() {
+        let items = stream::iter(vec![downloading("a1", 1024 * 1024, 4 * 1024 * 1024)])
+            .chain(stream::pending());
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let e = consume_pull_stream(items, STALL, Some(deadline))
+            .await
+            .unwrap_err();
+        assert!(e.message.starts_with("the pull did not finish in time"));
+        assert!(!e.transient);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn any_message_counts_as_progress() {
+        // a big layer takes longer to extract than the stall timeout, and sends no bytes meanwhile
+        let spaced = |item: Item| async move {
+            tokio::time::sleep(Duration::from_secs(40)).await;
+            item
+        };
+        let items = stream::iter(vec![
+            downloading("a1", 1024, 1024),
+            status("a1", "Download complete"),
+            status("a1", "Extracting"),
+            status("a1", "Extracting"),
+            status("a1", "Pull complete"),
+            status("", "Digest: sha256:0123"),
+        ])
+        .then(spaced);
+        let pulled = consume_pull_stream(Box::pin(items), STALL, None).await;
+        assert_eq!(pulled.unwrap().as_deref(), Some("sha256:0123"));
+    }
+
+    #[test]
+    fn hints_at_the_cause() {
+        assert_eq!(
+            hint("manifest for python:3.14-nonexistent not found: manifest unknown"),
+            Some("check the image name and tag")
+        );
+        assert_eq!(
+            hint("Error response from daemon: repository nonexistent does not exist"),
+            Some("check the image name and tag")
+        );
+        assert!(
+            hint("pull access denied for private/app, unauthorized: authentication required")
+                .is_some_and(|hint| hint.contains("docker login"))
+        );
+        assert_eq!(
+            hint("no matching manifest for linux/arm64/v8 in the manifest list entries"),
+            Some("the image is not built for this platform, try --platform")
+        );
+        assert_eq!(hint("read: connection reset by peer"), None);
+    }
+
+    #[test]
+    fn classifies_failures() {
+        assert!(!is_transient(Some(404), "pull access denied"));
+        assert!(!is_transient(Some(401), ""));
+        assert!(!is_transient(None, "manifest unknown"));
+        assert!(!is_transient(
+            None,
+            "write /var/lib/docker: no space left on device"
+        ));
+        assert!(!is_transient(Some(500), "toomanyrequests: unauthorized"));
+        assert!(is_transient(Some(502), "bad gateway"));
+        assert!(is_transient(None, "read: connection reset by peer"));
+        assert!(is_transient(None, "net/http: TLS handshake timeout"));
+        // unknown errors fail right away instead of being retried
+        assert!(!is_transient(None, "something unexpected"));
+    }
+
+    #[test]
+    fn tells_missing_images() {
+        let missing =
+            anyhow!("manifest unknown").context("unable to pull `python:3.14-nonexistent`");
+        assert!(is_not_found(&missing));
+        let unreachable = anyhow!("dial tcp: lookup registry-1.docker.io: no such host");
+        assert!(!is_not_found(&unreachable));
+    }
+
+    fn reference(registry: &str, repository: &str, suffix: &str) -> Reference {
+        Reference {
+            registry: registry.to_string(),
+            repository: repository.to_string(),
+            suffix: suffix.to_string(),
+        }
+    }
+
+    #[test]
+    fn parses_docker_hub_references() {
+        assert_eq!(
+            Reference::parse("python"),
+            reference("docker.io", "library/python", ":latest")
+        );
+        assert_eq!(
+            Reference::parse("python:3.12"),
+            reference("docker.io", "library/python", ":3.12")
+        );
+        assert_eq!(
+            Reference::parse("me/tools:v1"),
+            reference("docker.io", "me/tools", ":v1")
+        );
+    }
+
+    #[test]
+    fn parses_registries_and_ports() {
+        assert_eq!(
+            Reference::parse("ghcr.io/me/tools"),
+            reference("ghcr.io", "me/tools", ":latest")
+        );
+        assert_eq!(
+            Reference::parse("localhost/tools:dev"),
+            reference("localhost", "tools", ":dev")
+        );
+        // the port is not a tag
+        assert_eq!(
+            Reference::parse("localhost:5000/tools"),
+            reference("localhost:5000", "tools", ":latest")
+        );
+        assert_eq!(
+            Reference::parse("registry.example.com:5000/team/tools:1.2"),
+            reference("registry.example.com:5000", "team/tools", ":1.2")
+        );
+    }
+
+    #[test]
+    fn parses_digests() {
+        let digest = "sha256:0123456789abcdef";
+        assert_eq!(
+            Reference::parse(&format!("python@{}", digest)),
+            reference("docker.io", "library/python", &format!("@{}", digest))
+        );
+        // the digest wins over the tag
+        assert_eq!(
+            Reference::parse(&format!("localhost:5000/tools:1.2@{}", digest)),
+            reference("localhost:5000", "tools", &format!("@{}", digest))
+        );
+    }
+
+    #[test]
+    fn moves_references_to_a_mirror() {
+        let python = Reference::parse("python:3.12");
+        assert_eq!(
+            python.on("mirror.example.com"),
+            "mirror.example.com/library/python:3.12"
+        );
+        assert_eq!(
+            python.on("https://mirror.example.com/"),
+            "mirror.example.com/library/python:3.12"
+        );
+        assert_eq!(
+            Reference::parse("ghcr.io/me/tools@sha256:abc").on("http://cache:5000"),
+            "cache:5000/me/tools@sha256:abc"
+        );
+    }
+}
@@ -0,0 +1,907 @@
+//! `Session`: the lifecycle of a quarantine container as a library api. the container gets the hardened defaults
+//! of the command line: no capabilities (but those needed to write to writable mounts), a read-only root filesystem
+//! with a tmpfs at `/tmp`, and `no-new-privileges`. the command line builds its containers with `SessionBuilder`
+//! too, so both always get the same defaults.
+//!
+//! ```no_run
+//! # async fn example() -> Result<(), quarantine::Error> {
+//! let mut session = quarantine::Session::builder()
+//!     .image("python:latest")
+//!     .runtime("runsc")
+//!     .mount("./submission", "/work")
+//!     .command(["python", "/work/main.py"])
+//!     .build()?;
+//! session.pull().await?;
+//! session.start().await?;
+//! let exit_code = session
+//!     .attach(tokio::io::empty(), tokio::io::stdout(), tokio::io::stderr())
+//!     .await?;
+//! let hi = session.exec(["echo", "hi"]).await?;
+//! assert_eq!(hi.stdout, b"hi\n");
+//! session.teardown(false).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use bollard::container::{
+    Config, CreateContainerOptions, LogOutput, RemoveContainerOptions, StartContainerOptions,
+};
+use bollard::exec::{CreateExecOptions, StartExecOptions, StartExecResults};
+use bollard::secret::{HostConfig, Mount, MountTypeEnum};
+use bollard::Docker;
+use futures::StreamExt;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+
+use crate::{
+    engine, pull, session, socket, Error, ENGINE_SOCKET_LABEL, MANAGED_LABEL, PROJECT_CAPABILITIES,
+    TMP_OPTIONS,
+};
+
+/// a host directory mounted into the container.
+#[derive(Debug, Clone, PartialEq)]
+struct Bind {
+    source: PathBuf,
+    target: String,
+    read_only: bool,
+}
+
+/// the settings of a `Session`, see `Session::builder`.
+#[derive(Debug, Clone, Default)]
+pub struct SessionBuilder {
+    image: Option<String>,
+    runtime: Option<String>,
+    name: Option<String>,
+    binds: Vec<Bind>,
+    env: Vec<String>,
+    user: Option<String>,
+    command: Option<Vec<String>>,
+    docker: Option<Docker>,
+    /// everything the builder has no method for
+    base: Config<String>,
+    hardened: Option<bool>,
+    privileged: bool,
+    cap_drop: Vec<String>,
+    cap_add: Vec<String>,
+    file_capabilities: Option<bool>,
+    writable_rootfs: bool,
+    allow_privilege_escalation: bool,
+    security_opt: Vec<String>,
+    tmpfs: Vec<(String, String)>,
+    expose_engine_socket: bool,
+}
+
+impl SessionBuilder {
+    /// the image to run, eg: `python:latest`. required
+    pub fn image(mut self, image: impl Into<String>) -> Self {
+        self.image = Some(image.into());
+        self
+    }
+
+    /// the container runtime, eg: `runsc`. the daemon's default when not set
+    pub fn runtime(mut self, runtime: impl Into<String>) -> Self {
+        self.runtime = Some(runtime.into());
+        self
+    }
+
+    /// the name of the container. by default it is named after the image, with `-2`, `-3`.. appended while
+    /// other sessions use the name
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// mounts the host directory `source` at `target`, writable. can be called multiple times
+    pub fn mount(mut self, source: impl Into<PathBuf>, target: impl Into<String>) -> Self {
+        self.binds.push(Bind {
+            source: source.into(),
+            target: target.into(),
+            read_only: false,
+        });
+        self
+    }
+
+    /// mounts the host directory `source` at `target`, read-only
+    pub fn mount_read_only(
+        mut self,
+        source: impl Into<PathBuf>,
+        target: impl Into<String>,
+    ) -> Self {
+        self.binds.push(Bind {
+            source: source.into(),
+            target: target.into(),
+            read_only: true,
+        });
+        self
+    }
+
+    /// a `KEY=VALUE` variable of the command. can be called multiple times
+    pub fn env(mut self, var: impl Into<String>) -> Self {
+        self.env.push(var.into());
+        self
+    }
+
+    /// who the container and the command run as, eg: `1000:1000`. the image's user when not set
+    pub fn user(mut self, user: impl Into<String>) -> Self {
+        self.user = Some(user.into());
+        self
+    }
+
+    /// what `attach` runs. `sh` by default
+    pub fn command<S: Into<String>>(mut self, command: impl IntoIterator<Item = S>) -> Self {
+        self.command = Some(command.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// the daemon to use. by default `--docker-host`, `DOCKER_HOST` or the local socket is connected to
+    pub fn docker(mut self, docker: Docker) -> Self {
+        self.docker = Some(docker);
+        self
+    }
+
+    /// the container config to start from, for the settings the builder has no method for (eg: limits or
+    /// ports). the image, mounts, env, labels and hardening of the builder are applied on top of it
+    pub fn base(mut self, config: Config<String>) -> Self {
+        self.base = config;
+        self
+    }
+
+    /// `false` turns the hardened defaults off, eg: for windows containers, which have none of them
+    pub fn hardened(mut self, hardened: bool) -> Self {
+        self.hardened = Some(hardened);
+        self
+    }
+
+    /// runs the container privileged, which turns the hardened defaults off
+    pub fn privileged(mut self, privileged: bool) -> Self {
+        self.privileged = privileged;
+        self
+    }
+
+    /// drops a capability (eg: `NET_RAW`). giving any replaces the default of dropping them all
+    pub fn cap_drop(mut self, cap: impl Into<String>) -> Self {
+        self.cap_drop.push(cap.into());
+        self
+    }
+
+    /// adds a capability (eg: `NET_BIND_SERVICE`)
+    pub fn cap_add(mut self, cap: impl Into<String>) -> Self {
+        self.cap_add.push(cap.into());
+        self
+    }
+
+    /// whether root keeps `CHOWN`, `DAC_OVERRIDE` and `FOWNER` when the capabilities are dropped, so it can write
+    /// to files owned by the host user. by default it does when there is a writable mount and no `user`
+    pub fn file_capabilities(mut self, keep: bool) -> Self {
+        self.file_capabilities = Some(keep);
+        self
+    }
+
+    /// keeps the root filesystem writable. by default it is read-only with a tmpfs at `/tmp`
+    pub fn writable_rootfs(mut self, writable: bool) -> Self {
+        self.writable_rootfs = writable;
+        self
+    }
+
+    /// leaves out the default `no-new-privileges`
+    pub fn allow_privilege_escalation(mut self, allow: bool) -> Self {
+        self.allow_privilege_escalation = allow;
+        self
+    }
+
+    /// a security option as the daemon takes it, eg: `apparmor=PROFILE`. an explicit `no-new-privileges[:BOOL]`
+    /// decides instead of the default
+    pub fn security_opt(mut self, option: impl Into<String>) -> Self {
+        self.security_opt.push(option.into());
+        self
+    }
+
+    /// mounts a tmpfs at `path` with the `mount -t tmpfs` options, eg: `size=64m`. one at `/tmp` replaces the
+    /// default
+    pub fn tmpfs(mut self, path: impl Into<String>, options: impl Into<String>) -> Self {
+        self.tmpfs.push((path.into(), options.into()));
+        self
+    }
+
+    /// allows mounts that expose the docker or podman socket, which gives the container control of the host.
+    /// such a container is labelled `ENGINE_SOCKET_LABEL`
+    pub fn dangerously_expose_engine_socket(mut self, expose: bool) -> Self {
+        self.expose_engine_socket = expose;
+        self
+    }
+
+    /// the config the container is created with. mounts with a relative host path are resolved against the
+    /// working directory, the daemon needs absolute ones
+    pub fn config(&self) -> Result<Config<String>, Error> {
+        let image = self.image.clone().ok_or(Error::NoImage)?;
+        let mut config = self.base.clone();
+        config.image = Some(image.clone());
+        config.tty = Some(true);
+        if let Some(user) = &self.user {
+            config.user = Some(user.clone());
+        }
+        if !self.env.is_empty() {
+            config
+                .env
+                .get_or_insert_with(Vec::new)
+                .extend(self.env.iter().cloned());
+        }
+        let labels = config.labels.get_or_insert_with(HashMap::new);
+        labels.insert(
+            MANAGED_LABEL.to_string(),
+            env!("CARGO_PKG_VERSION").to_string(),
+        );
+        labels.insert(session::IMAGE_LABEL.to_string(), image);
+
+        let mut mounts = self
+            .binds
+            .iter()
+            .map(|bind| {
+                let invalid =
+                    || Error::InvalidMount(format!("{}:{}", bind.source.display(), bind.target));
+                if !is_absolute(&bind.target) {
+                    return Err(invalid());
+                }
+                let source = std::path::absolute(&bind.source).map_err(|_| invalid())?;
+                Ok(Mount {
+                    source: Some(source.to_str().ok_or_else(invalid)?.to_string()),
+                    target: Some(bind.target.clone()),
+                    typ: Some(MountTypeEnum::BIND),
+                    read_only: Some(bind.read_only),
+                    ..Default::default()
+                })
+            })
+            .collect::<Result<Vec<Mount>, Error>>()?;
+        let host_config = config.host_config.get_or_insert_with(HostConfig::default);
+        mounts.extend(host_config.mounts.take().unwrap_or_default());
+        host_config.mounts = Some(mounts);
+        if let Some(runtime) = &self.runtime {
+            host_config.runtime = Some(runtime.clone());
+        }
+
+        let hardened = self.hardened.unwrap_or(true) && !self.privileged;
+        let (cap_drop, cap_add) = match hardened && self.cap_drop.is_empty() {
+            true => {
+                let writes = self.binds.iter().any(|bind| !bind.read_only) && config.user.is_none();
+                let mut added = match self.file_capabilities.unwrap_or(writes) {
+                    true => PROJECT_CAPABILITIES.map(String::from).to_vec(),
+                    false => vec![],
+                };
+                for cap in &self.cap_add {
+                    if !added.contains(cap) {
+                        added.push(cap.clone());
+                    }
+                }
+                (vec!["ALL".to_string()], added)
+            }
+            false => (self.cap_drop.clone(), self.cap_add.clone()),
+        };
+        let readonly_rootfs = hardened && !self.writable_rootfs;
+        let mut tmpfs = self
+            .tmpfs
+            .iter()
+            .cloned()
+            .collect::<HashMap<String, String>>();
+        // unless `/tmp` is mounted from elsewhere
+        let tmp_mounted = host_config
+            .binds
+            .iter()
+            .flatten()
+            .any(|bind| bind.split(':').nth(1) == Some("/tmp"))
+            || host_config
+                .mounts
+                .iter()
+                .flatten()
+                .any(|mount| mount.target.as_deref() == Some("/tmp"));
+        if readonly_rootfs && !tmp_mounted {
+            tmpfs
+                .entry("/tmp".to_string())
+                .or_insert_with(|| TMP_OPTIONS.to_string());
+        }
+        let no_new_privileges = hardened
+            && !self.allow_privilege_escalation
+            && !self
+                .security_opt
+                .iter()
+                .any(|option| option.starts_with("no-new-privileges"));
+
+        host_config.cap_drop = (!cap_drop.is_empty()).then_some(cap_drop);
+        host_config.cap_add = (!cap_add.is_empty()).then_some(cap_add);
+        host_config.readonly_rootfs = readonly_rootfs.then_some(true);
+        host_config.tmpfs = (!tmpfs.is_empty()).then_some(tmpfs);
+        host_config.security_opt = Some(
+            no_new_privileges
+                .then(|| "no-new-privileges".to_string())
+                .into_iter()
+                .chain(self.security_opt.iter().cloned())
+                .collect::<Vec<String>>(),
+        )
+        .filter(|options| !options.is_empty());
+        host_config.privileged = self.privileged.then_some(true);
+
+        if let Some(socket) = socket::exposed(host_config) {
+            if !self.expose_engine_socket {
+                return Err(Error::EngineSocket(socket));
+            }
+            config
+                .labels
+                .get_or_insert_with(HashMap::new)
+                .insert(ENGINE_SOCKET_LABEL.to_string(), "true".to_string());
+        }
+        Ok(config)
+    }
+
+    /// checks the settings and resolves the config of the container, see `config`.
+    pub fn build(self) -> Result<Session, Error> {
+        let config = self.config()?;
+        Ok(Session {
+            image: config.image.clone().unwrap_or_default(),
+            runtime: self.runtime,
+            name: self.name,
+            config,
+            command: self.command.unwrap_or_else(|| vec!["sh".to_string()]),
+            docker: self.docker,
+            container: None,
+            lease: None,
+        })
+    }
+}
+
+/// a unix path, or a windows one with a drive letter (eg: `C:\quarantine`).
+fn is_absolute(target: &str) -> bool {
+    target.starts_with('/') || target.get(1..3) == Some(":\\")
+}
+
+/// the output of `Session::exec`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExecResult {
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    pub exit_code: i64,
+}
+
+/// a sandbox container: `pull`, `start`, then `attach` or `exec` any number of times, and `teardown`.
+#[derive(Debug)]
+pub struct Session {
+    image: String,
+    runtime: Option<String>,
+    name: Option<String>,
+    config: Config<String>,
+    command: Vec<String>,
+    docker: Option<Docker>,
+    /// the id of the started container
+    container: Option<String>,
+    lease: Option<session::Lease>,
+}
+
+impl Session {
+    pub fn builder() -> SessionBuilder {
+        SessionBuilder::default()
+    }
+
+    /// the config the container is created with.
+    pub fn config(&self) -> &Config<String> {
+        &self.config
+    }
+
+    /// the id of the container, once it is started.
+    pub fn container(&self) -> Option<&str> {
+        self.container.as_deref()
+    }
+
+    /// pulls the image, unless it is present already.
+    pub async fn pull(&mut self) -> Result<(), Error> {
+        let docker = self.connect().await?;
+        pull::pull_image(&docker, &self.image, &pull::Options::default())
+            .await
+            .map_err(|e| match pull::is_not_found(&e) {
+                true => Error::ImageNotFound(self.image.clone()),
+                false => Error::Pull {
+                    image: self.image.clone(),
+                    source: e.into(),
+                },
+            })
+    }
+
+    /// creates and starts the container, and returns its id.
+    pub async fn start(&mut self) -> Result<&str, Error> {
+        let docker = self.connect().await?;
+        if let Some(runtime) = &self.runtime {
+            let available = docker
+                .info()
+                .await?
+                .runtimes
+                .unwrap_or_default()
+                .into_keys()
+                .collect::<Vec<String>>();
+            if !available.contains(runtime) {
+                return Err(Error::RuntimeNotFound {
+                    runtime: runtime.clone(),
+                    available,
+                });
+            }
+        }
+
+        let mut config = self.config.clone();
+        config
+            .labels
+            .get_or_insert_with(HashMap::new)
+            .extend(session::owner());
+        let name = match &self.name {
+            Some(name) => name.clone(),
+            None => session::free_name(&docker, &session::default_name(&self.image, None))
+                .await
+                .map_err(|e| Error::Start(e.into()))?,
+        };
+        let id = docker
+            .create_container(
+                Some(CreateContainerOptions {
+                    name: name.as_str(),
+                    platform: None,
+                }),
+                config,
+            )
+            .await
+            .map_err(|e| Error::Start(e.into()))?
+            .id;
+        self.lease = session::Lease::take(&id).map_err(|e| Error::Start(e.into()))?;
+        // kept before starting, so `teardown` removes a container that didn't start
+        let container = self.container.insert(id);
+        docker
+            .start_container(container, None::<StartContainerOptions<String>>)
+            .await
+            .map_err(|e| Error::Start(e.into()))?;
+        tracing::info!("container started: {} :: name: {}", container, name);
+        Ok(container)
+    }
+
+    /// runs the `command` in the container with the given streams, and returns its exit code. `stdin` is
+    /// closed for the command when it ends, the command's output is copied until the command exits.
+    pub async fn attach<I, O, E>(&self, stdin: I, stdout: O, stderr: E) -> Result<i64, Error>
+    where
+        I: AsyncRead + Unpin,
+        O: AsyncWrite + Unpin,
+        E: AsyncWrite + Unpin,
+    {
+        self.run(&self.command, stdin, stdout, stderr).await
+    }
+
+    /// runs `command` in the container without input, and collects its output.
+    pub async fn exec<S: Into<String>>(
+        &self,
+        command: impl IntoIterator<Item = S>,
+    ) -> Result<ExecResult, Error> {
+        let command = command.into_iter().map(Into::into).collect::<Vec<String>>();
+        let (mut stdout, mut stderr) = (vec![], vec![]);
+        let exit_code = self
+            .run(&command, tokio::io::empty(), &mut stdout, &mut stderr)
+            .await?;
+        Ok(ExecResult {
+            stdout,
+            stderr,
+            exit_code,
+        })
+    }
+
+    async fn run<I, O, E>(
+        &self,
+        command: &[String],
+        mut stdin: I,
+        mut stdout: O,
+        mut stderr: E,
+    ) -> Result<i64, Error>
+    where
+        I: AsyncRead + Unpin,
+        O: AsyncWrite + Unpin,
+        E: AsyncWrite + Unpin,
+    {
+        let container = self.container.as_deref().ok_or(Error::NotStarted)?;
+        let docker = self.docker.as_ref().ok_or(Error::NotStarted)?;
+        let exec = docker
+            .create_exec(
+                container,
+                CreateExecOptions {
+                    attach_stdin: Some(true),
+                    attach_stdout: Some(true),
+                    attach_stderr: Some(true),
+                    tty: Some(false),
+                    cmd: Some(command.iter().map(String::as_str).collect()),
+                    env: self
+                        .config
+                        .env
+                        .as_ref()
+                        .map(|env| env.iter().map(String::as_str).collect()),
+                    user: self.config.user.as_deref(),
+                    ..Default::default()
+                },
+            )
+            .await
+            .map_err(Error::Exec)?;
+        let StartExecResults::Attached {
+            mut output,
+            mut input,
+        } = docker
+            .start_exec(
+                &exec.id,
+                Some(StartExecOptions {
+                    detach: false,
+                    tty: false,
+                    output_capacity: None,
+                }),
+            )
+            .await
+            .map_err(Error::Exec)?
+        else {
+            return Err(Error::Detached);
+        };
+
+        let copy_in = async {
+            tokio::io::copy(&mut stdin, &mut input).await?;
+            // half-close, so the command sees EOF but can still finish writing its output
+            input.shutdown().await
+        };
+        let copy_out = async {
+            while let Some(chunk) = output.next().await {
+                match chunk? {
+                    LogOutput::StdOut { message } | LogOutput::Console { message } => {
+                        stdout.write_all(&message).await.map_err(Error::Attach)?
+                    }
+                    LogOutput::StdErr { message } => {
+                        stderr.write_all(&message).await.map_err(Error::Attach)?
+                    }
+                    LogOutput::StdIn { .. } => {}
+                }
+            }
+            stdout.flush().await.map_err(Error::Attach)?;
+            stderr.flush().await.map_err(Error::Attach)?;
+            Ok::<_, Error>(())
+        };
+        tokio::pin!(copy_in, copy_out);
+        // the command may exit without reading all of stdin, the output decides when the session is over
+        let mut input_done = false;
+        loop {
+            tokio::select! {
+                copied = &mut copy_in, if !input_done => {
+                    copied.map_err(Error::Attach)?;
+                    input_done = true;
+                }
+                copied = &mut copy_out => break copied?,
+            }
+        }
+
+        docker
+            .inspect_exec(&exec.id)
+            .await?
+            .exit_code
+            .ok_or(Error::NoExitCode)
+    }
+
+    /// stops and removes the container with its anonymous volumes. with `persist` the container is left running
+    /// instead, under its name and `container` id. nothing happens if it was never started.
+    pub async fn teardown(mut self, persist: bool) -> Result<(), Error> {
+        let (Some(container), Some(docker)) = (self.container.take(), &self.docker) else {
+            return Ok(());
+        };
+        if persist {
+            tracing::info!("keeping container: {}", container);
+            return Ok(());
+        }
+        docker
+            .remove_container(
+                &container,
+                Some(RemoveContainerOptions {
+                    force: true,
+                    v: true,
+                    link: false,
+                }),
+            )
+            .await?;
+        tracing::info!("removed container: {}", container);
+        Ok(())
+    }
+
+    /// the daemon of the builder, or the one the command line would use.
+    async fn connect(&mut self) -> Result<Docker, Error> {
+        if let Some(docker) = &self.docker {
+            return Ok(docker.clone());
+        }
+        let docker = engine::connect()
+            .await
+            .map_err(|e| Error::Connect(e.into()))?;
+        Ok(self.docker.insert(docker).clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn host_config(builder: SessionBuilder) -> HostConfig {
+        builder
+            .image("alpine")
+            .config()
+            .unwrap()
+            .host_config
+            .unwrap()
+    }
+
+    fn strings(values: &[&str]) -> Option<Vec<String>> {
+        Some(values.iter().map(|value| value.to_string()).collect())
+    }
+
+    #[test]
+    fn hardens_by_default() {
+        let host_config = host_config(Session::builder());
+        assert_eq!(host_config.cap_drop, strings(&["ALL"]));
+        assert_eq!(host_config.cap_add, None);
+        assert_eq!(host_config.readonly_rootfs, Some(true));
+        assert_eq!(
+            host_config.tmpfs,
+            Some(HashMap::from([(
+                "/tmp".to_string(),
+                TMP_OPTIONS.to_string()
+            )]))
+        );
+        assert_eq!(host_config.security_opt, strings(&["no-new-privileges"]));
+        assert_eq!(host_config.privileged, None);
+    }
+
+    #[test]
+    fn keeps_the_capabilities_to_write_to_mounts() {
+        let writable = host_config(Session::builder().mount("/project", "/quarantine"));
+        assert_eq!(writable.cap_add, strings(&PROJECT_CAPABILITIES));
+
+        let read_only = host_config(Session::builder().mount_read_only("/project", "/quarantine"));
+        assert_eq!(read_only.cap_add, None);
+
+        let as_user = host_config(
+            Session::builder()
+                .mount("/project", "/quarantine")
+                .user("1000:1000"),
+        );
+        assert_eq!(as_user.cap_add, None);
+
+        let without = host_config(
+            Session::builder()
+                .mount("/project", "/quarantine")
+                .file_capabilities(false),
+        );
+        assert_eq!(without.cap_add, None);
+    }
+
+    #[test]
+    fn keeps_colons_spaces_and_commas_in_mounts() {
+        for (source, target) in [
+            ("/home/me/my project:v2", "/quarantine"),
+            ("/a:b:ro", "/c d"),
+            ("/data/a,b", "/mnt/x,y"),
+        ] {
+            let mounts = host_config(Session::builder().mount(source, target))
+                .mounts
+                .unwrap();
+            assert_eq!(mounts.len(), 1);
+            assert_eq!(mounts[0].source.as_deref(), Some(source));
+            assert_eq!(mounts[0].target.as_deref(), Some(target));
+            assert_eq!(mounts[0].typ, Some(MountTypeEnum::BIND));
+            assert_eq!(mounts[0].read_only, Some(false));
+        }
+        let mounts = host_config(Session::builder().mount_read_only("/a:b:ro", "/c d"))
+            .mounts
+            .unwrap();
+        assert_eq!(mounts[0].source.as_deref(), Some("/a:b:ro"));
+        assert_eq!(mounts[0].read_only, Some(true));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn refuses_mounts_that_are_not_unicode() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let source = std::path::Path::new(std::ffi::OsStr::from_bytes(b"/tmp/\xff"));
+        let built = Session::builder()
+            .image("alpine")
+            .mount(source, "/quarantine")
+            .config();
+        assert!(matches!(built, Err(Error::InvalidMount(_))));
+    }
+
+    #[test]
+    fn adds_capabilities_once() {
+        let host_config = host_config(
+            Session::builder()
+                .mount("/project", "/quarantine")
+                .cap_add("CHOWN")
+                .cap_add("NET_BIND_SERVICE"),
+        );
+        assert_eq!(host_config.cap_drop, strings(&["ALL"]));
+        assert_eq!(
+            host_config.cap_add,
+            strings(&["CHOWN", "DAC_OVERRIDE", "FOWNER", "NET_BIND_SERVICE"])
+        );
+    }
+
+    #[test]
+    fn explicit_drops_replace_the_default() {
+        let host_config = host_config(
+            Session::builder()
+                .mount("/project", "/quarantine")
+                .cap_drop("NET_RAW")
+                .cap_add("SYS_PTRACE"),
+        );
+        assert_eq!(host_config.cap_drop, strings(&["NET_RAW"]));
+        assert_eq!(host_config.cap_add, strings(&["SYS_PTRACE"]));
+    }
+
+    #[test]
+    fn writable_rootfs_has_no_default_tmpfs() {
+        let host_config = host_config(Session::builder().writable_rootfs(true));
+        assert_eq!(host_config.readonly_rootfs, None);
+        assert_eq!(host_config.tmpfs, None);
+    }
+
+    #[test]
+    fn tmpfs_at_tmp_replaces_the_default() {
+        let host_config = host_config(
+            Session::builder()
+                .tmpfs("/tmp", "size=1g")
+                .tmpfs("/run", ""),
+        );
+        assert_eq!(
+            host_config.tmpfs,
+            Some(HashMap::from([
+                ("/tmp".to_string(), "size=1g".to_string()),
+                ("/run".to_string(), String::new()),
+            ]))
+        );
+    }
+
+    #[test]
+    fn mounted_tmp_has_no_default_tmpfs() {
+        let bound = host_config(Session::builder().base(Config {
+            host_config: Some(HostConfig {
+                binds: strings(&["/scratch:/tmp"]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }));
+        assert_eq!(bound.readonly_rootfs, Some(true));
+        assert_eq!(bound.tmpfs, None);
+
+        let mounted = host_config(Session::builder().mount("/scratch", "/tmp"));
+        assert_eq!(mounted.tmpfs, None);
+    }
+
+    #[test]
+    fn explicit_no_new_privileges_decides() {
+        let host_config = host_config(Session::builder().security_opt("no-new-privileges:false"));
+        assert_eq!(
+            host_config.security_opt,
+            strings(&["no-new-privileges:false"])
+        );
+
+        let allowed = super::tests::host_config(
+            Session::builder()
+                .allow_privilege_escalation(true)
+                .security_opt("apparmor=sandbox"),
+        );
+        assert_eq!(allowed.security_opt, strings(&["apparmor=sandbox"]));
+    }
+
+    #[test]
+    fn privileged_turns_hardening_off() {
+        let host_config = host_config(
+            Session::builder()
+                .mount("/project", "/quarantine")
+                .privileged(true),
+        );
+        assert_eq!(host_config.privileged, Some(true));
+        assert_eq!(host_config.cap_drop, None);
+        assert_eq!(host_config.cap_add, None);
+        assert_eq!(host_config.readonly_rootfs, None);
+        assert_eq!(host_config.tmpfs, None);
+        assert_eq!(host_config.security_opt, None);
+    }
+
+    #[test]
+    fn unhardened_passes_the_options_through() {
+        let host_config = host_config(
+            Session::builder()
+                .hardened(false)
+                .cap_add("NET_ADMIN")
+                .security_opt("seccomp=unconfined"),
+        );
+        assert_eq!(host_config.cap_drop, None);
+        assert_eq!(host_config.cap_add, strings(&["NET_ADMIN"]));
+        assert_eq!(host_config.readonly_rootfs, None);
+        assert_eq!(host_config.security_opt, strings(&["seccomp=unconfined"]));
+    }
+
+    #[test]
+    fn mounts_come_before_the_base_mounts() {
+        let host_config = host_config(Session::builder().mount("/project", "/quarantine").base(
+            Config {
+                host_config: Some(HostConfig {
+                    mounts: Some(vec![Mount {
+                        target: Some("/cache".to_string()),
+                        typ: Some(MountTypeEnum::VOLUME),
+                        ..Default::default()
+                    }]),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        ));
+        let targets = host_config
+            .mounts
+            .unwrap()
+            .into_iter()
+            .map(|mount| mount.target.unwrap())
+            .collect::<Vec<String>>();
+        assert_eq!(targets, ["/quarantine", "/cache"]);
+    }
+
+    #[test]
+    fn relative_mounts_are_resolved() {
+        let host_config = host_config(Session::builder().mount_read_only("project", "/quarantine"));
+        let mount = &host_config.mounts.unwrap()[0];
+        assert_eq!(
+            mount.source.as_deref().map(PathBuf::from),
+            Some(std::env::current_dir().unwrap().join("project"))
+        );
+        assert_eq!(mount.read_only, Some(true));
+    }
+
+    #[test]
+    fn mount_targets_must_be_absolute() {
+        let relative = Session::builder()
+            .image("alpine")
+            .mount("/project", "quarantine")
+            .config();
+        assert!(matches!(relative, Err(Error::InvalidMount(_))));
+
+        let windows = Session::builder()
+            .image("mcr.microsoft.com/windows/nanoserver")
+            .hardened(false)
+            .mount("/project", "C:\\quarantine")
+            .config();
+        assert!(windows.is_ok());
+    }
+
+    #[test]
+    fn refuses_the_engine_socket() {
+        let refused = Session::builder()
+            .image("alpine")
+            .mount("/var/run/docker.sock", "/var/run/docker.sock")
+            .config();
+        assert!(matches!(refused, Err(Error::EngineSocket(_))));
+
+        let exposed = Session::builder()
+            .image("alpine")
+            .mount("/var/run/docker.sock", "/var/run/docker.sock")
+            .dangerously_expose_engine_socket(true)
+            .config()
+            .unwrap();
+        assert!(exposed.labels.unwrap().contains_key(ENGINE_SOCKET_LABEL));
+    }
+
+    #[test]
+    fn labels_the_container() {
+        let config = Session::builder()
+            .image("alpine")
+            .env("A=1")
+            .config()
+            .unwrap();
+        let labels = config.labels.unwrap();
+        assert_eq!(
+            labels.get(MANAGED_LABEL).map(String::as_str),
+            Some(env!("CARGO_PKG_VERSION"))
+        );
+        assert_eq!(
+            labels.get(session::IMAGE_LABEL).map(String::as_str),
+            Some("alpine")
+        );
+        assert_eq!(config.env, strings(&["A=1"]));
+        assert_eq!(config.tty, Some(true));
+    }
+}
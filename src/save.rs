@@ -0,0 +1,130 @@
+//! `quarantine save`: the counterpart of `--load`, writes an image to a `docker save` archive.
+
+use anyhow::anyhow;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use futures::StreamExt;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::engine;
+
+/// progress is reported every time this much more was written.
+const PROGRESS_EVERY: u64 = 64 * 1024 * 1024;
+
+/// streams `image` to `output`, gzipped when `gzip` is set or the file ends in `.gz`.
+/// the archive is written next to `output` and only moved into place once it is complete.
+pub async fn save(image: &str, output: &Path, gzip: bool, force: bool) -> anyhow::Result<()> {
+    if output.exists() && !force {
+        return Err(anyhow!(
+            "`{}` already exists. pass --force to overwrite it",
+            output.display()
+        ));
+    }
+    let gzip = gzip
+        || output
+            .extension()
+            .is_some_and(|extension| extension == "gz");
+
+    let docker = engine::connect().await?;
+    let size = docker
+        .inspect_image(image)
+        .await
+        .map_err(|e| anyhow!("unable to find the image `{}`: {}", image, e))?
+        .size
+        .unwrap_or_default();
+
+    let partial = partial_path(output);
+    let written = write(&docker, image, &partial, gzip, size as u64).await;
+    let written = match written {
+        Ok(written) => written,
+        Err(e) => {
+            let _ = std::fs::remove_file(&partial);
+            return Err(e);
+        }
+    };
+    std::fs::rename(&partial, output)?;
+
+    tracing::info!(
+        "saved `{}` to `{}` ({} bytes)",
+        image,
+        output.display(),
+        written
+    );
+    Ok(())
+}
+
+/// writes the archive to `path` and waits until it reached the disk. the number of bytes of the archive
+/// the daemon sent.
+async fn write(
+    docker: &bollard::Docker,
+    image: &str,
+    path: &Path,
+    gzip: bool,
+    size: u64,
+) -> anyhow::Result<u64> {
+    let file =
+        File::create(path).map_err(|e| anyhow!("unable to create `{}`: {}", path.display(), e))?;
+    let (written, file) = match gzip {
+        true => {
+            let mut out = GzEncoder::new(BufWriter::new(file), Compression::default());
+            let written = copy(docker, image, &mut out, size).await?;
+            (
+                written,
+                out.finish()?.into_inner().map_err(|e| e.into_error())?,
+            )
+        }
+        false => {
+            let mut out = BufWriter::new(file);
+            let written = copy(docker, image, &mut out, size).await?;
+            (written, out.into_inner().map_err(|e| e.into_error())?)
+        }
+    };
+    file.sync_all()?;
+    if !gzip && file.metadata()?.len() != written {
+        return Err(anyhow!(
+            "`{}` is shorter than the archive the daemon sent",
+            path.display()
+        ));
+    }
+    Ok(written)
+}
+
+async fn copy(
+    docker: &bollard::Docker,
+    image: &str,
+    out: &mut dyn Write,
+    size: u64,
+) -> anyhow::Result<u64> {
+    let mut stream = docker.export_image(image);
+    let mut written = 0;
+    let mut reported = 0;
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|e| anyhow!("unable to export `{}`: {}", image, e))?;
+        out.write_all(&chunk)?;
+        written += chunk.len() as u64;
+        if written - reported >= PROGRESS_EVERY {
+            reported = written;
+            match size {
+                0 => tracing::info!("saved {} MiB", written / 1024 / 1024),
+                size => tracing::info!(
+                    "saved {} MiB (about {}%)",
+                    written / 1024 / 1024,
+                    (written * 100 / size).min(99)
+                ),
+            }
+        }
+    }
+    match written {
+        0 => Err(anyhow!("the daemon sent an empty archive for `{}`", image)),
+        written => Ok(written),
+    }
+}
+
+/// `image.tar` -> `image.tar.partial`
+fn partial_path(output: &Path) -> PathBuf {
+    let mut name = output.as_os_str().to_os_string();
+    name.push(".partial");
+    PathBuf::from(name)
+}
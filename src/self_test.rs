@@ -0,0 +1,260 @@
+use anyhow::anyhow;
+use bollard::container::{
+    Config, CreateContainerOptions, RemoveContainerOptions, StartContainerOptions,
+};
+use bollard::secret::HostConfig;
+use bollard::Docker;
+use std::future::Future;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::pull::pull_image;
+use crate::{engine, exec_output, pool, PROJECT_MOUNT};
+
+const IMAGE: &str = "busybox:latest";
+const MEMORY_LIMIT: i64 = 64 * 1024 * 1024;
+
+/// runs a short scripted session against a tiny image and reports pass/fail per step.
+/// everything it creates is removed again, even when a step fails.
+pub async fn self_test() -> anyhow::Result<()> {
+    let mut failures = 0;
+
+    let Some(docker) = step(
+        &mut failures,
+        "connect to the docker daemon",
+        engine::connect(),
+    )
+    .await
+    else {
+        return Err(anyhow!(
+            "self-test failed: unable to connect to the docker daemon"
+        ));
+    };
+
+    let name = format!("quarantine-self-test-{}", std::process::id());
+    let pooled = format!("{}-pooled", name);
+    let host_dir = std::env::temp_dir().join(&name);
+    let mut created_in = None;
+
+    if step(
+        &mut failures,
+        "pull image",
+        pull_image(&docker, IMAGE, &Default::default()),
+    )
+    .await
+    .is_some()
+        && step(
+            &mut failures,
+            "create and start container",
+            timed(start(&docker, &name, &host_dir)),
+        )
+        .await
+        .inspect(|elapsed| created_in = Some(*elapsed))
+        .is_some()
+    {
+        step(
+            &mut failures,
+            "bind mount",
+            bind_mount_roundtrip(&docker, &name, &host_dir),
+        )
+        .await;
+        step(&mut failures, "exec io", exec_roundtrip(&docker, &name)).await;
+        step(&mut failures, "resource limits", limits(&docker, &name)).await;
+    }
+
+    if let Some(created_in) = created_in {
+        if let Some(claimed_in) = step(
+            &mut failures,
+            "claim and start prewarmed container",
+            timed(claim_from_pool(&docker, &pooled, &host_dir)),
+        )
+        .await
+        {
+            println!(
+                "      prewarmed: {:.2?} :: from scratch: {:.2?}",
+                claimed_in, created_in
+            );
+        }
+    }
+
+    step(
+        &mut failures,
+        "cleanup",
+        cleanup(&docker, &name, &pooled, &host_dir),
+    )
+    .await;
+
+    match failures {
+        0 => {
+            println!("all steps passed");
+            Ok(())
+        }
+        n => Err(anyhow!("self-test failed: {} step(s) did not pass", n)),
+    }
+}
+
+async fn step<T>(
+    failures: &mut usize,
+    name: &str,
+    fut: impl Future<Output = anyhow::Result<T>>,
+) -> Option<T> {
+    let start = Instant::now();
+    let result = fut.await;
+    let elapsed = start.elapsed();
+
+    match result {
+        Ok(value) => {
+            println!("PASS  {} ({:.2?})", name, elapsed);
+            Some(value)
+        }
+        Err(e) => {
+            println!("FAIL  {} ({:.2?}) :: {}", name, elapsed, e);
+            *failures += 1;
+            None
+        }
+    }
+}
+
+async fn timed(fut: impl Future<Output = anyhow::Result<()>>) -> anyhow::Result<Duration> {
+    let start = Instant::now();
+    fut.await?;
+    Ok(start.elapsed())
+}
+
+/// the mount is made the way sessions make it, the rest is left as docker's defaults.
+fn config(host_dir: &Path) -> anyhow::Result<Config<String>> {
+    let config = quarantine::Session::builder()
+        .image(IMAGE)
+        .base(Config {
+            working_dir: Some(PROJECT_MOUNT.into()),
+            host_config: Some(HostConfig {
+                memory: Some(MEMORY_LIMIT),
+                ..Default::default()
+            }),
+            ..Default::default()
+        })
+        .mount(host_dir, PROJECT_MOUNT)
+        .hardened(false)
+        .config()?;
+    Ok(config)
+}
+
+async fn start(docker: &Docker, name: &str, host_dir: &Path) -> anyhow::Result<()> {
+    std::fs::create_dir_all(host_dir)?;
+
+    docker
+        .create_container(
+            Some(CreateContainerOptions {
+                name,
+                platform: None,
+            }),
+            config(host_dir)?,
+        )
+        .await?;
+    docker
+        .start_container(name, None::<StartContainerOptions<String>>)
+        .await?;
+    Ok(())
+}
+
+async fn bind_mount_roundtrip(docker: &Docker, name: &str, host_dir: &Path) -> anyhow::Result<()> {
+    let token = format!("bind-{}", std::process::id());
+    let script = format!("echo {} > {}/self-test", token, PROJECT_MOUNT);
+    let (exit_code, output) = exec_output(docker, name, vec!["sh", "-c", &script]).await?;
+    if exit_code != 0 {
+        return Err(anyhow!(
+            "writing to the mount exited with {} :: {}",
+            exit_code,
+            output
+        ));
+    }
+
+    let written = std::fs::read_to_string(host_dir.join("self-test"))?;
+    match written.trim() == token {
+        true => Ok(()),
+        false => Err(anyhow!(
+            "expected `{}` on the host, found `{}`",
+            token,
+            written.trim()
+        )),
+    }
+}
+
+async fn exec_roundtrip(docker: &Docker, name: &str) -> anyhow::Result<()> {
+    let token = format!("exec-{}", std::process::id());
+    let (exit_code, output) = exec_output(docker, name, vec!["echo", &token]).await?;
+    match exit_code == 0 && output.trim() == token {
+        true => Ok(()),
+        false => Err(anyhow!(
+            "expected `{}`, got `{}` (exit code {})",
+            token,
+            output.trim(),
+            exit_code
+        )),
+    }
+}
+
+async fn limits(docker: &Docker, name: &str) -> anyhow::Result<()> {
+    let inspect = docker.inspect_container(name, None).await?;
+    let memory = inspect
+        .host_config
+        .and_then(|host_config| host_config.memory);
+    match memory == Some(MEMORY_LIMIT) {
+        true => Ok(()),
+        false => Err(anyhow!(
+            "expected a memory limit of {} bytes, the daemon reports {:?}",
+            MEMORY_LIMIT,
+            memory
+        )),
+    }
+}
+
+/// warms one pool member outside of the timing, then measures how long claiming and starting it takes.
+async fn claim_from_pool(docker: &Docker, pooled: &str, host_dir: &Path) -> anyhow::Result<()> {
+    pool::warm(docker, config(host_dir)?, pooled, 1).await?;
+
+    let start = Instant::now();
+    let id = pool::claim(docker, &config(host_dir)?, pooled)
+        .await?
+        .ok_or_else(|| anyhow!("the prewarmed container could not be claimed"))?;
+    docker
+        .start_container(&id, None::<StartContainerOptions<String>>)
+        .await?;
+    tracing::debug!("claimed and started {} in {:.2?}", id, start.elapsed());
+    Ok(())
+}
+
+async fn cleanup(docker: &Docker, name: &str, pooled: &str, host_dir: &Path) -> anyhow::Result<()> {
+    let mut removed = vec![];
+    for name in [name, pooled] {
+        removed.push(
+            docker
+                .remove_container(
+                    name,
+                    Some(RemoveContainerOptions {
+                        force: true,
+                        v: true,
+                        ..Default::default()
+                    }),
+                )
+                .await,
+        );
+    }
+    // members that were warmed but never claimed
+    pool::clean_matching(docker, &config(host_dir)?).await?;
+
+    if host_dir.exists() {
+        std::fs::remove_dir_all(host_dir)?;
+    }
+
+    for result in removed {
+        match result {
+            Ok(())
+            | Err(bollard::errors::Error::DockerResponseServerError {
+                status_code: 404, ..
+            }) => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(())
+}
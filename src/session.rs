@@ -0,0 +1,655 @@
+//! which quarantine process a session container belongs to, so concurrent sessions with the same image don't
+//! remove each other's containers.
+
+use anyhow::anyhow;
+use bollard::container::{ListContainersOptions, RemoveContainerOptions};
+use bollard::Docker;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use crate::MANAGED_LABEL;
+
+/// set on session containers, the image of the session.
+pub const IMAGE_LABEL: &str = "quarantine.image";
+
+/// set on session containers, the working directory of the session.
+pub const CWD_LABEL: &str = "quarantine.cwd";
+
+/// set on session containers, the quarantine process that created it.
+pub const PID_LABEL: &str = "quarantine.pid";
+
+/// set on session containers, the machine the process of `PID_LABEL` runs on.
+pub const HOSTNAME_LABEL: &str = "quarantine.hostname";
+
+/// set on containers of `--persist` sessions. they are left running at the end, and entered again next time.
+pub const PERSIST_LABEL: &str = "quarantine.persist";
+
+/// removes the anonymous volumes the image declared with `VOLUME` along with the container.
+const REMOVE_WITH_ANONYMOUS_VOLUMES: RemoveContainerOptions = RemoveContainerOptions {
+    v: true,
+    force: false,
+    link: false,
+};
+
+/// `quarantine-IMAGE-HASH`, the hash after the working directory so sessions in different directories get
+/// different containers. characters docker doesn't allow in names (eg: the `/` and `:` of the image) become `-`.
+pub fn default_name(image_name: &str, working_dir: Option<&Path>) -> String {
+    let image = image_name
+        .chars()
+        .map(
+            |c| match c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-') {
+                true => c,
+                false => '-',
+            },
+        )
+        .collect::<String>();
+    match working_dir {
+        Some(dir) => {
+            let hash = Sha256::digest(dir.to_string_lossy().as_bytes());
+            let id = hash[..4]
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<String>();
+            format!("quarantine-{}-{}", image, id)
+        }
+        None => format!("quarantine-{}", image),
+    }
+}
+
+/// the labels that tie a container to this process.
+pub fn owner() -> [(String, String); 2] {
+    [
+        (PID_LABEL.to_string(), std::process::id().to_string()),
+        (HOSTNAME_LABEL.to_string(), hostname()),
+    ]
+}
+
+/// a lock on a session container, held for as long as the session runs. unlike the owner labels it can be taken
+/// on a container that exists already (eg: one claimed from the pool or entered again), and the system lets go
+/// of it however the process ends.
+#[derive(Debug)]
+pub struct Lease {
+    path: PathBuf,
+    _file: File,
+}
+
+impl Lease {
+    /// takes the lease of the container, `None` when a live session holds it.
+    pub fn take(id: &str) -> anyhow::Result<Option<Lease>> {
+        take_in(&lease_dir(), id)
+    }
+
+    /// lets go of the lease right away, eg: before `process::exit`, which skips the drop.
+    pub fn release(self) {}
+}
+
+/// the file goes with the lease, so it doesn't linger once the session ended. only a crashed session leaves it
+/// behind, unlocked.
+impl Drop for Lease {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// `$XDG_STATE_HOME/quarantine/sessions`, a file per container that has been in a session.
+fn lease_dir() -> PathBuf {
+    crate::logging::state_dir().join("sessions")
+}
+
+fn take_in(dir: &Path, id: &str) -> anyhow::Result<Option<Lease>> {
+    std::fs::create_dir_all(dir)?;
+    let path = dir.join(id);
+    let file = File::options()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&path)?;
+    match file.try_lock() {
+        Ok(()) => Ok(Some(Lease { path, _file: file })),
+        Err(std::fs::TryLockError::WouldBlock) => Ok(None),
+        Err(std::fs::TryLockError::Error(e)) => Err(e.into()),
+    }
+}
+
+/// whether a live session holds the lease of the container, `None` when it never had one.
+fn held_in(dir: &Path, id: &str) -> Option<bool> {
+    let file = File::options().write(true).open(dir.join(id)).ok()?;
+    match file.try_lock() {
+        Ok(()) => Some(false),
+        Err(std::fs::TryLockError::WouldBlock) => Some(true),
+        Err(std::fs::TryLockError::Error(_)) => None,
+    }
+}
+
+/// whether a container is attached to a live session. a stopped container never is. a running one is while
+/// its `Lease` is held. without a lease it is, unless the process that created it is gone, which can only be
+/// told on the same machine. containers without the labels either (eg: created by an older quarantine) are
+/// assumed to be in use.
+pub fn in_use(
+    id: Option<&str>,
+    state: Option<&str>,
+    labels: Option<&HashMap<String, String>>,
+) -> bool {
+    in_use_in(&lease_dir(), id, state, labels)
+}
+
+fn in_use_in(
+    dir: &Path,
+    id: Option<&str>,
+    state: Option<&str>,
+    labels: Option<&HashMap<String, String>>,
+) -> bool {
+    if state != Some("running") {
+        return false;
+    }
+    if let Some(held) = id.and_then(|id| held_in(dir, id)) {
+        return held;
+    }
+    let Some(labels) = labels else {
+        return true;
+    };
+    let pid = labels
+        .get(PID_LABEL)
+        .and_then(|pid| pid.parse::<u32>().ok());
+    match (pid, labels.get(HOSTNAME_LABEL)) {
+        (Some(pid), Some(host)) if *host == hostname() => alive(pid),
+        _ => true,
+    }
+}
+
+/// the first of `base`, `base-2`, `base-3`.. that neither a live session nor a container of another tool holds.
+/// a stale container of a crashed session doesn't hold its name, it is removed before the new one is created.
+pub async fn free_name(docker: &Docker, base: &str) -> anyhow::Result<String> {
+    let pattern = format!("^/{}(-[0-9]+)?$", crate::regex_escape(base));
+    let containers = docker
+        .list_containers(Some(ListContainersOptions {
+            all: true,
+            filters: HashMap::from([("name", vec![pattern.as_str()])]),
+            ..Default::default()
+        }))
+        .await?;
+    let held = containers
+        .iter()
+        .filter(|container| {
+            let labels = container.labels.as_ref();
+            !labels.is_some_and(|labels| labels.contains_key(MANAGED_LABEL))
+                || in_use(container.id.as_deref(), container.state.as_deref(), labels)
+        })
+        .flat_map(|container| container.names.iter().flatten())
+        .filter_map(|name| name.strip_prefix('/'))
+        .collect::<Vec<&str>>();
+    let name = (1..)
+        .map(|n| match n {
+            1 => base.to_string(),
+            n => format!("{}-{}", base, n),
+        })
+        .find(|name| !held.contains(&name.as_str()))
+        .unwrap_or_else(|| unreachable!("there are finitely many containers"));
+    if name != base {
+        tracing::info!(
+            "`{}` is used by another session, naming this one `{}`",
+            base,
+            name
+        );
+    }
+    Ok(name)
+}
+
+/// a container of an earlier `--persist` session.
+pub struct Kept {
+    pub id: String,
+    pub image_id: String,
+}
+
+/// stops and removes any leftover containers of crashed sessions with the session's name, except a kept one which
+/// is returned (unless `fresh`). a container another session is attached to is an error, it is never removed.
+/// the daemon does the filtering, so containers of other tools are never listed, let alone touched. a same-named
+/// container that quarantine did not create is refused later, when creating the new one.
+pub async fn remove_stale(
+    docker: &Docker,
+    container_name: &str,
+    fresh: bool,
+) -> anyhow::Result<Option<Kept>> {
+    // the name filter is a regex on the name, which always has a leading `/`
+    let name = format!("^/{}$", crate::regex_escape(container_name));
+    let list_containers_options = ListContainersOptions {
+        all: true,
+        filters: HashMap::from([
+            ("name", vec![name.as_str()]),
+            ("label", vec![MANAGED_LABEL]),
+        ]),
+        ..Default::default()
+    };
+
+    tracing::info!(
+        "checking for any previously running containers with the name: {}",
+        container_name
+    );
+    let containers = docker
+        .list_containers(Some(list_containers_options))
+        .await?;
+    for container in containers {
+        let exact = container
+            .names
+            .unwrap_or_default()
+            .iter()
+            .any(|name| name.strip_prefix('/') == Some(container_name));
+        let managed = container
+            .labels
+            .as_ref()
+            .is_some_and(|labels| labels.contains_key(MANAGED_LABEL));
+        // older daemons may ignore filters they don't know
+        if !exact || !managed {
+            continue;
+        }
+        // a kept container is entered again only when no other session is in it
+        if in_use(
+            container.id.as_deref(),
+            container.state.as_deref(),
+            container.labels.as_ref(),
+        ) {
+            return Err(in_use_error(container_name));
+        }
+        let kept = container
+            .labels
+            .as_ref()
+            .is_some_and(|labels| labels.contains_key(PERSIST_LABEL));
+        if let (true, false, Some(id)) = (kept, fresh, container.id) {
+            return Ok(Some(Kept {
+                id,
+                image_id: container.image_id.unwrap_or_default(),
+            }));
+        }
+        if container.state.as_deref() == Some("running") {
+            tracing::info!(
+                "stopping the container of a crashed session: {}",
+                container_name
+            );
+            docker.stop_container(container_name, None).await?;
+        }
+        tracing::info!("removing container: {}", container_name);
+        docker
+            .remove_container(container_name, Some(REMOVE_WITH_ANONYMOUS_VOLUMES))
+            .await?;
+    }
+    Ok(None)
+}
+
+/// the error for a `--name` that another session's container has.
+pub fn in_use_error(container_name: &str) -> anyhow::Error {
+    anyhow!(
+        "`{}` is the container of a running session. pick a different name with `--name`, \
+         or end that session with `quarantine kill {}`",
+        container_name,
+        container_name
+    )
+}
+
+/// whether the process exists. one of another user still counts.
+#[cfg(unix)]
+fn alive(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return true;
+    };
+    let signalled = unsafe { libc::kill(pid, 0) };
+    signalled == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(windows)]
+fn alive(_pid: u32) -> bool {
+    true
+}
+
+#[cfg(unix)]
+fn hostname() -> String {
+    let mut name = [0u8; 256];
+    match unsafe { libc::gethostname(name.as_mut_ptr().cast(), name.len()) } {
+        0 => {
+            let end = name.iter().position(|b| *b == 0).unwrap_or(name.len());
+            String::from_utf8_lossy(&name[..end]).into_owned()
+        }
+        _ => String::new(),
+    }
+}
+
+#[cfg(windows)]
+fn hostname() -> String {
+    std::env::var("COMPUTERNAME").unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake_engine::{FakeEngine, Route};
+    use serde_json::json;
+
+    #[cfg(unix)]
+    /// the pid of a process that has exited.
+    fn dead_pid() -> u32 {
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        let pid = child.id();
+        child.wait().unwrap();
+        pid
+    }
+
+    fn labels(pid: u32, host: &str) -> HashMap<String, String> {
+        HashMap::from([
+            (MANAGED_LABEL.to_string(), "0.1.3".to_string()),
+            (PID_LABEL.to_string(), pid.to_string()),
+            (HOSTNAME_LABEL.to_string(), host.to_string()),
+        ])
+    }
+
+    #[test]
+    fn names_after_the_image() {
+        assert_eq!(default_name("python", None), "quarantine-python");
+        assert_eq!(
+            default_name("ghcr.io/org/app:1.2_rc", None),
+            "quarantine-ghcr.io-org-app-1.2_rc"
+        );
+        assert_eq!(
+            default_name("python@sha256:abc", None),
+            "quarantine-python-sha256-abc"
+        );
+    }
+
+    #[test]
+    fn names_after_the_working_directory() {
+        let here = default_name("python", Some(Path::new("/home/me/project")));
+        let there = default_name("python", Some(Path::new("/home/me/other")));
+        assert!(here.starts_with("quarantine-python-"));
+        assert_eq!(here.len(), "quarantine-python-".len() + 8);
+        assert_ne!(here, there);
+        assert_eq!(
+            here,
+            default_name("python", Some(Path::new("/home/me/project")))
+        );
+    }
+
+    #[test]
+    fn stopped_containers_are_not_in_use() {
+        let dir = tempfile::tempdir().unwrap();
+        let live = labels(std::process::id(), &hostname());
+        assert!(!in_use_in(dir.path(), None, Some("exited"), Some(&live)));
+        assert!(!in_use_in(dir.path(), None, None, None));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn running_containers_are_in_use_while_their_process_lives() {
+        let dir = tempfile::tempdir().unwrap();
+        let live = labels(std::process::id(), &hostname());
+        assert!(in_use_in(dir.path(), None, Some("running"), Some(&live)));
+
+        let dead = labels(dead_pid(), &hostname());
+        assert!(!in_use_in(dir.path(), None, Some("running"), Some(&dead)));
+
+        // the process of another machine can't be checked
+        let remote = labels(dead_pid(), "another-machine");
+        assert!(in_use_in(dir.path(), None, Some("running"), Some(&remote)));
+
+        assert!(in_use_in(dir.path(), None, Some("running"), None));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn leases_decide_over_the_labels() {
+        let dir = tempfile::tempdir().unwrap();
+        let dead = labels(dead_pid(), &hostname());
+
+        let lease = take_in(dir.path(), "claimed").unwrap().unwrap();
+        assert!(in_use_in(
+            dir.path(),
+            Some("claimed"),
+            Some("running"),
+            Some(&dead)
+        ));
+        assert!(take_in(dir.path(), "claimed").unwrap().is_none());
+
+        // a crashed session lets go of it, even without the owner labels. it leaves the file behind, unlocked
+        drop(lease);
+        File::create(dir.path().join("claimed")).unwrap();
+        assert!(!in_use_in(
+            dir.path(),
+            Some("claimed"),
+            Some("running"),
+            None
+        ));
+        assert!(take_in(dir.path(), "claimed").unwrap().is_some());
+    }
+
+    #[test]
+    fn released_leases_are_removed() {
+        let dir = tempfile::tempdir().unwrap();
+        take_in(dir.path(), "removed").unwrap().unwrap().release();
+        assert!(!dir.path().join("removed").exists());
+        assert_eq!(held_in(dir.path(), "removed"), None);
+
+        // however the session ends
+        let lease = take_in(dir.path(), "failed").unwrap().unwrap();
+        drop(lease);
+        assert!(!dir.path().join("failed").exists());
+    }
+
+    fn container(
+        name: &str,
+        state: &str,
+        labels: Option<HashMap<String, String>>,
+    ) -> serde_json::Value {
+        json!({
+            "Id": format!("{}-id", name),
+            "Names": [format!("/{}", name)],
+            "State": state,
+            "Labels": labels,
+        })
+    }
+
+    #[tokio::test]
+    async fn free_names_skip_live_sessions() {
+        let live = labels(std::process::id(), &hostname());
+        let engine = FakeEngine::start(vec![Route::new(
+            "GET",
+            "/containers/json",
+            json!([
+                container("quarantine-python", "running", Some(live.clone())),
+                container("quarantine-python-2", "running", Some(live)),
+            ]),
+        )])
+        .await;
+        assert_eq!(
+            free_name(&engine.docker, "quarantine-python")
+                .await
+                .unwrap(),
+            "quarantine-python-3"
+        );
+        let listed = engine.requested("GET", "/containers/json");
+        assert_eq!(listed.len(), 1);
+        assert!(listed[0]
+            .query
+            .contains(r#"^/quarantine-python(-[0-9]+)?$"#));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn free_names_reuse_stale_ones() {
+        let engine = FakeEngine::start(vec![Route::new(
+            "GET",
+            "/containers/json",
+            json!([
+                container(
+                    "quarantine-python",
+                    "exited",
+                    Some(labels(std::process::id(), &hostname()))
+                ),
+                container(
+                    "quarantine-python-2",
+                    "running",
+                    Some(labels(dead_pid(), &hostname()))
+                ),
+            ]),
+        )])
+        .await;
+        assert_eq!(
+            free_name(&engine.docker, "quarantine-python")
+                .await
+                .unwrap(),
+            "quarantine-python"
+        );
+    }
+
+    #[tokio::test]
+    async fn free_names_skip_containers_of_other_tools() {
+        let engine = FakeEngine::start(vec![Route::new(
+            "GET",
+            "/containers/json",
+            json!([container("quarantine-python", "exited", None)]),
+        )])
+        .await;
+        assert_eq!(
+            free_name(&engine.docker, "quarantine-python")
+                .await
+                .unwrap(),
+            "quarantine-python-2"
+        );
+    }
+
+    #[tokio::test]
+    async fn free_names_fail_with_the_daemon() {
+        let engine = FakeEngine::start(vec![Route::new(
+            "GET",
+            "/containers/json",
+            json!({"message": "the daemon is shutting down"}),
+        )
+        .status(500)])
+        .await;
+        assert!(free_name(&engine.docker, "quarantine-python")
+            .await
+            .is_err());
+    }
+
+    fn stale_scan(containers: serde_json::Value) -> Vec<Route> {
+        vec![
+            Route::new("GET", "/containers/json", containers),
+            Route::new("POST", "/containers/quarantine-python/stop", json!({})).status(204),
+            Route::new("DELETE", "/containers/quarantine-python", json!({})).status(204),
+        ]
+    }
+
+    fn untouched(engine: &FakeEngine) -> bool {
+        engine
+            .requests()
+            .iter()
+            .all(|request| request.method == "GET")
+    }
+
+    #[tokio::test]
+    async fn stale_scan_filters_on_the_daemon() {
+        let engine = FakeEngine::start(stale_scan(json!([]))).await;
+        let kept = remove_stale(&engine.docker, "quarantine-python", false)
+            .await
+            .unwrap();
+        assert!(kept.is_none());
+
+        let listed = engine.requested("GET", "/containers/json");
+        assert_eq!(listed.len(), 1);
+        assert!(listed[0].query.contains("all=true"));
+        assert!(listed[0]
+            .query
+            .contains(r#""name":["^/quarantine-python$"]"#));
+        assert!(listed[0]
+            .query
+            .contains(&format!(r#""label":["{}"]"#, MANAGED_LABEL)));
+        assert!(untouched(&engine));
+    }
+
+    #[tokio::test]
+    async fn stale_scan_leaves_other_containers_alone() {
+        // what a daemon that ignores the filters would list
+        let engine = FakeEngine::start(stale_scan(json!([
+            container(
+                "quarantine-python-latest-backup",
+                "exited",
+                Some(labels(std::process::id(), &hostname()))
+            ),
+            container("quarantine-python", "exited", None),
+        ])))
+        .await;
+        let kept = remove_stale(&engine.docker, "quarantine-python", false)
+            .await
+            .unwrap();
+        assert!(kept.is_none());
+        assert!(untouched(&engine));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn stale_scan_removes_crashed_sessions() {
+        let engine = FakeEngine::start(stale_scan(json!([container(
+            "quarantine-python",
+            "running",
+            Some(labels(dead_pid(), &hostname()))
+        )])))
+        .await;
+        let kept = remove_stale(&engine.docker, "quarantine-python", false)
+            .await
+            .unwrap();
+        assert!(kept.is_none());
+        assert_eq!(
+            engine
+                .requested("POST", "/containers/quarantine-python/stop")
+                .len(),
+            1
+        );
+        let removed = engine.requested("DELETE", "/containers/quarantine-python");
+        assert_eq!(removed.len(), 1);
+        assert!(removed[0].query.contains("v=true"));
+    }
+
+    #[tokio::test]
+    async fn stale_scan_refuses_live_sessions() {
+        let mut kept = labels(std::process::id(), &hostname());
+        kept.insert(PERSIST_LABEL.to_string(), "true".to_string());
+        for labels in [labels(std::process::id(), &hostname()), kept] {
+            let engine = FakeEngine::start(stale_scan(json!([container(
+                "quarantine-python",
+                "running",
+                Some(labels)
+            )])))
+            .await;
+            assert!(remove_stale(&engine.docker, "quarantine-python", false)
+                .await
+                .is_err());
+            assert!(untouched(&engine));
+        }
+    }
+
+    #[tokio::test]
+    async fn stale_scan_returns_kept_containers() {
+        let mut kept = labels(std::process::id(), &hostname());
+        kept.insert(PERSIST_LABEL.to_string(), "true".to_string());
+        let mut listed = container("quarantine-python", "exited", Some(kept));
+        listed["ImageID"] = json!("sha256:python");
+        let engine = FakeEngine::start(stale_scan(json!([listed]))).await;
+
+        let kept = remove_stale(&engine.docker, "quarantine-python", false)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(kept.id, "quarantine-python-id");
+        assert_eq!(kept.image_id, "sha256:python");
+        assert!(untouched(&engine));
+
+        let kept = remove_stale(&engine.docker, "quarantine-python", true)
+            .await
+            .unwrap();
+        assert!(kept.is_none());
+        assert_eq!(
+            engine
+                .requested("DELETE", "/containers/quarantine-python")
+                .len(),
+            1
+        );
+    }
+}
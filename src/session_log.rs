@@ -0,0 +1,145 @@
+//! `--session-log` and `--record`: a transcript of what the container printed, for auditing and debugging.
+
+use std::path::Path;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use tokio::io::AsyncWriteExt;
+
+use crate::audit;
+
+/// how the transcript is written.
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum Format {
+    /// `--session-log`: every chunk on its own line after a timestamp, sessions appended
+    #[value(skip)]
+    Log,
+    /// an asciinema v2 recording, `asciinema play` replays it
+    Cast,
+    /// the bytes as they were printed
+    Raw,
+}
+
+/// an open transcript.
+pub struct SessionLog {
+    file: Option<tokio::fs::File>,
+    format: Format,
+    started: Instant,
+    /// the start of a utf-8 character whose other bytes are in the next chunk, for `Cast`
+    pending: Vec<u8>,
+}
+
+impl SessionLog {
+    /// a `Log` is appended to after a header line, the other formats replace the file.
+    pub async fn open(
+        path: &Path,
+        format: Format,
+        image: &str,
+        container: &str,
+    ) -> anyhow::Result<Self> {
+        let mut options = tokio::fs::OpenOptions::new();
+        match format {
+            Format::Log => options.create(true).append(true),
+            Format::Cast | Format::Raw => options.create(true).write(true).truncate(true),
+        };
+        let mut file = options
+            .open(path)
+            .await
+            .map_err(|e| anyhow::anyhow!("unable to open `{}`: {}", path.display(), e))?;
+        let header = match format {
+            Format::Log => format!(
+                "=== session :: image: {} :: container: {} :: started: {} ===\n",
+                image,
+                container,
+                timestamp()
+            ),
+            Format::Cast => {
+                let (width, height) = crossterm::terminal::size().unwrap_or((80, 24));
+                let header = serde_json::json!({
+                    "version": 2,
+                    "width": width,
+                    "height": height,
+                    "timestamp": SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_secs(),
+                    "title": format!("{} :: {}", image, container),
+                    "env": { "TERM": std::env::var("TERM").unwrap_or_default() },
+                });
+                format!("{}\n", header)
+            }
+            Format::Raw => String::new(),
+        };
+        file.write_all(header.as_bytes()).await?;
+        file.flush().await?;
+        tracing::info!("recording the session to `{}`", path.display());
+        Ok(Self {
+            file: Some(file),
+            format,
+            started: Instant::now(),
+            pending: vec![],
+        })
+    }
+
+    /// appends a chunk of output of `stream` (`stdout`, `stderr` or `console`). a failing write stops the
+    /// recording, it never interrupts the session.
+    pub async fn write(&mut self, stream: &str, chunk: &[u8]) {
+        let entry = match self.format {
+            Format::Log => {
+                let mut entry = format!("{} {} ", timestamp(), stream).into_bytes();
+                entry.extend_from_slice(chunk);
+                if !chunk.ends_with(b"\n") {
+                    entry.push(b'\n');
+                }
+                entry
+            }
+            Format::Cast => match self.event(chunk) {
+                Some(event) => event.into_bytes(),
+                None => return,
+            },
+            Format::Raw => chunk.to_vec(),
+        };
+        let Some(file) = &mut self.file else {
+            return;
+        };
+        // flushed right away, the output task may be aborted at the end of the session
+        let written = match file.write_all(&entry).await {
+            Ok(()) => file.flush().await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = written {
+            tracing::warn!(
+                "unable to write the session log, no longer recording: {}",
+                e
+            );
+            self.file = None;
+        }
+    }
+
+    /// an asciinema output event, `[elapsed, "o", text]`. none while the chunk is only part of a character.
+    fn event(&mut self, chunk: &[u8]) -> Option<String> {
+        self.pending.extend_from_slice(chunk);
+        let complete = match std::str::from_utf8(&self.pending) {
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            _ => self.pending.len(),
+        };
+        if complete == 0 {
+            return None;
+        }
+        let text = String::from_utf8_lossy(&self.pending[..complete]).into_owned();
+        self.pending.drain(..complete);
+        let elapsed = self.started.elapsed().as_secs_f64();
+        Some(format!("{}\n", serde_json::json!([elapsed, "o", text])))
+    }
+}
+
+/// the current utc time with microseconds, eg: `2024-05-01T12:00:00.123456Z`.
+fn timestamp() -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let seconds = audit::format_time(now.as_secs());
+    format!(
+        "{}.{:06}Z",
+        seconds.trim_end_matches('Z'),
+        now.subsec_micros()
+    )
+}
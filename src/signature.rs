@@ -0,0 +1,162 @@
+//! `--verify-signature`: checks the cosign signature of the image before a container is created from it.
+
+use anyhow::anyhow;
+use sigstore::cosign::verification_constraint::cert_subject_email_verifier::StringVerifier;
+use sigstore::cosign::verification_constraint::{
+    CertSubjectEmailVerifier, CertSubjectUrlVerifier, PublicKeyVerifier, VerificationConstraintVec,
+};
+use sigstore::cosign::{ClientBuilder, CosignCapabilities, SignatureLayer};
+use sigstore::registry::{Auth, OciReference};
+use sigstore::trust::sigstore::SigstoreTrustRoot;
+use std::path::PathBuf;
+
+use crate::config::Verify;
+
+pub const DIGEST_LABEL: &str = "quarantine.signature.digest";
+pub const SUBJECT_LABEL: &str = "quarantine.signature.subject";
+pub const ISSUER_LABEL: &str = "quarantine.signature.issuer";
+
+/// who has to have signed the image.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Policy {
+    Key(PathBuf),
+    /// a keyless signature made with a fulcio certificate for this identity (an email or a url)
+    Keyless {
+        identity: String,
+        issuer: String,
+    },
+}
+
+/// what a successful verification found.
+#[derive(Debug, Clone)]
+pub struct Verified {
+    /// the digest of the manifest the signature covers
+    pub digest: String,
+    pub subject: String,
+    pub issuer: Option<String>,
+}
+
+/// the policy from the command line, or else the one the config requires for the image.
+pub fn policy(cli: Option<Verify>, config: Option<&Verify>) -> anyhow::Result<Option<Policy>> {
+    let from_config = cli.is_none();
+    let Some(verify) = cli.or_else(|| config.cloned()) else {
+        return Ok(None);
+    };
+    let source = match from_config {
+        true => "the `verify` setting of the config",
+        false => "--verify-signature",
+    };
+    match verify {
+        Verify {
+            key: Some(key),
+            certificate_identity: None,
+            certificate_oidc_issuer: None,
+        } => Ok(Some(Policy::Key(key))),
+        Verify {
+            key: None,
+            certificate_identity: Some(identity),
+            certificate_oidc_issuer: Some(issuer),
+        } => Ok(Some(Policy::Keyless { identity, issuer })),
+        _ => Err(anyhow!(
+            "{} needs either a key, or a certificate identity together with its oidc issuer",
+            source
+        )),
+    }
+}
+
+/// fetches the signatures of `image` from its registry and checks them against `policy`.
+pub async fn verify(image: &str, policy: &Policy) -> anyhow::Result<Verified> {
+    let reference = image
+        .parse::<OciReference>()
+        .map_err(|e| anyhow!("invalid image reference `{}`: {}", image, e))?;
+
+    let mut builder = ClientBuilder::default();
+    let trust_root;
+    let mut constraints: VerificationConstraintVec = vec![];
+    match policy {
+        Policy::Key(path) => {
+            let key = std::fs::read(path)
+                .map_err(|e| anyhow!("unable to read the key `{}`: {}", path.display(), e))?;
+            constraints.push(Box::new(PublicKeyVerifier::try_from(&key).map_err(
+                |e| anyhow!("invalid cosign key `{}`: {}", path.display(), e),
+            )?));
+        }
+        Policy::Keyless { identity, issuer } => {
+            tracing::info!("fetching the sigstore trust root");
+            trust_root = SigstoreTrustRoot::new(None)
+                .await
+                .map_err(|e| anyhow!("unable to fetch the sigstore trust root: {}", e))?;
+            builder = builder.with_trust_repository(&trust_root)?;
+            match identity.contains('@') && !identity.contains("://") {
+                true => constraints.push(Box::new(CertSubjectEmailVerifier {
+                    email: StringVerifier::ExactMatch(identity.clone()),
+                    issuer: Some(StringVerifier::ExactMatch(issuer.clone())),
+                })),
+                false => constraints.push(Box::new(CertSubjectUrlVerifier {
+                    url: identity.clone(),
+                    issuer: issuer.clone(),
+                })),
+            }
+        }
+    }
+    let mut client = builder.build()?;
+
+    tracing::info!("verifying the signature of `{}`", image);
+    let (_, digest) = client
+        .triangulate(&reference, &Auth::Anonymous)
+        .await
+        .map_err(|e| anyhow!("unable to resolve the digest of `{}`: {}", image, e))?;
+    let layers = client
+        .trusted_signature_layers(&Auth::Anonymous, &reference)
+        .await
+        .map_err(|e| anyhow!("unable to fetch the signatures of `{}`: {}", image, e))?;
+    if layers.is_empty() {
+        return Err(anyhow!("`{}` is not signed", image));
+    }
+
+    let layer = layers
+        .iter()
+        .filter(|layer| layer.simple_signing.critical.image.docker_manifest_digest == digest)
+        .find(|layer| {
+            constraints
+                .iter()
+                .all(|constraint| constraint.verify(layer).unwrap_or(false))
+        })
+        .ok_or_else(|| {
+            anyhow!(
+                "none of the {} signature(s) of `{}` ({}) satisfies the policy",
+                layers.len(),
+                image,
+                digest
+            )
+        })?;
+
+    let verified = Verified {
+        subject: subject(layer, policy),
+        issuer: layer
+            .certificate_signature
+            .as_ref()
+            .and_then(|certificate| certificate.issuer.clone()),
+        digest,
+    };
+    tracing::info!(
+        "the signature of `{}` is valid :: digest: {} :: subject: {}",
+        image,
+        verified.digest,
+        verified.subject
+    );
+    Ok(verified)
+}
+
+fn subject(layer: &SignatureLayer, policy: &Policy) -> String {
+    use sigstore::cosign::signature_layers::CertificateSubject;
+
+    match (&layer.certificate_signature, policy) {
+        (Some(certificate), _) => match &certificate.subject {
+            CertificateSubject::Email(email) => email.clone(),
+            CertificateSubject::Uri(uri) => uri.clone(),
+        },
+        (None, Policy::Key(path)) => format!("key {}", path.display()),
+        (None, Policy::Keyless { identity, .. }) => identity.clone(),
+    }
+}
@@ -0,0 +1,69 @@
+//! `--snapshot`: saves the container's filesystem as a local image at the end of the session.
+
+use anyhow::anyhow;
+use bollard::container::Config;
+use bollard::image::CommitContainerOptions;
+use bollard::Docker;
+use std::collections::HashMap;
+
+use crate::pull::Reference;
+
+/// set on snapshots, the image the container was started from.
+pub const SOURCE_IMAGE_LABEL: &str = "quarantine.source-image";
+
+/// `quarantine/<image>-snapshot:latest`, eg: `quarantine/python-snapshot:latest` for `python:3.12`.
+pub fn default_name(image: &str) -> String {
+    let repository = Reference::parse(image).repository;
+    let name = repository.rsplit('/').next().unwrap_or(&repository);
+    format!("quarantine/{}-snapshot:latest", name)
+}
+
+/// commits `container` to the image `name`, labelled with the `source` image, and returns the id of the image.
+pub async fn commit(
+    docker: &Docker,
+    container: &str,
+    name: &str,
+    source: &str,
+) -> anyhow::Result<String> {
+    let (repo, tag) = match name.rsplit_once(':') {
+        Some((repo, tag)) if !tag.contains('/') => (repo, tag),
+        _ => (name, "latest"),
+    };
+    tracing::info!("saving `{}` as `{}:{}`", container, repo, tag);
+    docker
+        .commit_container(
+            CommitContainerOptions {
+                container,
+                repo,
+                tag,
+                comment: "quarantine --snapshot",
+                author: "",
+                pause: true,
+                changes: None,
+            },
+            // merged with the container's config by the daemon
+            Config {
+                labels: Some(HashMap::from([(
+                    SOURCE_IMAGE_LABEL.to_string(),
+                    source.to_string(),
+                )])),
+                ..Default::default()
+            },
+        )
+        .await?;
+
+    // the commit response doesn't carry the id in the form bollard expects, ask for it instead
+    let image = format!("{}:{}", repo, tag);
+    let id = docker
+        .inspect_image(&image)
+        .await?
+        .id
+        .ok_or_else(|| anyhow!("the snapshot `{}` has no id", image))?;
+    tracing::info!(
+        "saved the snapshot `{}` :: id: {}. start from it with `quarantine -i {}`",
+        image,
+        id,
+        image
+    );
+    Ok(id)
+}
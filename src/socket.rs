@@ -0,0 +1,71 @@
+use bollard::secret::{HostConfig, MountTypeEnum};
+use std::path::{Path, PathBuf};
+
+/// the engine socket, if any of the bind mounts of the container expose it.
+/// both the structured `mounts` and the `SRC:DEST[:OPTS]` bind strings are checked.
+pub fn exposed(host_config: &HostConfig) -> Option<PathBuf> {
+    let mounts = host_config
+        .mounts
+        .iter()
+        .flatten()
+        .filter(|mount| mount.typ == Some(MountTypeEnum::BIND))
+        .filter_map(|mount| mount.source.as_deref());
+    let binds = host_config
+        .binds
+        .iter()
+        .flatten()
+        .filter_map(|bind| bind.split_once(':'))
+        .map(|(source, _)| source)
+        // named volumes don't live at a host path the user picked
+        .filter(|source| source.starts_with('/'));
+
+    mounts
+        .chain(binds)
+        .find_map(|source| exposed_by(Path::new(source)))
+}
+
+/// the engine socket, if mounting `source` into the container would make it reachable from inside.
+/// mounting any directory the socket lives in exposes it just the same as mounting the socket itself.
+pub fn exposed_by(source: &Path) -> Option<PathBuf> {
+    let sources = with_resolved(source.to_path_buf());
+    socket_paths()
+        .into_iter()
+        .flat_map(with_resolved)
+        .find(|socket| sources.iter().any(|source| socket.starts_with(source)))
+}
+
+/// the paths the docker and podman sockets are commonly reachable at, rootful and rootless.
+fn socket_paths() -> Vec<PathBuf> {
+    let mut paths = vec![
+        PathBuf::from("/var/run/docker.sock"),
+        PathBuf::from("/run/docker.sock"),
+        PathBuf::from("/run/podman/podman.sock"),
+        PathBuf::from("/var/run/podman/podman.sock"),
+    ];
+    if let Some(runtime_dir) = std::env::var_os("XDG_RUNTIME_DIR") {
+        let runtime_dir = PathBuf::from(runtime_dir);
+        paths.push(runtime_dir.join("docker.sock"));
+        paths.push(runtime_dir.join("podman/podman.sock"));
+    }
+    if let Some(home) = std::env::var_os("HOME") {
+        // docker desktop
+        paths.push(PathBuf::from(home).join(".docker/run/docker.sock"));
+    }
+    for var in ["DOCKER_HOST", "CONTAINER_HOST"] {
+        if let Some(path) = std::env::var(var)
+            .ok()
+            .and_then(|host| host.strip_prefix("unix://").map(PathBuf::from))
+        {
+            paths.push(path);
+        }
+    }
+    paths
+}
+
+/// the path as given and, when it exists, with symlinks resolved.
+fn with_resolved(path: PathBuf) -> Vec<PathBuf> {
+    match std::fs::canonicalize(&path) {
+        Ok(resolved) if resolved != path => vec![path, resolved],
+        _ => vec![path],
+    }
+}
@@ -0,0 +1,234 @@
+//! the local terminal during an attached session: raw mode, window size and the detach sequence.
+
+use bollard::exec::ResizeExecOptions;
+use bollard::Docker;
+use std::io::IsTerminal;
+use std::sync::Once;
+
+/// how often the terminal size is checked where there is no `SIGWINCH`.
+#[cfg(not(unix))]
+const RESIZE_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// ctrl-p ctrl-q, like docker.
+const DEFAULT_DETACH_KEYS: [u8; 2] = [0x10, 0x11];
+
+/// keeps the terminal in raw mode while it lives, so keys (ctrl-c included) reach the container untouched.
+/// the original mode is restored when it is dropped, and on a panic anywhere in the program.
+pub struct RawMode(());
+
+impl RawMode {
+    /// `None` when stdin is not a terminal, eg: when input is piped in.
+    pub fn enable() -> anyhow::Result<Option<RawMode>> {
+        if !std::io::stdin().is_terminal() {
+            return Ok(None);
+        }
+        static HOOK: Once = Once::new();
+        HOOK.call_once(|| {
+            let previous = std::panic::take_hook();
+            std::panic::set_hook(Box::new(move |info| {
+                let _ = crossterm::terminal::disable_raw_mode();
+                previous(info);
+            }));
+        });
+        crossterm::terminal::enable_raw_mode()?;
+        Ok(Some(RawMode(())))
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        if let Err(e) = crossterm::terminal::disable_raw_mode() {
+            tracing::warn!("unable to restore the terminal: {}", e);
+        }
+    }
+}
+
+/// tells the exec's tty the size of the local terminal.
+pub async fn resize(docker: &Docker, exec_id: &str) {
+    let Ok((width, height)) = crossterm::terminal::size() else {
+        return;
+    };
+    if let Err(e) = docker
+        .resize_exec(exec_id, ResizeExecOptions { height, width })
+        .await
+    {
+        tracing::debug!("unable to resize the exec tty: {}", e);
+    }
+}
+
+/// resizes the exec's tty every time the local terminal is resized (on `SIGWINCH`, polled on windows). runs until it is aborted,
+/// a failed resize never ends the session.
+pub async fn forward_resizes(docker: Docker, exec_id: String) {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        // listen before the first resize, so a resize in between is not missed
+        let mut resized = match signal(SignalKind::window_change()) {
+            Ok(resized) => resized,
+            Err(e) => {
+                tracing::debug!("unable to listen for terminal resizes: {}", e);
+                resize(&docker, &exec_id).await;
+                return;
+            }
+        };
+        resize(&docker, &exec_id).await;
+        while resized.recv().await.is_some() {
+            resize(&docker, &exec_id).await;
+        }
+    }
+    // there is no resize signal, the size is polled instead
+    #[cfg(not(unix))]
+    {
+        let mut size = crossterm::terminal::size().ok();
+        resize(&docker, &exec_id).await;
+        loop {
+            tokio::time::sleep(RESIZE_POLL_INTERVAL).await;
+            let current = crossterm::terminal::size().ok();
+            if current != size {
+                size = current;
+                resize(&docker, &exec_id).await;
+            }
+        }
+    }
+}
+
+/// the key sequence that detaches from the session, in docker's `--detach-keys` format: keys separated by
+/// commas, each a single character or `ctrl-<key>`, eg: `ctrl-p,ctrl-q` or `ctrl-x,x`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DetachKeys(Vec<u8>);
+
+impl Default for DetachKeys {
+    fn default() -> Self {
+        Self(DEFAULT_DETACH_KEYS.to_vec())
+    }
+}
+
+impl std::str::FromStr for DetachKeys {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let keys = s
+            .split(',')
+            .map(|key| match key.strip_prefix("ctrl-") {
+                // ctrl-a is 0x01 ... ctrl-z is 0x1a, ctrl-@ is 0x00 and ctrl-[ \ ] ^ _ follow z
+                Some(key) if key.len() == 1 => match key.as_bytes()[0].to_ascii_lowercase() {
+                    byte @ b'a'..=b'z' => Ok(byte - b'a' + 1),
+                    byte @ (b'@' | b'[' | b'\\' | b']' | b'^' | b'_') => Ok(byte - b'@'),
+                    _ => Err(anyhow::anyhow!("`ctrl-{}` is not a control key", key)),
+                },
+                None if key.len() == 1 => Ok(key.as_bytes()[0]),
+                _ => Err(anyhow::anyhow!(
+                    "expected a single character or `ctrl-<key>`, got `{}`",
+                    key
+                )),
+            })
+            .collect::<anyhow::Result<Vec<u8>>>()?;
+        Ok(Self(keys))
+    }
+}
+
+impl std::fmt::Display for DetachKeys {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let keys = self
+            .0
+            .iter()
+            .map(|&byte| match byte {
+                0x00..=0x1f => format!("ctrl-{}", ((byte + b'@') as char).to_ascii_lowercase()),
+                byte => (byte as char).to_string(),
+            })
+            .collect::<Vec<String>>();
+        write!(f, "{}", keys.join(" "))
+    }
+}
+
+/// finds the detach sequence in the input, like `docker attach`. the start of the sequence is held back
+/// until it is clear whether the rest follows, and passed on with the next input when it doesn't.
+#[derive(Debug, Default)]
+pub struct Detach {
+    keys: DetachKeys,
+    matched: usize,
+}
+
+impl Detach {
+    pub fn new(keys: DetachKeys) -> Self {
+        Self { keys, matched: 0 }
+    }
+
+    /// the input to forward, and whether the detach sequence was typed (the input after it is dropped).
+    pub fn feed(&mut self, input: &[u8]) -> (Vec<u8>, bool) {
+        let keys = &self.keys.0;
+        let mut forward = Vec::with_capacity(input.len());
+        for &byte in input {
+            if byte != keys[self.matched] {
+                forward.extend_from_slice(&keys[..self.matched]);
+                self.matched = 0;
+            }
+            match byte == keys[self.matched] {
+                true => self.matched += 1,
+                false => forward.push(byte),
+            }
+            if self.matched == keys.len() {
+                self.matched = 0;
+                return (forward, true);
+            }
+        }
+        (forward, false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(s: &str) -> DetachKeys {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_detach_keys() {
+        assert_eq!(keys("ctrl-p,ctrl-q"), DetachKeys(vec![0x10, 0x11]));
+        assert_eq!(keys("ctrl-p,ctrl-q"), DetachKeys::default());
+        assert_eq!(keys("ctrl-x,x"), DetachKeys(vec![0x18, b'x']));
+        assert_eq!(keys("ctrl-X"), DetachKeys(vec![0x18]));
+        assert_eq!(keys("ctrl-@"), DetachKeys(vec![0x00]));
+        assert_eq!(keys("ctrl-["), DetachKeys(vec![0x1b]));
+        assert_eq!(keys("ctrl-x,x").to_string(), "ctrl-x x");
+    }
+
+    #[test]
+    fn refuses_invalid_detach_keys() {
+        for invalid in ["ctrl-1", "ab", "", "ctrl-p,", "ctrl-"] {
+            assert!(invalid.parse::<DetachKeys>().is_err(), "{}", invalid);
+        }
+        let e = "ctrl-1".parse::<DetachKeys>().unwrap_err();
+        assert_eq!(e.to_string(), "`ctrl-1` is not a control key");
+    }
+
+    #[test]
+    fn detaches_on_the_whole_sequence() {
+        let mut detach = Detach::new(keys("ctrl-p,ctrl-q"));
+        assert_eq!(detach.feed(b"ls\r"), (b"ls\r".to_vec(), false));
+        assert_eq!(detach.feed(b"a\x10\x11b"), (b"a".to_vec(), true));
+    }
+
+    #[test]
+    fn detaches_on_a_sequence_split_across_reads() {
+        let mut detach = Detach::new(keys("ctrl-p,ctrl-q"));
+        // held back until it is clear whether it is the sequence
+        assert_eq!(detach.feed(b"a\x10"), (b"a".to_vec(), false));
+        assert_eq!(detach.feed(b"\x11"), (vec![], true));
+    }
+
+    #[test]
+    fn passes_on_a_partial_match() {
+        let mut detach = Detach::new(keys("ctrl-p,ctrl-q"));
+        assert_eq!(detach.feed(b"\x10"), (vec![], false));
+        assert_eq!(detach.feed(b"x"), (b"\x10x".to_vec(), false));
+        // ctrl-p ctrl-p ctrl-q: the first one is passed on, the rest detaches
+        assert_eq!(detach.feed(b"\x10\x10\x11"), (b"\x10".to_vec(), true));
+
+        let mut detach = Detach::new(keys("ctrl-x,x"));
+        assert_eq!(detach.feed(b"\x18y"), (b"\x18y".to_vec(), false));
+    }
+}
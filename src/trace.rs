@@ -0,0 +1,109 @@
+//! `--trace-docker`: the docker client logs every request it sends (the url and the json body) through the `log`
+//! crate. those records are forwarded into the log file at trace level, with secrets redacted. the method, path,
+//! status and timing of each request are logged by the proxy the connections go through, see `proxy`.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// json keys whose values are never logged.
+const SECRET_KEYS: [&str; 5] = [
+    "password",
+    "auth",
+    "identitytoken",
+    "registrytoken",
+    "secret",
+];
+
+/// environment variable names containing any of these are logged with their value redacted.
+const SECRET_ENV_NAMES: [&str; 6] = ["TOKEN", "SECRET", "PASSWORD", "PASSWD", "KEY", "CREDENTIAL"];
+
+/// the target the forwarded records are logged under.
+pub const TARGET: &str = "docker";
+
+/// whether `init` ran, so connections to the daemon go through the proxy that logs every request.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+struct DockerTrace;
+
+impl log::Log for DockerTrace {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.target().starts_with("bollard")
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            tracing::trace!(target: TARGET, "{}", redact(&record.args().to_string()));
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+pub fn init() -> anyhow::Result<()> {
+    log::set_logger(&DockerTrace).map_err(|e| anyhow::anyhow!("unable to trace docker: {}", e))?;
+    log::set_max_level(log::LevelFilter::Trace);
+    ENABLED.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+/// whether `--trace-docker` is on.
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// replaces secrets in the json strings of `message` with `***`: the values of keys like `password`,
+/// and the values of `NAME=VALUE` environment entries whose name looks like it holds a secret.
+pub fn redact(message: &str) -> String {
+    let mut out = String::with_capacity(message.len());
+    let mut rest = message;
+    let mut secret_key = false;
+
+    while let Some(start) = rest.find('"') {
+        out.push_str(&rest[..=start]);
+        rest = &rest[start + 1..];
+
+        // the end of the string literal, skipping escaped quotes
+        let mut end = None;
+        let mut escaped = false;
+        for (i, c) in rest.char_indices() {
+            match c {
+                '\\' if !escaped => escaped = true,
+                '"' if !escaped => {
+                    end = Some(i);
+                    break;
+                }
+                _ => escaped = false,
+            }
+        }
+        let Some(end) = end else {
+            out.push_str(rest);
+            return out;
+        };
+        let literal = &rest[..end];
+        rest = &rest[end + 1..];
+
+        let is_key = rest.trim_start().starts_with(':');
+        if is_key {
+            out.push_str(literal);
+            out.push('"');
+            secret_key = SECRET_KEYS.contains(&literal.to_lowercase().as_str());
+            continue;
+        }
+
+        match literal.split_once('=') {
+            _ if secret_key => out.push_str("***"),
+            Some((name, _))
+                if SECRET_ENV_NAMES
+                    .iter()
+                    .any(|secret| name.to_uppercase().contains(secret)) =>
+            {
+                out.push_str(name);
+                out.push_str("=***");
+            }
+            _ => out.push_str(literal),
+        }
+        out.push('"');
+        secret_key = false;
+    }
+    out.push_str(rest);
+    out
+}
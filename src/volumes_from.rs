@@ -0,0 +1,59 @@
+use anyhow::anyhow;
+use bollard::secret::{MountPoint, MountPointTypeEnum};
+use bollard::Docker;
+use std::path::PathBuf;
+
+/// checks that the container of every `NAME[:ro|:rw]` exists and returns the mount points the sandbox inherits.
+/// containers that quarantine doesn't manage are allowed, with a warning.
+pub async fn resolve(docker: &Docker, specs: &[String]) -> anyhow::Result<Vec<MountPoint>> {
+    let mut inherited = vec![];
+    for spec in specs {
+        let name = match spec.rsplit_once(':') {
+            Some((name, "ro" | "rw")) => name,
+            Some(_) => {
+                return Err(anyhow!(
+                    "invalid --volumes-from `{}`. expected NAME, NAME:ro or NAME:rw",
+                    spec
+                ))
+            }
+            None => spec,
+        };
+
+        let inspect = docker.inspect_container(name, None).await.map_err(|e| {
+            anyhow!(
+                "unable to find the --volumes-from container `{}`: {}",
+                name,
+                e
+            )
+        })?;
+
+        let managed = inspect
+            .name
+            .as_deref()
+            .is_some_and(|name| name.trim_start_matches('/').starts_with("quarantine-"));
+        if !managed {
+            tracing::warn!(
+                "--volumes-from `{}` is not a quarantine container. the sandbox can see (and change) its data",
+                name
+            );
+        }
+
+        for mount in inspect.mounts.unwrap_or_default() {
+            tracing::info!(
+                "sharing `{}` from `{}`",
+                mount.destination.as_deref().unwrap_or_default(),
+                name
+            );
+            inherited.push(mount);
+        }
+    }
+    Ok(inherited)
+}
+
+/// the host paths of the inherited bind mounts.
+pub fn bind_sources(mounts: &[MountPoint]) -> impl Iterator<Item = PathBuf> + '_ {
+    mounts
+        .iter()
+        .filter(|mount| mount.typ == Some(MountPointTypeEnum::BIND))
+        .filter_map(|mount| mount.source.as_deref().map(PathBuf::from))
+}
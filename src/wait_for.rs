@@ -0,0 +1,127 @@
+use anyhow::anyhow;
+use bollard::Docker;
+use std::time::{Duration, Instant};
+
+use crate::exec_status;
+
+/// how long to wait between two rounds of probes.
+const PROBE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// exit code of the probe scripts when the image has none of the tools they use.
+const NO_PROBE_TOOL: i64 = 127;
+
+/// a dependency the session waits for, given as `tcp://HOST:PORT` or `http(s)://...`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Probe {
+    Tcp { host: String, port: u16 },
+    Http(String),
+}
+
+impl std::fmt::Display for Probe {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Probe::Tcp { host, port } => write!(f, "tcp://{}:{}", host, port),
+            Probe::Http(url) => write!(f, "{}", url),
+        }
+    }
+}
+
+impl Probe {
+    /// a posix sh script that exits 0 once the dependency is reachable from inside the container.
+    fn script(&self) -> String {
+        match self {
+            Probe::Tcp { host, port } => format!(
+                "if command -v nc >/dev/null; then nc -z -w 1 {host} {port}; \
+                 elif command -v bash >/dev/null; then bash -c 'exec 3<>/dev/tcp/{host}/{port}'; \
+                 else exit {NO_PROBE_TOOL}; fi"
+            ),
+            // both fail on non 2xx responses
+            Probe::Http(url) => format!(
+                "if command -v curl >/dev/null; then curl -fsS -o /dev/null --max-time 2 '{url}'; \
+                 elif command -v wget >/dev/null; then wget -q -T 2 -O /dev/null '{url}'; \
+                 else exit {NO_PROBE_TOOL}; fi"
+            ),
+        }
+    }
+}
+
+pub fn parse_probe(s: &str) -> anyhow::Result<Probe> {
+    if s.starts_with("http://") || s.starts_with("https://") {
+        if s.contains('\'') {
+            return Err(anyhow!("urls with `'` in them are not supported"));
+        }
+        return Ok(Probe::Http(s.to_string()));
+    }
+
+    let address = s
+        .strip_prefix("tcp://")
+        .ok_or_else(|| anyhow!("expected tcp://HOST:PORT, http://... or https://..."))?;
+    let (host, port) = address
+        .rsplit_once(':')
+        .ok_or_else(|| anyhow!("expected tcp://HOST:PORT"))?;
+    let valid_host = !host.is_empty()
+        && host
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_.[]:".contains(c));
+    if !valid_host {
+        return Err(anyhow!("invalid host `{}`", host));
+    }
+    Ok(Probe::Tcp {
+        host: host.to_string(),
+        port: port.parse()?,
+    })
+}
+
+/// runs the probes from inside the container until all of them succeed, or fails naming the ones
+/// that never did once `timeout` has passed.
+pub async fn wait(
+    docker: &Docker,
+    container: &str,
+    probes: &[Probe],
+    timeout: Duration,
+) -> anyhow::Result<()> {
+    let start = Instant::now();
+    let mut pending = probes.to_vec();
+
+    while !pending.is_empty() {
+        let mut still_pending = vec![];
+        for probe in pending {
+            match exec_status(docker, container, vec!["sh", "-c", &probe.script()]).await? {
+                0 => tracing::info!("{} is ready ({:.1?})", probe, start.elapsed()),
+                NO_PROBE_TOOL => {
+                    return Err(anyhow!(
+                        "unable to probe {}. the image has none of the tools the probe uses (nc, bash, curl, wget)",
+                        probe
+                    ))
+                }
+                _ => still_pending.push(probe),
+            }
+        }
+        pending = still_pending;
+
+        if pending.is_empty() {
+            break;
+        }
+        if start.elapsed() >= timeout {
+            return Err(anyhow!(
+                "gave up after {:?}. never ready: {}",
+                timeout,
+                pending
+                    .iter()
+                    .map(|probe| probe.to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ));
+        }
+        tracing::info!(
+            "waiting for {}",
+            pending
+                .iter()
+                .map(|probe| probe.to_string())
+                .collect::<Vec<String>>()
+                .join(", ")
+        );
+        tokio::time::sleep(PROBE_INTERVAL).await;
+    }
+    Ok(())
+}
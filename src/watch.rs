@@ -0,0 +1,178 @@
+use anyhow::anyhow;
+use bollard::container::LogOutput;
+use bollard::exec::{CreateExecOptions, StartExecResults};
+use bollard::Docker;
+use futures::StreamExt;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::mpsc;
+
+/// changes that arrive within this long of the first one are folded into the same rerun.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// where the command records its pid inside the container, so an in-flight run can be cancelled.
+const PID_FILE: &str = "/tmp/.quarantine-watch.pid";
+
+/// runs `command` in the container, and again whenever something under `dir` changes, until ctrl-c.
+/// paths matched by `dir/.gitignore` (and everything under `.git`) are not watched.
+pub async fn watch(
+    docker: &Docker,
+    container: &str,
+    command: &[String],
+    dir: &Path,
+) -> anyhow::Result<()> {
+    let ignored = ignore_rules(dir);
+    let (tx, mut changes) = mpsc::unbounded_channel::<PathBuf>();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let Ok(event) = event else { return };
+        if matches!(event.kind, EventKind::Access(_)) {
+            return;
+        }
+        for path in event.paths {
+            if !is_ignored(&ignored, &path) {
+                let _ = tx.send(path);
+            }
+        }
+    })?;
+    watcher.watch(dir, RecursiveMode::Recursive)?;
+    tracing::info!("watching `{}` for changes", dir.display());
+
+    let mut trigger = None;
+    for n in 1.. {
+        separator(n, trigger.as_deref(), dir);
+        let start = Instant::now();
+
+        let run = run_once(docker, container, command);
+        tokio::pin!(run);
+        tokio::select! {
+            exit_code = &mut run => {
+                eprintln!("--- exited with {} after {:.1?}, waiting for changes", exit_code?, start.elapsed());
+                tokio::select! {
+                    changed = changes.recv() => trigger = changed,
+                    _ = tokio::signal::ctrl_c() => return Ok(()),
+                }
+            }
+            changed = changes.recv() => {
+                trigger = changed;
+                eprintln!("--- change detected, cancelling the run after {:.1?}", start.elapsed());
+                cancel(docker, container).await;
+            }
+            _ = tokio::signal::ctrl_c() => {
+                cancel(docker, container).await;
+                return Ok(());
+            }
+        }
+
+        // fold bursts of changes (eg: a save touching several files) into one rerun
+        tokio::time::sleep(DEBOUNCE).await;
+        while changes.try_recv().is_ok() {}
+    }
+    Ok(())
+}
+
+/// runs the command to completion, streaming its output, and returns its exit code.
+async fn run_once(docker: &Docker, container: &str, command: &[String]) -> anyhow::Result<i64> {
+    // record the pid and replace the shell with the command, so `cancel` signals the command itself
+    let script = format!("echo $$ > {}; exec \"$@\"", PID_FILE);
+    let mut cmd = vec!["sh", "-c", &script, "sh"];
+    cmd.extend(command.iter().map(String::as_str));
+
+    let exec = docker
+        .create_exec(
+            container,
+            CreateExecOptions {
+                attach_stdout: Some(true),
+                attach_stderr: Some(true),
+                cmd: Some(cmd),
+                ..Default::default()
+            },
+        )
+        .await?;
+
+    if let StartExecResults::Attached { mut output, .. } = docker.start_exec(&exec.id, None).await?
+    {
+        while let Some(output) = output.next().await {
+            match output? {
+                LogOutput::StdErr { message } => {
+                    std::io::stderr().write_all(&message)?;
+                    std::io::stderr().flush()?;
+                }
+                other => {
+                    std::io::stdout().write_all(&other.into_bytes())?;
+                    std::io::stdout().flush()?;
+                }
+            }
+        }
+    }
+
+    docker
+        .inspect_exec(&exec.id)
+        .await?
+        .exit_code
+        .ok_or_else(|| anyhow!("exec `{}` did not report an exit code", exec.id))
+}
+
+/// signals the in-flight command and its direct children. docker has no api to stop an exec.
+async fn cancel(docker: &Docker, container: &str) {
+    let script = format!(
+        "pid=$(cat {0} 2>/dev/null) && {{ pkill -TERM -P \"$pid\" 2>/dev/null; kill -TERM \"$pid\" 2>/dev/null; rm -f {0}; }}",
+        PID_FILE
+    );
+    if let Err(e) = crate::exec_status(docker, container, vec!["sh", "-c", &script]).await {
+        tracing::warn!("unable to cancel the running command: {}", e);
+    }
+}
+
+fn separator(n: usize, trigger: Option<&Path>, dir: &Path) {
+    let trigger = match trigger {
+        Some(path) => format!(
+            " :: {} changed",
+            path.strip_prefix(dir).unwrap_or(path).display()
+        ),
+        None => String::new(),
+    };
+    eprintln!(
+        "--- run #{} at {}{}",
+        n,
+        utc_time(SystemTime::now()),
+        trigger
+    );
+}
+
+/// `HH:MM:SS` in utc.
+fn utc_time(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default();
+    format!(
+        "{:02}:{:02}:{:02} UTC",
+        secs / 3600 % 24,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+fn ignore_rules(dir: &Path) -> Gitignore {
+    let mut builder = GitignoreBuilder::new(dir);
+    if let Some(e) = builder.add(dir.join(".gitignore")) {
+        if dir.join(".gitignore").exists() {
+            tracing::warn!("unable to read `.gitignore`: {}", e);
+        }
+    }
+    builder.build().unwrap_or_else(|e| {
+        tracing::warn!("invalid `.gitignore`, watching everything: {}", e);
+        Gitignore::empty()
+    })
+}
+
+fn is_ignored(ignored: &Gitignore, path: &Path) -> bool {
+    path.components()
+        .any(|component| component.as_os_str() == ".git")
+        || ignored
+            .matched_path_or_any_parents(path, path.is_dir())
+            .is_ignore()
+}
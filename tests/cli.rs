@@ -0,0 +1,194 @@
+//! runs the quarantine binary against a real daemon. run with `cargo test -- --ignored` where docker is available.
+
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+
+/// runs quarantine in an empty project directory, with `input` piped to it.
+fn quarantine(args: &[&str], input: &[u8]) -> Output {
+    let project = tempfile::tempdir().unwrap();
+    quarantine_in(project.path(), args, input)
+}
+
+/// runs quarantine with `project` as the working directory, with `input` piped to it.
+fn quarantine_in(project: &Path, args: &[&str], input: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_quarantine"))
+        .args(["--no-config", "-i", "alpine:latest"])
+        .args(args)
+        .current_dir(project)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // written while the output is read, a command that echoes its input would block on a full pipe otherwise
+    let mut stdin = child.stdin.take().unwrap();
+    let input = input.to_vec();
+    let written = std::thread::spawn(move || stdin.write_all(&input));
+    let output = child.wait_with_output().unwrap();
+    // a command that doesn't read all of its input may close it early
+    let _ = written.join().unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    output
+}
+
+#[test]
+#[ignore = "needs a docker daemon"]
+fn sets_the_marker_variables() {
+    let output = quarantine(
+        &[
+            "--",
+            "sh",
+            "-c",
+            r#"echo "$QUARANTINE|$QUARANTINE_VERSION|$QUARANTINE_IMAGE|$QUARANTINE_PROJECT""#,
+        ],
+        b"",
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        format!(
+            "1|{}|alpine:latest|/quarantine\n",
+            env!("CARGO_PKG_VERSION")
+        )
+    );
+}
+
+#[test]
+#[ignore = "needs a docker daemon"]
+fn marker_variables_can_be_cleared() {
+    let output = quarantine(
+        &[
+            "-e",
+            "QUARANTINE=",
+            "--",
+            "sh",
+            "-c",
+            r#"echo "[$QUARANTINE]""#,
+        ],
+        b"",
+    );
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "[]\n");
+}
+
+#[test]
+#[ignore = "needs a docker daemon"]
+fn leaves_out_the_project_without_a_mount() {
+    let output = quarantine(
+        &[
+            "--no-mount",
+            "--",
+            "sh",
+            "-c",
+            r#"echo "$QUARANTINE ${QUARANTINE_PROJECT-unset}""#,
+        ],
+        b"",
+    );
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "1 unset\n");
+}
+
+#[test]
+#[ignore = "needs a docker daemon"]
+fn feeds_stdin_to_the_command() {
+    let output = quarantine(&["--", "cat"], b"x\n");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "x\n");
+    // a command that doesn't read its input still exits on its own
+    let output = quarantine(&["--", "echo", "done"], b"ignored\n");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "done\n");
+}
+
+#[test]
+#[ignore = "needs a docker daemon"]
+fn keeps_the_output_written_at_stdin_eof() {
+    // the command starts printing the moment all of its input arrived
+    let input = (1..=20_000)
+        .map(|i| format!("input {}\n", i))
+        .collect::<String>();
+    let output = quarantine(
+        &[
+            "--",
+            "sh",
+            "-c",
+            "cat; i=0; while [ $i -lt 5000 ]; do i=$((i+1)); echo \"line $i\"; done",
+        ],
+        input.as_bytes(),
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines = stdout.lines().collect::<Vec<&str>>();
+    assert_eq!(lines.len(), 25_000);
+    assert_eq!(lines[0], "input 1");
+    assert_eq!(lines[19_999], "input 20000");
+    assert_eq!(lines[24_999], "line 5000");
+}
+
+#[test]
+#[ignore = "needs a docker daemon"]
+fn keeps_the_output_of_a_piped_script() {
+    // without a command, the shell runs what is piped in. the last line prints after the input ended
+    let output = quarantine(&[], b"echo first\nhead -c 1000000 /dev/zero | tr '\\0' x\n");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with("first\n"));
+    assert_eq!(stdout.len(), "first\n".len() + 1_000_000);
+}
+
+#[test]
+#[ignore = "needs a docker daemon"]
+fn mounts_a_project_with_colons_and_spaces_in_its_path() {
+    let dir = tempfile::tempdir().unwrap();
+    let project = dir.path().join("my project:v2");
+    std::fs::create_dir(&project).unwrap();
+    std::fs::write(project.join("marker"), "here\n").unwrap();
+    let output = quarantine_in(&project, &["--", "cat", "/quarantine/marker"], b"");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "here\n");
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+#[ignore = "needs a docker daemon"]
+fn runs_as_the_host_user_unless_as_root() {
+    // SAFETY: getuid and getgid cannot fail and have no preconditions
+    let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+    let output = quarantine(&["--", "sh", "-c", "echo $(id -u):$(id -g)"], b"");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        format!("{}:{}\n", uid, gid)
+    );
+
+    let output = quarantine(&["--as-root", "--", "id", "-u"], b"");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "0\n");
+}
+
+#[test]
+#[ignore = "needs a docker daemon"]
+fn persisted_containers_keep_running() {
+    let name = format!("quarantine-test-persist-{}", std::process::id());
+    let args = ["--persist", "--name", &name, "--"];
+    // the /tmp tmpfs is gone once the container stops
+    quarantine(
+        &[&args[..], &["sh", "-c", "echo here > /tmp/marker"]].concat(),
+        b"",
+    );
+    let output = quarantine(&[&args[..], &["cat", "/tmp/marker"]].concat(), b"");
+    let removed = Command::new(env!("CARGO_BIN_EXE_quarantine"))
+        .args(["kill", &name])
+        .status()
+        .unwrap();
+    // the name of the kept container is printed last
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        format!("here\n{}\n", name)
+    );
+    assert!(removed.success());
+}
+
+#[test]
+#[ignore = "needs a docker daemon"]
+fn offline_sessions_have_no_network() {
+    // --offline needs the image to be present already
+    quarantine(&["--", "true"], b"");
+    let output = quarantine(&["--offline", "--", "ls", "/sys/class/net"], b"");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "lo\n");
+}
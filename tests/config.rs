@@ -0,0 +1,76 @@
+//! runs the quarantine binary on config files, without a daemon.
+
+use std::path::Path;
+use std::process::{Command, Output};
+
+/// runs quarantine in `project`, with `config_dir` holding the global config.
+fn quarantine(config_dir: &Path, project: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_quarantine"))
+        .args(args)
+        .current_dir(project)
+        .env("XDG_CONFIG_HOME", config_dir)
+        .output()
+        .unwrap()
+}
+
+/// a global config that sets `shm_size` and a project config that names the image.
+fn files() -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(dir.path().join("config/quarantine")).unwrap();
+    std::fs::write(
+        dir.path().join("config/quarantine/config.toml"),
+        "shm_size = \"1g\"\n",
+    )
+    .unwrap();
+    std::fs::create_dir(dir.path().join("project")).unwrap();
+    std::fs::write(
+        dir.path().join("project/quarantine.toml"),
+        "image = \"node:20\"\n",
+    )
+    .unwrap();
+    dir
+}
+
+#[test]
+fn shows_the_merged_config() {
+    let dir = files();
+    let output = quarantine(
+        &dir.path().join("config"),
+        &dir.path().join("project"),
+        &["--show-config"],
+    );
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("image = \"node:20\""), "{}", stdout);
+    assert!(stdout.contains("shm_size = \"1g\""), "{}", stdout);
+}
+
+#[test]
+fn no_config_ignores_the_config_files() {
+    let dir = files();
+    let output = quarantine(
+        &dir.path().join("config"),
+        &dir.path().join("project"),
+        &["--no-config", "--show-config"],
+    );
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "# --no-config: the config files are ignored\n"
+    );
+
+    // so the image of the project config is not used either
+    let output = quarantine(
+        &dir.path().join("config"),
+        &dir.path().join("project"),
+        &["--no-config"],
+    );
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("no image was given"), "{}", stderr);
+    assert!(
+        stderr.contains("no config files (--no-config)"),
+        "{}",
+        stderr
+    );
+}
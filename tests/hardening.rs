@@ -0,0 +1,85 @@
+//! checks the hardened defaults on a container started by a real daemon. run with `cargo test -- --ignored` where
+//! docker is available.
+
+use bollard::container::RemoveContainerOptions;
+use bollard::Docker;
+use quarantine::{Session, PROJECT_CAPABILITIES, TMP_OPTIONS};
+
+async fn inspect(mut session: Session) -> bollard::secret::ContainerInspectResponse {
+    let docker = Docker::connect_with_local_defaults().unwrap();
+    session.pull().await.unwrap();
+    let container = session.start().await.unwrap().to_string();
+    let inspected = docker.inspect_container(&container, None).await.unwrap();
+    docker
+        .remove_container(
+            &container,
+            Some(RemoveContainerOptions {
+                force: true,
+                v: true,
+                link: false,
+            }),
+        )
+        .await
+        .unwrap();
+    inspected
+}
+
+#[tokio::test]
+#[ignore = "needs a docker daemon"]
+async fn containers_are_hardened_by_default() {
+    let project = tempfile::tempdir().unwrap();
+    let session = Session::builder()
+        .image("alpine:latest")
+        .docker(Docker::connect_with_local_defaults().unwrap())
+        .mount(project.path(), "/quarantine")
+        .build()
+        .unwrap();
+    let inspected = inspect(session).await;
+    let host_config = inspected.host_config.unwrap();
+
+    assert_eq!(host_config.cap_drop, Some(vec!["ALL".to_string()]));
+    assert_eq!(
+        host_config.cap_add,
+        Some(PROJECT_CAPABILITIES.map(String::from).to_vec())
+    );
+    assert_eq!(host_config.readonly_rootfs, Some(true));
+    assert_eq!(
+        host_config.tmpfs.unwrap().get("/tmp").map(String::as_str),
+        Some(TMP_OPTIONS)
+    );
+    assert_eq!(
+        host_config.security_opt,
+        Some(vec!["no-new-privileges".to_string()])
+    );
+    // the project stays writable
+    let project_mount = inspected
+        .mounts
+        .unwrap()
+        .into_iter()
+        .find(|mount| mount.destination.as_deref() == Some("/quarantine"))
+        .unwrap();
+    assert_eq!(project_mount.rw, Some(true));
+}
+
+#[tokio::test]
+#[ignore = "needs a docker daemon"]
+async fn opt_outs_reach_the_container() {
+    let session = Session::builder()
+        .image("alpine:latest")
+        .docker(Docker::connect_with_local_defaults().unwrap())
+        .cap_add("NET_BIND_SERVICE")
+        .writable_rootfs(true)
+        .allow_privilege_escalation(true)
+        .build()
+        .unwrap();
+    let host_config = inspect(session).await.host_config.unwrap();
+
+    assert_eq!(host_config.cap_drop, Some(vec!["ALL".to_string()]));
+    assert_eq!(
+        host_config.cap_add,
+        Some(vec!["NET_BIND_SERVICE".to_string()])
+    );
+    assert_ne!(host_config.readonly_rootfs, Some(true));
+    assert!(host_config.tmpfs.unwrap_or_default().is_empty());
+    assert!(host_config.security_opt.unwrap_or_default().is_empty());
+}
@@ -0,0 +1,127 @@
+//! drives a `Session` against a real daemon. run with `cargo test -- --ignored` where docker is available.
+
+use quarantine::Session;
+
+#[tokio::test]
+#[ignore = "needs a docker daemon"]
+async fn copies_stdin_and_stdout() {
+    let mut session = Session::builder()
+        .image("alpine:latest")
+        .command(["sh", "-c", "cat; echo done >&2; exit 3"])
+        .build()
+        .unwrap();
+    session.pull().await.unwrap();
+    session.start().await.unwrap();
+
+    let (mut stdout, mut stderr) = (vec![], vec![]);
+    let exit_code = session
+        .attach(&b"hello\nworld\n"[..], &mut stdout, &mut stderr)
+        .await
+        .unwrap();
+    session.teardown(false).await.unwrap();
+
+    assert_eq!(exit_code, 3);
+    assert_eq!(stdout, b"hello\nworld\n");
+    assert_eq!(stderr, b"done\n");
+}
+
+#[tokio::test]
+#[ignore = "needs a docker daemon"]
+async fn mounts_are_shared_with_the_host() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("input"), "from the host").unwrap();
+    let mut session = Session::builder()
+        .image("alpine:latest")
+        .mount(dir.path(), "/work")
+        .command(["sh", "-c", "cat /work/input > /work/output"])
+        .build()
+        .unwrap();
+    session.pull().await.unwrap();
+    session.start().await.unwrap();
+
+    let exit_code = session
+        .attach(tokio::io::empty(), tokio::io::sink(), tokio::io::sink())
+        .await
+        .unwrap();
+    session.teardown(false).await.unwrap();
+
+    assert_eq!(exit_code, 0);
+    assert_eq!(
+        std::fs::read_to_string(dir.path().join("output")).unwrap(),
+        "from the host"
+    );
+}
+
+#[tokio::test]
+#[ignore = "needs a docker daemon"]
+async fn runs_a_command_and_cleans_up() {
+    let docker = bollard::Docker::connect_with_local_defaults().unwrap();
+    let mut session = Session::builder()
+        .image("alpine:latest")
+        .docker(docker.clone())
+        .build()
+        .unwrap();
+    session.pull().await.unwrap();
+    let container = session.start().await.unwrap().to_string();
+
+    let hi = session.exec(["echo", "hi"]).await.unwrap();
+    assert_eq!(hi.stdout, b"hi\n");
+    assert_eq!(hi.stderr, b"");
+    assert_eq!(hi.exit_code, 0);
+
+    session.teardown(false).await.unwrap();
+    assert!(docker.inspect_container(&container, None).await.is_err());
+}
+
+#[tokio::test]
+#[ignore = "needs a docker daemon"]
+async fn persisted_sessions_keep_running() {
+    let docker = bollard::Docker::connect_with_local_defaults().unwrap();
+    let mut session = Session::builder()
+        .image("alpine:latest")
+        .docker(docker.clone())
+        .build()
+        .unwrap();
+    session.pull().await.unwrap();
+    let container = session.start().await.unwrap().to_string();
+
+    session.teardown(true).await.unwrap();
+    let state = docker
+        .inspect_container(&container, None)
+        .await
+        .unwrap()
+        .state
+        .unwrap();
+    assert_eq!(state.running, Some(true));
+
+    docker
+        .remove_container(
+            &container,
+            Some(bollard::container::RemoveContainerOptions {
+                force: true,
+                v: true,
+                link: false,
+            }),
+        )
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn attach_before_start_fails() {
+    let session = Session::builder().image("alpine:latest").build().unwrap();
+    let attached = session
+        .attach(tokio::io::empty(), tokio::io::sink(), tokio::io::sink())
+        .await;
+    assert!(matches!(attached, Err(quarantine::Error::NotStarted)));
+    let executed = session.exec(["true"]).await;
+    assert!(matches!(executed, Err(quarantine::Error::NotStarted)));
+}
+
+#[tokio::test]
+async fn builds_need_an_image() {
+    assert!(matches!(
+        Session::builder().build(),
+        Err(quarantine::Error::NoImage)
+    ));
+}